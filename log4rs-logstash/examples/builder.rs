@@ -43,7 +43,7 @@ fn main() -> AnyResult<()> {
 }
 
 fn spawn_signal_handler() -> AnyResult<()> {
-    let mut signals = Signals::new(&[SIGINT, SIGTERM])?;
+    let mut signals = Signals::new([SIGINT, SIGTERM])?;
 
     std::thread::spawn(move || {
         let mut stop_in_progress = false;
@@ -0,0 +1,22 @@
+//! Logs through the appenders of `examples/logstash.yaml`. The Logstash address is taken
+//! from `LOGSTASH_HOST` and `LOGSTASH_PORT`, 127.0.0.1:5959 by default.
+
+use anyhow::Result as AnyResult;
+use qoollo_log4rs_logstash::config::DeserializersExt;
+
+fn main() -> AnyResult<()> {
+    log4rs::init_file(
+        concat!(env!("CARGO_MANIFEST_DIR"), "/examples/logstash.yaml"),
+        log4rs::config::Deserializers::default().with_logstash(),
+    )?;
+
+    log::debug!("Debug");
+    log::info!("Info");
+    log::warn!("Warn");
+    log::error!("Error");
+    log::info!(target: "audit", "Audit");
+
+    // Waits for the buffered records to be sent
+    log::logger().flush();
+    Ok(())
+}
@@ -1,17 +1,44 @@
 use anyhow::Result as AnyResult;
 use log::Level as LogLevel;
+use log::LevelFilter;
 use log::Record;
 use log4rs::append::Append;
-use qoollo_logstash_rs::LogStashRecord;
+use log4rs::encode::writer::simple::SimpleWriter;
+use log4rs::encode::Encode;
+use qoollo_logstash_rs::{EscapingTransformer, HostnameCache, HostnameProvider, LevelScale, LogStashRecord, OverflowPolicy, OversizedPolicy, PartialWritePolicy, WorkerDispatch};
 use qoollo_logstash_rs::Sender;
-use qoollo_logstash_rs::{BufferedSender, TcpSender};
+use qoollo_logstash_rs::{AdaptiveBatching, BatchFormat, BufferPolicy, BufferedSender, Framing, ProxyOptions, ReconnectPolicy, StartupCheck, TcpSender, TlsOptions};
+#[cfg(feature = "pool")]
+use qoollo_logstash_rs::RecordPool;
 use serde_json::Value;
-use std::collections::HashMap;
+use std::cell::Cell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+#[cfg(feature = "pool")]
+use std::sync::Arc;
 use std::time::Duration;
 
 pub struct Appender<S> {
     sender: S,
+    flush_timeout: Option<Duration>,
+    threshold: LevelFilter,
     extra_fields: HashMap<String, Value>,
+    default_tags: Vec<String>,
+    level_tags: HashMap<LogLevel, Vec<String>>,
+    module_short: bool,
+    level_value: Option<LevelScale>,
+    file_prefix: Option<String>,
+    location: bool,
+    escaping: Option<EscapingTransformer>,
+    encoder: Option<Box<dyn Encode>>,
+    host: HostnameCache,
+    /// Configuration hash written with the logger info, if enabled
+    logger_info: Option<String>,
+    #[cfg(feature = "pool")]
+    record_pool: Option<Arc<RecordPool>>,
 }
 
 impl<S> std::fmt::Debug for Appender<S> {
@@ -26,12 +53,64 @@ pub struct AppenderBuilder {
     port: u16,
     buffer_size: Option<usize>,
     buffer_lifetime: Option<Duration>,
+    level_buffer_policies: HashMap<LogLevel, BufferPolicy>,
+    adaptive_batching: Option<AdaptiveBatching>,
     connection_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
     ignore_buffer: LogLevel,
+    threshold: LevelFilter,
+    target_overrides: Vec<(String, LogLevel)>,
     use_tls: bool,
+    tls: TlsOptions,
+    proxy: Option<ProxyOptions>,
+    reconnect: ReconnectPolicy,
     error_period: Duration,
     extra_fields: HashMap<String, Value>,
     log_queue_len: usize,
+    pre_connect: bool,
+    ping_interval: Option<Duration>,
+    heartbeat_interval: Option<Duration>,
+    self_metrics_interval: Option<Duration>,
+    diagnostics: bool,
+    slow_send_threshold: Option<Duration>,
+    max_buffer_bytes: Option<usize>,
+    max_event_bytes: Option<usize>,
+    oversized_policy: OversizedPolicy,
+    oversized_diagnostics: bool,
+    flush_bytes: Option<usize>,
+    flush_on_level: Option<LogLevel>,
+    max_in_flight: Option<usize>,
+    overflow_policy: OverflowPolicy,
+    partial_write_policy: PartialWritePolicy,
+    ordered: bool,
+    coalesce_repeats: Option<u64>,
+    sub_ms_seq: bool,
+    workers: usize,
+    worker_dispatch: WorkerDispatch,
+    audit: Option<Duration>,
+    framing: Framing,
+    batch_format: BatchFormat,
+    dns_cache_ttl: Option<Duration>,
+    startup_check: StartupCheck,
+    shutdown_timeout: Duration,
+    flush_timeout: Option<Duration>,
+    record_ttl: Option<Duration>,
+    persist_on_shutdown: Option<PathBuf>,
+    default_tags: Vec<String>,
+    level_tags: HashMap<LogLevel, Vec<String>>,
+    module_short: bool,
+    logger_info: bool,
+    level_value: Option<LevelScale>,
+    level_names: HashMap<LogLevel, String>,
+    file_prefix: Option<String>,
+    location: bool,
+    escaping: Option<EscapingTransformer>,
+    encoder: Option<Box<dyn Encode>>,
+    host: HostnameCache,
+    #[cfg(feature = "pool")]
+    record_pool: Option<Arc<RecordPool>>,
+    #[cfg(feature = "metrics")]
+    metrics_prefix: Option<String>,
 }
 
 impl Default for AppenderBuilder {
@@ -41,12 +120,64 @@ impl Default for AppenderBuilder {
             port: 5044,
             buffer_size: Some(100),
             buffer_lifetime: Some(Duration::from_secs(1)),
+            level_buffer_policies: Default::default(),
+            adaptive_batching: None,
             connection_timeout: Some(Duration::from_secs(10)),
+            write_timeout: None,
             use_tls: false,
+            tls: Default::default(),
+            proxy: None,
+            reconnect: Default::default(),
             ignore_buffer: LogLevel::Error,
+            threshold: LevelFilter::Trace,
+            target_overrides: vec![],
             error_period: Duration::from_secs(10),
             extra_fields: Default::default(),
             log_queue_len: 1000,
+            pre_connect: false,
+            ping_interval: None,
+            heartbeat_interval: None,
+            self_metrics_interval: None,
+            diagnostics: true,
+            slow_send_threshold: None,
+            max_buffer_bytes: None,
+            max_event_bytes: None,
+            oversized_policy: Default::default(),
+            oversized_diagnostics: false,
+            flush_bytes: None,
+            flush_on_level: None,
+            max_in_flight: None,
+            overflow_policy: Default::default(),
+            partial_write_policy: Default::default(),
+            ordered: false,
+            coalesce_repeats: None,
+            sub_ms_seq: false,
+            workers: 1,
+            worker_dispatch: Default::default(),
+            audit: None,
+            framing: Default::default(),
+            batch_format: Default::default(),
+            dns_cache_ttl: None,
+            startup_check: Default::default(),
+            shutdown_timeout: Duration::from_secs(2),
+            flush_timeout: Some(Duration::from_secs(1)),
+            record_ttl: None,
+            persist_on_shutdown: None,
+            default_tags: Default::default(),
+            level_tags: Default::default(),
+            module_short: false,
+            logger_info: false,
+            level_value: None,
+            level_names: Default::default(),
+            file_prefix: None,
+            location: false,
+            escaping: None,
+            encoder: None,
+            host: Default::default(),
+            #[cfg(feature = "pool")]
+            record_pool: None,
+            #[cfg(feature = "metrics")]
+            metrics_prefix: None,
         }
     }
 }
@@ -58,6 +189,23 @@ impl AppenderBuilder {
         self
     }
 
+    /// Drops records less severe than `threshold` before they are converted. Unlike log4rs
+    /// filters attached to the appender, it is applied by the appender itself.
+    pub fn with_threshold(mut self, threshold: LevelFilter) -> AppenderBuilder {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Overrides the ignore buffer level for targets starting with `target_prefix`.
+    pub fn with_target_override(
+        mut self,
+        target_prefix: impl Into<String>,
+        level: LogLevel,
+    ) -> AppenderBuilder {
+        self.target_overrides.push((target_prefix.into(), level));
+        self
+    }
+
     /// Sets the hostname of the remote server.
     pub fn with_hostname(mut self, hostname: impl Into<String>) -> AppenderBuilder {
         self.hostname = hostname.into();
@@ -88,6 +236,20 @@ impl AppenderBuilder {
         self
     }
 
+    /// Overrides the buffer size and lifetime for records of `level`.
+    /// All levels share one buffer, it is sent as soon as any record in it reaches its limits.
+    pub fn with_level_buffer_policy(mut self, level: LogLevel, policy: BufferPolicy) -> AppenderBuilder {
+        self.level_buffer_policies.insert(level, policy);
+        self
+    }
+
+    /// Adjusts the buffer size within the bounds of `adaptive_batching` by the latency of
+    /// the sends.
+    pub fn with_adaptive_batching(mut self, adaptive_batching: AdaptiveBatching) -> AppenderBuilder {
+        self.adaptive_batching = Some(adaptive_batching);
+        self
+    }
+
     /// Sets the timeout for network connections.
     pub fn with_connection_timeout(mut self, timeout: Duration) -> AppenderBuilder {
         self.connection_timeout = Some(timeout);
@@ -112,31 +274,484 @@ impl AppenderBuilder {
         self
     }
 
+    /// Connect to the remote server in the background right after start.
+    pub fn with_pre_connect(mut self, pre_connect: bool) -> AppenderBuilder {
+        self.pre_connect = pre_connect;
+        self
+    }
+
+    /// Sets the period of connection liveness checks.
+    pub fn with_ping_interval(mut self, ping_interval: Duration) -> AppenderBuilder {
+        self.ping_interval = Some(ping_interval);
+        self
+    }
+
+    /// Sends a heartbeat record at this interval to prove the pipeline is alive.
+    pub fn with_heartbeat_interval(mut self, heartbeat_interval: Duration) -> AppenderBuilder {
+        self.heartbeat_interval = Some(heartbeat_interval);
+        self
+    }
+
+    /// Sends a record of the logger counters marked with `_self_metrics` at this interval.
+    pub fn with_self_metrics_interval(mut self, self_metrics_interval: Duration) -> AppenderBuilder {
+        self.self_metrics_interval = Some(self_metrics_interval);
+        self
+    }
+
+    /// Send a self-diagnostic record after recovering from sender errors.
+    pub fn with_diagnostics(mut self, diagnostics: bool) -> AppenderBuilder {
+        self.diagnostics = diagnostics;
+        self
+    }
+
+    /// Send a self-diagnostic warning record when a single send or flush takes longer than
+    /// `threshold`.
+    pub fn with_slow_send_threshold(mut self, threshold: Duration) -> AppenderBuilder {
+        self.slow_send_threshold = Some(threshold);
+        self
+    }
+
+    /// Upper bound on the estimated size of buffered records.
+    pub fn with_max_buffer_bytes(mut self, max_buffer_bytes: usize) -> AppenderBuilder {
+        self.max_buffer_bytes = Some(max_buffer_bytes);
+        self
+    }
+
+    /// Upper bound on the estimated size of a single record, larger ones are handled by the
+    /// oversized policy.
+    pub fn with_max_event_bytes(mut self, max_event_bytes: usize) -> AppenderBuilder {
+        self.max_event_bytes = Some(max_event_bytes);
+        self
+    }
+
+    /// Sets what to do with records above the maximum event size, sent anyway by default.
+    pub fn with_oversized_policy(mut self, oversized_policy: OversizedPolicy) -> AppenderBuilder {
+        self.oversized_policy = oversized_policy;
+        self
+    }
+
+    /// Send a self-diagnostic record per target with records above the maximum event size,
+    /// at most once an hour.
+    pub fn with_oversized_diagnostics(mut self, oversized_diagnostics: bool) -> AppenderBuilder {
+        self.oversized_diagnostics = oversized_diagnostics;
+        self
+    }
+
+    /// Sends the buffer before the estimated size of its records would exceed `flush_bytes`.
+    pub fn with_flush_bytes(mut self, flush_bytes: usize) -> AppenderBuilder {
+        self.flush_bytes = Some(flush_bytes);
+        self
+    }
+
+    /// Sends the whole buffer once a record of `level` or more severe is logged.
+    pub fn with_flush_on_level(mut self, level: LogLevel) -> AppenderBuilder {
+        self.flush_on_level = Some(level);
+        self
+    }
+
+    /// Upper bound on records queued and buffered, the overflow policy applies above it.
+    pub fn with_max_in_flight(mut self, max_in_flight: usize) -> AppenderBuilder {
+        self.max_in_flight = Some(max_in_flight);
+        self
+    }
+
+    /// Reuse up to `capacity` records instead of allocating one per log call. Records come
+    /// back to the pool once sent in a batch, records above the ignore buffer level are sent on
+    /// their own and not reused.
+    #[cfg(feature = "pool")]
+    pub fn with_record_pool(mut self, capacity: usize) -> AppenderBuilder {
+        self.record_pool = Some(Arc::new(RecordPool::new(capacity)));
+        self
+    }
+
+    /// Publish sender statistics through the `metrics` facade, with names starting with `prefix`.
+    #[cfg(feature = "metrics")]
+    pub fn with_metrics_prefix(mut self, prefix: impl Into<String>) -> AppenderBuilder {
+        self.metrics_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Sets what to drop once the buffer exceeds its memory budget.
+    pub fn with_overflow_policy(mut self, overflow_policy: OverflowPolicy) -> AppenderBuilder {
+        self.overflow_policy = overflow_policy;
+        self
+    }
+
+    /// Sets what happens to records of a batch which was written only partially.
+    pub fn with_partial_write_policy(mut self, partial_write_policy: PartialWritePolicy) -> AppenderBuilder {
+        self.partial_write_policy = partial_write_policy;
+        self
+    }
+
+    /// Delivers records in the order they were logged, including those bypassing the buffer
+    /// and batches retried after a failure.
+    pub fn with_ordered(mut self, ordered: bool) -> AppenderBuilder {
+        self.ordered = ordered;
+        self
+    }
+
+    /// Number records of a batch sharing the same millisecond with a `sub_ms_seq` field.
+    pub fn with_sub_ms_seq(mut self, sub_ms_seq: bool) -> AppenderBuilder {
+        self.sub_ms_seq = sub_ms_seq;
+        self
+    }
+
+    /// Number of worker threads, each with its own connection.
+    pub fn with_workers(mut self, workers: usize) -> AppenderBuilder {
+        self.workers = workers;
+        self
+    }
+
+    /// Sets how records are distributed between workers.
+    pub fn with_worker_dispatch(mut self, worker_dispatch: WorkerDispatch) -> AppenderBuilder {
+        self.worker_dispatch = worker_dispatch;
+        self
+    }
+
+    /// Count repeats of the last buffered record in its `repeat_count` field, up to `cap`.
+    pub fn with_coalesce_repeats(mut self, cap: u64) -> AppenderBuilder {
+        self.coalesce_repeats = Some(cap);
+        self
+    }
+
+    /// Confirm every write by waiting up to `timeout` for a connection reset.
+    pub fn with_audit(mut self, timeout: Duration) -> AppenderBuilder {
+        self.audit = Some(timeout);
+        self
+    }
+
+    /// Reuse the resolved address of the hostname for reconnects within `ttl`.
+    pub fn with_dns_cache_ttl(mut self, ttl: Duration) -> AppenderBuilder {
+        self.dns_cache_ttl = Some(ttl);
+        self
+    }
+
+    /// Connect while building the appender, printing an error or failing the build if the
+    /// destination is unreachable.
+    pub fn with_startup_check(mut self, startup_check: StartupCheck) -> AppenderBuilder {
+        self.startup_check = startup_check;
+        self
+    }
+
+    /// Sets where the newlines between records are written.
+    pub fn with_framing(mut self, framing: Framing) -> AppenderBuilder {
+        self.framing = framing;
+        self
+    }
+
+    /// Sends every batch as a single JSON envelope instead of newline-delimited records.
+    pub fn with_batch_format(mut self, batch_format: BatchFormat) -> AppenderBuilder {
+        self.batch_format = batch_format;
+        self
+    }
+
+    /// Sets certificates and server name used when TLS is enabled.
+    pub fn with_tls_options(mut self, tls: TlsOptions) -> AppenderBuilder {
+        self.tls = tls;
+        self
+    }
+
+    /// Connects through the `proxy`, which resolves the hostname of the server.
+    pub fn with_proxy(mut self, proxy: ProxyOptions) -> AppenderBuilder {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Sets the backoff between failed connection attempts.
+    pub fn with_reconnect_policy(mut self, reconnect: ReconnectPolicy) -> AppenderBuilder {
+        self.reconnect = reconnect;
+        self
+    }
+
+    /// Sets the maximum time a write to the socket may block.
+    pub fn with_write_timeout(mut self, timeout: Duration) -> AppenderBuilder {
+        self.write_timeout = Some(timeout);
+        self
+    }
+
+    /// Drops buffered records older than `record_ttl` instead of sending them.
+    pub fn with_record_ttl(mut self, record_ttl: Duration) -> AppenderBuilder {
+        self.record_ttl = Some(record_ttl);
+        self
+    }
+
+    /// Writes the records still buffered at shutdown to `path` and sends them on the next
+    /// start with a `replayed: true` field, unless they were delivered before the shutdown
+    /// timeout. See [`BufferedSenderBuilder::with_persist_on_shutdown`].
+    ///
+    /// [`BufferedSenderBuilder::with_persist_on_shutdown`]: qoollo_logstash_rs::BufferedSenderBuilder::with_persist_on_shutdown
+    pub fn with_persist_on_shutdown(mut self, path: impl Into<PathBuf>) -> AppenderBuilder {
+        self.persist_on_shutdown = Some(path.into());
+        self
+    }
+
+    /// Maximum time dropping the appender waits for buffered records to be sent.
+    pub fn with_shutdown_timeout(mut self, timeout: Duration) -> AppenderBuilder {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Maximum time [`Append::flush`] waits for the buffered records to be sent, 1 second by
+    /// default. `None` only asks the sender to flush without waiting.
+    pub fn with_flush_timeout(mut self, timeout: Option<Duration>) -> AppenderBuilder {
+        self.flush_timeout = timeout;
+        self
+    }
+
     /// Additional fields to send to logstash
     pub fn with_extra_fields(mut self, extra_fields: HashMap<String, Value>) -> AppenderBuilder {
         self.extra_fields = extra_fields;
         self
     }
 
+    /// Tags added to every record
+    pub fn with_default_tags(mut self, default_tags: Vec<String>) -> AppenderBuilder {
+        self.default_tags = default_tags;
+        self
+    }
+
+    /// Tags added to records of the given level
+    pub fn with_level_tags(mut self, level: LogLevel, tags: Vec<String>) -> AppenderBuilder {
+        self.level_tags.insert(level, tags);
+        self
+    }
+
+    /// Add `module_short` field with the last segment of the module path.
+    pub fn with_module_short(mut self, module_short: bool) -> AppenderBuilder {
+        self.module_short = module_short;
+        self
+    }
+
+    /// Add `location` field with the file and line of the call, e.g. `src/main.rs:42`. The
+    /// file prefix set by [`with_file_prefix`](Self::with_file_prefix) is stripped from it.
+    pub fn with_location(mut self, location: bool) -> AppenderBuilder {
+        self.location = location;
+        self
+    }
+
+    /// Add `logger` field with the name and version of the sender crate and the
+    /// [`config_hash`](Self::config_hash) of this builder.
+    pub fn with_logger_info(mut self, logger_info: bool) -> AppenderBuilder {
+        self.logger_info = logger_info;
+        self
+    }
+
+    /// Short hash of the settings of this builder, the same for builders with the same
+    /// settings. The hostname provider and the record pool are left out.
+    pub fn config_hash(&self) -> String {
+        fn sorted<K: Ord, V>(map: &HashMap<K, V>) -> BTreeMap<&K, &V> {
+            map.iter().collect()
+        }
+        let mut hasher = DefaultHasher::new();
+        let mut add = |setting: &dyn Debug| format!("{:?}", setting).hash(&mut hasher);
+        add(&(&self.hostname, self.port, self.use_tls, &self.tls, &self.proxy, self.startup_check));
+        add(&(self.connection_timeout, self.write_timeout, self.reconnect, self.dns_cache_ttl));
+        add(&(self.audit, self.framing, self.batch_format));
+        add(&(self.buffer_size, self.buffer_lifetime, sorted(&self.level_buffer_policies), self.adaptive_batching));
+        add(&(self.ignore_buffer, self.threshold, &self.target_overrides, self.flush_on_level));
+        add(&(self.max_buffer_bytes, self.flush_bytes, self.max_in_flight, self.log_queue_len));
+        add(&(self.max_event_bytes, self.oversized_policy, self.oversized_diagnostics));
+        add(&(self.overflow_policy, self.partial_write_policy, self.ordered, self.coalesce_repeats));
+        add(&(self.error_period, self.pre_connect, self.ping_interval, self.heartbeat_interval));
+        add(&(self.self_metrics_interval, self.diagnostics, self.slow_send_threshold, self.sub_ms_seq));
+        add(&(self.workers, self.worker_dispatch, self.shutdown_timeout, self.record_ttl));
+        add(&(self.flush_timeout, &self.persist_on_shutdown));
+        add(&(sorted(&self.extra_fields), &self.default_tags, sorted(&self.level_tags)));
+        add(&(self.module_short, self.logger_info, self.level_value, sorted(&self.level_names)));
+        add(&(&self.file_prefix, self.location, &self.escaping, &self.encoder));
+        #[cfg(feature = "metrics")]
+        add(&self.metrics_prefix);
+        format!("{:08x}", hasher.finish() >> 32)
+    }
+
+    /// Add `level_value` field with the numeric level on the given scale next to `level`.
+    pub fn with_level_value(mut self, scale: LevelScale) -> AppenderBuilder {
+        self.level_value = Some(scale);
+        self
+    }
+
+    /// Serialize `name` in the `level` field of records of `level`, e.g. `"WRN"`.
+    /// The names are shared by all appenders and senders of the process, the names of the
+    /// appender built last replace those set before, see
+    /// [`set_level_names`](qoollo_logstash_rs::set_level_names).
+    pub fn with_level_name(mut self, level: LogLevel, name: impl Into<String>) -> AppenderBuilder {
+        self.level_names.insert(level, name.into());
+        self
+    }
+
+    /// Escape non-ASCII characters of the string values of `fields` as `\uXXXX`.
+    pub fn with_escape_non_ascii(mut self, fields: Vec<String>) -> AppenderBuilder {
+        self.escaping = Some(EscapingTransformer::new(fields));
+        self
+    }
+
+    /// Strip `prefix` from the `file` field, e.g. the crate root, to send relative paths.
+    pub fn with_file_prefix(mut self, prefix: impl Into<String>) -> AppenderBuilder {
+        self.file_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Render the `message` field with a log4rs encoder, e.g. a `PatternEncoder`, instead of
+    /// the plain log message. Trailing newlines of the output are trimmed.
+    pub fn with_encoder(mut self, encoder: Box<dyn Encode>) -> AppenderBuilder {
+        self.encoder = Some(encoder);
+        self
+    }
+
+    /// Sets the source of the `host` field.
+    pub fn with_hostname_provider(self, provider: HostnameProvider) -> AppenderBuilder {
+        self.with_hostname_cache(HostnameCache::new(provider))
+    }
+
+    /// Sets the handle providing the `host` field. Keep a clone of it to swap the provider
+    /// at runtime.
+    pub fn with_hostname_cache(mut self, host: HostnameCache) -> AppenderBuilder {
+        self.host = host;
+        self
+    }
+
     /// Invoke the builder and return a [`Appender`](struct.Appender.html).
     pub fn build(self) -> AnyResult<Appender<BufferedSender>> {
-        Ok(Appender {
-            sender: BufferedSender::new(
-                TcpSender::new(
-                    self.hostname,
-                    self.port,
-                    self.use_tls,
-                    self.connection_timeout,
-                ),
-                self.buffer_size,
-                self.buffer_lifetime,
-                self.ignore_buffer,
-                self.error_period,
-                self.log_queue_len,
-            ),
-            extra_fields: self.extra_fields,
+        let sender = self.try_build_sender()?;
+        Ok(self.build_with_sender(sender))
+    }
+
+    /// Starts the buffered sender configured by this builder without creating an appender.
+    /// The startup check is skipped.
+    ///
+    /// Panics if the hostname is empty or the port is 0, see
+    /// [`try_build_sender`](Self::try_build_sender).
+    pub fn build_sender(&self) -> BufferedSender {
+        self.start_sender(None)
+    }
+
+    /// Starts the buffered sender after the startup check, the first worker keeps the checked
+    /// connection.
+    pub fn try_build_sender(&self) -> qoollo_logstash_rs::Result<BufferedSender> {
+        let checked = self.tcp_sender_factory()()?.check_on_start(self.startup_check)?;
+        Ok(self.start_sender(Some(checked)))
+    }
+
+    fn start_sender(&self, first: Option<TcpSender>) -> BufferedSender {
+        let mut sender = BufferedSender::builder();
+        if let Some(max_buffer_bytes) = self.max_buffer_bytes {
+            sender = sender.with_max_buffer_bytes(max_buffer_bytes);
+        }
+        for (target_prefix, level) in &self.target_overrides {
+            sender = sender.with_target_override(target_prefix.clone(), *level);
+        }
+        for (level, policy) in &self.level_buffer_policies {
+            sender = sender.with_level_buffer_policy(*level, *policy);
+        }
+        let sender = sender
+            .with_buffer_size(self.buffer_size)
+            .with_buffer_lifetime(self.buffer_lifetime)
+            .with_adaptive_batching(self.adaptive_batching)
+            .with_flush_bytes(self.flush_bytes)
+            .with_flush_on_level(self.flush_on_level)
+            .with_ignore_buffer_level(self.ignore_buffer)
+            .with_error_period(self.error_period)
+            .with_log_queue_len(self.log_queue_len)
+            .with_pre_connect(self.pre_connect)
+            .with_ping_interval(self.ping_interval)
+            .with_heartbeat_interval(self.heartbeat_interval)
+            .with_self_metrics_interval(self.self_metrics_interval)
+            .with_diagnostics(self.diagnostics)
+            .with_slow_send_threshold(self.slow_send_threshold)
+            .with_max_event_bytes(self.max_event_bytes, self.oversized_policy)
+            .with_oversized_diagnostics(self.oversized_diagnostics)
+            .with_overflow_policy(self.overflow_policy)
+            .with_partial_write_policy(self.partial_write_policy)
+            .with_ordered(self.ordered)
+            .with_coalesce_repeats(self.coalesce_repeats)
+            .with_max_in_flight(self.max_in_flight)
+            .with_sub_ms_seq(self.sub_ms_seq)
+            .with_hostname_refresh(self.host.clone())
+            .with_workers(self.workers)
+            .with_worker_dispatch(self.worker_dispatch)
+            .with_record_ttl(self.record_ttl)
+            .with_persist_on_shutdown(self.persist_on_shutdown.clone())
+            .with_shutdown_timeout(self.shutdown_timeout);
+        #[cfg(feature = "pool")]
+        let sender = match &self.record_pool {
+            Some(pool) => sender.with_record_pool(pool.clone()),
+            None => sender,
+        };
+        #[cfg(feature = "metrics")]
+        let sender = match &self.metrics_prefix {
+            Some(prefix) => sender.with_metrics_prefix(prefix.clone()),
+            None => sender,
+        };
+        let factory = self.tcp_sender_factory();
+        let first = Cell::new(first);
+        sender.build_with_factory(move || {
+            first.take().unwrap_or_else(|| factory().expect("invalid logstash address"))
         })
     }
+
+    fn tcp_sender_factory(&self) -> impl Fn() -> qoollo_logstash_rs::Result<TcpSender> {
+        let mut tcp = TcpSender::builder()
+            .hostname(self.hostname.clone())
+            .port(self.port)
+            .reconnect(self.reconnect)
+            .framing(self.framing);
+        if let Some(connection_timeout) = self.connection_timeout {
+            tcp = tcp.connect_timeout(connection_timeout);
+        }
+        if let Some(write_timeout) = self.write_timeout {
+            tcp = tcp.write_timeout(write_timeout);
+        }
+        if self.use_tls {
+            tcp = tcp.tls(self.tls.clone());
+        }
+        if let Some(proxy) = &self.proxy {
+            tcp = tcp.proxy(proxy.clone());
+        }
+        let (audit, batch_format, dns_cache_ttl) = (self.audit, self.batch_format, self.dns_cache_ttl);
+        move || {
+            Ok(tcp
+                .clone()
+                .build()?
+                .with_audit(audit)
+                .with_batch_format(batch_format)
+                .with_dns_cache_ttl(dns_cache_ttl))
+        }
+    }
+
+    /// Builds an [`Appender`](struct.Appender.html) on top of an existing sender, e.g. a clone
+    /// of a [`BufferedSender`] shared with other appenders. Connection and buffering settings
+    /// of this builder are ignored.
+    pub fn build_with_sender<S: Sender>(self, sender: S) -> Appender<S> {
+        if self.threshold < self.ignore_buffer {
+            eprintln!(
+                "Logstash appender threshold {} is more severe than ignore buffer level {}, \
+                 records of levels between them are dropped",
+                self.threshold, self.ignore_buffer
+            );
+        }
+        let logger_info = if self.logger_info { Some(self.config_hash()) } else { None };
+        if !self.level_names.is_empty() {
+            qoollo_logstash_rs::set_level_names(self.level_names);
+        }
+        Appender {
+            sender,
+            flush_timeout: self.flush_timeout,
+            logger_info,
+            threshold: self.threshold,
+            extra_fields: self.extra_fields,
+            default_tags: self.default_tags,
+            level_tags: self.level_tags,
+            module_short: self.module_short,
+            level_value: self.level_value,
+            file_prefix: self.file_prefix,
+            location: self.location,
+            escaping: self.escaping,
+            encoder: self.encoder,
+            host: self.host,
+            #[cfg(feature = "pool")]
+            record_pool: self.record_pool,
+        }
+    }
 }
 
 impl<S> Appender<S>
@@ -147,8 +762,31 @@ where
         AppenderBuilder::default()
     }
 
+    /// Sender the records are passed to
+    pub fn sender(&self) -> &S {
+        &self.sender
+    }
+
+    fn new_record(&self, log_record: &Record) -> LogStashRecord {
+        #[cfg(feature = "pool")]
+        if let Some(pool) = &self.record_pool {
+            return LogStashRecord::from_record_pooled(log_record, pool).into_inner();
+        }
+        LogStashRecord::from_record(log_record)
+    }
+
+    fn encode_message(&self, encoder: &dyn Encode, record: &Record) -> AnyResult<String> {
+        let mut writer = SimpleWriter(Vec::new());
+        encoder.encode(&mut writer, record)?;
+        let message = String::from_utf8_lossy(&writer.0);
+        Ok(message.trim_end_matches(&['\r', '\n'][..]).to_string())
+    }
+
     fn try_flush(&self) -> AnyResult<()> {
-        self.sender.flush()?;
+        match self.flush_timeout {
+            Some(timeout) => self.sender.flush_and_wait(timeout)?,
+            None => self.sender.flush()?,
+        }
         Ok(())
     }
 }
@@ -157,9 +795,43 @@ impl<S> Append for Appender<S>
 where
     S: Sender + Sync + Send + 'static,
 {
-    fn append(&self, record: &Record) -> AnyResult<()> {
-        self.sender
-            .send(LogStashRecord::from_record(record).with_data_from_map(&self.extra_fields))?;
+    fn append(&self, log_record: &Record) -> AnyResult<()> {
+        if log_record.level() > self.threshold || !self.sender.enabled(log_record.metadata()) {
+            return Ok(());
+        }
+        let mut record = self
+            .new_record(log_record)
+            .with_data_from_map(&self.extra_fields)
+            .with_tags(&self.default_tags);
+        if let Some(encoder) = &self.encoder {
+            let message = self.encode_message(encoder.as_ref(), log_record)?;
+            record.add_data("message", message.into());
+        }
+        if let Some(tags) = self.level_tags.get(&record.level) {
+            record = record.with_tags(tags);
+        }
+        if self.module_short {
+            record.add_module_short();
+        }
+        if let Some(scale) = self.level_value {
+            record.add_level_value(scale);
+        }
+        if let Some(prefix) = &self.file_prefix {
+            record.strip_file_prefix(prefix);
+        }
+        if self.location {
+            record.add_location();
+        }
+        if let Some(escaping) = &self.escaping {
+            escaping.apply(&mut record);
+        }
+        if let Some(host) = self.host.get() {
+            record.add_data("host", host.into());
+        }
+        if let Some(config_hash) = &self.logger_info {
+            record.add_logger_info(Some(config_hash));
+        }
+        self.sender.send(record)?;
         Ok(())
     }
     fn flush(&self) {
@@ -1,15 +1,34 @@
 use log4rs::append::Append;
 use log4rs::config::{Deserialize, Deserializers};
+use log4rs::encode::EncoderConfig;
+use serde::de::{DeserializeOwned, Error as _};
+use serde::Deserialize as _;
 use serde_json::Value;
 
 use crate::appender::AppenderBuilder;
+use crate::interpolation::interpolate_env;
 use anyhow::Result as AnyResult;
 use log::Level as LogLevel;
+use log::LevelFilter;
+use qoollo_logstash_rs::{
+    AdaptiveBatching, BatchFormat, BufferPolicy, Framing, HostnameProvider, Jitter, LevelScale, OverflowPolicy, OversizedPolicy, PartialWritePolicy, ReconnectPolicy,
+    BufferedSender, BufferedSenderBuilder, ProxyOptions, Sender, StartupCheck, TlsOptions, WeakBufferedSender, WorkerDispatch,
+};
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
+use std::fmt::Display;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Mutex;
 use std::time::Duration;
 
+#[derive(Default)]
 struct AppenderDeserializer {
-    extra_fields: Option<HashMap<String, Value>>
+    extra_fields: Option<HashMap<String, Value>>,
+    /// Senders of live appenders by config fingerprint, reused when a reload doesn't change
+    /// the config so the connection is kept
+    senders: Mutex<HashMap<u64, WeakBufferedSender>>,
 }
 
 pub trait DeserializersExt {
@@ -21,86 +40,558 @@ pub trait DeserializersExt {
 
 #[derive(Debug, serde::Deserialize)]
 pub struct AppenderConfig {
-    ignore_buffer_level: Option<LogLevel>,
+    #[serde(flatten)]
+    buffered: BufferedSenderConfig,
+    threshold: Option<LevelFilter>,
+    target_overrides: Option<HashMap<String, LogLevel>>,
     hostname: String,
+    #[serde(deserialize_with = "scalar")]
     port: u16,
-    buffer_size: Option<usize>,
-    #[serde(default)]
-    #[serde(with = "humantime_serde")]
-    buffer_lifetime: Option<Duration>,
+    level_buffers: Option<HashMap<LogLevel, BufferPolicyConfig>>,
+    adaptive_batching: Option<AdaptiveBatchingConfig>,
     #[serde(default)]
     #[serde(with = "humantime_serde")]
     connection_timeout: Option<Duration>,
+    #[serde(default, deserialize_with = "optional_scalar")]
     use_tls: Option<bool>,
+    #[serde(default, deserialize_with = "tls_section")]
+    tls: Option<TlsOptions>,
+    #[serde(default, deserialize_with = "proxy_section")]
+    proxy: Option<ProxyOptions>,
+    #[serde(default, deserialize_with = "timeouts_section")]
+    timeouts: Option<TimeoutsConfig>,
+    reconnect: Option<ReconnectConfig>,
+    extra_fields: Option<HashMap<String, Value>>,
+    #[serde(default, deserialize_with = "optional_scalar")]
+    pre_connect: Option<bool>,
     #[serde(default)]
     #[serde(with = "humantime_serde")]
-    error_period: Option<Duration>,
-    extra_fields: Option<HashMap<String, Value>>,
-    log_queue_len: Option<usize>,
+    ping_interval: Option<Duration>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    heartbeat_interval: Option<Duration>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    self_metrics_interval: Option<Duration>,
+    #[serde(default, deserialize_with = "optional_scalar")]
+    diagnostics: Option<bool>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    slow_send_threshold: Option<Duration>,
+    #[serde(default, deserialize_with = "optional_scalar")]
+    max_buffer_bytes: Option<usize>,
+    #[serde(default, deserialize_with = "optional_scalar")]
+    max_event_bytes: Option<usize>,
+    oversized_policy: Option<OversizedPolicy>,
+    #[serde(default, deserialize_with = "optional_scalar")]
+    oversized_diagnostics: Option<bool>,
+    #[serde(default, deserialize_with = "optional_scalar")]
+    flush_bytes: Option<usize>,
+    flush_on_level: Option<LogLevel>,
+    #[serde(default, deserialize_with = "optional_scalar")]
+    max_in_flight: Option<usize>,
+    #[cfg(feature = "pool")]
+    #[serde(default, deserialize_with = "optional_scalar")]
+    record_pool: Option<usize>,
+    #[cfg(feature = "metrics")]
+    metrics_prefix: Option<String>,
+    overflow_policy: Option<OverflowPolicy>,
+    partial_write_policy: Option<PartialWritePolicy>,
+    #[serde(default, deserialize_with = "optional_scalar")]
+    ordered: Option<bool>,
+    #[serde(default, deserialize_with = "optional_scalar")]
+    coalesce_repeats: Option<u64>,
+    #[serde(default, deserialize_with = "optional_scalar")]
+    sub_ms_seq: Option<bool>,
+    #[serde(default, deserialize_with = "optional_scalar")]
+    workers: Option<usize>,
+    worker_dispatch: Option<WorkerDispatch>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    audit: Option<Duration>,
+    framing: Option<Framing>,
+    batch_format: Option<BatchFormat>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    dns_cache_ttl: Option<Duration>,
+    startup_check: Option<StartupCheck>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    shutdown_timeout: Option<Duration>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    flush_timeout: Option<Duration>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    record_ttl: Option<Duration>,
+    persist_on_shutdown: Option<PathBuf>,
+    default_tags: Option<Vec<String>>,
+    level_tags: Option<HashMap<LogLevel, Vec<String>>>,
+    #[serde(default, deserialize_with = "optional_scalar")]
+    module_short: Option<bool>,
+    #[serde(default, deserialize_with = "optional_scalar")]
+    logger_info: Option<bool>,
+    level_value: Option<LevelScale>,
+    level_names: Option<HashMap<LogLevel, String>>,
+    file_prefix: Option<String>,
+    #[serde(default, deserialize_with = "optional_scalar")]
+    location: Option<bool>,
+    escape_non_ascii: Option<Vec<String>>,
+    encoder: Option<EncoderConfig>,
+    host: Option<HostConfig>,
+}
+
+/// Settings of [`BufferedSender::new`], read from the top level of the `logstash` appender
+/// config or from any other serde source, e.g.
+///
+/// ```yaml
+/// buffer_size: 100
+/// buffer_lifetime: 1s
+/// ignore_buffer_level: error
+/// ```
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct BufferedSenderConfig {
+    /// Values below 2 disable buffering
+    #[serde(default, deserialize_with = "optional_scalar")]
+    pub buffer_size: Option<usize>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    pub buffer_lifetime: Option<Duration>,
+    #[serde(alias = "ignore_buffer")]
+    pub ignore_buffer_level: Option<LogLevel>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    pub error_period: Option<Duration>,
+    #[serde(default, deserialize_with = "optional_scalar")]
+    pub log_queue_len: Option<usize>,
+}
+
+impl BufferedSenderConfig {
+    /// Builder with these settings, the defaults of [`BufferedSender::builder`] for the
+    /// missing ones
+    pub fn builder(&self) -> BufferedSenderBuilder {
+        let mut builder = BufferedSender::builder();
+        if let Some(buffer_size) = self.buffer_size {
+            builder = builder.with_buffer_size(Some(buffer_size).filter(|size| *size >= 2));
+        }
+        if let Some(buffer_lifetime) = self.buffer_lifetime {
+            builder = builder.with_buffer_lifetime(Some(buffer_lifetime));
+        }
+        if let Some(ignore_level) = self.ignore_buffer_level {
+            builder = builder.with_ignore_buffer_level(ignore_level);
+        }
+        if let Some(error_period) = self.error_period {
+            builder = builder.with_error_period(error_period);
+        }
+        if let Some(log_queue_len) = self.log_queue_len {
+            builder = builder.with_log_queue_len(log_queue_len);
+        }
+        builder
+    }
+
+    pub fn build<S: Sender>(&self, sender: S) -> BufferedSender {
+        self.builder().build(sender)
+    }
+
+    fn apply(self, mut builder: AppenderBuilder) -> AppenderBuilder {
+        if let Some(buffer_size) = self.buffer_size {
+            builder = builder.with_buffer_size(buffer_size);
+        }
+        if let Some(buffer_lifetime) = self.buffer_lifetime {
+            builder = builder.with_buffer_lifetime(buffer_lifetime);
+        }
+        if let Some(ignore_level) = self.ignore_buffer_level {
+            builder = builder.with_ignore_buffer_level(ignore_level);
+        }
+        if let Some(error_period) = self.error_period {
+            builder = builder.with_error_period(error_period);
+        }
+        if let Some(log_queue_len) = self.log_queue_len {
+            builder = builder.with_log_queue_len(log_queue_len);
+        }
+        builder
+    }
+}
+
+/// Parses the `key` section on its own so its errors start with the key, e.g.
+/// "timeouts: unknown field `read`"
+fn section<'de, D: serde::Deserializer<'de>, T: DeserializeOwned>(deserializer: D, key: &str) -> Result<Option<T>, D::Error> {
+    Option::<Value>::deserialize(deserializer)?
+        .map(|value| serde_json::from_value(value).map_err(|err| D::Error::custom(format_args!("{}: {}", key, err))))
+        .transpose()
+}
+
+fn tls_section<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<TlsOptions>, D::Error> {
+    let mut tls = Option::<Value>::deserialize(deserializer)?;
+    // The only scalar of the section that is not a string, it may hold a substituted variable
+    if let Some(skip) = tls.as_mut().and_then(|tls| tls.get_mut("insecure_skip_verify")) {
+        if skip.is_string() {
+            *skip = scalar::<_, bool>(skip.take()).map_err(|err| D::Error::custom(format_args!("tls: {}", err)))?.into();
+        }
+    }
+    section(tls.unwrap_or_default(), "tls").map_err(D::Error::custom)
+}
+
+/// Number or boolean written as is or as a string, as the values with a `${VAR}` substituted
+/// into them are
+fn scalar<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: DeserializeOwned + FromStr,
+    T::Err: Display,
+{
+    match Value::deserialize(deserializer)? {
+        Value::String(s) => s.parse().map_err(|err| D::Error::custom(format_args!("invalid value `{}`: {}", s, err))),
+        value => serde_json::from_value(value).map_err(D::Error::custom),
+    }
+}
+
+fn optional_scalar<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: DeserializeOwned + FromStr,
+    T::Err: Display,
+{
+    Option::<Value>::deserialize(deserializer)?.map(|value| scalar(value).map_err(D::Error::custom)).transpose()
+}
+
+fn proxy_section<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<ProxyOptions>, D::Error> {
+    section(deserializer, "proxy")
+}
+
+fn timeouts_section<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<TimeoutsConfig>, D::Error> {
+    section(deserializer, "timeouts")
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TimeoutsConfig {
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    connect: Option<Duration>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    write: Option<Duration>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    flush: Option<Duration>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReconnectConfig {
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    initial_delay: Option<Duration>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    max_delay: Option<Duration>,
+    jitter: Option<Jitter>,
+    #[serde(default, deserialize_with = "optional_scalar")]
+    tolerated_write_errors: Option<u32>,
+}
+
+impl From<ReconnectConfig> for ReconnectPolicy {
+    fn from(config: ReconnectConfig) -> Self {
+        let default = ReconnectPolicy::default();
+        ReconnectPolicy {
+            initial_delay: config.initial_delay.unwrap_or(default.initial_delay),
+            max_delay: config.max_delay.unwrap_or(default.max_delay),
+            jitter: config.jitter.unwrap_or(default.jitter),
+            tolerated_write_errors: config
+                .tolerated_write_errors
+                .unwrap_or(default.tolerated_write_errors),
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BufferPolicyConfig {
+    #[serde(default, deserialize_with = "optional_scalar")]
+    size: Option<usize>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    lifetime: Option<Duration>,
+}
+
+impl From<BufferPolicyConfig> for BufferPolicy {
+    fn from(config: BufferPolicyConfig) -> Self {
+        BufferPolicy {
+            size: config.size,
+            lifetime: config.lifetime,
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AdaptiveBatchingConfig {
+    #[serde(deserialize_with = "scalar")]
+    min_size: usize,
+    #[serde(deserialize_with = "scalar")]
+    max_size: usize,
+    #[serde(with = "humantime_serde")]
+    target_latency: Duration,
+}
+
+impl From<AdaptiveBatchingConfig> for AdaptiveBatching {
+    fn from(config: AdaptiveBatchingConfig) -> Self {
+        AdaptiveBatching {
+            min_size: config.min_size,
+            max_size: config.max_size,
+            target_latency: config.target_latency,
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HostConfig {
+    Static(String),
+    CachedSystem {
+        #[serde(default)]
+        #[serde(with = "humantime_serde")]
+        refresh: Option<Duration>,
+    },
+    Disabled,
+}
+
+impl From<HostConfig> for HostnameProvider {
+    fn from(config: HostConfig) -> Self {
+        match config {
+            HostConfig::Static(hostname) => HostnameProvider::Static(hostname),
+            HostConfig::CachedSystem { refresh } => HostnameProvider::CachedSystem { refresh },
+            HostConfig::Disabled => HostnameProvider::Disabled,
+        }
+    }
 }
 
 impl AppenderDeserializer {
     fn new(extra_fields: Option<HashMap<String, Value>>) -> Self {
         Self {
-            extra_fields
+            extra_fields,
+            senders: Default::default(),
         }
     }
-}
 
-impl Default for AppenderDeserializer {
-    fn default() -> Self {
-        Self {
-            extra_fields: None
+    fn build(&self, builder: AppenderBuilder, fingerprint: u64) -> AnyResult<Box<dyn Append>> {
+        let mut senders = self.senders.lock().unwrap_or_else(|e| e.into_inner());
+        senders.retain(|_, sender| sender.upgrade().is_some());
+        if let Some(sender) = senders.get(&fingerprint).and_then(|s| s.upgrade()) {
+            return Ok(Box::new(builder.build_with_sender(sender)));
         }
+        let appender = builder.build()?;
+        senders.insert(fingerprint, appender.sender().downgrade());
+        Ok(Box::new(appender))
     }
 }
 
 impl Deserialize for AppenderDeserializer {
     type Trait = dyn Append;
-    type Config = AppenderConfig;
+    /// Raw config, environment variables are substituted before parsing it
+    type Config = Value;
 
     fn deserialize(
         &self,
-        config: Self::Config,
-        _deserializers: &Deserializers,
+        mut config: Self::Config,
+        deserializers: &Deserializers,
     ) -> AnyResult<Box<Self::Trait>> {
+        interpolate_env(&mut config)?;
+        let fingerprint = fingerprint(&config);
+        let mut config: AppenderConfig = serde_json::from_value(config)?;
+        let mut extra_fields = self.extra_fields.clone().unwrap_or_default();
+        extra_fields.extend(config.extra_fields.take().unwrap_or_default());
+        let builder = config.into_builder(deserializers)?.with_extra_fields(extra_fields);
+        self.build(builder, fingerprint)
+    }
+}
+
+impl AppenderConfig {
+    /// Appender builder with these settings, the encoder is looked up in `deserializers`
+    pub fn into_builder(self, deserializers: &Deserializers) -> AnyResult<AppenderBuilder> {
         let mut builder = AppenderBuilder::default();
         builder = builder
-            .with_hostname(&config.hostname)
-            .with_port(config.port)
-            .with_use_tls(config.use_tls.unwrap_or(false));
-        if let Some(buffer_size) = config.buffer_size {
-            builder = builder.with_buffer_size(buffer_size);
+            .with_hostname(&self.hostname)
+            .with_port(self.port)
+            .with_use_tls(self.use_tls.unwrap_or(self.tls.is_some()));
+        if let Some(tls) = self.tls {
+            builder = builder.with_tls_options(tls);
         }
-        if let Some(buffer_lifetime) = config.buffer_lifetime {
-            builder = builder.with_buffer_lifetime(buffer_lifetime);
+        if let Some(proxy) = self.proxy {
+            builder = builder.with_proxy(proxy);
+        }
+        if let Some(timeouts) = self.timeouts {
+            if let Some(connect) = timeouts.connect {
+                builder = builder.with_connection_timeout(connect);
+            }
+            if let Some(write) = timeouts.write {
+                builder = builder.with_write_timeout(write);
+            }
+            if let Some(flush) = timeouts.flush {
+                builder = builder.with_flush_timeout(Some(flush));
+            }
+        }
+        if let Some(reconnect) = self.reconnect {
+            builder = builder.with_reconnect_policy(reconnect.into());
         }
-        if let Some(connection_timeout) = config.connection_timeout {
+        builder = self.buffered.apply(builder);
+        for (level, policy) in self.level_buffers.unwrap_or_default() {
+            builder = builder.with_level_buffer_policy(level, policy.into());
+        }
+        if let Some(adaptive_batching) = self.adaptive_batching {
+            builder = builder.with_adaptive_batching(adaptive_batching.into());
+        }
+        if let Some(connection_timeout) = self.connection_timeout {
             builder = builder.with_connection_timeout(connection_timeout);
         }
-        if let Some(ignore_level) = config.ignore_buffer_level {
-            builder = builder.with_ignore_buffer_level(ignore_level);
+        if let Some(threshold) = self.threshold {
+            builder = builder.with_threshold(threshold);
         }
-        if let Some(error_period) = config.error_period {
-            builder = builder.with_error_period(error_period);
+        for (target_prefix, level) in self.target_overrides.unwrap_or_default() {
+            builder = builder.with_target_override(target_prefix, level);
         }
-        if let Some(log_queue_len) = config.log_queue_len {
-            builder = builder.with_log_queue_len(log_queue_len);
+        if let Some(pre_connect) = self.pre_connect {
+            builder = builder.with_pre_connect(pre_connect);
         }
-
-        let mut extra_fields = self.extra_fields.clone().unwrap_or_default();
-        if let Some(config_extra_fields) = config.extra_fields {
-            extra_fields.extend(config_extra_fields);   
+        if let Some(ping_interval) = self.ping_interval {
+            builder = builder.with_ping_interval(ping_interval);
         }
-
-        builder = builder.with_extra_fields(extra_fields);
-
-        let appender = builder.build()?;
-
-        Ok(Box::new(appender))
+        if let Some(heartbeat_interval) = self.heartbeat_interval {
+            builder = builder.with_heartbeat_interval(heartbeat_interval);
+        }
+        if let Some(self_metrics_interval) = self.self_metrics_interval {
+            builder = builder.with_self_metrics_interval(self_metrics_interval);
+        }
+        if let Some(diagnostics) = self.diagnostics {
+            builder = builder.with_diagnostics(diagnostics);
+        }
+        if let Some(threshold) = self.slow_send_threshold {
+            builder = builder.with_slow_send_threshold(threshold);
+        }
+        if let Some(max_buffer_bytes) = self.max_buffer_bytes {
+            builder = builder.with_max_buffer_bytes(max_buffer_bytes);
+        }
+        if let Some(max_event_bytes) = self.max_event_bytes {
+            builder = builder.with_max_event_bytes(max_event_bytes);
+        }
+        if let Some(oversized_policy) = self.oversized_policy {
+            builder = builder.with_oversized_policy(oversized_policy);
+        }
+        if let Some(oversized_diagnostics) = self.oversized_diagnostics {
+            builder = builder.with_oversized_diagnostics(oversized_diagnostics);
+        }
+        if let Some(flush_bytes) = self.flush_bytes {
+            builder = builder.with_flush_bytes(flush_bytes);
+        }
+        if let Some(flush_on_level) = self.flush_on_level {
+            builder = builder.with_flush_on_level(flush_on_level);
+        }
+        if let Some(max_in_flight) = self.max_in_flight {
+            builder = builder.with_max_in_flight(max_in_flight);
+        }
+        #[cfg(feature = "pool")]
+        if let Some(record_pool) = self.record_pool {
+            builder = builder.with_record_pool(record_pool);
+        }
+        #[cfg(feature = "metrics")]
+        if let Some(metrics_prefix) = self.metrics_prefix {
+            builder = builder.with_metrics_prefix(metrics_prefix);
+        }
+        if let Some(overflow_policy) = self.overflow_policy {
+            builder = builder.with_overflow_policy(overflow_policy);
+        }
+        if let Some(partial_write_policy) = self.partial_write_policy {
+            builder = builder.with_partial_write_policy(partial_write_policy);
+        }
+        if let Some(ordered) = self.ordered {
+            builder = builder.with_ordered(ordered);
+        }
+        if let Some(coalesce_repeats) = self.coalesce_repeats {
+            builder = builder.with_coalesce_repeats(coalesce_repeats);
+        }
+        if let Some(sub_ms_seq) = self.sub_ms_seq {
+            builder = builder.with_sub_ms_seq(sub_ms_seq);
+        }
+        if let Some(workers) = self.workers {
+            builder = builder.with_workers(workers);
+        }
+        if let Some(worker_dispatch) = self.worker_dispatch {
+            builder = builder.with_worker_dispatch(worker_dispatch);
+        }
+        if let Some(audit) = self.audit {
+            builder = builder.with_audit(audit);
+        }
+        if let Some(framing) = self.framing {
+            builder = builder.with_framing(framing);
+        }
+        if let Some(batch_format) = self.batch_format {
+            builder = builder.with_batch_format(batch_format);
+        }
+        if let Some(dns_cache_ttl) = self.dns_cache_ttl {
+            builder = builder.with_dns_cache_ttl(dns_cache_ttl);
+        }
+        if let Some(startup_check) = self.startup_check {
+            builder = builder.with_startup_check(startup_check);
+        }
+        if let Some(shutdown_timeout) = self.shutdown_timeout {
+            builder = builder.with_shutdown_timeout(shutdown_timeout);
+        }
+        if let Some(flush_timeout) = self.flush_timeout {
+            builder = builder.with_flush_timeout(Some(flush_timeout));
+        }
+        if let Some(record_ttl) = self.record_ttl {
+            builder = builder.with_record_ttl(record_ttl);
+        }
+        if let Some(path) = self.persist_on_shutdown {
+            builder = builder.with_persist_on_shutdown(path);
+        }
+        if let Some(default_tags) = self.default_tags {
+            builder = builder.with_default_tags(default_tags);
+        }
+        for (level, tags) in self.level_tags.unwrap_or_default() {
+            builder = builder.with_level_tags(level, tags);
+        }
+        if let Some(module_short) = self.module_short {
+            builder = builder.with_module_short(module_short);
+        }
+        if let Some(logger_info) = self.logger_info {
+            builder = builder.with_logger_info(logger_info);
+        }
+        for (level, name) in self.level_names.unwrap_or_default() {
+            builder = builder.with_level_name(level, name);
+        }
+        if let Some(level_value) = self.level_value {
+            builder = builder.with_level_value(level_value);
+        }
+        if let Some(file_prefix) = self.file_prefix {
+            builder = builder.with_file_prefix(file_prefix);
+        }
+        if let Some(location) = self.location {
+            builder = builder.with_location(location);
+        }
+        if let Some(fields) = self.escape_non_ascii {
+            builder = builder.with_escape_non_ascii(fields);
+        }
+        if let Some(encoder) = self.encoder {
+            builder = builder.with_encoder(deserializers.deserialize(&encoder.kind, encoder.config)?);
+        }
+        if let Some(host) = self.host {
+            builder = builder.with_hostname_provider(host.into());
+        }
+        if let Some(extra_fields) = self.extra_fields {
+            builder = builder.with_extra_fields(extra_fields);
+        }
+        Ok(builder)
     }
 }
 
+fn fingerprint(config: &Value) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    config.to_string().hash(&mut hasher);
+    hasher.finish()
+}
+
 /// Returns default Deserializers extended with logstash appender
 pub fn deserializers() -> Deserializers {
     let mut d = Deserializers::default();
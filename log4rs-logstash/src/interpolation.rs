@@ -0,0 +1,75 @@
+//! `${VAR}` and `${VAR:-default}` substitution of environment variables in config values.
+//! `$$` stands for a literal `$`.
+
+use anyhow::{anyhow, Result as AnyResult};
+use serde_json::Value;
+
+/// Substitutes environment variables in every string of `value`. The strings stay strings,
+/// the numbers and booleans of the config accept them, so `port: ${LOGSTASH_PORT}` can still
+/// be parsed as a number.
+pub(crate) fn interpolate_env(value: &mut Value) -> AnyResult<()> {
+    interpolate_value(value, "", &|name| std::env::var(name).ok())
+}
+
+fn interpolate_value(
+    value: &mut Value,
+    path: &str,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> AnyResult<()> {
+    match value {
+        Value::String(s) if s.contains('$') => {
+            *s = interpolate_str(s, lookup).map_err(|err| anyhow!("{} (at `{}`)", err, path))?;
+        }
+        Value::Array(values) => {
+            for (i, value) in values.iter_mut().enumerate() {
+                interpolate_value(value, &format!("{}[{}]", path, i), lookup)?;
+            }
+        }
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                interpolate_value(value, &path, lookup)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn interpolate_str(s: &str, lookup: &dyn Fn(&str) -> Option<String>) -> Result<String, String> {
+    let mut result = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(pos) = rest.find('$') {
+        result.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        if let Some(stripped) = rest.strip_prefix("$$") {
+            result.push('$');
+            rest = stripped;
+        } else if let Some(stripped) = rest.strip_prefix("${") {
+            let end = stripped
+                .find('}')
+                .ok_or_else(|| format!("unterminated variable reference in `{}`", s))?;
+            let (name, default) = match stripped[..end].split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (&stripped[..end], None),
+            };
+            match (lookup(name), default) {
+                (Some(value), _) => result.push_str(&value),
+                (None, Some(default)) => result.push_str(default),
+                (None, None) => {
+                    return Err(format!("environment variable `{}` is not set", name))
+                }
+            }
+            rest = &stripped[end + 1..];
+        } else {
+            result.push('$');
+            rest = &rest[1..];
+        }
+    }
+    result.push_str(rest);
+    Ok(result)
+}
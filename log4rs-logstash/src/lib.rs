@@ -1,2 +1,3 @@
 pub mod appender;
 pub mod config;
+mod interpolation;
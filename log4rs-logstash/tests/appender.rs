@@ -0,0 +1,232 @@
+//! Fields added by the appender to the records it passes to its sender.
+
+use log::{Level, Record};
+use log4rs::append::Append;
+use log4rs::encode::pattern::PatternEncoder;
+use qoollo_log4rs_logstash::appender::{Appender, AppenderBuilder};
+use qoollo_log4rs_logstash::config::AppenderConfig;
+use qoollo_logstash_rs::testing::CapturingSender;
+use qoollo_logstash_rs::{HostnameCache, HostnameProvider, LevelScale, LogStashRecord, LOGGER_NAME};
+use serde_json::Value;
+use std::collections::HashMap;
+
+fn capturing(builder: AppenderBuilder) -> (Appender<CapturingSender>, CapturingSender) {
+    let captured = CapturingSender::new();
+    (builder.build_with_sender(captured.clone()), captured)
+}
+
+/// Appends an `Info` record with `module` and returns the record passed to the sender
+fn append_from(builder: AppenderBuilder, module: Option<&'static str>) -> LogStashRecord {
+    let (appender, captured) = capturing(builder);
+    appender
+        .append(
+            &Record::builder()
+                .args(format_args!("hello"))
+                .level(Level::Info)
+                .target("my_crate")
+                .module_path_static(module)
+                .build(),
+        )
+        .unwrap();
+    captured.take().pop().expect("record should be sent")
+}
+
+#[test]
+fn module_short_is_the_last_module_segment() {
+    let builder = AppenderBuilder::default().with_module_short(true);
+    let record = append_from(builder, Some("my_crate::net::tcp"));
+    let json = serde_json::to_value(&record).unwrap();
+    assert_eq!(json["target"], "my_crate");
+    assert_eq!(json["module"], "my_crate::net::tcp");
+    assert_eq!(json["module_short"], "tcp");
+}
+
+#[test]
+fn module_short_of_a_crate_root_is_the_crate() {
+    let builder = AppenderBuilder::default().with_module_short(true);
+    let record = append_from(builder, Some("my_crate"));
+    assert_eq!(record.fields["module_short"], "my_crate");
+}
+
+#[test]
+fn module_short_is_left_out_without_module_or_option() {
+    let builder = AppenderBuilder::default().with_module_short(true);
+    assert!(!append_from(builder, None).fields.contains_key("module_short"));
+
+    let record = append_from(AppenderBuilder::default(), Some("my_crate::net"));
+    assert!(!record.fields.contains_key("module_short"));
+}
+
+#[test]
+fn level_value_is_added_next_to_the_level() {
+    let record = append_from(AppenderBuilder::default().with_level_value(LevelScale::Inverted), None);
+    let json = serde_json::to_value(&record).unwrap();
+    assert_eq!(json["level"], "INFO");
+    assert_eq!(json["level_value"], 3);
+
+    let (appender, captured) = capturing(AppenderBuilder::default().with_level_value(LevelScale::Log));
+    appender.append(&Record::builder().args(format_args!("hello")).level(Level::Warn).build()).unwrap();
+    let json = serde_json::to_value(captured.take().pop().unwrap()).unwrap();
+    assert_eq!(json["level"], "WARN");
+    assert_eq!(json["level_value"], 2);
+
+    assert!(!append_from(AppenderBuilder::default(), None).fields.contains_key("level_value"));
+}
+
+/// `host` field of an `Info` record appended through `appender`
+fn host_of(appender: &Appender<CapturingSender>, captured: &CapturingSender) -> Option<String> {
+    appender
+        .append(&Record::builder().args(format_args!("hello")).level(Level::Info).build())
+        .unwrap();
+    let record = captured.take().pop().expect("record should be sent");
+    record.fields.get("host").map(|host| host.as_str().unwrap().to_string())
+}
+
+#[test]
+fn system_hostname_is_the_default_host() {
+    let (appender, captured) = capturing(AppenderBuilder::default());
+    let system = HostnameCache::new(HostnameProvider::CachedSystem { refresh: None }).get();
+    assert!(system.is_some());
+    assert_eq!(host_of(&appender, &captured), system);
+}
+
+#[test]
+fn static_and_disabled_providers_override_the_system_hostname() {
+    let builder = AppenderBuilder::default()
+        .with_hostname_provider(HostnameProvider::Static("pod-7".into()));
+    let (appender, captured) = capturing(builder);
+    assert_eq!(host_of(&appender, &captured).as_deref(), Some("pod-7"));
+
+    let builder = AppenderBuilder::default().with_hostname_provider(HostnameProvider::Disabled);
+    let (appender, captured) = capturing(builder);
+    assert_eq!(host_of(&appender, &captured), None);
+}
+
+#[test]
+fn host_provider_takes_precedence_over_extra_fields() {
+    let mut extra_fields = HashMap::new();
+    extra_fields.insert("host".to_string(), Value::from("from-extra-fields"));
+    extra_fields.insert("service".to_string(), Value::from("billing"));
+    let builder = AppenderBuilder::default()
+        .with_extra_fields(extra_fields.clone())
+        .with_hostname_provider(HostnameProvider::Static("pod-7".into()));
+    let (appender, captured) = capturing(builder);
+    assert_eq!(host_of(&appender, &captured).as_deref(), Some("pod-7"));
+
+    // Without a host the extra field is kept
+    let builder = AppenderBuilder::default()
+        .with_extra_fields(extra_fields)
+        .with_hostname_provider(HostnameProvider::Disabled);
+    let (appender, captured) = capturing(builder);
+    assert_eq!(host_of(&appender, &captured).as_deref(), Some("from-extra-fields"));
+}
+
+#[test]
+fn swapping_the_provider_changes_the_emitted_host() {
+    let host = HostnameCache::new(HostnameProvider::Static("pod-a".into()));
+    let (appender, captured) = capturing(AppenderBuilder::default().with_hostname_cache(host.clone()));
+    assert_eq!(host_of(&appender, &captured).as_deref(), Some("pod-a"));
+
+    host.set_provider(HostnameProvider::Static("pod-b".into()));
+    assert_eq!(host_of(&appender, &captured).as_deref(), Some("pod-b"));
+
+    host.set_provider(HostnameProvider::Disabled);
+    assert_eq!(host_of(&appender, &captured), None);
+}
+
+/// Appends an `Info` record logged from line 7 of `file` and returns the record passed to the sender
+fn append_at(builder: AppenderBuilder, file: &'static str) -> LogStashRecord {
+    let (appender, captured) = capturing(builder);
+    appender
+        .append(&Record::builder().args(format_args!("hello")).level(Level::Info).file_static(Some(file)).line(Some(7)).build())
+        .unwrap();
+    captured.take().pop().expect("record should be sent")
+}
+
+#[test]
+fn configured_file_prefix_is_stripped() {
+    let prefixed = || AppenderBuilder::default().with_file_prefix("/home/builder/work/my_crate");
+    let file = |builder, file| append_at(builder, file).file.map(|file| file.into_owned());
+    assert_eq!(file(prefixed(), "/home/builder/work/my_crate/src/net/tcp.rs").as_deref(), Some("src/net/tcp.rs"));
+
+    // Paths outside of the prefix and appenders without one keep the full path
+    assert_eq!(file(prefixed(), "/rustc/library/std/src/rt.rs").as_deref(), Some("/rustc/library/std/src/rt.rs"));
+    let unprefixed = AppenderBuilder::default();
+    assert_eq!(file(unprefixed, "/home/builder/work/my_crate/src/lib.rs").as_deref(), Some("/home/builder/work/my_crate/src/lib.rs"));
+}
+
+#[test]
+fn manifest_dir_prefix_keeps_paths_relative_to_the_crate() {
+    let builder = AppenderBuilder::default().with_file_prefix(env!("CARGO_MANIFEST_DIR")).with_location(true);
+    let record = append_at(builder, concat!(env!("CARGO_MANIFEST_DIR"), "/tests/appender.rs"));
+
+    let json = serde_json::to_value(&record).unwrap();
+    assert_eq!(json["file"], "tests/appender.rs");
+    assert_eq!(json["location"], "tests/appender.rs:7");
+}
+
+#[test]
+fn encoder_renders_the_message() {
+    let builder = AppenderBuilder::default().with_encoder(Box::new(PatternEncoder::new("{h({l})} {m}")));
+    let record = append_from(builder, Some("my_crate::net"));
+
+    let json = serde_json::to_value(&record).unwrap();
+    assert_eq!(json["message"], "INFO hello");
+    assert_eq!(json["level"], "INFO");
+    assert_eq!(json["target"], "my_crate");
+    assert_eq!(json["module"], "my_crate::net");
+}
+
+#[test]
+fn encoder_output_is_trimmed_of_trailing_newlines() {
+    let builder = AppenderBuilder::default().with_encoder(Box::new(PatternEncoder::new("[{t}] {m}{n}{n}")));
+    assert_eq!(append_from(builder, None).fields["message"], "[my_crate] hello");
+}
+
+#[test]
+fn encoder_is_read_from_the_appender_config() {
+    let config: AppenderConfig =
+        serde_yaml::from_str("hostname: logstash\nport: 5044\nencoder:\n  kind: pattern\n  pattern: \"{h({l})} {M}: {m}\"\n").unwrap();
+    let builder = config.into_builder(&log4rs::config::Deserializers::default()).unwrap();
+    let record = append_from(builder, Some("my_crate::net"));
+    assert_eq!(record.fields["message"], "INFO my_crate::net: hello");
+}
+
+#[test]
+fn logger_info_names_the_crate_version_and_config() {
+    let builder = AppenderBuilder::default().with_port(5044).with_logger_info(true);
+    let config_hash = builder.config_hash();
+    let record = append_from(builder, None);
+    let json = serde_json::to_value(&record).unwrap();
+    // Both crates are released with the same version
+    let expected = serde_json::json!({ "name": LOGGER_NAME, "version": env!("CARGO_PKG_VERSION"), "config_hash": config_hash });
+    assert_eq!(json["logger"], expected);
+    assert_eq!(config_hash.len(), 8);
+
+    assert!(!append_from(AppenderBuilder::default(), None).fields.contains_key("logger"));
+}
+
+#[test]
+fn config_hash_is_stable_for_identical_configs_only() {
+    let builder = || AppenderBuilder::default().with_hostname("logstash").with_port(5044).with_logger_info(true);
+    let first = append_from(builder(), None);
+    let second = append_from(builder(), None);
+    assert_eq!(first.fields["logger"]["config_hash"], second.fields["logger"]["config_hash"]);
+    assert_eq!(first.fields["logger"]["config_hash"], builder().config_hash());
+
+    let changed = [builder().with_port(5045), builder().with_hostname("logstash-2"), builder().with_level_value(LevelScale::Log), builder().with_logger_info(false)];
+    for other in changed.iter() {
+        assert_ne!(other.config_hash(), builder().config_hash());
+    }
+    let other = append_from(builder().with_module_short(true), None);
+    assert_ne!(other.fields["logger"]["config_hash"], first.fields["logger"]["config_hash"]);
+}
+
+#[test]
+fn location_is_the_file_and_line_of_the_call_when_enabled() {
+    let record = append_at(AppenderBuilder::default().with_location(true), "src/net/tcp.rs");
+    assert_eq!(record.fields["location"], "src/net/tcp.rs:7");
+    assert_eq!(serde_json::to_value(&record).unwrap()["line"], 7);
+
+    assert!(!append_at(AppenderBuilder::default(), "src/net/tcp.rs").fields.contains_key("location"));
+}
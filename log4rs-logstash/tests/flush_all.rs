@@ -0,0 +1,66 @@
+//! `flush_all` delivering the records buffered by every appender before returning.
+
+use log::{Level, Record};
+use log4rs::append::Append;
+use qoollo_log4rs_logstash::appender::{Appender, AppenderBuilder};
+use qoollo_logstash_rs::testing::MockLogstash;
+use qoollo_logstash_rs::BufferedSender;
+use std::time::Duration;
+
+const RECORDS: usize = 20;
+
+fn appender(server: &MockLogstash) -> Appender<BufferedSender> {
+    AppenderBuilder::default()
+        .with_hostname("127.0.0.1")
+        .with_port(server.port())
+        .with_buffer_size(1000)
+        .with_buffer_lifetime(Duration::from_secs(600))
+        .with_ignore_buffer_level(Level::Trace)
+        .build()
+        .unwrap()
+}
+
+fn log(appender: &Appender<BufferedSender>, name: &str) {
+    for i in 0..RECORDS {
+        appender
+            .append(
+                &Record::builder()
+                    .args(format_args!("{} {}", name, i))
+                    .level(Level::Info)
+                    .target("flush_all")
+                    .build(),
+            )
+            .unwrap();
+    }
+}
+
+fn delivered(server: &MockLogstash) -> usize {
+    server
+        .events()
+        .iter()
+        .filter(|event| event["target"] == "flush_all")
+        .count()
+}
+
+#[test]
+fn flush_all_delivers_records_of_every_appender() {
+    let first_server = MockLogstash::start().unwrap();
+    let second_server = MockLogstash::start().unwrap();
+    let first = appender(&first_server);
+    let second = appender(&second_server);
+    log(&first, "first");
+    log(&second, "second");
+    std::thread::sleep(Duration::from_millis(200));
+    assert_eq!(delivered(&first_server), 0);
+    assert_eq!(delivered(&second_server), 0);
+
+    qoollo_logstash_rs::flush_all(Duration::from_secs(5)).unwrap();
+
+    // The records were written to the sockets, give the servers a moment to read them
+    first_server.wait_for_events(RECORDS, Duration::from_secs(1));
+    second_server.wait_for_events(RECORDS, Duration::from_secs(1));
+    assert_eq!(delivered(&first_server), RECORDS);
+    assert_eq!(delivered(&second_server), RECORDS);
+    first_server.assert_json_field(0, "message", "first 0");
+    second_server.assert_json_field(0, "message", "second 0");
+}
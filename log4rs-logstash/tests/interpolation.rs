@@ -0,0 +1,86 @@
+//! `${VAR}` substitution in the appender config deserialized by log4rs.
+
+use log::{Level, Record};
+use log4rs::append::Append;
+use qoollo_log4rs_logstash::config::deserializers;
+use qoollo_logstash_rs::testing::MockLogstash;
+use std::time::Duration;
+
+fn deserialize(yaml: &str) -> anyhow::Result<Box<dyn Append>> {
+    let config: serde_value::Value = serde_yaml::from_str(yaml)?;
+    deserializers().deserialize("logstash", config)
+}
+
+#[test]
+fn variables_and_defaults_are_substituted() {
+    let server = MockLogstash::start().unwrap();
+    std::env::set_var("INTERPOLATION_TEST_PORT", server.port().to_string());
+    std::env::set_var("INTERPOLATION_TEST_TOKEN", "s3cr3t");
+    let appender = deserialize(
+        r#"
+hostname: "${INTERPOLATION_TEST_UNSET_HOST:-127.0.0.1}"
+port: "${INTERPOLATION_TEST_PORT}"
+extra_fields:
+  token: "${INTERPOLATION_TEST_TOKEN}"
+  region: "${INTERPOLATION_TEST_UNSET_REGION:-eu-west-1}"
+  workers: "${INTERPOLATION_TEST_UNSET_WORKERS:-4}"
+  build: "${INTERPOLATION_TEST_UNSET_BUILD:-0012}"
+  price: "$$5 per ${INTERPOLATION_TEST_UNSET_UNIT:-event}"
+  literal: "costs $5"
+"#,
+    )
+    .unwrap();
+
+    appender
+        .append(&Record::builder().args(format_args!("hello")).level(Level::Error).target("interpolation").build())
+        .unwrap();
+    appender.flush();
+    let events = server.wait_for_events(1, Duration::from_secs(5));
+    let event = events[0].json().unwrap();
+    assert_eq!(event["token"], "s3cr3t");
+    assert_eq!(event["region"], "eu-west-1");
+    // Only the numbers of the config are parsed, as the port is
+    assert_eq!(event["workers"], "4");
+    assert_eq!(event["build"], "0012");
+    assert_eq!(event["price"], "$5 per event");
+    assert_eq!(event["literal"], "costs $5");
+}
+
+#[test]
+fn unset_variable_without_default_fails_naming_it() {
+    let err = deserialize("hostname: 127.0.0.1\nport: \"${INTERPOLATION_TEST_MISSING_PORT}\"\n").unwrap_err().to_string();
+    assert_eq!(err, "environment variable `INTERPOLATION_TEST_MISSING_PORT` is not set (at `port`)");
+
+    let err = deserialize("hostname: 127.0.0.1\nport: 5044\nextra_fields:\n  token: \"Bearer ${INTERPOLATION_TEST_MISSING_TOKEN}\"\n")
+        .unwrap_err()
+        .to_string();
+    assert_eq!(err, "environment variable `INTERPOLATION_TEST_MISSING_TOKEN` is not set (at `extra_fields.token`)");
+}
+
+#[test]
+fn unterminated_reference_fails() {
+    let err = deserialize("hostname: \"${INTERPOLATION_TEST_HOST\"\nport: 5044\n").unwrap_err().to_string();
+    assert!(err.starts_with("unterminated variable reference"), "{}", err);
+    assert!(err.ends_with("(at `hostname`)"), "{}", err);
+}
+
+#[test]
+fn numbers_and_booleans_of_the_config_accept_substituted_strings() {
+    std::env::set_var("INTERPOLATION_TEST_QUEUE_LEN", "5");
+    let yaml = r#"
+hostname: 127.0.0.1
+port: "${INTERPOLATION_TEST_UNSET_PORT:-5044}"
+log_queue_len: "${INTERPOLATION_TEST_QUEUE_LEN}"
+pre_connect: "${INTERPOLATION_TEST_UNSET_PRE_CONNECT:-false}"
+tls: { insecure_skip_verify: "${INTERPOLATION_TEST_UNSET_INSECURE:-true}" }
+reconnect: { tolerated_write_errors: "${INTERPOLATION_TEST_UNSET_TOLERATED:-3}" }
+"#;
+    deserialize(yaml).unwrap();
+
+    let err = deserialize("hostname: 127.0.0.1\nport: \"${INTERPOLATION_TEST_UNSET_PORT:-logstash}\"\n").unwrap_err().to_string();
+    assert!(err.contains("invalid value `logstash`"), "{}", err);
+    let err = deserialize("hostname: 127.0.0.1\nport: 5044\ntls: { insecure_skip_verify: \"${INTERPOLATION_TEST_UNSET_INSECURE:-yes}\" }\n")
+        .unwrap_err()
+        .to_string();
+    assert!(err.starts_with("tls: invalid value `yes`"), "{}", err);
+}
@@ -0,0 +1,88 @@
+//! Appenders rebuilt by config reloads: dropped appenders release their worker thread and
+//! connection, an unchanged config keeps the sender. Threads and sockets are counted for the
+//! whole process, so this file holds a single test.
+#![cfg(target_os = "linux")]
+
+use log::{Level, Record};
+use log4rs::append::Append;
+use log4rs::config::Deserializers;
+use qoollo_log4rs_logstash::config::deserializers;
+use qoollo_logstash_rs::testing::MockLogstash;
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+const RELOADS: usize = 20;
+
+fn threads() -> usize {
+    let status = std::fs::read_to_string("/proc/self/status").unwrap();
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("Threads:"))
+        .and_then(|count| count.trim().parse().ok())
+        .unwrap()
+}
+
+fn sockets() -> usize {
+    std::fs::read_dir("/proc/self/fd")
+        .unwrap()
+        .filter_map(|entry| std::fs::read_link(entry.ok()?.path()).ok())
+        .filter(|target| target.to_string_lossy().starts_with("socket:"))
+        .count()
+}
+
+/// Waits until the threads and sockets are back to `baseline`
+fn wait_for_baseline(baseline: (usize, usize)) {
+    let started = Instant::now();
+    while (threads(), sockets()) != baseline {
+        assert!(
+            started.elapsed() < TIMEOUT,
+            "{} threads and {} sockets, {:?} before",
+            threads(),
+            sockets(),
+            baseline
+        );
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn appender(deserializers: &Deserializers, port: u16, generation: usize) -> Box<dyn Append> {
+    let yaml = format!("hostname: 127.0.0.1\nport: {}\nextra_fields:\n  generation: {}\n", port, generation);
+    let config: serde_value::Value = serde_yaml::from_str(&yaml).unwrap();
+    deserializers.deserialize("logstash", config).unwrap()
+}
+
+fn log(appender: &dyn Append) {
+    appender
+        .append(&Record::builder().args(format_args!("reloaded")).level(Level::Error).target("reload").build())
+        .unwrap();
+}
+
+#[test]
+fn reloads_release_threads_and_sockets_and_keep_unchanged_senders() {
+    let server = MockLogstash::start().unwrap();
+    let deserializers = deserializers();
+    let baseline = (threads(), sockets());
+
+    // Every generation changes the config, so every reload builds a new sender
+    for generation in 0..RELOADS {
+        let appender = appender(&deserializers, server.port(), generation);
+        log(&*appender);
+        appender.flush();
+        drop(appender);
+    }
+    server.wait_for_events(RELOADS, TIMEOUT);
+    assert_eq!(server.connections(), RELOADS);
+    wait_for_baseline(baseline);
+
+    // A reload with an unchanged config reuses the live sender and its connection
+    let first = appender(&deserializers, server.port(), RELOADS);
+    log(&*first);
+    let second = appender(&deserializers, server.port(), RELOADS);
+    log(&*second);
+    drop(first);
+    second.flush();
+    server.wait_for_events(RELOADS + 2, TIMEOUT);
+    assert_eq!(server.connections(), RELOADS + 1);
+    drop(second);
+    wait_for_baseline(baseline);
+}
@@ -0,0 +1,58 @@
+//! Startup connection check of appenders: a wrong address fails the build or is reported, a
+//! checked connection is kept for the first records.
+
+use log::{Level, Record};
+use log4rs::append::Append;
+use qoollo_log4rs_logstash::appender::AppenderBuilder;
+use qoollo_logstash_rs::testing::MockLogstash;
+use qoollo_logstash_rs::StartupCheck;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Port nothing listens on
+fn closed_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+fn builder(port: u16, check: StartupCheck) -> AppenderBuilder {
+    AppenderBuilder::default()
+        .with_hostname("127.0.0.1")
+        .with_port(port)
+        .with_connection_timeout(Duration::from_secs(1))
+        .with_startup_check(check)
+}
+
+#[test]
+fn failing_check_rejects_a_bad_port() {
+    let port = closed_port();
+    let error = builder(port, StartupCheck::Fail).build().err().unwrap().to_string();
+    assert!(error.contains(&format!("cannot connect to 127.0.0.1:{}", port)), "{}", error);
+}
+
+#[test]
+fn warning_check_and_no_check_build_with_a_bad_port() {
+    assert!(builder(closed_port(), StartupCheck::Warn).build().is_ok());
+    assert!(builder(closed_port(), StartupCheck::Off).build().is_ok());
+}
+
+#[test]
+fn checked_connection_is_reused_for_the_first_send() {
+    let server = MockLogstash::start().unwrap();
+    let appender = builder(server.port(), StartupCheck::Fail).build().unwrap();
+
+    let record = Record::builder().args(format_args!("after the check")).level(Level::Error).target("startup").build();
+    appender.append(&record).unwrap();
+    appender.flush();
+    let lines = server.wait_for_events(1, TIMEOUT);
+    assert_eq!(lines[0].connection, 0);
+    assert_eq!(server.connections(), 1);
+}
+
+#[test]
+fn unchecked_appender_connects_on_the_first_send() {
+    let server = MockLogstash::start().unwrap();
+    let _appender = builder(server.port(), StartupCheck::Off).build().unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(server.connections(), 0);
+}
@@ -0,0 +1,203 @@
+//! Combinations of the appender `threshold` dropping records and the buffered sender
+//! `ignore_buffer` level sending records right away, and records dropped by the filters before
+//! they are converted.
+
+use log::{Level, LevelFilter, Log, Record};
+use log4rs::append::Append;
+use log4rs::config::{Appender as AppenderConfig, Config, Root};
+use log4rs::filter::threshold::ThresholdFilter;
+use qoollo_log4rs_logstash::appender::{Appender, AppenderBuilder};
+use qoollo_log4rs_logstash::config::AppenderConfig as LogstashConfig;
+use qoollo_logstash_rs::testing::CapturingSender;
+use qoollo_logstash_rs::BufferedSender;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+const LEVELS: [Level; 5] = [Level::Error, Level::Warn, Level::Info, Level::Debug, Level::Trace];
+
+/// Appender with `threshold` on top of a buffered sender sending records at least as verbose
+/// as `ignore_buffer` right away and holding the others until flushed
+fn appender(threshold: LevelFilter, ignore_buffer: Level) -> (Appender<BufferedSender>, CapturingSender) {
+    let captured = CapturingSender::new();
+    let sender = BufferedSender::builder()
+        .with_buffer_size(Some(100))
+        .with_buffer_lifetime(Some(Duration::from_secs(60)))
+        .with_ignore_buffer_level(ignore_buffer)
+        .with_diagnostics(false)
+        .build(captured.clone());
+    let builder = AppenderBuilder::default().with_threshold(threshold).with_ignore_buffer_level(ignore_buffer);
+    (builder.build_with_sender(sender), captured)
+}
+
+fn log(appender: &dyn Append, level: Level) {
+    appender.append(&Record::builder().args(format_args!("{}", level)).level(level).target("threshold").build()).unwrap();
+}
+
+/// Levels of the records received so far, waiting until there are at least `expected` of them
+fn wait_for_levels(captured: &CapturingSender, expected: usize) -> Vec<Level> {
+    let started = Instant::now();
+    while captured.records().len() < expected && started.elapsed() < TIMEOUT {
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    captured.records().iter().map(|record| record.level).collect()
+}
+
+/// Logs a record of every level and returns the levels sent right away and after a flush
+fn sent_levels(threshold: LevelFilter, ignore_buffer: Level, immediate: usize) -> (Vec<Level>, Vec<Level>) {
+    let (appender, captured) = appender(threshold, ignore_buffer);
+    for &level in LEVELS.iter() {
+        log(&appender, level);
+    }
+    let sent_right_away = wait_for_levels(&captured, immediate);
+    appender.sender().flush_and_wait(TIMEOUT).unwrap();
+    (sent_right_away, wait_for_levels(&captured, 0))
+}
+
+#[test]
+fn default_ignore_buffer_level_sends_every_record_right_away() {
+    let (immediate, all) = sent_levels(LevelFilter::Trace, Level::Error, LEVELS.len());
+    assert_eq!(immediate, LEVELS);
+    assert_eq!(all, LEVELS);
+}
+
+#[test]
+fn threshold_drops_records_that_would_bypass_the_buffer() {
+    // Warn and Info bypass the buffer, Debug and Trace are dropped, Error waits for the flush
+    let (immediate, all) = sent_levels(LevelFilter::Info, Level::Warn, 2);
+    assert_eq!(immediate, [Level::Warn, Level::Info]);
+    assert_eq!(all, [Level::Warn, Level::Info, Level::Error]);
+}
+
+#[test]
+fn records_above_the_threshold_are_buffered_below_a_trace_ignore_buffer_level() {
+    let (immediate, all) = sent_levels(LevelFilter::Warn, Level::Trace, 0);
+    assert!(immediate.is_empty());
+    assert_eq!(all, [Level::Error, Level::Warn]);
+}
+
+#[test]
+fn threshold_more_severe_than_ignore_buffer_drops_records_between_them() {
+    let (immediate, all) = sent_levels(LevelFilter::Error, Level::Info, 0);
+    assert!(immediate.is_empty());
+    assert_eq!(all, [Level::Error]);
+}
+
+#[test]
+fn threshold_off_sends_nothing() {
+    let (immediate, all) = sent_levels(LevelFilter::Off, Level::Error, 0);
+    assert!(immediate.is_empty());
+    assert!(all.is_empty());
+}
+
+#[test]
+fn attached_threshold_filter_applies_before_the_appender_threshold() {
+    let (appender, captured) = appender(LevelFilter::Trace, Level::Trace);
+    let config = Config::builder()
+        .appender(
+            AppenderConfig::builder()
+                .filter(Box::new(ThresholdFilter::new(LevelFilter::Warn)))
+                .build("logstash", Box::new(appender)),
+        )
+        .build(Root::builder().appender("logstash").build(LevelFilter::Trace))
+        .unwrap();
+    let logger = log4rs::Logger::new(config);
+
+    for &level in LEVELS.iter() {
+        logger.log(&Record::builder().args(format_args!("{}", level)).level(level).target("threshold").build());
+    }
+    Log::flush(&logger);
+    assert_eq!(wait_for_levels(&captured, 2), [Level::Error, Level::Warn]);
+}
+
+#[test]
+fn threshold_and_ignore_buffer_are_distinct_config_keys() {
+    let config: LogstashConfig =
+        serde_yaml::from_str("hostname: logstash\nport: 5044\nthreshold: info\nignore_buffer: warn\n").unwrap();
+    let builder = config.into_builder(&log4rs::config::Deserializers::default()).unwrap();
+    let expected = AppenderBuilder::default()
+        .with_hostname("logstash")
+        .with_port(5044)
+        .with_threshold(LevelFilter::Info)
+        .with_ignore_buffer_level(Level::Warn);
+    assert_eq!(format!("{:?}", builder), format!("{:?}", expected));
+}
+
+/// Message counting how many times it is formatted
+struct CountedMessage<'a>(&'a AtomicUsize);
+
+impl fmt::Display for CountedMessage<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        f.write_str("counted")
+    }
+}
+
+/// Appends a record of every level for `target` with a message counting its formatting
+fn log_counted(appender: &dyn Append, target: &str, formatted: &AtomicUsize) {
+    for &level in LEVELS.iter() {
+        let message = CountedMessage(formatted);
+        appender.append(&Record::builder().args(format_args!("{}", message)).level(level).target(target).build()).unwrap();
+    }
+}
+
+#[test]
+fn records_below_the_threshold_are_neither_formatted_nor_sent() {
+    let captured = CapturingSender::new();
+    let appender = AppenderBuilder::default().with_threshold(LevelFilter::Off).build_with_sender(captured.clone());
+    let formatted = AtomicUsize::new(0);
+    for _ in 0..100 {
+        log_counted(&appender, "threshold", &formatted);
+    }
+    assert_eq!(formatted.load(Ordering::Relaxed), 0);
+    assert!(captured.records().is_empty());
+
+    let appender = AppenderBuilder::default().with_threshold(LevelFilter::Warn).build_with_sender(captured.clone());
+    log_counted(&appender, "threshold", &formatted);
+    assert_eq!(formatted.load(Ordering::Relaxed), 2);
+    let levels: Vec<_> = captured.records().iter().map(|record| record.level).collect();
+    assert_eq!(levels, [Level::Error, Level::Warn]);
+}
+
+#[test]
+fn records_disabled_by_the_sender_filters_are_neither_formatted_nor_sent() {
+    let captured = CapturingSender::new();
+    let sender = BufferedSender::builder()
+        .with_buffer_size(None)
+        .with_level_filter(LevelFilter::Info)
+        .with_target_level_filter("threshold::noisy", LevelFilter::Error)
+        .with_diagnostics(false)
+        .build(captured.clone());
+    let appender = AppenderBuilder::default().build_with_sender(sender);
+    let formatted = AtomicUsize::new(0);
+
+    log_counted(&appender, "threshold::noisy::pool", &formatted);
+    log_counted(&appender, "threshold", &formatted);
+    assert_eq!(formatted.load(Ordering::Relaxed), 4);
+    appender.sender().flush_and_wait(TIMEOUT).unwrap();
+    let sent: Vec<_> = captured.records().iter().map(|record| (record.target.to_string(), record.level)).collect();
+    assert_eq!(
+        sent,
+        [
+            ("threshold::noisy::pool".to_string(), Level::Error),
+            ("threshold".to_string(), Level::Error),
+            ("threshold".to_string(), Level::Warn),
+            ("threshold".to_string(), Level::Info),
+        ]
+    );
+}
+
+#[test]
+fn buffered_sender_logger_skips_disabled_records() {
+    let captured = CapturingSender::new();
+    let sender = BufferedSender::builder().with_buffer_size(None).with_level_filter(LevelFilter::Warn).with_diagnostics(false).build(captured.clone());
+    let formatted = AtomicUsize::new(0);
+    for &level in LEVELS.iter() {
+        let message = CountedMessage(&formatted);
+        sender.log(&Record::builder().args(format_args!("{}", message)).level(level).target("threshold").build());
+    }
+    sender.flush_and_wait(TIMEOUT).unwrap();
+    assert_eq!(formatted.load(Ordering::Relaxed), 2);
+    assert_eq!(captured.records().len(), 2);
+}
@@ -0,0 +1,158 @@
+//! Parses the example config of `examples/logstash.yaml` and appender configs into builders.
+
+use log::Level;
+use qoollo_log4rs_logstash::appender::AppenderBuilder;
+use qoollo_log4rs_logstash::config::{AppenderConfig, BufferedSenderConfig, DeserializersExt};
+use qoollo_logstash_rs::{BufferPolicy, ProxyKind, ProxyOptions, TlsOptions};
+use std::time::Duration;
+
+const CONFIG: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/logstash.yaml");
+
+#[test]
+fn example_config_builds_the_appenders() {
+    let deserializers = log4rs::config::Deserializers::default().with_logstash();
+    let config = log4rs::config::load_config_file(CONFIG, deserializers).unwrap();
+
+    let mut names: Vec<&str> = config.appenders().iter().map(|a| a.name()).collect();
+    names.sort();
+    assert_eq!(names, ["logstash", "stdout"]);
+    assert_eq!(config.root().appenders(), ["stdout", "logstash"]);
+}
+
+#[test]
+fn example_config_holds_buffered_sender_settings() {
+    let config: serde_yaml::Value =
+        serde_yaml::from_str(&std::fs::read_to_string(CONFIG).unwrap()).unwrap();
+    let appender = config["appenders"]["logstash"].clone();
+    let buffered: BufferedSenderConfig = serde_yaml::from_value(appender).unwrap();
+
+    assert_eq!(buffered.buffer_size, Some(100));
+    assert_eq!(buffered.buffer_lifetime, Some(Duration::from_secs(1)));
+    assert_eq!(buffered.ignore_buffer_level, Some(Level::Error));
+    assert_eq!(buffered.error_period, Some(Duration::from_secs(10)));
+    assert_eq!(buffered.log_queue_len, Some(1000));
+}
+
+fn builder_from_yaml(yaml: &str) -> anyhow::Result<AppenderBuilder> {
+    let config: AppenderConfig = serde_yaml::from_str(yaml)?;
+    config.into_builder(&log4rs::config::Deserializers::default())
+}
+
+fn assert_same_settings(actual: &AppenderBuilder, expected: &AppenderBuilder) {
+    assert_eq!(format!("{:?}", actual), format!("{:?}", expected));
+    assert_eq!(actual.config_hash(), expected.config_hash());
+}
+
+fn tls_options() -> TlsOptions {
+    TlsOptions {
+        ca_cert: Some("/etc/ssl/ca.pem".into()),
+        client_cert: Some("/etc/ssl/client.pem".into()),
+        client_key: Some("/etc/ssl/client.key".into()),
+        server_name: Some("logstash.example.com".into()),
+        insecure_skip_verify: true,
+    }
+}
+
+#[test]
+fn tls_proxy_and_timeouts_sections_round_trip_into_the_builder() {
+    let builder = builder_from_yaml(
+        r#"
+hostname: logstash.internal
+port: 5044
+tls:
+  ca_cert: /etc/ssl/ca.pem
+  client_cert: /etc/ssl/client.pem
+  client_key: /etc/ssl/client.key
+  server_name: logstash.example.com
+  insecure_skip_verify: true
+proxy:
+  kind: socks5
+  addr: proxy.internal:1080
+  username: logger
+  password: secret
+timeouts:
+  connect: 3s
+  write: 500ms
+  flush: 2s
+"#,
+    )
+    .unwrap();
+
+    let expected = AppenderBuilder::default()
+        .with_hostname("logstash.internal")
+        .with_port(5044)
+        .with_use_tls(true)
+        .with_tls_options(tls_options())
+        .with_proxy(ProxyOptions::new(ProxyKind::Socks5, "proxy.internal:1080").with_credentials("logger", "secret"))
+        .with_connection_timeout(Duration::from_secs(3))
+        .with_write_timeout(Duration::from_millis(500))
+        .with_flush_timeout(Some(Duration::from_secs(2)));
+    assert_same_settings(&builder, &expected);
+
+    let insecure = expected.with_tls_options(TlsOptions { insecure_skip_verify: false, ..tls_options() });
+    assert_ne!(builder.config_hash(), insecure.config_hash());
+}
+
+#[test]
+fn tls_section_defaults_and_use_tls_override() {
+    // An empty section enables TLS with the default options
+    let builder = builder_from_yaml("hostname: logstash\nport: 5044\ntls: {}\n").unwrap();
+    let expected = AppenderBuilder::default().with_hostname("logstash").with_port(5044).with_use_tls(true);
+    assert_same_settings(&builder, &expected);
+
+    let builder = builder_from_yaml("hostname: logstash\nport: 5044\nuse_tls: false\ntls: { server_name: elk }\n").unwrap();
+    let expected = AppenderBuilder::default()
+        .with_hostname("logstash")
+        .with_port(5044)
+        .with_tls_options(TlsOptions { server_name: Some("elk".into()), ..Default::default() });
+    assert_same_settings(&builder, &expected);
+}
+
+#[test]
+fn level_buffers_override_the_global_buffering_per_level() {
+    let builder = builder_from_yaml(
+        r#"
+hostname: logstash
+port: 5044
+buffer_size: 500
+buffer_lifetime: 30s
+level_buffers:
+  error: { lifetime: 1s }
+  warn: { size: 10, lifetime: 1s }
+  debug: {}
+"#,
+    )
+    .unwrap();
+
+    let expected = AppenderBuilder::default()
+        .with_hostname("logstash")
+        .with_port(5044)
+        .with_buffer_size(500)
+        .with_buffer_lifetime(Duration::from_secs(30))
+        .with_level_buffer_policy(Level::Error, BufferPolicy { size: None, lifetime: Some(Duration::from_secs(1)) })
+        .with_level_buffer_policy(Level::Warn, BufferPolicy { size: Some(10), lifetime: Some(Duration::from_secs(1)) })
+        .with_level_buffer_policy(Level::Debug, BufferPolicy::default());
+    assert_eq!(builder.config_hash(), expected.config_hash());
+    let slower = expected.with_level_buffer_policy(Level::Error, BufferPolicy { size: None, lifetime: Some(Duration::from_secs(2)) });
+    assert_ne!(builder.config_hash(), slower.config_hash());
+
+    let error = builder_from_yaml("hostname: logstash\nport: 5044\nlevel_buffers:\n  error: { flush: 1s }\n").unwrap_err();
+    assert!(error.to_string().contains("unknown field `flush`"), "{}", error);
+}
+
+#[test]
+fn section_errors_name_the_section() {
+    let error = |yaml: &str| builder_from_yaml(yaml).unwrap_err().to_string();
+
+    let unknown = error("hostname: logstash\nport: 5044\ntimeouts: { connect: 1s, read: 2s }\n");
+    assert!(unknown.starts_with("timeouts: unknown field `read`"), "{}", unknown);
+
+    let invalid = error("hostname: logstash\nport: 5044\ntimeouts: { write: soon }\n");
+    assert!(invalid.starts_with("timeouts: "), "{}", invalid);
+
+    let mistyped = error("hostname: logstash\nport: 5044\ntls: { insecure_skip_verify: [true] }\n");
+    assert!(mistyped.starts_with("tls: invalid type"), "{}", mistyped);
+
+    let kind = error("hostname: logstash\nport: 5044\nproxy: { kind: ftp, addr: proxy:21 }\n");
+    assert!(kind.starts_with("proxy: unknown variant `ftp`"), "{}", kind);
+}
@@ -0,0 +1,147 @@
+//! `#[derive(LogStashEvent)]` converting structs into `qoollo_logstash_rs::LogStashRecord`.
+//! Use it through the `derive` feature of `qoollo-logstash-rs`.
+//!
+//! ```
+//! use qoollo_logstash_rs::{LogStashEvent, LogStashRecord};
+//! use serde::Serialize;
+//!
+//! #[derive(Serialize)]
+//! struct Client {
+//!     ip: String,
+//!     agent: String,
+//! }
+//!
+//! #[derive(LogStashEvent)]
+//! struct HttpRequest {
+//!     method: String,
+//!     #[logstash(rename = "url.path")]
+//!     path: String,
+//!     status: u16,
+//!     #[logstash(flatten)]
+//!     client: Client,
+//!     #[logstash(skip)]
+//!     #[allow(dead_code)]
+//!     body: Vec<u8>,
+//! }
+//!
+//! let record = LogStashRecord::from(HttpRequest {
+//!     method: "GET".into(),
+//!     path: "/orders".into(),
+//!     status: 200,
+//!     client: Client { ip: "10.0.0.1".into(), agent: "curl".into() },
+//!     body: Vec::new(),
+//! });
+//! assert_eq!(record.fields["method"], "GET");
+//! assert_eq!(record.fields["url.path"], "/orders");
+//! assert_eq!(record.fields["status"], 200);
+//! assert_eq!(record.fields["ip"], "10.0.0.1");
+//! assert!(!record.fields.contains_key("body"));
+//! ```
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Field, Fields, LitStr, Result};
+
+/// Implements `From<T> for LogStashRecord`, adding every field of the struct to the record
+/// under its name. Fields accept `#[logstash(rename = "...")]` to change the key,
+/// `#[logstash(skip)]` to leave them out and `#[logstash(flatten)]` to add the keys of a
+/// field serialized as a JSON object instead of the field itself. Fields are serialized
+/// with `serde_json::to_value`, which panics on maps with non-string keys.
+#[proc_macro_derive(LogStashEvent, attributes(logstash))]
+pub fn derive_logstash_event(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new_spanned(
+                    &input.ident,
+                    "LogStashEvent requires a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "LogStashEvent can only be derived for structs",
+            ))
+        }
+    };
+    let mut adds = Vec::with_capacity(fields.len());
+    for field in fields {
+        let options = FieldOptions::parse(field)?;
+        if options.skip {
+            continue;
+        }
+        let ident = field.ident.as_ref().expect("named field");
+        let key = options.rename.unwrap_or_else(|| ident.to_string());
+        let value = quote! {
+            ::qoollo_logstash_rs::__private::serde_json::to_value(&value.#ident).unwrap()
+        };
+        adds.push(if options.flatten {
+            quote! {
+                match #value {
+                    ::qoollo_logstash_rs::__private::serde_json::Value::Object(fields) => {
+                        record.fields.extend(fields)
+                    }
+                    field => {
+                        record.add_data(#key, field);
+                    }
+                }
+            }
+        } else {
+            quote! {
+                record.add_data(#key, #value);
+            }
+        });
+    }
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::core::convert::From<#name #ty_generics>
+            for ::qoollo_logstash_rs::LogStashRecord #where_clause
+        {
+            fn from(value: #name #ty_generics) -> Self {
+                let mut record = ::qoollo_logstash_rs::LogStashRecord::new();
+                #(#adds)*
+                record
+            }
+        }
+    })
+}
+
+/// Options of a field set by its `#[logstash(...)]` attributes
+#[derive(Default)]
+struct FieldOptions {
+    rename: Option<String>,
+    skip: bool,
+    flatten: bool,
+}
+
+impl FieldOptions {
+    fn parse(field: &Field) -> Result<Self> {
+        let mut options = Self::default();
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("logstash")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    options.rename = Some(meta.value()?.parse::<LitStr>()?.value());
+                } else if meta.path.is_ident("skip") {
+                    options.skip = true;
+                } else if meta.path.is_ident("flatten") {
+                    options.flatten = true;
+                } else {
+                    return Err(meta.error("expected `rename`, `skip` or `flatten`"));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(options)
+    }
+}
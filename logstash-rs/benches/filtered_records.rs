@@ -0,0 +1,76 @@
+//! A workload where 99% of the records are filtered out, logged through `BufferedSender`
+//! checking its filters against the metadata first, compared with building every record
+//! before checking them.
+//!
+//! ```sh
+//! cargo bench -p qoollo-logstash-rs --bench filtered_records
+//! ```
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use log::{Level, LevelFilter, Log};
+use qoollo_logstash_rs::{BufferedSender, LogStashRecord, Result, Sender};
+
+/// Records per iteration, all but one filtered out
+const RECORDS: usize = 100;
+
+/// Sender discarding every record
+struct SinkSender;
+
+impl Sender for SinkSender {
+    fn send(&self, _event: LogStashRecord) -> Result<()> {
+        Ok(())
+    }
+
+    fn send_batch(&self, _events: Vec<LogStashRecord>) -> Result<()> {
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Calls `f` with `RECORDS` records: Debug records of the database, filtered out, and a
+/// single Info record of the application
+fn for_each_record(f: impl Fn(&log::Record)) {
+    for seq in 0..RECORDS {
+        let (level, target) = match seq {
+            0 => (Level::Info, "app::orders"),
+            _ => (Level::Debug, "app::db"),
+        };
+        f(&log::Record::builder()
+            .args(format_args!("query {} took {}ms", seq, 3))
+            .level(level)
+            .target(target)
+            .module_path_static(Some(target))
+            .file_static(Some("src/db.rs"))
+            .line(Some(42))
+            .build());
+    }
+}
+
+fn filtered_records(c: &mut Criterion) {
+    let sender = BufferedSender::builder()
+        .with_level_filter(LevelFilter::Info)
+        .with_diagnostics(false)
+        .build(SinkSender);
+
+    let mut group = c.benchmark_group("filtered_records");
+    group.bench_function("filtered_before_building", |b| {
+        b.iter(|| for_each_record(|record| sender.log(record)))
+    });
+    group.bench_function("built_then_filtered", |b| {
+        b.iter(|| {
+            for_each_record(|record| {
+                let event = LogStashRecord::from_record(record);
+                if Sender::enabled(&sender, record.metadata()) {
+                    let _ = sender.send(event);
+                }
+            })
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, filtered_records);
+criterion_main!(benches);
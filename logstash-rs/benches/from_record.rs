@@ -0,0 +1,88 @@
+//! `LogStashRecord::from_record` for a record with static module, file and target strings,
+//! borrowed by the record, compared with the same record whose strings are not static and
+//! are copied as before they were borrowed. Prints the allocations per record of both
+//! before timing them.
+//!
+//! ```sh
+//! cargo bench -p qoollo-logstash-rs --bench from_record
+//! ```
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use log::Level;
+use qoollo_logstash_rs::LogStashRecord;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Allocator counting allocations
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const RECORDS: usize = 1000;
+
+const MODULE: &str = "app::orders::handler";
+const FILE: &str = "src/orders/handler.rs";
+
+/// Builds a record from a `log::Record` of the module, the way `log!` does when `borrowed`,
+/// otherwise with strings only living as long as the record, and drops it
+fn build(borrowed: bool) {
+    let (module, file) = (MODULE.to_string(), FILE.to_string());
+    let mut builder = log::Record::builder();
+    builder
+        .args(format_args!("order accepted"))
+        .level(Level::Info)
+        .target(MODULE)
+        .line(Some(42));
+    if borrowed {
+        builder
+            .module_path_static(Some(MODULE))
+            .file_static(Some(FILE));
+    } else {
+        builder.module_path(Some(&module)).file(Some(&file));
+    }
+    criterion::black_box(LogStashRecord::from_record(&builder.build()));
+}
+
+fn allocations_per_record(borrowed: bool) -> f64 {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for _ in 0..RECORDS {
+        build(borrowed);
+    }
+    // The two strings of the record source are allocated either way
+    (ALLOCATIONS.load(Ordering::Relaxed) - before) as f64 / RECORDS as f64 - 2.0
+}
+
+fn from_record(c: &mut Criterion) {
+    println!(
+        "allocations per record: {:.1} static strings, {:.1} copied strings",
+        allocations_per_record(true),
+        allocations_per_record(false)
+    );
+
+    let mut group = c.benchmark_group("from_record");
+    group.bench_function("static_strings", |b| b.iter(|| build(true)));
+    group.bench_function("copied_strings", |b| b.iter(|| build(false)));
+    group.finish();
+}
+
+criterion_group!(benches, from_record);
+criterion_main!(benches);
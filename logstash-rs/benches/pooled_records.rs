@@ -0,0 +1,89 @@
+//! Records built from `log::Record`s, freshly allocated or taken from a `RecordPool`.
+//! Prints the allocations per record of both before timing them.
+//!
+//! ```sh
+//! cargo bench -p qoollo-logstash-rs --features pool --bench pooled_records
+//! ```
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use log::Level;
+use qoollo_logstash_rs::{LogStashRecord, RecordPool};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Allocator counting allocations
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const RECORDS: usize = 1000;
+
+/// Builds a record with two fields, from `pool` if there is one, and drops it
+fn build(pool: Option<&RecordPool>, seq: usize) {
+    let record = log::Record::builder()
+        .args(format_args!("request handled"))
+        .level(Level::Info)
+        .target("bench")
+        .module_path_static(Some("bench::handler"))
+        .build();
+    match pool {
+        Some(pool) => {
+            let mut event = LogStashRecord::from_record_pooled(&record, pool);
+            event.add_data("seq", seq.into());
+            event.add_data("status", 200.into());
+            criterion::black_box(&event);
+        }
+        None => {
+            let mut event = LogStashRecord::from_record(&record);
+            event.add_data("seq", seq.into());
+            event.add_data("status", 200.into());
+            criterion::black_box(&event);
+        }
+    }
+}
+
+fn allocations_per_record(pool: Option<&RecordPool>) -> f64 {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for seq in 0..RECORDS {
+        build(pool, seq);
+    }
+    (ALLOCATIONS.load(Ordering::Relaxed) - before) as f64 / RECORDS as f64
+}
+
+fn pooled_records(c: &mut Criterion) {
+    let pool = RecordPool::new(16);
+    // Fills the pool so the counted round only reuses records
+    build(Some(&pool), 0);
+    println!(
+        "allocations per record: {:.1} fresh, {:.1} pooled",
+        allocations_per_record(None),
+        allocations_per_record(Some(&pool))
+    );
+
+    let mut group = c.benchmark_group("record_pool");
+    group.bench_function("fresh", |b| b.iter(|| build(None, 1)));
+    group.bench_function("pooled", |b| b.iter(|| build(Some(&pool), 1)));
+    group.finish();
+}
+
+criterion_group!(benches, pooled_records);
+criterion_main!(benches);
@@ -0,0 +1,111 @@
+//! Batches of 1000 records sent by `TcpSender` to a local server discarding them, copied
+//! into one buffer by `send_batch` or written with scatter-gather IO by
+//! `send_batch_vectored`. Prints the allocations per batch of both before timing them.
+//!
+//! ```sh
+//! cargo bench -p qoollo-logstash-rs --bench vectored_batches
+//! ```
+
+/// `send_batch_vectored` is only available on Unix
+#[cfg(unix)]
+mod vectored {
+    use criterion::{Criterion, Throughput};
+    use log::Level;
+    use qoollo_logstash_rs::{LogStashRecord, Sender, TcpSender};
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Allocator counting allocations
+    struct CountingAllocator;
+
+    static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout);
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            System.realloc(ptr, layout, new_size)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    const BATCH: usize = 1000;
+
+    /// Port of a server reading and discarding everything sent to it
+    fn start_sink() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                std::thread::spawn(move || std::io::copy(&mut stream, &mut std::io::sink()));
+            }
+        });
+        port
+    }
+
+    fn batch() -> Vec<LogStashRecord> {
+        (0..BATCH)
+            .map(|seq| {
+                LogStashRecord::builder(Level::Info)
+                    .target("bench")
+                    .message(format!("request {} handled", seq))
+                    .field("seq", seq)
+                    .field("status", 200)
+                    .build()
+            })
+            .collect()
+    }
+
+    fn allocations(send: impl Fn()) -> usize {
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        send();
+        ALLOCATIONS.load(Ordering::Relaxed) - before
+    }
+
+    pub fn vectored_batches(c: &mut Criterion) {
+        let port = start_sink();
+        let tcp = TcpSender::builder()
+            .hostname("127.0.0.1")
+            .port(port)
+            .build()
+            .unwrap();
+        let events = batch();
+        tcp.send_batch_ref(&events).unwrap();
+        println!(
+            "allocations per batch of {}: {} send_batch, {} send_batch_vectored",
+            BATCH,
+            allocations(|| tcp.send_batch_ref(&events).unwrap()),
+            allocations(|| tcp.send_batch_vectored(&events).unwrap())
+        );
+
+        let mut group = c.benchmark_group("send_batch_1000");
+        group.throughput(Throughput::Elements(BATCH as u64));
+        group.bench_function("send_batch", |b| {
+            b.iter(|| tcp.send_batch_ref(&events).unwrap())
+        });
+        group.bench_function("send_batch_vectored", |b| {
+            b.iter(|| tcp.send_batch_vectored(&events).unwrap())
+        });
+        group.finish();
+    }
+}
+
+#[cfg(unix)]
+criterion::criterion_group!(benches, vectored::vectored_batches);
+#[cfg(unix)]
+criterion::criterion_main!(benches);
+
+#[cfg(not(unix))]
+fn main() {}
@@ -0,0 +1,136 @@
+//! CPU time the worker of a `BufferedSender` spends on batches of 100 records, serializing
+//! them itself or writing records serialized early on the calling thread. The worker
+//! delivers to a sender serializing records the way `TcpSender` does, and its CPU time is
+//! read from procfs once it is done with the batch.
+//!
+//! ```sh
+//! cargo bench -p qoollo-logstash-rs --features bytes --bench worker_serialization
+//! ```
+
+/// The CPU time of a thread is read from procfs
+#[cfg(target_os = "linux")]
+mod worker_cpu {
+    use criterion::{Criterion, Throughput};
+    use log::Level;
+    use qoollo_logstash_rs::{BufferedSender, LogStashRecord, Result, Sender};
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+    const RECORDS: usize = 100;
+
+    /// Sender serializing the records it gets and discarding them, keeping the procfs
+    /// directory of the thread calling it
+    struct CpuSink {
+        task: Arc<Mutex<Option<PathBuf>>>,
+    }
+
+    impl CpuSink {
+        fn track(&self) {
+            let mut task = self.task.lock().unwrap();
+            if task.is_none() {
+                *task = Some(std::fs::read_link("/proc/thread-self").unwrap());
+            }
+        }
+    }
+
+    /// CPU time in nanoseconds of the thread with the procfs directory `task`, as of its
+    /// last switch off the CPU
+    fn cpu_time(task: &Mutex<Option<PathBuf>>) -> u64 {
+        let task = task.lock().unwrap();
+        let path = PathBuf::from("/proc")
+            .join(task.as_ref().unwrap())
+            .join("schedstat");
+        let schedstat = std::fs::read_to_string(path).unwrap();
+        schedstat
+            .split_whitespace()
+            .next()
+            .unwrap()
+            .parse()
+            .unwrap()
+    }
+
+    impl Sender for CpuSink {
+        fn send(&self, event: LogStashRecord) -> Result<()> {
+            self.send_batch(vec![event])
+        }
+
+        fn send_batch(&self, events: Vec<LogStashRecord>) -> Result<()> {
+            let mut buf = Vec::new();
+            for event in &events {
+                serde_json::to_writer(&mut buf, event)?;
+                buf.push(b'\n');
+            }
+            criterion::black_box(buf);
+            self.track();
+            Ok(())
+        }
+
+        fn send_raw(&self, frames: &[bytes::Bytes]) -> Result<()> {
+            criterion::black_box(frames.iter().map(|frame| frame.len()).sum::<usize>());
+            self.track();
+            Ok(())
+        }
+
+        fn flush(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn records() -> Vec<LogStashRecord> {
+        (0..RECORDS)
+            .map(|seq| {
+                LogStashRecord::builder(Level::Info)
+                    .target("app::orders")
+                    .message(format!("order {} accepted", seq))
+                    .field("seq", seq)
+                    .field("customer", "c-1024")
+                    .field(
+                        "items",
+                        serde_json::json!([{ "sku": "a-1", "qty": 2 }, { "sku": "b-7", "qty": 1 }]),
+                    )
+                    .build()
+            })
+            .collect()
+    }
+
+    pub fn worker_serialization(c: &mut Criterion) {
+        let records = records();
+        let mut group = c.benchmark_group("worker_cpu_per_batch");
+        group.throughput(Throughput::Elements(RECORDS as u64));
+        for (name, serialize_early) in [("worker_serializes", false), ("serialized_early", true)] {
+            let task = Arc::new(Mutex::new(None));
+            let sender = BufferedSender::builder()
+                .with_buffer_size(Some(RECORDS))
+                .with_buffer_lifetime(None)
+                .with_serialize_early(serialize_early)
+                .with_diagnostics(false)
+                .build(CpuSink { task: task.clone() });
+            // Delivers a first batch so the worker thread is known
+            sender.send_batch(records.clone()).unwrap();
+            sender.flush_and_wait(TIMEOUT).unwrap();
+
+            // Reports the CPU time of the worker instead of the wall time of the loop
+            group.bench_function(name, |b| {
+                b.iter_custom(|iters| {
+                    let start = cpu_time(&task);
+                    for _ in 0..iters {
+                        sender.send_batch(records.clone()).unwrap();
+                        sender.flush_and_wait(TIMEOUT).unwrap();
+                    }
+                    Duration::from_nanos(cpu_time(&task) - start)
+                })
+            });
+        }
+        group.finish();
+    }
+}
+
+#[cfg(target_os = "linux")]
+criterion::criterion_group!(benches, worker_cpu::worker_serialization);
+#[cfg(target_os = "linux")]
+criterion::criterion_main!(benches);
+
+#[cfg(not(target_os = "linux"))]
+fn main() {}
@@ -0,0 +1,59 @@
+//! Drives a `BatchAccumulator` from a tokio event loop, sending batches to stdout.
+//!
+//! Run with `cargo run --example batch_accumulator --features async`.
+
+use qoollo_logstash_rs::{BatchAccumulator, LogStashRecord, Result, Sender, ShouldFlush};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+struct StdoutSender;
+
+impl Sender for StdoutSender {
+    fn send(&self, event: LogStashRecord) -> Result<()> {
+        self.send_batch(vec![event])
+    }
+
+    fn send_batch(&self, events: Vec<LogStashRecord>) -> Result<()> {
+        println!("batch of {} records", events.len());
+        for event in events {
+            println!("{}", serde_json::to_string(&event)?);
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+    let (records, mut incoming) = mpsc::channel(100);
+    tokio::spawn(async move {
+        for i in 0..25u32 {
+            let record = LogStashRecord::builder(log::Level::Info)
+                .message(format!("event {}", i))
+                .build();
+            let _ = records.send(record).await;
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    });
+
+    let mut batch = BatchAccumulator::new(StdoutSender, 10, Duration::from_millis(100));
+    loop {
+        let deadline = batch.deadline().map(tokio::time::Instant::from_std);
+        tokio::select! {
+            record = incoming.recv() => match record {
+                Some(record) => {
+                    if batch.push(record) == ShouldFlush::Yes {
+                        batch.flush()?;
+                    }
+                }
+                None => break,
+            },
+            _ = tokio::time::sleep_until(deadline.unwrap_or_else(tokio::time::Instant::now)),
+                if deadline.is_some() => batch.try_flush_if_ready()?,
+        }
+    }
+    batch.flush()
+}
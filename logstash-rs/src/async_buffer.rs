@@ -0,0 +1,296 @@
+use crate::prelude::*;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+#[allow(clippy::large_enum_variant)]
+enum AsyncCommand {
+    Send(LogStashRecord),
+    Flush(Option<oneshot::Sender<Result<()>>>),
+}
+
+/// Buffered sender running as a tokio task and writing to a plain TCP connection.
+///
+/// Counterpart of [`BufferedSender`] for async services, no thread is spawned. Must be
+/// created inside a tokio runtime. TLS is not supported.
+pub struct AsyncBufferedSender {
+    commands: mpsc::Sender<AsyncCommand>,
+    task: JoinHandle<()>,
+    dropped: Arc<AtomicU64>,
+}
+
+/// Builder of an [`AsyncBufferedSender`] connecting to `hostname` and `port`
+#[derive(Debug, Clone)]
+pub struct AsyncBufferedSenderBuilder {
+    hostname: String,
+    port: u16,
+    buffer_size: Option<usize>,
+    buffer_lifetime: Option<Duration>,
+    log_queue_len: usize,
+    max_retained: usize,
+    connect_timeout: Duration,
+}
+
+impl AsyncBufferedSenderBuilder {
+    pub fn with_buffer_size(mut self, buffer_size: Option<usize>) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    pub fn with_buffer_lifetime(mut self, buffer_lifetime: Option<Duration>) -> Self {
+        self.buffer_lifetime = buffer_lifetime;
+        self
+    }
+
+    pub fn with_log_queue_len(mut self, log_queue_len: usize) -> Self {
+        self.log_queue_len = log_queue_len;
+        self
+    }
+
+    /// Maximum number of records kept in the buffer while they can't be sent, 10000 by
+    /// default. The oldest records are dropped beyond it and counted by
+    /// [`AsyncBufferedSender::dropped`].
+    pub fn with_max_retained(mut self, max_retained: usize) -> Self {
+        self.max_retained = max_retained.max(1);
+        self
+    }
+
+    /// Maximum time a connection attempt may take, 10 seconds by default
+    pub fn with_connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+
+    /// Spawns the task of the sender, must be called inside a tokio runtime
+    pub fn build(self) -> AsyncBufferedSender {
+        let (commands, receiver) = mpsc::channel(self.log_queue_len.max(1));
+        let dropped = Arc::new(AtomicU64::new(0));
+        let worker = AsyncWorker {
+            hostname: self.hostname,
+            port: self.port,
+            stream: None,
+            buffer: VecDeque::new(),
+            buffer_size: self.buffer_size,
+            buffer_lifetime: self.buffer_lifetime,
+            max_retained: self.max_retained,
+            connect_timeout: self.connect_timeout,
+            dropped: dropped.clone(),
+            deadline: None,
+        };
+        AsyncBufferedSender {
+            commands,
+            task: tokio::spawn(worker.run(receiver)),
+            dropped,
+        }
+    }
+}
+
+impl AsyncBufferedSender {
+    pub fn new(
+        hostname: String,
+        port: u16,
+        buffer_size: Option<usize>,
+        buffer_lifetime: Option<Duration>,
+        log_queue_len: usize,
+    ) -> Self {
+        Self::builder(hostname, port)
+            .with_buffer_size(buffer_size)
+            .with_buffer_lifetime(buffer_lifetime)
+            .with_log_queue_len(log_queue_len)
+            .build()
+    }
+
+    pub fn builder(hostname: impl Into<String>, port: u16) -> AsyncBufferedSenderBuilder {
+        AsyncBufferedSenderBuilder {
+            hostname: hostname.into(),
+            port,
+            buffer_size: Some(100),
+            buffer_lifetime: Some(Duration::from_secs(1)),
+            log_queue_len: 1000,
+            max_retained: 10_000,
+            connect_timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Records dropped so far because the buffer held the maximum number of retained records
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Queues `event`, waiting for room in the queue
+    pub async fn send(&self, event: LogStashRecord) -> Result<()> {
+        Ok(self.commands.send(AsyncCommand::Send(event)).await?)
+    }
+
+    /// Queues `event` without waiting, fails if the queue is full
+    pub fn try_send(&self, event: LogStashRecord) -> Result<()> {
+        Ok(self.commands.try_send(AsyncCommand::Send(event))?)
+    }
+
+    /// Sends buffered records and waits for the result
+    pub async fn flush(&self) -> Result<()> {
+        let (reply, result) = oneshot::channel();
+        self.commands.send(AsyncCommand::Flush(Some(reply))).await?;
+        result.await?
+    }
+
+    /// Stops the task after it sends all queued and buffered records
+    pub async fn shutdown(self) -> Result<()> {
+        drop(self.commands);
+        Ok(self.task.await?)
+    }
+}
+
+struct AsyncWorker {
+    hostname: String,
+    port: u16,
+    stream: Option<TcpStream>,
+    buffer: VecDeque<LogStashRecord>,
+    buffer_size: Option<usize>,
+    buffer_lifetime: Option<Duration>,
+    max_retained: usize,
+    connect_timeout: Duration,
+    dropped: Arc<AtomicU64>,
+    deadline: Option<Instant>,
+}
+
+impl AsyncWorker {
+    async fn run(mut self, mut receiver: mpsc::Receiver<AsyncCommand>) {
+        loop {
+            let cmd = match self.deadline {
+                Some(deadline) => tokio::select! {
+                    cmd = receiver.recv() => cmd,
+                    _ = tokio::time::sleep_until(deadline) => {
+                        report(self.flush().await);
+                        continue;
+                    }
+                },
+                None => receiver.recv().await,
+            };
+            match cmd {
+                Some(AsyncCommand::Send(event)) => {
+                    let result = self.send(event).await;
+                    report(result);
+                }
+                Some(AsyncCommand::Flush(reply)) => {
+                    let result = self.flush().await;
+                    match reply {
+                        Some(reply) => {
+                            let _ = reply.send(result);
+                        }
+                        None => report(result),
+                    }
+                }
+                None => {
+                    // Every handle is gone, deliver what is left before stopping
+                    report(self.flush().await);
+                    break;
+                }
+            }
+        }
+    }
+
+    async fn send(&mut self, event: LogStashRecord) -> Result<()> {
+        match self.buffer_size {
+            Some(buffer_size) => {
+                if self.buffer.is_empty() {
+                    self.deadline = self
+                        .buffer_lifetime
+                        .map(|lifetime| Instant::now() + lifetime);
+                }
+                if self.buffer.len() >= self.max_retained {
+                    // Records kept after failed flushes, the oldest make room for the new one
+                    self.buffer.pop_front();
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                self.buffer.push_back(event);
+                if self.buffer.len() >= buffer_size {
+                    self.flush().await?;
+                }
+                Ok(())
+            }
+            None => self.write(std::slice::from_ref(&event)).await,
+        }
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.deadline = None;
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let mut events = std::mem::take(&mut self.buffer);
+        let result = self.write(events.make_contiguous()).await;
+        if result.is_err() {
+            // Kept for the next flush, as the buffer lifetime or a full buffer triggers it
+            self.buffer = events;
+            self.deadline = self
+                .buffer_lifetime
+                .map(|lifetime| Instant::now() + lifetime);
+        }
+        result
+    }
+
+    /// Writes `events`, retrying once on a fresh connection
+    async fn write(&mut self, events: &[LogStashRecord]) -> Result<()> {
+        let mut lines = Vec::with_capacity(events.len() * 256);
+        for event in events {
+            event.write_json(&mut lines)?;
+            lines.push(b'\n');
+        }
+        if self.stream.is_some() && self.write_lines(&lines).await.is_ok() {
+            return Ok(());
+        }
+        self.stream = None;
+        self.write_lines(&lines).await
+    }
+
+    async fn write_lines(&mut self, lines: &[u8]) -> Result<()> {
+        let stream = match &mut self.stream {
+            Some(stream) => stream,
+            None => {
+                let connect = TcpStream::connect((self.hostname.as_str(), self.port));
+                let stream = tokio::time::timeout(self.connect_timeout, connect)
+                    .await
+                    .map_err(|_| {
+                        Error::Timeout(format!(
+                            "connecting to {}:{} took over {:?}",
+                            self.hostname, self.port, self.connect_timeout
+                        ))
+                    })??;
+                self.stream.insert(stream)
+            }
+        };
+        let result = stream.write_all(lines).await;
+        if result.is_err() {
+            self.stream = None;
+        }
+        Ok(result?)
+    }
+}
+
+fn report(result: Result<()>) {
+    if let Err(err) = result {
+        println!("logstash logger error: {}", err);
+    }
+}
+
+/// Enqueues records without blocking, dropping them if the queue is full
+impl log::Log for AsyncBufferedSender {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        let _ = self.try_send(LogStashRecord::from_record(record));
+    }
+
+    fn flush(&self) {
+        let _ = self.commands.try_send(AsyncCommand::Flush(None));
+    }
+}
@@ -0,0 +1,91 @@
+use crate::prelude::*;
+use std::time::{Duration, Instant};
+
+/// Whether the accumulated batch should be sent now
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShouldFlush {
+    Yes,
+    No,
+}
+
+/// Collects records until a count or a time window is exceeded, without a background thread.
+///
+/// The caller drives it from its own event loop, e.g. selecting between incoming records and
+/// a sleep until [`BatchAccumulator::deadline`].
+pub struct BatchAccumulator<S> {
+    sender: S,
+    records: Vec<LogStashRecord>,
+    max_records: usize,
+    window: Duration,
+    window_start: Option<Instant>,
+}
+
+impl<S: Sender> BatchAccumulator<S> {
+    /// Sends batches of up to `max_records` records, or less once the oldest accumulated
+    /// record has waited for `window`
+    pub fn new(sender: S, max_records: usize, window: Duration) -> Self {
+        Self {
+            sender,
+            records: Vec::with_capacity(max_records),
+            max_records,
+            window,
+            window_start: None,
+        }
+    }
+
+    /// Adds `record` to the batch
+    pub fn push(&mut self, record: LogStashRecord) -> ShouldFlush {
+        if self.records.is_empty() {
+            self.window_start = Some(Instant::now());
+        }
+        self.records.push(record);
+        if self.is_ready() {
+            ShouldFlush::Yes
+        } else {
+            ShouldFlush::No
+        }
+    }
+
+    /// Whether the count or the time window is exceeded
+    pub fn is_ready(&self) -> bool {
+        self.records.len() >= self.max_records
+            || self
+                .deadline()
+                .is_some_and(|deadline| deadline <= Instant::now())
+    }
+
+    /// When the time window of the accumulated records closes, `None` if there are none
+    pub fn deadline(&self) -> Option<Instant> {
+        self.window_start.map(|start| start + self.window)
+    }
+
+    /// Sends the batch if the count or the time window is exceeded
+    pub fn try_flush_if_ready(&mut self) -> Result<()> {
+        if self.is_ready() {
+            return self.flush();
+        }
+        Ok(())
+    }
+
+    /// Sends all accumulated records as one batch
+    pub fn flush(&mut self) -> Result<()> {
+        self.window_start = None;
+        if self.records.is_empty() {
+            return Ok(());
+        }
+        let records = std::mem::replace(&mut self.records, Vec::with_capacity(self.max_records));
+        self.sender.send_batch(records)
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub fn sender(&self) -> &S {
+        &self.sender
+    }
+}
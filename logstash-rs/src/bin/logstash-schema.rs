@@ -0,0 +1,7 @@
+use qoollo_logstash_rs::LogStashRecord;
+
+fn main() -> anyhow::Result<()> {
+    let schema = LogStashRecord::json_schema();
+    println!("{}", serde_json::to_string_pretty(&schema)?);
+    Ok(())
+}
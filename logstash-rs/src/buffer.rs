@@ -1,20 +1,285 @@
-use log::Level;
+#[cfg(feature = "bytes")]
+use bytes::Bytes;
+use log::{Level, LevelFilter};
 
+use crate::diagnostics::Diagnostics;
+use crate::error::combine;
+use crate::hostname::HostnameCache;
+#[cfg(feature = "pool")]
+use crate::pool::RecordPool;
 use crate::prelude::*;
+use crate::record_buffer::{add_sub_ms_seq, MemoryBudget, RecordBuffer};
+use crate::stats::StatsCounters;
+use crate::stats::TargetSize;
 use std::{
-    sync::mpsc::{self, TrySendError},
+    collections::{hash_map::DefaultHasher, HashMap},
+    fs::{self, File},
+    hash::{Hash, Hasher},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        mpsc::{self, TryRecvError, TrySendError},
+        Arc, Mutex, MutexGuard, Weak,
+    },
+    thread::JoinHandle,
     time::{Duration, Instant},
 };
 
+// `Send` carries the record inline, boxing it would add an allocation per logged record
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone)]
 pub(crate) enum Command {
     Send(LogStashRecord),
     SendBatch(Vec<LogStashRecord>),
+    /// Record serialized by the caller, with its level for the ignore buffer check
+    #[cfg(feature = "bytes")]
+    SendRaw(Bytes, Level),
+    #[cfg(feature = "bytes")]
+    SendRawBatch(Vec<(Bytes, Level)>),
     Flush,
+    /// Flush and report the result back
+    FlushAck(mpsc::Sender<Result<()>>),
+    /// Establish the connection of the wrapped sender and report the result back
+    Connect(mpsc::Sender<Result<()>>),
+    Connected(Option<String>),
+    /// Send the records held while paused, the paused flag is shared with the worker
+    Resume,
+    /// Stop without sending anything more, handing back the records left
+    Drain(mpsc::Sender<Vec<LogStashRecord>>),
 }
 
+/// Hook run by the workers over every record right before it is sent
+#[derive(Clone)]
+pub(crate) struct Redactor(Arc<dyn Fn(&mut LogStashRecord) + Send + Sync>);
+
+impl std::fmt::Debug for Redactor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Redactor")
+    }
+}
+
+/// How records are distributed between several worker threads
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkerDispatch {
+    /// Every record or batch goes to the next worker in turn
+    #[default]
+    RoundRobin,
+    /// Records of the same target always go to the same worker, preserving their order
+    StickyByTarget,
+}
+
+/// Target of the heartbeat records sent by the workers
+pub const HEARTBEAT_TARGET: &str = "logstash_rs::heartbeat";
+
+/// Target of the self-metrics records sent by the workers
+pub const SELF_METRICS_TARGET: &str = "logstash_rs::self_metrics";
+
+/// What the worker does with records of a batch the sender reports as not fully written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PartialWritePolicy {
+    /// Drop the records which were not confirmed
+    #[default]
+    Drop,
+    /// Put the records after the last confirmed one back in the buffer for the next flush
+    RetryUnconfirmed,
+}
+
+/// Buffering settings of records of one level, each unset one falls back to the global
+/// setting
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BufferPolicy {
+    /// Number of buffered records triggering a flush
+    pub size: Option<usize>,
+    /// Longest time a record waits in the buffer
+    pub lifetime: Option<Duration>,
+}
+
+/// Bounds within which a worker adjusts its buffer size after every delivery: a call to
+/// the wrapped sender slower than `target_latency` doubles it to send larger batches, one
+/// faster than half of it halves it to send smaller batches sooner
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdaptiveBatching {
+    pub min_size: usize,
+    pub max_size: usize,
+    pub target_latency: Duration,
+}
+
+impl AdaptiveBatching {
+    /// Buffer size following `size` after a delivery taking `latency`
+    pub fn adjust(&self, size: usize, latency: Duration) -> usize {
+        let size = if latency > self.target_latency {
+            size.saturating_mul(2)
+        } else if latency < self.target_latency / 2 {
+            size / 2
+        } else {
+            size
+        };
+        self.clamp(size)
+    }
+
+    fn clamp(&self, size: usize) -> usize {
+        size.min(self.max_size).max(self.min_size).max(1)
+    }
+}
+
+/// Handle to background worker threads sending records to the wrapped senders.
+///
+/// Clones are cheap and share the same worker threads and connections, so several appenders
+/// can feed one endpoint. Once every clone has been dropped the workers flush their buffers
+/// and stop, the last drop waits for them up to the shutdown timeout.
+#[derive(Clone)]
 pub struct BufferedSender {
-    sender: mpsc::SyncSender<Command>,
+    workers: Arc<Workers>,
+    dispatch: WorkerDispatch,
+    next_worker: Arc<AtomicUsize>,
+    in_flight: Arc<InFlight>,
+    paused: Arc<AtomicBool>,
+    level_filter: LevelFilter,
+    target_level_filters: Vec<(String, LevelFilter)>,
+    #[cfg(feature = "bytes")]
+    serialize_early: bool,
+}
+
+/// References to every [`BufferedSender`] built, for [`flush_all`]
+static REGISTRY: Mutex<Vec<WeakBufferedSender>> = Mutex::new(Vec::new());
+
+/// Locks the registry, dropping the references to stopped senders
+fn registry() -> MutexGuard<'static, Vec<WeakBufferedSender>> {
+    let mut registry = REGISTRY.lock().unwrap_or_else(|err| err.into_inner());
+    registry.retain(|sender| sender.workers.strong_count() > 0);
+    registry
+}
+
+/// Flushes every running [`BufferedSender`] and waits up to `timeout` in total for all of
+/// them to confirm, e.g. at the end of `main` so everything logged is delivered before the
+/// process exits
+pub fn flush_all(timeout: Duration) -> Result<()> {
+    let deadline = Instant::now() + timeout;
+    let senders: Vec<_> = registry()
+        .iter()
+        .filter_map(WeakBufferedSender::upgrade)
+        .collect();
+    let errors = senders
+        .iter()
+        .filter_map(|sender| {
+            sender
+                .flush_and_wait(deadline.saturating_duration_since(Instant::now()))
+                .err()
+        })
+        .collect();
+    combine(errors)
+}
+
+/// Non-owning reference to a [`BufferedSender`], which doesn't keep its workers running
+#[derive(Clone)]
+pub struct WeakBufferedSender {
+    workers: Weak<Workers>,
+    dispatch: WorkerDispatch,
+    next_worker: Arc<AtomicUsize>,
+    in_flight: Arc<InFlight>,
+    paused: Arc<AtomicBool>,
+    level_filter: LevelFilter,
+    target_level_filters: Vec<(String, LevelFilter)>,
+    #[cfg(feature = "bytes")]
+    serialize_early: bool,
+}
+
+impl WeakBufferedSender {
+    /// Returns the sender if its workers are still running
+    pub fn upgrade(&self) -> Option<BufferedSender> {
+        Some(BufferedSender {
+            workers: self.workers.upgrade()?,
+            dispatch: self.dispatch,
+            next_worker: self.next_worker.clone(),
+            in_flight: self.in_flight.clone(),
+            paused: self.paused.clone(),
+            level_filter: self.level_filter,
+            target_level_filters: self.target_level_filters.clone(),
+            #[cfg(feature = "bytes")]
+            serialize_early: self.serialize_early,
+        })
+    }
+}
+
+struct WorkerHandle {
+    /// Always `Some` until dropped, disconnecting it stops the worker
+    commands: Option<mpsc::SyncSender<Command>>,
+    thread: Option<JoinHandle<Result<()>>>,
+    shutdown_timeout: Duration,
+    saturation: Arc<Saturation>,
+    stats: Arc<StatsCounters>,
+}
+
+impl WorkerHandle {
+    fn commands(&self) -> &mpsc::SyncSender<Command> {
+        self.commands.as_ref().expect("should be some")
+    }
+}
+
+impl Drop for WorkerHandle {
+    fn drop(&mut self) {
+        // The worker flushes and stops once the queue is disconnected
+        drop(self.commands.take());
+        if let Some(thread) = self.thread.take() {
+            join_until(thread, Instant::now() + self.shutdown_timeout);
+        }
+    }
+}
+
+/// Workers of a sender and its clones
+struct Workers(Vec<WorkerHandle>);
+
+impl std::ops::Deref for Workers {
+    type Target = [WorkerHandle];
+
+    fn deref(&self) -> &[WorkerHandle] {
+        &self.0
+    }
+}
+
+impl Drop for Workers {
+    /// Stops every worker before waiting for any of them, so the shutdown timeout bounds the
+    /// drop rather than each worker in turn
+    fn drop(&mut self) {
+        for worker in self.0.iter_mut() {
+            drop(worker.commands.take());
+        }
+        let timeout = self.0.iter().map(|w| w.shutdown_timeout).max();
+        let deadline = Instant::now() + timeout.unwrap_or_default();
+        for worker in self.0.iter_mut() {
+            if let Some(thread) = worker.thread.take() {
+                join_until(thread, deadline);
+            }
+        }
+    }
+}
+
+/// Joins `thread` if it finishes before `deadline`, leaves it running otherwise
+fn join_until(thread: JoinHandle<Result<()>>, deadline: Instant) {
+    while !thread.is_finished() && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    if thread.is_finished() {
+        let _ = thread.join();
+    }
+}
+
+/// Queues `cmd`, retrying while the queue is full until `deadline`. Returns whether it was
+/// queued.
+fn send_until(commands: &mpsc::SyncSender<Command>, mut cmd: Command, deadline: Instant) -> bool {
+    loop {
+        match commands.try_send(cmd) {
+            Ok(()) => return true,
+            Err(TrySendError::Full(returned)) if Instant::now() < deadline => {
+                cmd = returned;
+                std::thread::sleep(Duration::from_millis(5));
+            }
+            Err(_) => return false,
+        }
+    }
 }
 
 impl BufferedSender {
@@ -26,116 +291,1275 @@ impl BufferedSender {
         error_period: Duration,
         log_queue_len: usize,
     ) -> Self {
-        let sender = BufferedSenderThread::new(
-            sender,
+        BufferedSenderBuilder {
             buffer_size,
             buffer_lifetime,
             ignore_buffer,
             error_period,
             log_queue_len,
+            ..Default::default()
+        }
+        .build(sender)
+    }
+
+    pub fn builder() -> BufferedSenderBuilder {
+        BufferedSenderBuilder::default()
+    }
+
+    /// Submits `records` to the worker as a single queue message, reducing channel
+    /// contention for bulk producers. When buffering is disabled they are sent downstream
+    /// as one batch.
+    pub fn send_records(&self, records: Vec<LogStashRecord>) -> Result<()> {
+        Sender::send_batch(self, records)
+    }
+
+    /// Creates a reference which doesn't keep the workers running
+    pub fn downgrade(&self) -> WeakBufferedSender {
+        WeakBufferedSender {
+            workers: Arc::downgrade(&self.workers),
+            dispatch: self.dispatch,
+            next_worker: self.next_worker.clone(),
+            in_flight: self.in_flight.clone(),
+            paused: self.paused.clone(),
+            level_filter: self.level_filter,
+            target_level_filters: self.target_level_filters.clone(),
+            #[cfg(feature = "bytes")]
+            serialize_early: self.serialize_early,
+        }
+    }
+
+    /// Current values of the worker counters, summed over all workers
+    pub fn stats(&self) -> SenderStats {
+        self.workers.iter().map(|w| w.stats.snapshot()).fold(
+            SenderStats::default(),
+            |total, stats| SenderStats {
+                batch_size: total.batch_size.max(stats.batch_size),
+                dropped: total.dropped + stats.dropped,
+                buffered_bytes: total.buffered_bytes + stats.buffered_bytes,
+                sent: total.sent + stats.sent,
+                buffered: total.buffered + stats.buffered,
+                last_send_latency: total.last_send_latency.max(stats.last_send_latency),
+                last_batch_id: total.last_batch_id.max(stats.last_batch_id),
+                latency: total.latency.merge(stats.latency),
+            },
+        )
+    }
+
+    /// Estimated JSON sizes of the records by target, largest first. Each worker keeps the
+    /// targets with the largest records, see
+    /// [`with_max_tracked_targets`](BufferedSenderBuilder::with_max_tracked_targets).
+    pub fn target_sizes(&self) -> Vec<(String, TargetSize)> {
+        let mut sizes: HashMap<String, TargetSize> = HashMap::new();
+        for worker in self.workers.iter() {
+            for (target, size) in worker.stats.target_sizes() {
+                let entry = sizes.entry(target).or_default();
+                *entry = entry.merge(size);
+            }
+        }
+        let mut sizes: Vec<_> = sizes.into_iter().collect();
+        sizes.sort_by(|(a, a_size), (b, b_size)| {
+            b_size
+                .max_bytes
+                .cmp(&a_size.max_bytes)
+                .then_with(|| a.cmp(b))
+        });
+        sizes
+    }
+
+    /// Sets the counts of the latency histogram of [`stats`](Self::stats) back to 0
+    pub fn reset_latency_histogram(&self) {
+        self.workers.iter().for_each(|w| w.stats.reset_latency());
+    }
+
+    /// Flushes every worker and waits up to `timeout` for all of them to finish
+    pub fn flush_and_wait(&self, timeout: Duration) -> Result<()> {
+        self.round_trip(Command::FlushAck, timeout, "flush")
+    }
+
+    /// Makes every worker establish the connection of its sender, e.g. to fail at startup
+    /// if the destination is unreachable, and waits up to `timeout` for the results
+    pub fn ensure_connected(&self, timeout: Duration) -> Result<()> {
+        self.round_trip(Command::Connect, timeout, "connection")
+    }
+
+    /// Sends the command built by `cmd` to every worker and waits up to `timeout` for all
+    /// of them to report back
+    fn round_trip(
+        &self,
+        cmd: impl Fn(mpsc::Sender<Result<()>>) -> Command,
+        timeout: Duration,
+        what: &str,
+    ) -> Result<()> {
+        let deadline = Instant::now() + timeout;
+        let (reply, results) = mpsc::channel();
+        for worker in 0..self.workers.len() {
+            self.try_send(worker, cmd(reply.clone()), true)?;
+        }
+        let mut errors = vec![];
+        for _ in 0..self.workers.len() {
+            match results.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => errors.push(err),
+                Err(_) => {
+                    errors.push(Error::Timeout(format!(
+                        "{} was not confirmed in time",
+                        what
+                    )));
+                    break;
+                }
+            }
+        }
+        combine(errors)
+    }
+
+    /// Stops the workers without sending anything more and returns the records they still
+    /// hold, buffered or queued, e.g. to hand them to a replacement sender. The records are
+    /// returned as received, before the redactor runs. Clones of this sender fail to send
+    /// afterwards.
+    ///
+    /// Waits up to the [shutdown timeout](BufferedSenderBuilder::with_shutdown_timeout) for
+    /// all workers together. A worker that doesn't hand its records back in time keeps them
+    /// and delivers them, or writes them to the persist file, when it stops.
+    pub fn drain_pending(self) -> Vec<LogStashRecord> {
+        let timeout = self.workers.iter().map(|w| w.shutdown_timeout).max();
+        let deadline = Instant::now() + timeout.unwrap_or_default();
+        // Every worker is asked before waiting for any, so they drain concurrently
+        let replies: Vec<_> = self
+            .workers
+            .iter()
+            .filter_map(|worker| {
+                let (reply, drained) = mpsc::channel();
+                // A stopped worker holds no records
+                send_until(worker.commands(), Command::Drain(reply), deadline).then_some(drained)
+            })
+            .collect();
+        let mut records = vec![];
+        for drained in replies {
+            match drained.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(drained) => records.extend(drained),
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    println!("logstash logger error: worker records were not drained in time");
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => {}
+            }
+        }
+        records
+    }
+
+    /// Stops the workers from calling the wrapped senders, e.g. during maintenance of the
+    /// destination. Records keep being buffered within the memory budget, see
+    /// [`with_max_buffer_bytes`](BufferedSenderBuilder::with_max_buffer_bytes), without
+    /// connection attempts or errors.
+    ///
+    /// [`flush_and_wait`](Self::flush_and_wait) fails with [`Error::Paused`] until the sender
+    /// is resumed.
+    pub fn pause(&self) -> Result<()> {
+        // The workers check the flag before every delivery
+        self.paused.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Resumes sending, the records buffered meanwhile are sent in batches of the buffer size
+    pub fn resume(&self) -> Result<()> {
+        self.paused.store(false, Ordering::Relaxed);
+        for worker in self.workers.iter() {
+            // A worker with a full queue sends the held records along with the queued ones
+            match worker.commands().try_send(Command::Resume) {
+                Ok(()) | Err(TrySendError::Full(_)) => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// Level filter applied to `target`, using the longest matching target prefix
+    fn level_filter_for(&self, target: &str) -> LevelFilter {
+        self.target_level_filters
+            .iter()
+            .find(|(prefix, _)| target.starts_with(prefix.as_str()))
+            .map(|(_, filter)| *filter)
+            .unwrap_or(self.level_filter)
+    }
+
+    fn is_saturated(&self) -> bool {
+        self.workers.iter().any(|w| w.saturation.is_saturated())
+    }
+
+    /// Index of the worker receiving records of `target`
+    fn worker_for(&self, target: &str) -> usize {
+        let count = self.workers.len();
+        if count == 1 {
+            return 0;
+        }
+        match self.dispatch {
+            WorkerDispatch::RoundRobin => self.next_worker.fetch_add(1, Ordering::Relaxed) % count,
+            WorkerDispatch::StickyByTarget => {
+                let mut hasher = DefaultHasher::new();
+                target.hash(&mut hasher);
+                (hasher.finish() % count as u64) as usize
+            }
+        }
+    }
+
+    fn try_send(&self, worker: usize, cmd: Command, log_full: bool) -> Result<()> {
+        let worker = &self.workers[worker];
+        let result = worker.commands().try_send(cmd);
+        if let Err(TrySendError::Full(..)) = &result {
+            worker.saturation.mark_full();
+        }
+        process_result(result, log_full)
+    }
+
+    /// Command queuing `event`, serialized right away in serialize-early mode
+    fn send_command(&self, event: LogStashRecord) -> Result<Command> {
+        #[cfg(feature = "bytes")]
+        if self.serialize_early {
+            let frame = event.to_json_bytes_with_newline()?;
+            return Ok(Command::SendRaw(frame, event.level));
+        }
+        Ok(Command::Send(event))
+    }
+
+    /// Command queuing `events` as one message, serialized right away in serialize-early mode
+    fn send_batch_command(&self, events: Vec<LogStashRecord>) -> Result<Command> {
+        #[cfg(feature = "bytes")]
+        if self.serialize_early {
+            let frames = events
+                .iter()
+                .map(|event| Ok((event.to_json_bytes_with_newline()?, event.level)))
+                .collect::<Result<_>>()?;
+            return Ok(Command::SendRawBatch(frames));
+        }
+        Ok(Command::SendBatch(events))
+    }
+
+    /// Queues `count` records within the in-flight limit
+    fn try_send_records(
+        &self,
+        worker: usize,
+        cmd: Command,
+        count: usize,
+        log_full: bool,
+    ) -> Result<()> {
+        if !self.in_flight.acquire(count) {
+            self.workers[worker].stats.add_dropped(count);
+            return process_result(Err(TrySendError::Full(())), log_full);
+        }
+        let result = self.try_send(worker, cmd, log_full);
+        if result.is_err() {
+            self.in_flight.release(count);
+        }
+        result
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BufferedSenderBuilder {
+    buffer_size: Option<usize>,
+    buffer_lifetime: Option<Duration>,
+    level_policies: HashMap<Level, BufferPolicy>,
+    ignore_buffer: Level,
+    target_overrides: Vec<(String, Level)>,
+    error_period: Duration,
+    log_queue_len: usize,
+    pre_connect: bool,
+    level_filter: LevelFilter,
+    target_level_filters: Vec<(String, LevelFilter)>,
+    saturation_timeout: Option<Duration>,
+    ping_interval: Option<Duration>,
+    heartbeat_interval: Option<Duration>,
+    self_metrics_interval: Option<Duration>,
+    diagnostics: bool,
+    slow_send_threshold: Option<Duration>,
+    adaptive_batching: Option<AdaptiveBatching>,
+    max_event_bytes: Option<usize>,
+    oversized_policy: OversizedPolicy,
+    oversized_diagnostics: bool,
+    hostname: Option<HostnameCache>,
+    max_buffer_bytes: usize,
+    flush_bytes: Option<usize>,
+    flush_on_level: Option<Level>,
+    overflow_policy: OverflowPolicy,
+    max_tracked_targets: usize,
+    sub_ms_seq: bool,
+    workers: usize,
+    worker_dispatch: WorkerDispatch,
+    shutdown_timeout: Duration,
+    max_in_flight: Option<usize>,
+    record_ttl: Option<Duration>,
+    partial_write_policy: PartialWritePolicy,
+    ordered: bool,
+    coalesce_repeats: Option<u64>,
+    redactor: Option<Redactor>,
+    persist_on_shutdown: Option<PathBuf>,
+    #[cfg(feature = "pool")]
+    record_pool: Option<Arc<RecordPool>>,
+    #[cfg(feature = "bytes")]
+    serialize_early: bool,
+    #[cfg(feature = "metrics")]
+    metrics_prefix: Option<String>,
+}
+
+impl Default for BufferedSenderBuilder {
+    fn default() -> Self {
+        Self {
+            buffer_size: Some(100),
+            buffer_lifetime: Some(Duration::from_secs(1)),
+            level_policies: HashMap::new(),
+            ignore_buffer: Level::Error,
+            target_overrides: vec![],
+            error_period: Duration::from_secs(10),
+            log_queue_len: 1000,
+            pre_connect: false,
+            level_filter: LevelFilter::Trace,
+            target_level_filters: vec![],
+            saturation_timeout: None,
+            ping_interval: None,
+            heartbeat_interval: None,
+            self_metrics_interval: None,
+            diagnostics: true,
+            slow_send_threshold: None,
+            adaptive_batching: None,
+            max_event_bytes: None,
+            oversized_policy: OversizedPolicy::SendAnyway,
+            oversized_diagnostics: false,
+            hostname: None,
+            max_buffer_bytes: 64 * 1024 * 1024,
+            flush_bytes: None,
+            flush_on_level: None,
+            overflow_policy: OverflowPolicy::DropOldest,
+            max_tracked_targets: 32,
+            sub_ms_seq: false,
+            workers: 1,
+            worker_dispatch: WorkerDispatch::RoundRobin,
+            shutdown_timeout: Duration::from_secs(2),
+            max_in_flight: None,
+            record_ttl: None,
+            partial_write_policy: Default::default(),
+            ordered: false,
+            coalesce_repeats: None,
+            redactor: None,
+            persist_on_shutdown: None,
+            #[cfg(feature = "pool")]
+            record_pool: None,
+            #[cfg(feature = "bytes")]
+            serialize_early: false,
+            #[cfg(feature = "metrics")]
+            metrics_prefix: None,
+        }
+    }
+}
+
+impl BufferedSenderBuilder {
+    /// Sets the upperbound limit on the number of records that can be placed in the buffer.
+    /// `None` disables buffering.
+    pub fn with_buffer_size(mut self, buffer_size: Option<usize>) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    /// Sets the maximum lifetime of the buffer before send it to the remote server.
+    pub fn with_buffer_lifetime(mut self, buffer_lifetime: Option<Duration>) -> Self {
+        self.buffer_lifetime = buffer_lifetime;
+        self
+    }
+
+    /// Overrides the buffer size and lifetime for records of `level`, e.g. to flush warnings
+    /// sooner than debug records. All levels share one buffer to keep the records in order,
+    /// so it is flushed as soon as any buffered record reaches its lifetime or size, taking
+    /// along the records of other levels buffered before.
+    pub fn with_level_buffer_policy(mut self, level: Level, policy: BufferPolicy) -> Self {
+        self.level_policies.insert(level, policy);
+        self
+    }
+
+    /// Flush the whole buffer as soon as a record of `level` or more severe arrives, sending
+    /// the records logged before it along, e.g. the context of an error. The record itself
+    /// is buffered or not as usual.
+    pub fn with_flush_on_level(mut self, level: Option<Level>) -> Self {
+        self.flush_on_level = level;
+        self
+    }
+
+    /// Records with level greater or equal to this one are sent without buffering.
+    pub fn with_ignore_buffer_level(mut self, level: Level) -> Self {
+        self.ignore_buffer = level;
+        self
+    }
+
+    /// Overrides the ignore buffer level for targets starting with `target_prefix`.
+    /// The longest matching prefix wins.
+    pub fn with_target_override(mut self, target_prefix: impl Into<String>, level: Level) -> Self {
+        self.target_overrides.push((target_prefix.into(), level));
+        self.target_overrides
+            .sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+        self
+    }
+
+    /// Print period for internal logstash errors.
+    pub fn with_error_period(mut self, error_period: Duration) -> Self {
+        self.error_period = error_period;
+        self
+    }
+
+    /// Maximum length of log message queue
+    pub fn with_log_queue_len(mut self, log_queue_len: usize) -> Self {
+        self.log_queue_len = log_queue_len;
+        self
+    }
+
+    /// Connect in the background right after start. Records are buffered until the
+    /// connection is established, and `flush_and_wait` waits for the connection attempt.
+    pub fn with_pre_connect(mut self, pre_connect: bool) -> Self {
+        self.pre_connect = pre_connect;
+        self
+    }
+
+    /// Maximum level reported as enabled by `log::Log::enabled`.
+    pub fn with_level_filter(mut self, level_filter: LevelFilter) -> Self {
+        self.level_filter = level_filter;
+        self
+    }
+
+    /// Overrides the level filter for targets starting with `target_prefix`.
+    /// The longest matching prefix wins.
+    pub fn with_target_level_filter(
+        mut self,
+        target_prefix: impl Into<String>,
+        level_filter: LevelFilter,
+    ) -> Self {
+        self.target_level_filters
+            .push((target_prefix.into(), level_filter));
+        self.target_level_filters
+            .sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+        self
+    }
+
+    /// Report records as disabled once the queue has been full for longer than `timeout`.
+    /// They are enabled again after the worker drains the queue.
+    pub fn with_saturation_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.saturation_timeout = timeout;
+        self
+    }
+
+    /// Periodically check that the connection is alive while idle.
+    pub fn with_ping_interval(mut self, ping_interval: Option<Duration>) -> Self {
+        self.ping_interval = ping_interval;
+        self
+    }
+
+    /// Send a record with target [`HEARTBEAT_TARGET`] from every worker at this interval,
+    /// with the worker uptime, records sent since the previous heartbeat and buffer fill.
+    /// Heartbeats go straight to the downstream sender.
+    pub fn with_heartbeat_interval(mut self, heartbeat_interval: Option<Duration>) -> Self {
+        self.heartbeat_interval = heartbeat_interval;
+        self
+    }
+
+    /// Send a record with target [`SELF_METRICS_TARGET`] and the `_self_metrics` field set
+    /// from every worker at this interval, with its counters of sent and dropped records,
+    /// reconnects of the downstream sender and buffer depth since the worker started.
+    pub fn with_self_metrics_interval(mut self, self_metrics_interval: Option<Duration>) -> Self {
+        self.self_metrics_interval = self_metrics_interval;
+        self
+    }
+
+    /// Send a self-diagnostic record with target `logstash_rs::internal` after the sender
+    /// recovers from errors.
+    pub fn with_diagnostics(mut self, diagnostics: bool) -> Self {
+        self.diagnostics = diagnostics;
+        self
+    }
+
+    /// Send a self-diagnostic warning record when a single call to the wrapped sender, e.g.
+    /// a send or a flush, takes longer than `threshold`. Requires the diagnostics.
+    pub fn with_slow_send_threshold(mut self, threshold: Option<Duration>) -> Self {
+        self.slow_send_threshold = threshold;
+        self
+    }
+
+    /// Adjust the buffer size of every worker by the latency of its deliveries, starting
+    /// from the buffer size brought within the bounds. Has no effect without a buffer size.
+    pub fn with_adaptive_batching(mut self, adaptive_batching: Option<AdaptiveBatching>) -> Self {
+        self.adaptive_batching = adaptive_batching;
+        self
+    }
+
+    /// Applies `oversized_policy` to records whose estimated JSON size is above
+    /// `max_event_bytes`, counting them in the violations of their target. Records
+    /// serialized early are left out.
+    pub fn with_max_event_bytes(
+        mut self,
+        max_event_bytes: Option<usize>,
+        oversized_policy: OversizedPolicy,
+    ) -> Self {
+        self.max_event_bytes = max_event_bytes;
+        self.oversized_policy = oversized_policy;
+        self
+    }
+
+    /// Send a self-diagnostic warning record for a target with records above the maximum
+    /// event size, at most once per target per hour. Requires the diagnostics.
+    pub fn with_oversized_diagnostics(mut self, oversized_diagnostics: bool) -> Self {
+        self.oversized_diagnostics = oversized_diagnostics;
+        self
+    }
+
+    /// Number of targets with the largest records kept in the record sizes of every worker,
+    /// 32 by default. 0 turns the record sizes off.
+    pub fn with_max_tracked_targets(mut self, max_tracked_targets: usize) -> Self {
+        self.max_tracked_targets = max_tracked_targets;
+        self
+    }
+
+    /// Refresh `hostname` from the worker thread at the interval of its provider.
+    /// The interval is read at start and after every refresh.
+    pub fn with_hostname_refresh(mut self, hostname: HostnameCache) -> Self {
+        self.hostname = Some(hostname);
+        self
+    }
+
+    /// Upper bound on the estimated size of records held by the worker, 64MB by default.
+    pub fn with_max_buffer_bytes(mut self, max_buffer_bytes: usize) -> Self {
+        self.max_buffer_bytes = max_buffer_bytes;
+        self
+    }
+
+    /// Flushes the buffer before the estimated size of its records would exceed `flush_bytes`,
+    /// bounding batches of large records by size as well as by count. Unlike the maximum
+    /// buffer bytes no record is dropped, except while connecting or paused.
+    pub fn with_flush_bytes(mut self, flush_bytes: Option<usize>) -> Self {
+        self.flush_bytes = flush_bytes;
+        self
+    }
+
+    /// Sets what to drop once the buffer exceeds its memory budget.
+    pub fn with_overflow_policy(mut self, overflow_policy: OverflowPolicy) -> Self {
+        self.overflow_policy = overflow_policy;
+        self
+    }
+
+    /// Adds a `sub_ms_seq` field to records of a batch sharing the same millisecond timestamp,
+    /// so they can be ordered after the timestamp is truncated to milliseconds.
+    pub fn with_sub_ms_seq(mut self, sub_ms_seq: bool) -> Self {
+        self.sub_ms_seq = sub_ms_seq;
+        self
+    }
+
+    /// Number of worker threads spawned by [`build_with_factory`](Self::build_with_factory).
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// Sets how records are distributed between workers.
+    pub fn with_worker_dispatch(mut self, worker_dispatch: WorkerDispatch) -> Self {
+        self.worker_dispatch = worker_dispatch;
+        self
+    }
+
+    /// Maximum time the drop of the last handle waits for the workers to flush and stop, and
+    /// [`BufferedSender::drain_pending`] waits for them to hand back their records.
+    pub fn with_shutdown_timeout(mut self, shutdown_timeout: Duration) -> Self {
+        self.shutdown_timeout = shutdown_timeout;
+        self
+    }
+
+    /// Upper bound on records queued for and buffered in all workers together. Above it
+    /// `DropNewest` rejects new records right away, while `DropOldest` makes the workers
+    /// drop the oldest records, buffered ones first, as they receive new ones.
+    pub fn with_max_in_flight(mut self, max_in_flight: Option<usize>) -> Self {
+        self.max_in_flight = max_in_flight;
+        self
+    }
+
+    /// Drop records older than `record_ttl`, by their timestamp, instead of sending them
+    /// with a batch, e.g. the backlog buffered while the destination was unreachable.
+    /// Records serialized early are never dropped.
+    pub fn with_record_ttl(mut self, record_ttl: Option<Duration>) -> Self {
+        self.record_ttl = record_ttl;
+        self
+    }
+
+    /// Sets what happens to records of a batch the sender failed to write completely, see
+    /// [`Error::PartialWrite`].
+    pub fn with_partial_write_policy(mut self, partial_write_policy: PartialWritePolicy) -> Self {
+        self.partial_write_policy = partial_write_policy;
+        self
+    }
+
+    /// Strict ordering: a worker delivers records in the order it received them. Records
+    /// above the ignore buffer level go through the buffer and only trigger a flush, and a
+    /// batch the sender failed to deliver is kept in front of the buffer, blocking later
+    /// batches until it is delivered or dropped by the buffer limits. Requires a buffer
+    /// size, records of different workers are not ordered relative to each other.
+    pub fn with_ordered(mut self, ordered: bool) -> Self {
+        self.ordered = ordered;
+        self
+    }
+
+    /// Count a record with the level, target and message of the last buffered one as its
+    /// repeat instead of buffering it, up to `cap` records per buffered one. The buffered
+    /// record gets a `repeat_count` field once it stands for more than one record.
+    pub fn with_coalesce_repeats(mut self, cap: Option<u64>) -> Self {
+        self.coalesce_repeats = cap;
+        self
+    }
+
+    /// Run `redactor` over every record in the worker right before it is sent, e.g. to mask
+    /// or remove sensitive fields without slowing down the logging thread.
+    pub fn with_redactor(
+        mut self,
+        redactor: impl Fn(&mut LogStashRecord) + Send + Sync + 'static,
+    ) -> Self {
+        self.redactor = Some(Redactor(Arc::new(redactor)));
+        self
+    }
+
+    /// Write the records left in the buffer at shutdown to `path` as JSON lines before the
+    /// last flush, and remove the file once they are delivered. A file left by a flush that
+    /// failed or did not finish in time is read, removed and sent on the next start, before
+    /// any other record, with a `replayed: true` field. Records delivered before the flush
+    /// failed are sent again. Lines that fail to parse are dropped. Worker `i > 0` of
+    /// [`build_with_factory`](Self::build_with_factory) uses `path` suffixed with `.{i}`.
+    pub fn with_persist_on_shutdown(mut self, path: Option<PathBuf>) -> Self {
+        self.persist_on_shutdown = path;
+        self
+    }
+
+    /// Return records to `pool` once they are sent in batches. Batches are passed to the
+    /// sender by reference, so it should override [`Sender::send_batch_ref`] to avoid copies.
+    /// Records sent on their own, e.g. above the ignore buffer level, still go through
+    /// [`Sender::send`] and are not returned.
+    #[cfg(feature = "pool")]
+    pub fn with_record_pool(mut self, pool: Arc<RecordPool>) -> Self {
+        self.record_pool = Some(pool);
+        self
+    }
+
+    /// Serialize records on the calling thread in `send`, leaving the workers to buffer and
+    /// write bytes only. The sender must implement [`Sender::send_raw`], as [`TcpSender`]
+    /// does. Target overrides of the ignore buffer level, `sub_ms_seq`, the redactor and
+    /// the record pool don't apply to serialized records.
+    #[cfg(feature = "bytes")]
+    pub fn with_serialize_early(mut self, serialize_early: bool) -> Self {
+        self.serialize_early = serialize_early;
+        self
+    }
+
+    /// Publish the [`SenderStats`] counters through the `metrics` facade, with names starting
+    /// with `prefix`
+    #[cfg(feature = "metrics")]
+    pub fn with_metrics_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.metrics_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Spawns the worker thread owning `sender`.
+    pub fn build<S: Sender>(self, sender: S) -> BufferedSender {
+        let in_flight = Arc::new(InFlight::new(self.max_in_flight, self.overflow_policy));
+        let paused = Arc::new(AtomicBool::new(false));
+        let worker = self.spawn_worker(self.clone(), sender, in_flight.clone(), paused.clone());
+        self.into_sender(vec![worker], in_flight, paused)
+    }
+
+    /// Spawns [`with_workers`](Self::with_workers) worker threads, each owning its own
+    /// sender created by `factory`.
+    pub fn build_with_factory<S: Sender>(self, factory: impl Fn() -> S) -> BufferedSender {
+        let in_flight = Arc::new(InFlight::new(self.max_in_flight, self.overflow_policy));
+        let paused = Arc::new(AtomicBool::new(false));
+        let workers = (0..self.workers)
+            .map(|i| {
+                let mut options = self.clone();
+                // The hostname cache is shared, one worker is enough to refresh it
+                if i > 0 {
+                    options.hostname = None;
+                    options.persist_on_shutdown =
+                        options.persist_on_shutdown.map(|path| worker_path(path, i));
+                }
+                self.spawn_worker(options, factory(), in_flight.clone(), paused.clone())
+            })
+            .collect();
+        self.into_sender(workers, in_flight, paused)
+    }
+
+    fn spawn_worker<S: Sender>(
+        &self,
+        options: BufferedSenderBuilder,
+        sender: S,
+        in_flight: Arc<InFlight>,
+        paused: Arc<AtomicBool>,
+    ) -> WorkerHandle {
+        let saturation = Arc::new(Saturation::new(self.saturation_timeout));
+        let stats = Arc::new(self.stats_counters());
+        let (commands, thread) = BufferedSenderThread::new(
+            sender,
+            options,
+            saturation.clone(),
+            stats.clone(),
+            in_flight,
+            paused,
         )
         .run();
-        Self { sender }
+        WorkerHandle {
+            commands: Some(commands),
+            thread: Some(thread),
+            shutdown_timeout: self.shutdown_timeout,
+            saturation,
+            stats,
+        }
+    }
+
+    fn stats_counters(&self) -> StatsCounters {
+        #[cfg(feature = "metrics")]
+        if let Some(prefix) = &self.metrics_prefix {
+            return StatsCounters::with_metrics(prefix)
+                .with_tracked_targets(self.max_tracked_targets);
+        }
+        StatsCounters::default().with_tracked_targets(self.max_tracked_targets)
+    }
+
+    fn into_sender(
+        self,
+        workers: Vec<WorkerHandle>,
+        in_flight: Arc<InFlight>,
+        paused: Arc<AtomicBool>,
+    ) -> BufferedSender {
+        let sender = BufferedSender {
+            workers: Arc::new(Workers(workers)),
+            dispatch: self.worker_dispatch,
+            next_worker: Arc::new(AtomicUsize::new(0)),
+            in_flight,
+            paused,
+            level_filter: self.level_filter,
+            target_level_filters: self.target_level_filters,
+            #[cfg(feature = "bytes")]
+            serialize_early: self.serialize_early,
+        };
+        registry().push(sender.downgrade());
+        sender
+    }
+}
+
+/// Tracks how long the command queue has been full
+#[derive(Debug)]
+struct Saturation {
+    start: Instant,
+    timeout: Option<Duration>,
+    /// Milliseconds since `start` when the queue was first seen full plus one, 0 if not full
+    full_since: AtomicU64,
+    saturated: AtomicBool,
+}
+
+impl Saturation {
+    fn new(timeout: Option<Duration>) -> Self {
+        Self {
+            start: Instant::now(),
+            timeout,
+            full_since: AtomicU64::new(0),
+            saturated: AtomicBool::new(false),
+        }
+    }
+
+    fn mark_full(&self) {
+        if let Some(timeout) = self.timeout {
+            let now = self.start.elapsed().as_millis() as u64 + 1;
+            let since =
+                match self
+                    .full_since
+                    .compare_exchange(0, now, Ordering::Relaxed, Ordering::Relaxed)
+                {
+                    Ok(_) => now,
+                    Err(since) => since,
+                };
+            if now - since > timeout.as_millis() as u64 {
+                self.saturated.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn mark_drained(&self) {
+        if self.full_since.load(Ordering::Relaxed) != 0 {
+            self.full_since.store(0, Ordering::Relaxed);
+            self.saturated.store(false, Ordering::Relaxed);
+        }
+    }
+
+    fn is_saturated(&self) -> bool {
+        self.saturated.load(Ordering::Relaxed)
+    }
+}
+
+/// Records queued for and buffered in the workers, not counted without a limit
+#[derive(Debug)]
+struct InFlight {
+    count: AtomicUsize,
+    limit: Option<usize>,
+    policy: OverflowPolicy,
+}
+
+impl InFlight {
+    fn new(limit: Option<usize>, policy: OverflowPolicy) -> Self {
+        Self {
+            count: AtomicUsize::new(0),
+            limit,
+            policy,
+        }
+    }
+
+    /// Counts `count` new records, fails if `DropNewest` rejects them
+    fn acquire(&self, count: usize) -> bool {
+        let limit = match self.limit {
+            Some(limit) => limit,
+            None => return true,
+        };
+        if self.policy == OverflowPolicy::DropOldest {
+            self.add(count);
+            return true;
+        }
+        self.count
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |current| {
+                Some(current + count).filter(|&total| total <= limit)
+            })
+            .is_ok()
+    }
+
+    /// Counts `count` records regardless of the limit
+    fn add(&self, count: usize) {
+        if self.limit.is_some() {
+            self.count.fetch_add(count, Ordering::Relaxed);
+        }
+    }
+
+    fn release(&self, count: usize) {
+        if self.limit.is_some() && count > 0 {
+            self.count.fetch_sub(count, Ordering::Relaxed);
+        }
+    }
+
+    /// Number of records to drop under `DropOldest` to get back to the limit, counting
+    /// `pending` records received but not yet buffered or sent
+    fn excess(&self, pending: usize) -> usize {
+        if self.policy != OverflowPolicy::DropOldest {
+            return 0;
+        }
+        self.limit.map_or(0, |limit| {
+            (self.count.load(Ordering::Relaxed) + pending).saturating_sub(limit)
+        })
     }
 }
 
 impl Sender for BufferedSender {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= self.level_filter_for(metadata.target())
+    }
+
     fn send(&self, event: LogStashRecord) -> Result<()> {
         let important = event.level <= Level::Warn;
-        let result = self.sender.try_send(Command::Send(event));
-        process_result(result, important)
+        let worker = self.worker_for(&event.target);
+        let cmd = self.send_command(event)?;
+        self.try_send_records(worker, cmd, 1, important)
     }
 
     fn send_batch(&self, events: Vec<LogStashRecord>) -> Result<()> {
-        let important = events.iter().any(|e| e.level <= Level::Warn);
-        let result = self.sender.try_send(Command::SendBatch(events));
-        process_result(result, important)
+        if self.workers.len() == 1 || self.dispatch == WorkerDispatch::RoundRobin {
+            let important = events.iter().any(|e| e.level <= Level::Warn);
+            let worker = self.worker_for("");
+            let count = events.len();
+            let cmd = self.send_batch_command(events)?;
+            return self.try_send_records(worker, cmd, count, important);
+        }
+        let mut batches = vec![vec![]; self.workers.len()];
+        for event in events {
+            batches[self.worker_for(&event.target)].push(event);
+        }
+        let mut result = Ok(());
+        for (worker, batch) in batches.into_iter().enumerate() {
+            if !batch.is_empty() {
+                let important = batch.iter().any(|e| e.level <= Level::Warn);
+                let count = batch.len();
+                result = result.and(
+                    self.send_batch_command(batch)
+                        .and_then(|cmd| self.try_send_records(worker, cmd, count, important)),
+                );
+            }
+        }
+        result
     }
 
     fn flush(&self) -> Result<()> {
-        let result = self.sender.try_send(Command::Flush);
-        process_result(result, false)
+        let mut result = Ok(());
+        for worker in 0..self.workers.len() {
+            result = result.and(self.try_send(worker, Command::Flush, false));
+        }
+        result
+    }
+
+    fn flush_and_wait(&self, timeout: Duration) -> Result<()> {
+        BufferedSender::flush_and_wait(self, timeout)
+    }
+
+    fn capabilities(&self) -> SenderCapabilities {
+        SenderCapabilities {
+            supports_native_batch: true,
+            supports_flush: true,
+            is_async: true,
+        }
     }
 }
 
+/// Persist file of worker `i` when several share the same path
+fn worker_path(path: PathBuf, i: usize) -> PathBuf {
+    let mut path = path.into_os_string();
+    path.push(format!(".{}", i));
+    path.into()
+}
+
+/// Record back from its serialized form
+#[cfg(feature = "bytes")]
+fn parse_frame(frame: &Bytes) -> Option<LogStashRecord> {
+    LogStashRecord::from_json_str(std::str::from_utf8(frame).ok()?).ok()
+}
+
 fn process_result<T>(r: std::result::Result<(), TrySendError<T>>, log_full: bool) -> Result<()> {
     match r {
-        Err(TrySendError::Disconnected(..)) => {
-            Err(Error::SenderThreadStopped(r.unwrap_err().to_string()))
-        }
-        Err(TrySendError::Full(..)) if log_full => Err(Error::BufferFull()),
-        _ => Ok(()),
+        Err(TrySendError::Full(..)) if !log_full => Ok(()),
+        r => Ok(r?),
     }
 }
 
 #[derive(Debug)]
 struct BufferedSenderThread<S: Sender> {
-    sender: S,
-    buffer: Vec<LogStashRecord>,
+    sender: Arc<S>,
+    buffer: RecordBuffer,
     buffer_size: Option<usize>,
     buffer_lifetime: Option<Duration>,
+    level_policies: HashMap<Level, BufferPolicy>,
     deadline: Option<Instant>,
+    /// Smallest buffer size applying to the buffered records
+    flush_size: Option<usize>,
+    flush_bytes: Option<usize>,
+    flush_on_level: Option<Level>,
     ignore_buffer: Level,
+    target_overrides: Vec<(String, Level)>,
     error_period: Duration,
     log_queue_len: usize,
+    pre_connect: bool,
+    connecting: bool,
+    /// Replies of flushes requested while connecting, answered once connected
+    connecting_flushes: Vec<mpsc::Sender<Result<()>>>,
+    /// Set by [`BufferedSender::pause`], shared by the workers
+    paused: Arc<AtomicBool>,
+    saturation: Arc<Saturation>,
+    ping_interval: Option<Duration>,
+    next_ping: Option<Instant>,
+    heartbeat_interval: Option<Duration>,
+    next_heartbeat: Option<Instant>,
+    self_metrics_interval: Option<Duration>,
+    next_self_metrics: Option<Instant>,
+    started: Instant,
+    /// Records sent when the previous heartbeat was sent
+    sent_at_heartbeat: u64,
+    diagnostics: Diagnostics,
+    slow_send_threshold: Option<Duration>,
+    adaptive_batching: Option<AdaptiveBatching>,
+    max_event_bytes: Option<usize>,
+    oversized_policy: OversizedPolicy,
+    oversized_diagnostics: bool,
+    hostname: Option<HostnameCache>,
+    next_hostname_refresh: Option<Instant>,
+    stats: Arc<StatsCounters>,
+    in_flight: Arc<InFlight>,
+    /// Records serialized by the callers in serialize-early mode
+    #[cfg(feature = "bytes")]
+    raw_buffer: RecordBuffer<Bytes>,
+    sub_ms_seq: bool,
+    record_ttl: Option<Duration>,
+    partial_write_policy: PartialWritePolicy,
+    ordered: bool,
+    coalesce_repeats: Option<u64>,
+    redactor: Option<Redactor>,
+    persist_on_shutdown: Option<PathBuf>,
+    #[cfg(feature = "pool")]
+    record_pool: Option<Arc<RecordPool>>,
 }
 
 impl<S: Sender> BufferedSenderThread<S> {
     fn new(
         sender: S,
-        buffer_size: Option<usize>,
-        buffer_lifetime: Option<Duration>,
-        ignore_buffer: Level,
-        error_period: Duration,
-        log_queue_len: usize,
+        options: BufferedSenderBuilder,
+        saturation: Arc<Saturation>,
+        stats: Arc<StatsCounters>,
+        in_flight: Arc<InFlight>,
+        paused: Arc<AtomicBool>,
     ) -> Self {
+        let diagnostics = Diagnostics::new(options.diagnostics, sender.endpoint());
+        let budget = MemoryBudget {
+            max_bytes: options.max_buffer_bytes,
+            policy: options.overflow_policy,
+        };
+        let buffer_size = match options.adaptive_batching {
+            Some(adaptive) => options.buffer_size.map(|size| adaptive.clamp(size)),
+            None => options.buffer_size,
+        };
+        stats.set_batch_size(buffer_size.unwrap_or(0));
         Self {
-            sender,
-            buffer: Vec::with_capacity(buffer_size.unwrap_or(0)),
+            sender: Arc::new(sender),
+            buffer: RecordBuffer::new(buffer_size.unwrap_or(0), budget),
+            #[cfg(feature = "bytes")]
+            raw_buffer: RecordBuffer::new(
+                if options.serialize_early {
+                    buffer_size.unwrap_or(0)
+                } else {
+                    0
+                },
+                budget,
+            ),
             buffer_size,
-            buffer_lifetime,
+            buffer_lifetime: options.buffer_lifetime,
+            level_policies: options.level_policies,
             deadline: None,
-            ignore_buffer,
-            error_period,
-            log_queue_len,
+            flush_size: None,
+            flush_bytes: options.flush_bytes,
+            flush_on_level: options.flush_on_level,
+            ignore_buffer: options.ignore_buffer,
+            target_overrides: options.target_overrides,
+            error_period: options.error_period,
+            log_queue_len: options.log_queue_len,
+            pre_connect: options.pre_connect,
+            connecting: false,
+            connecting_flushes: vec![],
+            paused,
+            saturation,
+            ping_interval: options.ping_interval,
+            next_ping: options
+                .ping_interval
+                .map(|interval| Instant::now() + interval),
+            heartbeat_interval: options.heartbeat_interval,
+            next_heartbeat: options
+                .heartbeat_interval
+                .map(|interval| Instant::now() + interval),
+            self_metrics_interval: options.self_metrics_interval,
+            next_self_metrics: options
+                .self_metrics_interval
+                .map(|interval| Instant::now() + interval),
+            started: Instant::now(),
+            sent_at_heartbeat: 0,
+            diagnostics,
+            slow_send_threshold: options.slow_send_threshold,
+            adaptive_batching: options.adaptive_batching,
+            max_event_bytes: options.max_event_bytes,
+            oversized_policy: options.oversized_policy,
+            oversized_diagnostics: options.oversized_diagnostics,
+            next_hostname_refresh: options
+                .hostname
+                .as_ref()
+                .and_then(|h| h.refresh_interval())
+                .map(|interval| Instant::now() + interval),
+            hostname: options.hostname,
+            stats,
+            in_flight,
+            sub_ms_seq: options.sub_ms_seq,
+            record_ttl: options.record_ttl,
+            partial_write_policy: options.partial_write_policy,
+            ordered: options.ordered,
+            coalesce_repeats: options.coalesce_repeats,
+            redactor: options.redactor,
+            persist_on_shutdown: options.persist_on_shutdown,
+            #[cfg(feature = "pool")]
+            record_pool: options.record_pool,
         }
     }
 
-    fn run(self) -> mpsc::SyncSender<Command> {
+    fn run(mut self) -> (mpsc::SyncSender<Command>, JoinHandle<Result<()>>) {
         let (sender, receiver) = mpsc::sync_channel(self.log_queue_len);
-        self.run_thread(receiver);
-        sender
+        if self.pre_connect {
+            self.connecting = true;
+            self.spawn_connect(sender.clone());
+        }
+        let thread = self.run_thread(receiver);
+        (sender, thread)
+    }
+
+    /// Connects on a separate thread so the worker keeps draining the queue meanwhile
+    fn spawn_connect(&self, commands: mpsc::SyncSender<Command>) {
+        let sender = self.sender.clone();
+        std::thread::spawn(move || {
+            let error = sender.connect().err().map(|err| err.to_string());
+            let _ = commands.send(Command::Connected(error));
+        });
     }
 
-    fn next_deadline(&self) -> Option<Instant> {
-        if self.buffer.is_empty() && self.buffer_size.is_some() {
-            return self.buffer_lifetime.map(|lt| Instant::now() + lt);
+    /// Moves the flush deadline and size closer as required by a buffered record of `level`
+    fn track_buffered(&mut self, level: Level) {
+        let policy = self.level_policies.get(&level).copied().unwrap_or_default();
+        self.track_buffered_policy(policy);
+    }
+
+    fn track_buffered_policy(&mut self, policy: BufferPolicy) {
+        if let Some(lifetime) = policy.lifetime.or(self.buffer_lifetime) {
+            let deadline = Instant::now() + lifetime;
+            self.deadline = Some(self.deadline.map_or(deadline, |d| d.min(deadline)));
         }
-        None
+        if let Some(size) = policy.size.or(self.buffer_size) {
+            self.flush_size = Some(self.flush_size.map_or(size, |s| s.min(size)));
+        }
+    }
+
+    /// Number of records buffered, serialized or not
+    fn buffered_len(&self) -> usize {
+        #[cfg(feature = "bytes")]
+        return self.buffer.len() + self.raw_buffer.len();
+        #[cfg(not(feature = "bytes"))]
+        self.buffer.len()
+    }
+
+    /// Estimated size of the buffered records
+    fn buffered_bytes(&self) -> usize {
+        #[cfg(feature = "bytes")]
+        return self.buffer.bytes() + self.raw_buffer.bytes();
+        #[cfg(not(feature = "bytes"))]
+        self.buffer.bytes()
+    }
+
+    fn wake_at(&self) -> Option<Instant> {
+        [
+            self.deadline,
+            self.next_ping,
+            self.next_heartbeat,
+            self.next_self_metrics,
+            self.next_hostname_refresh,
+        ]
+        .iter()
+        .flatten()
+        .min()
+        .copied()
     }
 
-    fn run_thread(mut self, receiver: mpsc::Receiver<Command>) {
+    fn on_timeout(&mut self) -> Result<()> {
+        let now = Instant::now();
+        let mut result = Ok(());
+        if self.next_ping.map(|p| p <= now).unwrap_or(false) {
+            self.next_ping = self.ping_interval.map(|interval| now + interval);
+            if !self.holds_records() {
+                result = self.deliver(|s| s.ping());
+            }
+        }
+        if self.next_heartbeat.map(|h| h <= now).unwrap_or(false) {
+            self.next_heartbeat = self.heartbeat_interval.map(|interval| now + interval);
+            if !self.holds_records() {
+                result = result.and(self.send_heartbeat(now));
+            }
+        }
+        if self.next_self_metrics.map(|m| m <= now).unwrap_or(false) {
+            self.next_self_metrics = self.self_metrics_interval.map(|interval| now + interval);
+            if !self.holds_records() {
+                result = result.and(self.send_self_metrics());
+            }
+        }
+        if self
+            .next_hostname_refresh
+            .map(|r| r <= now)
+            .unwrap_or(false)
+        {
+            if let Some(hostname) = &self.hostname {
+                hostname.refresh();
+                self.next_hostname_refresh = hostname.refresh_interval().map(|i| now + i);
+            }
+        }
+        if self.deadline.map(|d| d <= now).unwrap_or(false) {
+            result = result.and(self.flush());
+        }
+        result
+    }
+
+    fn run_thread(mut self, receiver: mpsc::Receiver<Command>) -> JoinHandle<Result<()>> {
         std::thread::spawn::<_, Result<()>>(move || {
             {
                 let mut last_error: Option<Instant> = None;
+                // Command read while collapsing flushes, handled before the queue
+                let mut next: Option<Command> = None;
+                if let Err(err) = self.replay_persisted() {
+                    println!("logstash logger error: {}", err);
+                }
                 loop {
-                    let cmd = match self.deadline {
-                        Some(deadline) => receiver
-                            .recv_timeout(deadline.saturating_duration_since(Instant::now())),
-                        None => receiver
-                            .recv()
-                            .map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+                    let cmd = match next.take().map_or_else(|| receiver.try_recv(), Ok) {
+                        Ok(cmd) => Ok(cmd),
+                        Err(TryRecvError::Disconnected) => {
+                            Err(mpsc::RecvTimeoutError::Disconnected)
+                        }
+                        Err(TryRecvError::Empty) => {
+                            self.saturation.mark_drained();
+                            match self.wake_at() {
+                                Some(wake_at) => receiver.recv_timeout(
+                                    wake_at.saturating_duration_since(Instant::now()),
+                                ),
+                                None => receiver
+                                    .recv()
+                                    .map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+                            }
+                        }
                     };
 
-                    if let Ok(Command::SendBatch(_) | Command::Send(_)) = &cmd {
-                        self.deadline = self.next_deadline();
+                    let received = match &cmd {
+                        Ok(Command::Send(_)) => Some(1),
+                        Ok(Command::SendBatch(events)) => Some(events.len()),
+                        #[cfg(feature = "bytes")]
+                        Ok(Command::SendRaw(..)) => Some(1),
+                        #[cfg(feature = "bytes")]
+                        Ok(Command::SendRawBatch(frames)) => Some(frames.len()),
+                        _ => None,
+                    };
+                    if let Some(count) = received {
+                        self.in_flight.release(count);
                     }
-                    let _ = match cmd {
-                        Ok(Command::Flush) | Err(mpsc::RecvTimeoutError::Timeout) => self.flush(),
+                    match cmd {
+                        Ok(Command::Flush) => {
+                            next = Self::skip_flushes(&receiver);
+                            self.flush()
+                        }
+                        Ok(Command::FlushAck(reply)) if self.connecting => {
+                            self.connecting_flushes.push(reply);
+                            Ok(())
+                        }
+                        Ok(Command::FlushAck(reply)) => {
+                            let _ = reply.send(self.flush_ack());
+                            Ok(())
+                        }
+                        Ok(Command::Connect(reply)) => {
+                            let _ = reply.send(self.deliver(|s| s.connect()));
+                            Ok(())
+                        }
+                        Err(mpsc::RecvTimeoutError::Timeout) => self.on_timeout(),
                         Ok(Command::Send(event)) => self.send(event),
                         Ok(Command::SendBatch(events)) => self.send_batch(events),
-                        Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                        #[cfg(feature = "bytes")]
+                        Ok(Command::SendRaw(frame, level)) => self.send_raw(frame, level),
+                        #[cfg(feature = "bytes")]
+                        Ok(Command::SendRawBatch(frames)) => self.send_raw_batch(frames),
+                        Ok(Command::Connected(error)) => self.connected(error),
+                        Ok(Command::Resume) => self.flush(),
+                        Ok(Command::Drain(reply)) => {
+                            // The caller gave up waiting, keep the records and stop as if
+                            // every handle was gone
+                            if let Err(mpsc::SendError(records)) = reply.send(self.drain(receiver))
+                            {
+                                self.restore_buffer(records);
+                                self.shutdown();
+                            }
+                            break;
+                        }
+                        Err(mpsc::RecvTimeoutError::Disconnected) => {
+                            // Every handle is gone, deliver what is left before stopping
+                            self.shutdown();
+                            break;
+                        }
                     }
+                    .map(|_| self.send_diagnostics())
                     .or_else(|err| {
                         if last_error
                             .as_ref()
@@ -161,50 +1585,674 @@ impl<S: Sender> BufferedSenderThread<S> {
                 println!("fatal logger error: {}", err);
                 err
             })
-        });
+        })
+    }
+
+    /// Discards the `Flush` commands queued right behind one being handled, so callers
+    /// flushing at the same time cause a single flush. Returns the command following them.
+    fn skip_flushes(receiver: &mpsc::Receiver<Command>) -> Option<Command> {
+        loop {
+            match receiver.try_recv() {
+                Ok(Command::Flush) => continue,
+                Ok(cmd) => return Some(cmd),
+                Err(_) => return None,
+            }
+        }
+    }
+
+    /// Takes the buffered records and the ones still queued, closing the queue
+    fn drain(&mut self, receiver: mpsc::Receiver<Command>) -> Vec<LogStashRecord> {
+        let mut records = self.buffer.take(0);
+        self.in_flight.release(records.len());
+        #[cfg(feature = "bytes")]
+        {
+            let frames = self.raw_buffer.take(0);
+            self.in_flight.release(frames.len());
+            records.extend(frames.iter().filter_map(parse_frame));
+        }
+        for reply in std::mem::take(&mut self.connecting_flushes) {
+            let _ = reply.send(Err(Error::SenderThreadStopped("drained".into())));
+        }
+        while let Ok(cmd) = receiver.try_recv() {
+            match cmd {
+                Command::Send(event) => {
+                    self.in_flight.release(1);
+                    records.push(event);
+                }
+                Command::SendBatch(events) => {
+                    self.in_flight.release(events.len());
+                    records.extend(events);
+                }
+                #[cfg(feature = "bytes")]
+                Command::SendRaw(frame, _) => {
+                    self.in_flight.release(1);
+                    records.extend(parse_frame(&frame));
+                }
+                #[cfg(feature = "bytes")]
+                Command::SendRawBatch(frames) => {
+                    self.in_flight.release(frames.len());
+                    records.extend(frames.iter().filter_map(|(frame, _)| parse_frame(frame)));
+                }
+                Command::FlushAck(reply) | Command::Connect(reply) => {
+                    let _ = reply.send(Err(Error::SenderThreadStopped("drained".into())));
+                }
+                Command::Drain(reply) => {
+                    let _ = reply.send(vec![]);
+                }
+                Command::Flush | Command::Connected(_) | Command::Resume => {}
+            }
+        }
+        self.deadline = None;
+        self.flush_size = None;
+        self.update_buffered_stats();
+        records
+    }
+
+    /// Flushes the buffer, writing it to the persist file first if there is one. The file
+    /// is removed once the records are delivered.
+    fn shutdown(&mut self) {
+        let persisted = match &self.persist_on_shutdown {
+            Some(path) if self.buffered_len() > 0 => match self.persist_buffer(path) {
+                Ok(()) => Some(path.clone()),
+                Err(err) => {
+                    println!("logstash logger error: {}", err);
+                    None
+                }
+            },
+            _ => None,
+        };
+        let result = self.flush();
+        if let Err(err) = &result {
+            println!("logstash logger error: {}", err);
+        }
+        if let Some(path) = persisted {
+            if result.is_ok() && self.buffered_len() == 0 {
+                if let Err(err) = fs::remove_file(path) {
+                    println!("logstash logger error: {}", err);
+                }
+            }
+        }
+    }
+
+    /// Writes the buffered records to `path` as JSON lines
+    fn persist_buffer(&self, path: &Path) -> Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        for record in self.buffer.records() {
+            serde_json::to_writer(&mut file, record)?;
+            file.write_all(b"\n")?;
+        }
+        // Serialized records already end with a newline
+        #[cfg(feature = "bytes")]
+        for frame in self.raw_buffer.records() {
+            file.write_all(frame)?;
+        }
+        file.into_inner()
+            .map_err(|err| err.into_error())?
+            .sync_all()?;
+        Ok(())
+    }
+
+    /// Reads and removes the persist file left by the previous shutdown and sends its
+    /// records, marked with a `replayed` field. Lines that fail to parse are dropped.
+    fn replay_persisted(&mut self) -> Result<()> {
+        let path = match &self.persist_on_shutdown {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let content = match fs::read(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        fs::remove_file(path)?;
+        let lines = String::from_utf8_lossy(&content);
+        let mut skipped = 0;
+        let records = lines
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| match LogStashRecord::from_json_str(line) {
+                Ok(mut record) => {
+                    record.add_data("replayed", true.into());
+                    Some(record)
+                }
+                Err(_) => {
+                    skipped += 1;
+                    None
+                }
+            })
+            .collect();
+        self.stats.add_dropped(skipped);
+        self.send_batch(records)
+    }
+
+    /// Whether records are only buffered, while connecting or paused
+    fn holds_records(&self) -> bool {
+        self.connecting || self.is_paused()
+    }
+
+    fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// Flush of a caller waiting for the records to be delivered, which fails while paused
+    /// as nothing is delivered then
+    fn flush_ack(&mut self) -> Result<()> {
+        if self.is_paused() {
+            return Err(Error::Paused());
+        }
+        self.flush()
+    }
+
+    /// Leaves the connecting state and drains records buffered meanwhile
+    fn connected(&mut self, error: Option<String>) -> Result<()> {
+        self.connecting = false;
+        let result = match error {
+            Some(error) => Err(Error::Connection(error)),
+            None => Ok(()),
+        };
+        if result.is_err() {
+            self.diagnostics.track(&result);
+        }
+        let flushed = self.flush();
+        for reply in std::mem::take(&mut self.connecting_flushes) {
+            let _ = reply.send(self.flush_ack());
+        }
+        flushed.and(result)
+    }
+
+    /// Runs `f` on the downstream sender, tracking its wall time and the outcome for
+    /// diagnostics
+    fn deliver(&mut self, f: impl FnOnce(&S) -> Result<()>) -> Result<()> {
+        let started = Instant::now();
+        let result = f(&self.sender);
+        let latency = started.elapsed();
+        self.stats.add_latency(latency);
+        if let Some(threshold) = self.slow_send_threshold.filter(|t| latency > *t) {
+            self.diagnostics.track_slow(latency, threshold);
+        }
+        self.diagnostics.track(&result);
+        result
+    }
+
+    /// Runs `f` sending `count` records, counting them as sent if it succeeds
+    fn deliver_records(&mut self, count: usize, f: impl FnOnce(&S) -> Result<()>) -> Result<()> {
+        let started = Instant::now();
+        let result = self.deliver(f);
+        if result.is_ok() {
+            let latency = started.elapsed();
+            self.stats.add_sent(count, latency);
+            self.adapt_batch_size(latency);
+            if let Some(batch_id) = self.sender.last_batch_id() {
+                self.stats.set_last_batch_id(batch_id);
+            }
+        }
+        result
+    }
+
+    /// Adjusts the buffer size to the latency of a delivery with adaptive batching
+    fn adapt_batch_size(&mut self, latency: Duration) {
+        if let (Some(adaptive), Some(size)) = (self.adaptive_batching, self.buffer_size) {
+            let size = adaptive.adjust(size, latency);
+            self.buffer_size = Some(size);
+            self.stats.set_batch_size(size);
+        }
+    }
+
+    fn update_buffered_stats(&self) {
+        self.stats
+            .set_buffered(self.buffered_len(), self.buffered_bytes());
+    }
+
+    /// Delivers `events` as one batch, putting back the records to retry in the buffer
+    fn deliver_batch(&mut self, events: Vec<LogStashRecord>) -> Result<()> {
+        let (result, retry) = self.try_deliver_batch(events);
+        self.restore_buffer(retry);
+        result
+    }
+
+    /// Delivers `events` as one batch, returning them to the record pool if there is one.
+    /// Returns the records to retry along with the outcome.
+    fn try_deliver_batch(
+        &mut self,
+        mut events: Vec<LogStashRecord>,
+    ) -> (Result<()>, Vec<LogStashRecord>) {
+        if !self.keeps_sent_records() {
+            let result = self.deliver_records(events.len(), |s| s.send_batch(events));
+            return (result, vec![]);
+        }
+        let result = self.deliver_records(events.len(), |s| s.send_batch_ref(&events));
+        let retry = match &result {
+            Err(Error::PartialWrite { confirmed, .. })
+                if self.ordered
+                    || self.partial_write_policy == PartialWritePolicy::RetryUnconfirmed =>
+            {
+                events.split_off((*confirmed).min(events.len()))
+            }
+            Err(_) if self.ordered => std::mem::take(&mut events),
+            _ => vec![],
+        };
+        #[cfg(feature = "pool")]
+        if let Some(pool) = &self.record_pool {
+            events.into_iter().for_each(|event| pool.release(event));
+        }
+        (result, retry)
+    }
+
+    /// Whether batches are sent by reference to retry or recycle the records afterwards
+    fn keeps_sent_records(&self) -> bool {
+        #[cfg(feature = "pool")]
+        if self.record_pool.is_some() {
+            return true;
+        }
+        self.ordered || self.partial_write_policy == PartialWritePolicy::RetryUnconfirmed
+    }
+
+    /// Whether records above the ignore buffer level go through the buffer
+    fn buffers_all(&self) -> bool {
+        self.ordered && self.buffer_size.is_some()
+    }
+
+    /// Puts records back in front of the buffer, to be sent with the next flush
+    fn restore_buffer(&mut self, events: Vec<LogStashRecord>) {
+        if events.is_empty() {
+            return;
+        }
+        events
+            .iter()
+            .for_each(|event| self.track_buffered(event.level));
+        self.in_flight.add(events.len());
+        let dropped = self.buffer.restore(events);
+        self.in_flight.release(dropped);
+        self.stats.add_dropped(dropped);
+        self.update_buffered_stats();
+    }
+
+    /// Delivers a single record with [`Sender::send`], which takes it, so it is not returned
+    /// to the record pool
+    fn deliver_one(&mut self, mut event: LogStashRecord) -> Result<()> {
+        self.finalize_batch(std::slice::from_mut(&mut event));
+        self.deliver_records(1, |s| s.send(event))
+    }
+
+    fn send_heartbeat(&mut self, now: Instant) -> Result<()> {
+        let sent = self.stats.snapshot().sent;
+        let record = LogStashRecord::builder(Level::Info)
+            .target(HEARTBEAT_TARGET)
+            .field(
+                "uptime_seconds",
+                now.duration_since(self.started).as_secs_f64(),
+            )
+            .field("events_sent", sent - self.sent_at_heartbeat)
+            .field("buffer_fill", self.buffered_len())
+            .build();
+        self.sent_at_heartbeat = sent;
+        self.deliver(|s| s.send(record))
+    }
+
+    fn send_self_metrics(&mut self) -> Result<()> {
+        let stats = self.stats.snapshot();
+        let record = LogStashRecord::builder(Level::Info)
+            .target(SELF_METRICS_TARGET)
+            .field("_self_metrics", true)
+            .field("events_sent", stats.sent)
+            .field("events_dropped", stats.dropped)
+            .field("reconnects", self.sender.reconnects())
+            .field("buffer_depth", stats.buffered)
+            .field("buffered_bytes", stats.buffered_bytes)
+            .build();
+        self.deliver(|s| s.send(record))
+    }
+
+    /// Sends pending diagnostic records. Failures are not tracked so diagnostics never
+    /// produce further diagnostics.
+    fn send_diagnostics(&mut self) {
+        if self.is_paused() {
+            return;
+        }
+        if let Some(records) = self.diagnostics.take_pending() {
+            if self.sender.send_batch(records.clone()).is_err() {
+                self.diagnostics.restore(records);
+            }
+        }
+    }
+
+    /// Drops buffered records, then up to `incoming` just received ones as they are the
+    /// oldest left, until the records in flight fit the limit again. Returns the number of
+    /// received records to drop.
+    fn shed_excess(&mut self, incoming: usize) -> usize {
+        let excess = self.in_flight.excess(incoming);
+        if excess == 0 {
+            return 0;
+        }
+        let buffered = self.buffer.drop_oldest(excess);
+        #[cfg(feature = "bytes")]
+        let buffered = buffered + self.raw_buffer.drop_oldest(excess - buffered);
+        self.in_flight.release(buffered);
+        self.update_buffered_stats();
+        let incoming = (excess - buffered).min(incoming);
+        self.stats.add_dropped(buffered + incoming);
+        incoming
     }
 
     fn send(&mut self, event: LogStashRecord) -> Result<()> {
-        if event.level >= self.ignore_buffer {
-            self.sender.send(event)?;
+        match self.limit_size(event) {
+            Some(event) => self.send_record(event),
+            None => Ok(()),
+        }
+    }
+
+    /// Tracks the size of `event` and applies the oversized policy to it, returns the
+    /// record to send unless it is dropped
+    fn limit_size(&mut self, mut event: LogStashRecord) -> Option<LogStashRecord> {
+        let size = event.estimated_json_size();
+        let max = self.max_event_bytes.filter(|max| size > *max);
+        self.stats
+            .add_record_size(&event.target, size, max.is_some());
+        let max = match max {
+            Some(max) => max,
+            None => return Some(event),
+        };
+        if self.oversized_diagnostics {
+            self.diagnostics
+                .track_oversized(&event.target, size, max, self.oversized_policy);
+        }
+        match self.oversized_policy {
+            OversizedPolicy::Truncate => {
+                event.truncate_to(max);
+                Some(event)
+            }
+            OversizedPolicy::Drop => {
+                self.stats.add_dropped(1);
+                None
+            }
+            OversizedPolicy::SendAnyway => Some(event),
+        }
+    }
+
+    /// Sends or buffers a record already checked by [`limit_size`](Self::limit_size)
+    fn send_record(&mut self, event: LogStashRecord) -> Result<()> {
+        if self.shed_excess(1) > 0 {
+            return Ok(());
+        }
+        if self.is_paused() {
+            self.push_buffer(event);
+        } else if self.connecting {
+            if self.buffer.len() < self.log_queue_len {
+                self.push_buffer(event);
+            } else {
+                self.stats.add_dropped(1);
+            }
+        } else if self.buffers_all() && event.level >= self.ignore_buffer_for(&event.target) {
+            // Sent behind the records buffered before it
+            self.push_buffer(event);
+            self.flush()?;
+        } else if event.level >= self.ignore_buffer_for(&event.target) {
+            if self.flushes_on(event.level) && self.buffered_len() > 0 {
+                self.flush()?;
+            }
+            self.deliver_one(event)?;
+        } else if let Some(max_size) = self.buffer_size {
+            self.flush_before(event.estimated_json_size())?;
+            let level = event.level;
+            self.push_buffer(event);
+            if self.buffer.len() >= self.flush_size.unwrap_or(max_size) || self.flushes_on(level) {
+                self.flush()?;
+            }
+        } else {
+            self.deliver_one(event)?;
+        }
+        Ok(())
+    }
+
+    /// Whether a record of `level` flushes the buffer
+    fn flushes_on(&self, level: Level) -> bool {
+        self.flush_on_level
+            .is_some_and(|flush_on| level <= flush_on)
+    }
+
+    /// Flushes the buffer if `incoming` more bytes would take it over the flush bytes
+    fn flush_before(&mut self, incoming: usize) -> Result<()> {
+        match self.flush_bytes {
+            Some(limit) if self.buffered_len() > 0 && self.buffered_bytes() + incoming > limit => {
+                self.flush()
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Ignore buffer level applied to `target`, using the longest matching target prefix
+    fn ignore_buffer_for(&self, target: &str) -> Level {
+        self.target_overrides
+            .iter()
+            .find(|(prefix, _)| target.starts_with(prefix.as_str()))
+            .map(|(_, level)| *level)
+            .unwrap_or(self.ignore_buffer)
+    }
+
+    fn push_buffer(&mut self, event: LogStashRecord) {
+        let event = match self.coalesce(event) {
+            Some(event) => event,
+            None => return,
+        };
+        self.track_buffered(event.level);
+        self.in_flight.add(1);
+        let dropped = self.buffer.push(event);
+        self.in_flight.release(dropped);
+        self.stats.add_dropped(dropped);
+        self.update_buffered_stats();
+    }
+
+    /// Counts `event` as a repeat of the last buffered record if coalescing is enabled and
+    /// they match, returns `event` back if it has to be buffered on its own
+    fn coalesce(&mut self, event: LogStashRecord) -> Option<LogStashRecord> {
+        let cap = match self.coalesce_repeats {
+            Some(cap) => cap,
+            None => return Some(event),
+        };
+        let last = match self.buffer.last_mut() {
+            Some(last) => last,
+            None => return Some(event),
+        };
+        let repeats = last
+            .fields
+            .get("repeat_count")
+            .and_then(serde_json::Value::as_u64)
+            .unwrap_or(1);
+        if repeats >= cap
+            || last.level != event.level
+            || last.target != event.target
+            || last.fields.get("message") != event.fields.get("message")
+        {
+            return Some(event);
+        }
+        last.fields
+            .insert("repeat_count".into(), (repeats + 1).into());
+        #[cfg(feature = "pool")]
+        if let Some(pool) = &self.record_pool {
+            pool.release(event);
+        }
+        None
+    }
+
+    #[cfg(feature = "bytes")]
+    fn send_raw(&mut self, frame: Bytes, level: Level) -> Result<()> {
+        if self.shed_excess(1) > 0 {
+            return Ok(());
+        }
+        if self.is_paused() {
+            self.push_raw(frame, level);
+        } else if self.connecting {
+            if self.raw_buffer.len() < self.log_queue_len {
+                self.push_raw(frame, level);
+            } else {
+                self.stats.add_dropped(1);
+            }
+        } else if self.buffers_all() && level >= self.ignore_buffer {
+            self.push_raw(frame, level);
+            self.flush()?;
+        } else if level >= self.ignore_buffer {
+            if self.flushes_on(level) && self.buffered_len() > 0 {
+                self.flush()?;
+            }
+            self.deliver_records(1, |s| s.send_raw(std::slice::from_ref(&frame)))?;
         } else if let Some(max_size) = self.buffer_size {
-            self.buffer.push(event);
-            if self.buffer.len() >= max_size {
+            self.flush_before(frame.len())?;
+            self.push_raw(frame, level);
+            if self.raw_buffer.len() >= self.flush_size.unwrap_or(max_size)
+                || self.flushes_on(level)
+            {
                 self.flush()?;
             }
         } else {
-            self.sender.send(event)?;
+            self.deliver_records(1, |s| s.send_raw(std::slice::from_ref(&frame)))?;
+        }
+        Ok(())
+    }
+
+    /// Puts serialized records back in front of the buffer, to be sent with the next flush
+    #[cfg(feature = "bytes")]
+    fn restore_raw(&mut self, frames: Vec<Bytes>) {
+        if frames.is_empty() {
+            return;
+        }
+        // Levels of serialized records are not kept, their retry uses the global settings
+        self.track_buffered_policy(BufferPolicy::default());
+        self.in_flight.add(frames.len());
+        let dropped = self.raw_buffer.restore(frames);
+        self.in_flight.release(dropped);
+        self.stats.add_dropped(dropped);
+        self.update_buffered_stats();
+    }
+
+    #[cfg(feature = "bytes")]
+    fn push_raw(&mut self, frame: Bytes, level: Level) {
+        self.track_buffered(level);
+        self.in_flight.add(1);
+        let dropped = self.raw_buffer.push(frame);
+        self.in_flight.release(dropped);
+        self.stats.add_dropped(dropped);
+        self.update_buffered_stats();
+    }
+
+    #[cfg(feature = "bytes")]
+    fn send_raw_batch(&mut self, mut frames: Vec<(Bytes, Level)>) -> Result<()> {
+        let dropped = self.shed_excess(frames.len());
+        frames.drain(..dropped);
+        if !self.holds_records() && self.buffer_size.is_none() {
+            let frames: Vec<_> = frames.into_iter().map(|(frame, _)| frame).collect();
+            return self.deliver_records(frames.len(), |s| s.send_raw(&frames));
+        }
+        for (frame, level) in frames {
+            self.send_raw(frame, level)?;
         }
         Ok(())
     }
 
     fn send_batch(&mut self, events: Vec<LogStashRecord>) -> Result<()> {
+        let mut events: Vec<_> = events
+            .into_iter()
+            .filter_map(|event| self.limit_size(event))
+            .collect();
+        let dropped = self.shed_excess(events.len());
+        events.drain(..dropped);
+        if !self.holds_records() && self.buffer_size.is_none() {
+            self.drop_expired(&mut events);
+            self.finalize_batch(&mut events);
+            return self.deliver_batch(events);
+        }
         for event in events {
-            self.send(event)?;
+            self.send_record(event)?;
         }
         Ok(())
     }
 
+    /// Removes records older than the TTL, counting them as dropped
+    fn drop_expired(&self, events: &mut Vec<LogStashRecord>) {
+        let cutoff = self
+            .record_ttl
+            .and_then(|ttl| chrono::Duration::from_std(ttl).ok())
+            .and_then(|ttl| chrono::Utc::now().checked_sub_signed(ttl));
+        if let Some(cutoff) = cutoff {
+            let count = events.len();
+            events.retain(|event| event.timestamp >= cutoff);
+            self.stats.add_dropped(count - events.len());
+        }
+    }
+
+    /// Last adjustments of the records before they are handed to the downstream sender
+    fn finalize_batch(&self, events: &mut [LogStashRecord]) {
+        if self.sub_ms_seq {
+            add_sub_ms_seq(events);
+        }
+        if let Some(Redactor(redact)) = &self.redactor {
+            events.iter_mut().for_each(|event| redact(event));
+        }
+    }
+
     fn flush(&mut self) -> Result<()> {
-        if !self.buffer.is_empty() {
-            let buffer = std::mem::replace(
-                &mut self.buffer,
-                Vec::with_capacity(self.buffer_size.unwrap_or_default()),
-            );
-            self.sender.send_batch(buffer)?;
+        if self.holds_records() {
+            self.deadline = None;
+            return Ok(());
         }
-        self.sender.flush()?;
+        // Records put back in the buffer by a failed delivery arm a new deadline
         self.deadline = None;
-        Ok(())
+        self.flush_size = None;
+        if !self.buffer.is_empty() {
+            let mut buffer = self.buffer.take(self.buffer_size.unwrap_or_default());
+            self.in_flight.release(buffer.len());
+            self.update_buffered_stats();
+            self.drop_expired(&mut buffer);
+            self.finalize_batch(&mut buffer);
+            // A backlog left by pausing or connecting is sent in batches of the buffer size
+            let chunk = self.buffer_size.unwrap_or(buffer.len()).max(1);
+            while !buffer.is_empty() {
+                let rest = buffer.split_off(chunk.min(buffer.len()));
+                let (result, mut retry) = self.try_deliver_batch(buffer);
+                if result.is_err() {
+                    // Later batches wait for the next flush, behind the failed one if kept
+                    retry.extend(rest);
+                    self.restore_buffer(retry);
+                    return result;
+                }
+                self.restore_buffer(retry);
+                buffer = rest;
+            }
+        }
+        #[cfg(feature = "bytes")]
+        if !self.raw_buffer.is_empty() {
+            let mut frames = self.raw_buffer.take(self.buffer_size.unwrap_or_default());
+            self.in_flight.release(frames.len());
+            self.update_buffered_stats();
+            let chunk = self.buffer_size.unwrap_or(frames.len()).max(1);
+            while !frames.is_empty() {
+                let rest = frames.split_off(chunk.min(frames.len()));
+                let result = self.deliver_records(frames.len(), |s| s.send_raw(&frames));
+                if result.is_err() {
+                    if self.ordered {
+                        frames.extend(rest);
+                        self.restore_raw(frames);
+                    } else {
+                        self.restore_raw(rest);
+                    }
+                    return result;
+                }
+                frames = rest;
+            }
+        }
+        // Flush even with an empty buffer to push bytes still held by the transport
+        self.deliver(|s| s.flush())
     }
 }
 
 impl log::Log for BufferedSender {
-    fn enabled(&self, _metadata: &log::Metadata) -> bool {
-        true
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        Sender::enabled(self, metadata) && !self.is_saturated()
     }
 
     fn log(&self, record: &log::Record) {
+        if !log::Log::enabled(self, record.metadata()) {
+            return;
+        }
         let record = LogStashRecord::from_record(record);
         let _ = self.send(record);
     }
@@ -0,0 +1,71 @@
+use chrono::{DateTime, Utc};
+use std::sync::{Arc, OnceLock};
+
+/// Source of record timestamps
+pub trait ClockSource: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// Clock returning the current system time
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl ClockSource for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+static DEFAULT_CLOCK: OnceLock<Arc<dyn ClockSource>> = OnceLock::new();
+
+/// Installs the clock used by [`LogStashRecord::new`](crate::LogStashRecord::new).
+/// Returns the clock back if the default clock was already set or used.
+pub fn set_default_clock(clock: Arc<dyn ClockSource>) -> Result<(), Arc<dyn ClockSource>> {
+    DEFAULT_CLOCK.set(clock)
+}
+
+/// Clock used by [`LogStashRecord::new`](crate::LogStashRecord::new), [`SystemClock`] unless
+/// another one was installed with [`set_default_clock`].
+pub fn default_clock() -> &'static Arc<dyn ClockSource> {
+    DEFAULT_CLOCK.get_or_init(|| Arc::new(SystemClock))
+}
+
+#[cfg(feature = "test-utils")]
+pub use mock::MockClock;
+
+#[cfg(feature = "test-utils")]
+mod mock {
+    use super::ClockSource;
+    use chrono::{DateTime, Utc};
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    /// Clock returning a fixed time which can be changed programmatically
+    #[derive(Debug)]
+    pub struct MockClock {
+        now: Mutex<DateTime<Utc>>,
+    }
+
+    impl MockClock {
+        pub fn new(now: DateTime<Utc>) -> Self {
+            Self {
+                now: Mutex::new(now),
+            }
+        }
+
+        pub fn set(&self, now: DateTime<Utc>) {
+            *self.now.lock().expect("mock clock poisoned") = now;
+        }
+
+        pub fn advance(&self, duration: Duration) {
+            let mut now = self.now.lock().expect("mock clock poisoned");
+            *now += chrono::Duration::from_std(duration).expect("duration out of range");
+        }
+    }
+
+    impl ClockSource for MockClock {
+        fn now(&self) -> DateTime<Utc> {
+            *self.now.lock().expect("mock clock poisoned")
+        }
+    }
+}
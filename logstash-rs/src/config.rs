@@ -0,0 +1,265 @@
+//! Sender settings read from a TOML file and reloaded when the file changes.
+//!
+//! ```no_run
+//! use qoollo_logstash_rs::config::{watch_config_file, HotReloadableSender, TcpSenderConfig};
+//! use qoollo_logstash_rs::BufferedSender;
+//! use std::path::Path;
+//!
+//! let path = Path::new("logstash.toml");
+//! let tcp = TcpSenderConfig::from_file(path).unwrap().build().unwrap();
+//! let sender = HotReloadableSender::new(Box::new(tcp));
+//! watch_config_file(path, sender.handle()).unwrap();
+//! let logger = BufferedSender::builder().build(sender);
+//! ```
+
+use crate::prelude::*;
+use notify::{RecursiveMode, Watcher};
+use std::fs;
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How often the watcher thread checks whether the watched sender was dropped
+const DROP_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Settings of a [`TcpSender`], e.g.
+///
+/// ```toml
+/// hostname = "logstash.local"
+/// port = 5000
+/// connect_timeout = "5s"
+/// ```
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TcpSenderConfig {
+    pub hostname: String,
+    pub port: u16,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    pub connect_timeout: Option<Duration>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    pub write_timeout: Option<Duration>,
+    #[serde(default)]
+    pub nodelay: bool,
+    /// Connects over TLS when set
+    pub tls: Option<TlsOptions>,
+    pub framing: Option<Framing>,
+    pub batch_format: Option<BatchFormat>,
+}
+
+impl TcpSenderConfig {
+    pub fn from_toml(toml: &str) -> Result<Self> {
+        toml::from_str(toml).map_err(|err| Error::Config(err.to_string()))
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        Self::from_toml(&fs::read_to_string(path)?)
+    }
+
+    /// Fails if the hostname is empty or the port is 0
+    pub fn build(&self) -> Result<TcpSender> {
+        let mut builder = TcpSender::builder()
+            .hostname(self.hostname.clone())
+            .port(self.port)
+            .nodelay(self.nodelay);
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.write_timeout {
+            builder = builder.write_timeout(timeout);
+        }
+        if let Some(tls) = &self.tls {
+            builder = builder.tls(tls.clone());
+        }
+        if let Some(framing) = self.framing {
+            builder = builder.framing(framing);
+        }
+        let sender = builder.build()?;
+        Ok(match self.batch_format {
+            Some(batch_format) => sender.with_batch_format(batch_format),
+            None => sender,
+        })
+    }
+}
+
+/// Sender forwarding records to a sender that can be replaced while in use, see
+/// [`watch_config_file`]
+#[derive(Clone)]
+pub struct HotReloadableSender {
+    sender: Arc<Mutex<Box<dyn Sender>>>,
+}
+
+impl HotReloadableSender {
+    pub fn new(sender: Box<dyn Sender>) -> Self {
+        Self::from(Arc::new(Mutex::new(sender)))
+    }
+
+    /// Shared slot holding the current sender, replace its content to swap the sender
+    pub fn handle(&self) -> Arc<Mutex<Box<dyn Sender>>> {
+        self.sender.clone()
+    }
+
+    /// Flushes the current sender and replaces it with `sender`
+    pub fn replace(&self, sender: Box<dyn Sender>) -> Result<()> {
+        replace_sender(&self.sender, sender)
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Box<dyn Sender>>> {
+        Ok(self.sender.lock()?)
+    }
+}
+
+impl From<Arc<Mutex<Box<dyn Sender>>>> for HotReloadableSender {
+    fn from(sender: Arc<Mutex<Box<dyn Sender>>>) -> Self {
+        Self { sender }
+    }
+}
+
+impl Sender for HotReloadableSender {
+    fn send(&self, event: LogStashRecord) -> Result<()> {
+        self.lock()?.send(event)
+    }
+
+    fn send_batch(&self, events: Vec<LogStashRecord>) -> Result<()> {
+        self.lock()?.send_batch(events)
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.lock()?.flush()
+    }
+
+    fn flush_and_wait(&self, timeout: Duration) -> Result<()> {
+        self.lock()?.flush_and_wait(timeout)
+    }
+
+    fn send_batch_ref(&self, events: &[LogStashRecord]) -> Result<()> {
+        self.lock()?.send_batch_ref(events)
+    }
+
+    #[cfg(feature = "bytes")]
+    fn send_raw(&self, frames: &[bytes::Bytes]) -> Result<()> {
+        self.lock()?.send_raw(frames)
+    }
+
+    fn connect(&self) -> Result<()> {
+        self.lock()?.connect()
+    }
+
+    fn ping(&self) -> Result<()> {
+        self.lock()?.ping()
+    }
+
+    fn endpoint(&self) -> Option<String> {
+        self.lock().ok()?.endpoint()
+    }
+
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.lock().map_or(true, |sender| sender.enabled(metadata))
+    }
+
+    fn capabilities(&self) -> SenderCapabilities {
+        self.lock()
+            .map(|sender| sender.capabilities())
+            .unwrap_or_default()
+    }
+
+    fn last_batch_id(&self) -> Option<BatchId> {
+        self.lock().ok()?.last_batch_id()
+    }
+
+    fn reconnects(&self) -> u64 {
+        self.lock().map_or(0, |sender| sender.reconnects())
+    }
+}
+
+fn replace_sender(slot: &Mutex<Box<dyn Sender>>, sender: Box<dyn Sender>) -> Result<()> {
+    let old = std::mem::replace(&mut *slot.lock()?, sender);
+    old.flush()
+}
+
+/// Watches the TOML file at `path` and replaces the content of `sender` with a new
+/// [`TcpSender`] built from the [`TcpSenderConfig`] in it whenever the file changes. The
+/// previous sender is flushed and dropped. A file failing to parse is reported as a warning
+/// and the previous sender is kept. The returned thread stops once all other references to
+/// `sender` are dropped.
+pub fn watch_config_file(
+    path: &Path,
+    sender: Arc<Mutex<Box<dyn Sender>>>,
+) -> Result<JoinHandle<()>> {
+    let path = path.canonicalize()?;
+    // Watch the directory, editors often replace the file instead of writing into it
+    let dir = path
+        .parent()
+        .ok_or_else(|| Error::Config(format!("{} has no parent directory", path.display())))?
+        .to_owned();
+    let (events, changes) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(events).map_err(watch_error)?;
+    watcher
+        .watch(&dir, RecursiveMode::NonRecursive)
+        .map_err(watch_error)?;
+    let mut loaded = fs::read_to_string(&path).ok();
+    let sender = Arc::downgrade(&sender);
+    Ok(thread::spawn(move || {
+        // Keeps watching as long as the watcher lives
+        let _watcher = watcher;
+        loop {
+            let event = match changes.recv_timeout(DROP_CHECK_INTERVAL) {
+                Ok(event) => event,
+                Err(RecvTimeoutError::Timeout) if sender.strong_count() > 0 => continue,
+                Err(_) => return,
+            };
+            if !touches(event, &path) {
+                continue;
+            }
+            let slot = match sender.upgrade() {
+                Some(slot) => slot,
+                None => return,
+            };
+            reload(&path, &slot, &mut loaded);
+        }
+    }))
+}
+
+fn touches(event: notify::Result<notify::Event>, path: &Path) -> bool {
+    match event {
+        Ok(event) => !event.kind.is_access() && event.paths.iter().any(|p| p == path),
+        Err(_) => false,
+    }
+}
+
+/// Replaces the sender in `slot` if the content of `path` differs from `loaded`
+fn reload(path: &Path, slot: &Mutex<Box<dyn Sender>>, loaded: &mut Option<String>) {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        // Removed or being replaced, the next event brings the new file
+        Err(_) => return,
+    };
+    if loaded.as_deref() == Some(content.as_str()) {
+        return;
+    }
+    let sender = TcpSenderConfig::from_toml(&content).and_then(|config| config.build());
+    *loaded = Some(content);
+    match sender {
+        Ok(sender) => {
+            if let Err(err) = replace_sender(slot, Box::new(sender)) {
+                log::warn!(
+                    "failed to flush the sender replaced by {}: {}",
+                    path.display(),
+                    err
+                );
+            }
+        }
+        Err(err) => log::warn!(
+            "invalid logstash config {}, keeping the current sender: {}",
+            path.display(),
+            err
+        ),
+    }
+}
+
+fn watch_error(err: notify::Error) -> Error {
+    Error::Config(format!("failed to watch config file: {}", err))
+}
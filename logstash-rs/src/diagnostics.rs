@@ -0,0 +1,188 @@
+use crate::prelude::*;
+use log::Level;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+pub(crate) const DIAGNOSTICS_TARGET: &str = "logstash_rs::internal";
+const MAX_PENDING: usize = 16;
+/// Shortest time between two oversized record warnings for the same target
+const OVERSIZED_REPORT_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Turns streaks of worker errors into self-diagnostic records sent once the sender recovers
+#[derive(Debug)]
+pub(crate) struct Diagnostics {
+    enabled: bool,
+    endpoint: Option<String>,
+    failures: usize,
+    last_error: Option<(&'static str, String)>,
+    pending: VecDeque<LogStashRecord>,
+    /// Last oversized record warning of every target
+    oversized_reported: HashMap<String, Instant>,
+}
+
+impl Diagnostics {
+    pub(crate) fn new(enabled: bool, endpoint: Option<String>) -> Self {
+        Self {
+            enabled,
+            endpoint,
+            failures: 0,
+            last_error: None,
+            pending: VecDeque::new(),
+            oversized_reported: HashMap::new(),
+        }
+    }
+
+    /// Records the outcome of an operation on the downstream sender
+    pub(crate) fn track(&mut self, result: &Result<()>) {
+        if !self.enabled {
+            return;
+        }
+        match result {
+            Err(err) => {
+                self.failures += 1;
+                self.last_error = Some((err.kind(), err.to_string()));
+            }
+            Ok(()) if self.failures > 0 => {
+                let record = self.recovery_record();
+                self.push(record);
+                self.failures = 0;
+                self.last_error = None;
+            }
+            Ok(()) => {}
+        }
+    }
+
+    /// Records a call to the downstream sender taking `latency`, above `threshold`
+    pub(crate) fn track_slow(&mut self, latency: Duration, threshold: Duration) {
+        if !self.enabled {
+            return;
+        }
+        let record = self.slow_record(latency, threshold);
+        self.push(record);
+    }
+
+    /// Records a record of `target` of `size` bytes above `max` bytes, reported once per
+    /// target per hour
+    pub(crate) fn track_oversized(
+        &mut self,
+        target: &str,
+        size: usize,
+        max: usize,
+        policy: OversizedPolicy,
+    ) {
+        if !self.enabled {
+            return;
+        }
+        let now = Instant::now();
+        if let Some(reported) = self.oversized_reported.get(target) {
+            if now.duration_since(*reported) < OVERSIZED_REPORT_INTERVAL {
+                return;
+            }
+        }
+        self.oversized_reported
+            .retain(|_, reported| now.duration_since(*reported) < OVERSIZED_REPORT_INTERVAL);
+        self.oversized_reported.insert(target.to_owned(), now);
+        let record = self.oversized_record(target, size, max, policy);
+        self.push(record);
+    }
+
+    fn push(&mut self, record: LogStashRecord) {
+        if self.pending.len() == MAX_PENDING {
+            self.pending.pop_front();
+        }
+        self.pending.push_back(record);
+    }
+
+    /// Diagnostic records ready to be sent, available only while the sender is healthy
+    pub(crate) fn take_pending(&mut self) -> Option<Vec<LogStashRecord>> {
+        if self.failures > 0 || self.pending.is_empty() {
+            return None;
+        }
+        Some(self.pending.drain(..).collect())
+    }
+
+    /// Puts back records which failed to be sent
+    pub(crate) fn restore(&mut self, records: Vec<LogStashRecord>) {
+        for record in records.into_iter().rev() {
+            self.pending.push_front(record);
+        }
+        self.pending.truncate(MAX_PENDING);
+    }
+
+    fn slow_record(&self, latency: Duration, threshold: Duration) -> LogStashRecord {
+        let mut record = LogStashRecord::new();
+        record.level = Level::Warn;
+        record.target = DIAGNOSTICS_TARGET.into();
+        record
+            .add_data(
+                "message",
+                format!(
+                    "logstash sender call took {:?}, above the slow send threshold of {:?}",
+                    latency, threshold
+                )
+                .into(),
+            )
+            .add_data("latency_ms", (latency.as_secs_f64() * 1000.0).into())
+            .add_data("threshold_ms", (threshold.as_millis() as u64).into());
+        if let Some(endpoint) = &self.endpoint {
+            record.add_data("endpoint", endpoint.as_str().into());
+        }
+        record
+    }
+
+    fn oversized_record(
+        &self,
+        target: &str,
+        size: usize,
+        max: usize,
+        policy: OversizedPolicy,
+    ) -> LogStashRecord {
+        let mut record = LogStashRecord::new();
+        record.level = Level::Warn;
+        record.target = DIAGNOSTICS_TARGET.into();
+        record
+            .add_data(
+                "message",
+                format!(
+                    "logstash record of target {} is about {} bytes, above the maximum event size of {} bytes",
+                    target, size, max
+                )
+                .into(),
+            )
+            .add_data("record_target", target.into())
+            .add_data("size_bytes", size.into())
+            .add_data("max_event_bytes", max.into())
+            .add_data("policy", policy.name().into());
+        if let Some(endpoint) = &self.endpoint {
+            record.add_data("endpoint", endpoint.as_str().into());
+        }
+        record
+    }
+
+    fn recovery_record(&self) -> LogStashRecord {
+        let (kind, error) = self.last_error.clone().unwrap_or_default();
+        let mut record = LogStashRecord::new();
+        record.level = if matches!(kind, "io" | "connection" | "address_resolution") {
+            Level::Warn
+        } else {
+            Level::Error
+        };
+        record.target = DIAGNOSTICS_TARGET.into();
+        record
+            .add_data(
+                "message",
+                format!(
+                    "logstash sender recovered after {} failed attempts: {}",
+                    self.failures, error
+                )
+                .into(),
+            )
+            .add_data("error_kind", kind.into())
+            .add_data("error", error.into())
+            .add_data("retry_count", self.failures.into());
+        if let Some(endpoint) = &self.endpoint {
+            record.add_data("endpoint", endpoint.as_str().into());
+        }
+        record
+    }
+}
@@ -13,8 +13,12 @@ pub enum Error {
     TlsError(#[from] native_tls::Error),
     #[error("sender thread stopped: {0}")]
     SenderThreadStopped(String),
+    #[error("connection error: {0}")]
+    Connection(String),
     #[error("address resolution error: {0}:{1}")]
     AddressResolution(String, u16),
+    #[error("protocol error: {0}")]
+    Protocol(String),
     #[error("fatal internal error: {0}")]
     FatalInternal(String),
     #[cfg(all(not(feature = "tls"), feature = "rustls"))]
@@ -23,8 +27,115 @@ pub enum Error {
     #[cfg(all(not(feature = "tls"), feature = "rustls"))]
     #[error("rustls: {0}")]
     Rustls(#[from] rustls_crate::Error),
+    #[error("configuration error: {0}")]
+    Config(String),
+    #[error("timeout: {0}")]
+    Timeout(String),
+    #[cfg(feature = "cbor")]
+    #[error("cbor: {0}")]
+    Cbor(String),
     #[error("buffer is full")]
     BufferFull(),
+    #[error("sender is paused")]
+    Paused(),
+    #[error("partial write, {confirmed} records confirmed and {unconfirmed} not: {source}")]
+    PartialWrite {
+        confirmed: usize,
+        unconfirmed: usize,
+        source: Box<Error>,
+    },
+    #[error("multiple errors: {}", .0.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("; "))]
+    Multiple(Vec<Error>),
+}
+
+impl Error {
+    /// Short machine-readable name of the error variant
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::IO(_) => "io",
+            Error::FmtError(_) => "fmt",
+            Error::Serde(_) => "serde",
+            #[cfg(all(feature = "tls", not(feature = "rustls")))]
+            Error::TlsError(_) => "tls",
+            Error::SenderThreadStopped(_) => "sender_thread_stopped",
+            Error::Connection(_) => "connection",
+            Error::AddressResolution(..) => "address_resolution",
+            Error::Protocol(_) => "protocol",
+            Error::FatalInternal(_) => "fatal_internal",
+            #[cfg(all(not(feature = "tls"), feature = "rustls"))]
+            Error::InvalidDNSName(_) => "tls",
+            #[cfg(all(not(feature = "tls"), feature = "rustls"))]
+            Error::Rustls(_) => "tls",
+            Error::Config(_) => "config",
+            Error::Timeout(_) => "timeout",
+            #[cfg(feature = "cbor")]
+            Error::Cbor(_) => "cbor",
+            Error::BufferFull() => "buffer_full",
+            Error::Paused() => "paused",
+            Error::PartialWrite { .. } => "partial_write",
+            Error::Multiple(_) => "multiple",
+        }
+    }
+}
+
+/// The worker receiving from the channel has stopped
+impl<T> From<std::sync::mpsc::SendError<T>> for Error {
+    fn from(err: std::sync::mpsc::SendError<T>) -> Self {
+        Error::SenderThreadStopped(err.to_string())
+    }
+}
+
+impl<T> From<std::sync::mpsc::TrySendError<T>> for Error {
+    fn from(err: std::sync::mpsc::TrySendError<T>) -> Self {
+        match err {
+            std::sync::mpsc::TrySendError::Full(_) => Error::BufferFull(),
+            std::sync::mpsc::TrySendError::Disconnected(_) => {
+                Error::SenderThreadStopped(err.to_string())
+            }
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T> From<tokio::sync::mpsc::error::SendError<T>> for Error {
+    fn from(err: tokio::sync::mpsc::error::SendError<T>) -> Self {
+        Error::SenderThreadStopped(err.to_string())
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T> From<tokio::sync::mpsc::error::TrySendError<T>> for Error {
+    fn from(err: tokio::sync::mpsc::error::TrySendError<T>) -> Self {
+        match err {
+            tokio::sync::mpsc::error::TrySendError::Full(_) => Error::BufferFull(),
+            tokio::sync::mpsc::error::TrySendError::Closed(_) => {
+                Error::SenderThreadStopped(err.to_string())
+            }
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl From<tokio::sync::oneshot::error::RecvError> for Error {
+    fn from(err: tokio::sync::oneshot::error::RecvError) -> Self {
+        Error::SenderThreadStopped(err.to_string())
+    }
+}
+
+#[cfg(feature = "async")]
+impl From<tokio::task::JoinError> for Error {
+    fn from(err: tokio::task::JoinError) -> Self {
+        Error::SenderThreadStopped(err.to_string())
+    }
+}
+
+/// Turns errors collected from several senders into a single result
+pub(crate) fn combine(mut errors: Vec<Error>) -> crate::Result<()> {
+    match errors.len() {
+        0 => Ok(()),
+        1 => Err(errors.remove(0)),
+        _ => Err(Error::Multiple(errors)),
+    }
 }
 
 impl<T> From<PoisonError<T>> for Error {
@@ -1,60 +1,372 @@
+use crate::clock::{default_clock, ClockSource};
+use arc_swap::ArcSwapOption;
 use chrono::{DateTime, Utc};
 use log::Level;
+use regex::Regex;
 use serde::Serialize;
 use serde_json::Value;
-use std::{collections::HashMap, time::SystemTime};
+use std::{
+    borrow::Cow,
+    collections::hash_map::DefaultHasher,
+    collections::HashMap,
+    convert::TryFrom,
+    hash::{Hash, Hasher},
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+    sync::{Arc, OnceLock},
+    time::{Duration, SystemTime},
+};
 
-#[derive(Debug, Clone, Serialize)]
+#[cfg(feature = "cbor")]
+mod cbor;
+mod escape;
+
+#[cfg(feature = "cbor")]
+pub use cbor::SELF_DESCRIBE_TAG;
+pub use escape::EscapingTransformer;
+
+#[cfg(feature = "bytes")]
+thread_local! {
+    /// Records serialized to bytes are split off this buffer. Its allocation is reused once
+    /// the frames split off it are dropped, and a new one holds at least
+    /// `SERIALIZE_BUFFER_CAPACITY` bytes for the records that follow.
+    static SERIALIZE_BUFFER: std::cell::RefCell<bytes::BytesMut> =
+        std::cell::RefCell::new(bytes::BytesMut::with_capacity(SERIALIZE_BUFFER_CAPACITY));
+}
+
+#[cfg(feature = "bytes")]
+const SERIALIZE_BUFFER_CAPACITY: usize = 8 * 1024;
+
+/// Maximum number of fields added by a single [`LogStashRecord::snapshot_env`] or
+/// [`LogStashRecord::snapshot_env_keys`] call
+pub const MAX_ENV_FIELDS: usize = 50;
+
+/// Name written in `logger.name` by [`LogStashRecord::add_logger_info`]
+pub const LOGGER_NAME: &str = "logstash-rs";
+
+/// How `None` values of the optional record fields `module`, `file` and `line` are serialized
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NullPolicy {
+    /// Serialize as JSON `null`
+    SerializeAsNull,
+    /// Leave the field out, so Elasticsearch doesn't map it as null
+    #[default]
+    OmitIfNull,
+}
+
+/// Numeric scale of the `level_value` field
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LevelScale {
+    /// Order of `log::Level`: Error=1, Warn=2, Info=3, Debug=4, Trace=5
+    #[default]
+    Log,
+    /// Higher is more severe: Trace=1, Debug=2, Info=3, Warn=4, Error=5
+    Inverted,
+}
+
+impl LevelScale {
+    /// Numeric value of `level` on this scale
+    pub fn value(self, level: Level) -> usize {
+        match self {
+            LevelScale::Log => level as usize,
+            LevelScale::Inverted => Level::Trace as usize + 1 - level as usize,
+        }
+    }
+}
+
+static SERIALIZE_NULLS: AtomicBool = AtomicBool::new(false);
+
+/// Sets the null policy used when serializing all records
+pub fn set_null_policy(policy: NullPolicy) {
+    SERIALIZE_NULLS.store(policy == NullPolicy::SerializeAsNull, Ordering::Relaxed);
+}
+
+/// Null policy used when serializing records, [`NullPolicy::OmitIfNull`] unless changed with
+/// [`set_null_policy`]
+pub fn null_policy() -> NullPolicy {
+    if SERIALIZE_NULLS.load(Ordering::Relaxed) {
+        NullPolicy::SerializeAsNull
+    } else {
+        NullPolicy::OmitIfNull
+    }
+}
+
+static OMIT_TIMESTAMP: AtomicBool = AtomicBool::new(false);
+
+/// Leaves the `@timestamp` key out when serializing all records, for pipelines stamping
+/// records on ingest and rejecting a timestamp sent by the client
+///
+/// ```
+/// use qoollo_logstash_rs::{set_timestamp_enabled, LogStashRecord};
+///
+/// set_timestamp_enabled(false);
+/// let json = serde_json::to_value(LogStashRecord::new()).unwrap();
+/// assert!(json.get("@timestamp").is_none());
+/// ```
+pub fn set_timestamp_enabled(enabled: bool) {
+    OMIT_TIMESTAMP.store(!enabled, Ordering::Relaxed);
+}
+
+/// Whether records are serialized with `@timestamp`, `true` unless changed with
+/// [`set_timestamp_enabled`]
+pub fn timestamp_enabled() -> bool {
+    !OMIT_TIMESTAMP.load(Ordering::Relaxed)
+}
+
+/// Names of the levels from [`Level::Error`] to [`Level::Trace`], `None` until
+/// [`set_level_names`] is called. Read without locking by every serialized record.
+static LEVEL_NAMES: ArcSwapOption<[Cow<'static, str>; 5]> = ArcSwapOption::const_empty();
+
+/// Sets the names serialized in the `level` field of all records, e.g. `"WRN"` for
+/// [`Level::Warn`], replacing the names set before. Levels missing from `names` keep the
+/// names of `log::Level`.
+pub fn set_level_names(names: HashMap<Level, String>) {
+    let names = [
+        Level::Error,
+        Level::Warn,
+        Level::Info,
+        Level::Debug,
+        Level::Trace,
+    ]
+    .map(|level| match names.get(&level) {
+        Some(name) => Cow::Owned(name.clone()),
+        None => Cow::Borrowed(level.as_str()),
+    });
+    LEVEL_NAMES.store(Some(Arc::new(names)));
+}
+
+/// Level serialized as `name`, by the installed level names or the names of `log::Level`
+fn parse_level(name: &str) -> Option<Level> {
+    LEVEL_NAMES
+        .load()
+        .as_ref()
+        .and_then(|names| names.iter().position(|n| n == name))
+        .and_then(|index| Level::iter().nth(index))
+        .or_else(|| Level::from_str(name).ok())
+}
+
+/// Calls `f` with the name serialized in the `level` field for `level`
+fn with_level_name<R>(level: Level, f: impl FnOnce(&str) -> R) -> R {
+    match &*LEVEL_NAMES.load() {
+        Some(names) => f(&names[level as usize - 1]),
+        None => f(level.as_str()),
+    }
+}
+
+/// Name serialized in the `level` field for `level`, borrowed unless level names were set
+/// with [`set_level_names`]
+pub fn level_name(level: Level) -> Cow<'static, str> {
+    match &*LEVEL_NAMES.load() {
+        Some(names) => names[level as usize - 1].clone(),
+        None => Cow::Borrowed(level.as_str()),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct LogStashRecord {
+    /// Always set, records are stamped with the clock time when created so `@timestamp` is
+    /// never serialized as `null`. Left out of the JSON after `set_timestamp_enabled(false)`.
     #[serde(rename = "@timestamp")]
+    #[serde(skip_serializing_if = "omit_timestamp")]
     #[serde(with = "logstash_date_format")]
+    #[cfg_attr(feature = "schema", schemars(with = "DateTime<Utc>"))]
     pub timestamp: DateTime<Utc>,
-    pub module: Option<String>,
-    pub file: Option<String>,
+    /// Borrowed when taken from the `'static` locations of the log macros
+    #[serde(skip_serializing_if = "omit_null")]
+    pub module: Option<Cow<'static, str>>,
+    #[serde(skip_serializing_if = "omit_null")]
+    pub file: Option<Cow<'static, str>>,
+    #[serde(skip_serializing_if = "omit_null")]
     pub line: Option<u32>,
+    /// Not provided by `log::Record`, set by sources knowing the column
+    #[serde(skip_serializing_if = "omit_null")]
+    pub column: Option<u32>,
     #[serde(with = "level_serializer")]
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
     pub level: Level,
-    pub target: String,
+    pub target: Cow<'static, str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[cfg_attr(feature = "schema", schemars(default))]
+    pub tags: Vec<String>,
     #[serde(flatten)]
     pub fields: HashMap<String, Value>,
+    /// Fields whose strings are serialized as JSON with non-ASCII characters escaped
+    #[serde(skip)]
+    pub ascii_only_fields: Vec<String>,
+    /// Fields holding base64-encoded binary data, written as byte strings in CBOR
+    #[serde(skip)]
+    pub bytes_fields: Vec<String>,
 }
 
+/// Hashes the fields sorted by key, so equal records hash the same whatever order their
+/// fields were added in
+impl Hash for LogStashRecord {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.timestamp.hash(state);
+        self.module.hash(state);
+        self.file.hash(state);
+        self.line.hash(state);
+        self.column.hash(state);
+        self.level.hash(state);
+        self.target.hash(state);
+        self.tags.hash(state);
+        let mut fields: Vec<_> = self.fields.iter().collect();
+        fields.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        fields.hash(state);
+        self.ascii_only_fields.hash(state);
+        self.bytes_fields.hash(state);
+    }
+}
+
+/// Empty record stamped with the current time. The level defaults to `Warn`, use
+/// [`LogStashRecord::builder`] to set it explicitly.
 impl Default for LogStashRecord {
     fn default() -> Self {
         Self {
-            timestamp: Utc::now(),
+            timestamp: default_clock().now(),
             module: Default::default(),
             file: Default::default(),
             line: Default::default(),
+            column: Default::default(),
             level: Level::Warn,
             target: Default::default(),
+            tags: Default::default(),
             fields: Default::default(),
+            ascii_only_fields: Default::default(),
+            bytes_fields: Default::default(),
         }
     }
 }
 
 impl LogStashRecord {
-    /// Initialize record with current time in `timestamp` field
+    /// Initialize record with current time of the default clock in `timestamp` field
     pub fn new() -> Self {
+        Self::new_with_clock(default_clock().as_ref())
+    }
+
+    /// Initialize record with current time of `clock` in `timestamp` field
+    pub fn new_with_clock(clock: &dyn ClockSource) -> Self {
         Self {
-            timestamp: Utc::now(),
+            timestamp: clock.now(),
             ..Default::default()
         }
     }
 
+    /// Starts building a record of the given level
+    ///
+    /// The level has no default, leaving it out doesn't compile:
+    ///
+    /// ```compile_fail
+    /// use qoollo_logstash_rs::LogStashRecord;
+    ///
+    /// let record = LogStashRecord::builder().message("hello").build();
+    /// ```
+    pub fn builder(level: Level) -> LogStashRecordBuilder {
+        LogStashRecordBuilder::new(level)
+    }
+
+    /// JSON schema of the serialized record
+    #[cfg(feature = "schema")]
+    pub fn json_schema() -> schemars::schema::RootSchema {
+        schemars::schema_for!(LogStashRecord)
+    }
+
     pub fn from_record(record: &log::Record) -> Self {
-        let mut event = LogStashRecord::new();
-        let meta = record.metadata();
+        Self::from_record_with_clock(record, default_clock().as_ref())
+    }
+
+    pub fn from_record_with_clock(record: &log::Record, clock: &dyn ClockSource) -> Self {
+        let mut event = LogStashRecord::new_with_clock(clock);
+        event.fill_from_record(record);
+        event
+    }
 
-        event.module = record.module_path().map(|p| p.into());
-        event.file = record.file().map(|p| p.into());
-        event.line = record.line();
-        event.level = meta.level();
-        event.target = meta.target().into();
-        event.add_data("message", record.args().to_string().into());
+    /// Same as [`from_record`](Self::from_record), reusing a record of `pool`
+    #[cfg(feature = "pool")]
+    pub fn from_record_pooled<'a>(
+        record: &log::Record,
+        pool: &'a crate::pool::RecordPool,
+    ) -> crate::pool::PooledRecord<'a> {
+        let mut event = pool.acquire();
+        event.fill_from_record(record);
         event
     }
 
+    /// Parses a record serialized as a JSON object, e.g. a line of a log file to re-send.
+    /// Missing or mistyped known fields keep their defaults: the current time for
+    /// `@timestamp` and `Warn` for `level`. Other keys are kept in `fields`.
+    /// Fails only if `json` is not a JSON object.
+    pub fn from_json_str(json: &str) -> crate::Result<Self> {
+        Ok(Self::from_json_map(serde_json::from_str(json)?))
+    }
+
+    /// Record from the keys of a parsed JSON object, see [`from_json_str`](Self::from_json_str)
+    fn from_json_map(mut fields: serde_json::Map<String, Value>) -> Self {
+        let mut take_string = |key: &str| match fields.remove(key) {
+            Some(Value::String(value)) => Some(value),
+            _ => None,
+        };
+
+        let mut event = Self::default();
+        if let Some(timestamp) = take_string("@timestamp")
+            .and_then(|timestamp| DateTime::parse_from_rfc3339(&timestamp).ok())
+        {
+            event.timestamp = timestamp.with_timezone(&Utc);
+        }
+        event.module = take_string("module").map(Cow::Owned);
+        event.file = take_string("file").map(Cow::Owned);
+        if let Some(level) = take_string("level").and_then(|name| parse_level(&name)) {
+            event.level = level;
+        }
+        event.target = take_string("target").map_or(Cow::Borrowed(""), Cow::Owned);
+        let mut take_u32 = |key| {
+            fields
+                .remove(key)
+                .and_then(|value| value.as_u64())
+                .and_then(|value| u32::try_from(value).ok())
+        };
+        event.line = take_u32("line");
+        event.column = take_u32("column");
+        if let Some(Value::Array(tags)) = fields.remove("tags") {
+            event.tags = tags
+                .into_iter()
+                .filter_map(|tag| match tag {
+                    Value::String(tag) => Some(tag),
+                    _ => None,
+                })
+                .collect();
+        }
+        event.fields = fields.into_iter().collect();
+        event
+    }
+
+    fn fill_from_record(&mut self, record: &log::Record) {
+        let meta = record.metadata();
+
+        self.module = static_or_owned(record.module_path_static(), record.module_path());
+        self.file = static_or_owned(record.file_static(), record.file());
+        self.line = record.line();
+        self.column = None;
+        self.level = meta.level();
+        // The target defaults to the module path, share it instead of copying
+        self.target = match record.module_path_static() {
+            Some(module) if module == meta.target() => Cow::Borrowed(module),
+            _ => Cow::Owned(meta.target().to_string()),
+        };
+        self.add_data("message", record.args().to_string().into());
+    }
+
+    /// Hash of the whole record for deduplication, equal for equal records within a build
+    /// of the program
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
+
     pub fn set_timestamp(&mut self, timestamp: SystemTime) -> &mut Self {
         self.timestamp = timestamp.into();
         self
@@ -70,6 +382,343 @@ impl LogStashRecord {
         self
     }
 
+    /// Adds any serializable value as field `key`, converted to JSON. Fails leaving the
+    /// record unchanged if the conversion fails, e.g. for a map with non-string keys; use
+    /// `.ok()` to drop such values instead.
+    ///
+    /// ```
+    /// use qoollo_logstash_rs::LogStashRecord;
+    /// use serde_json::json;
+    ///
+    /// #[derive(serde::Serialize)]
+    /// struct Request {
+    ///     method: &'static str,
+    ///     path: String,
+    ///     status: u16,
+    /// }
+    ///
+    /// let request = Request { method: "GET", path: "/health".into(), status: 200 };
+    /// let mut record = LogStashRecord::new();
+    /// record.try_add_data("request", &request).unwrap();
+    /// assert_eq!(
+    ///     record.fields["request"],
+    ///     json!({ "method": "GET", "path": "/health", "status": 200 })
+    /// );
+    ///
+    /// let by_id: std::collections::HashMap<(u8, u8), u8> = [((1, 2), 3)].into();
+    /// assert!(record.try_add_data("by_id", &by_id).is_err());
+    /// assert!(!record.fields.contains_key("by_id"));
+    /// ```
+    pub fn try_add_data<T: Serialize + ?Sized>(
+        &mut self,
+        key: &str,
+        value: &T,
+    ) -> crate::Result<&mut Self> {
+        let value = serde_json::to_value(value)?;
+        Ok(self.add_data(key, value))
+    }
+
+    /// Serialize the strings of `fields` with non-ASCII characters escaped as `\uXXXX`
+    pub fn escape_non_ascii(&mut self, fields: &[&str]) -> &mut Self {
+        EscapingTransformer::new(fields.iter().map(|f| f.to_string()).collect()).apply(self);
+        self
+    }
+
+    /// Serializes the record as compact JSON into `writer`
+    pub(crate) fn write_json<W: std::io::Write>(&self, writer: W) -> serde_json::Result<()> {
+        if self.ascii_only_fields.is_empty() {
+            return serde_json::to_writer(writer, self);
+        }
+        escape::write_escaped(writer, self)
+    }
+
+    /// Serializes the record into a reference-counted buffer
+    #[cfg(feature = "bytes")]
+    pub fn to_json_bytes(&self) -> crate::Result<bytes::Bytes> {
+        self.serialize_bytes(false)
+    }
+
+    /// Serializes the record followed by a newline into a reference-counted buffer
+    #[cfg(feature = "bytes")]
+    pub fn to_json_bytes_with_newline(&self) -> crate::Result<bytes::Bytes> {
+        self.serialize_bytes(true)
+    }
+
+    /// Serializes the record at the end of the buffer of this thread and splits it off, so
+    /// records serialized one after the other share an allocation
+    #[cfg(feature = "bytes")]
+    fn serialize_bytes(&self, newline: bool) -> crate::Result<bytes::Bytes> {
+        use bytes::BufMut;
+        SERIALIZE_BUFFER.with(|buffer| {
+            let mut buffer = buffer.borrow_mut();
+            // Leftovers of a record that failed to serialize
+            buffer.clear();
+            let needed = self.estimated_json_size() + 1;
+            if buffer.capacity() < needed {
+                buffer.reserve(needed.max(SERIALIZE_BUFFER_CAPACITY));
+            }
+            self.write_json((&mut *buffer).writer())?;
+            if newline {
+                buffer.put_u8(b'\n');
+            }
+            Ok(buffer.split().freeze())
+        })
+    }
+
+    /// Cheap estimate of the serialized JSON size, ignoring string escaping
+    pub fn estimated_json_size(&self) -> usize {
+        // Timestamp, level and the names of fixed fields
+        const FIXED_SIZE: usize = 96;
+        FIXED_SIZE
+            + self.module.as_ref().map_or(4, |m| m.len() + 2)
+            + self.file.as_ref().map_or(4, |f| f.len() + 2)
+            + self.target.len()
+            + self.tags.iter().map(|t| t.len() + 3).sum::<usize>()
+            + self
+                .fields
+                .iter()
+                .map(|(k, v)| k.len() + 4 + estimated_value_size(v))
+                .sum::<usize>()
+    }
+
+    /// Shrinks the largest field values until the estimated JSON size fits in `max_bytes`,
+    /// adding a `truncated` field. Strings are cut at the end, other values replaced with
+    /// null. Returns whether the record fits, the fixed fields are never truncated.
+    pub fn truncate_to(&mut self, max_bytes: usize) -> bool {
+        if self.estimated_json_size() <= max_bytes {
+            return true;
+        }
+        self.add_data("truncated", true.into());
+        loop {
+            let size = self.estimated_json_size();
+            if size <= max_bytes {
+                return true;
+            }
+            let largest = self
+                .fields
+                .iter_mut()
+                .filter(|(key, value)| {
+                    *key != "truncated"
+                        && !matches!(value, Value::Null)
+                        && !matches!(value, Value::String(s) if s.is_empty())
+                })
+                .map(|(_, value)| value)
+                .max_by_key(|value| estimated_value_size(value));
+            match largest {
+                Some(Value::String(value)) => {
+                    let mut len = value.len().saturating_sub(size - max_bytes);
+                    while !value.is_char_boundary(len) {
+                        len -= 1;
+                    }
+                    value.truncate(len);
+                }
+                Some(value) => *value = Value::Null,
+                None => return false,
+            }
+        }
+    }
+
+    /// Replaces the value of the string field `key` with `mask`
+    pub fn redact_value(&mut self, key: &str, mask: &str) -> &mut Self {
+        if let Some(Value::String(value)) = self.fields.get_mut(key) {
+            *value = mask.into();
+        }
+        self
+    }
+
+    /// Replaces substrings matching `pattern` with `mask` in all string field values
+    pub fn redact_pattern(&mut self, pattern: &Regex, mask: &str) -> &mut Self {
+        for value in self.fields.values_mut() {
+            redact_pattern_in(value, pattern, mask);
+        }
+        self
+    }
+
+    /// Removes ANSI color sequences from the string field `field`
+    pub fn strip_ansi_codes(&mut self, field: &str) -> &mut Self {
+        if let Some(Value::String(value)) = self.fields.get_mut(field) {
+            if ansi_regex().is_match(value) {
+                *value = ansi_regex().replace_all(value, "").into_owned();
+            }
+        }
+        self
+    }
+
+    /// Removes ANSI color sequences from all string field values
+    pub fn strip_ansi_codes_all(&mut self) -> &mut Self {
+        self.redact_pattern(ansi_regex(), "")
+    }
+
+    /// Removes `prefix` and the path separators following it from the `file` field. Pass
+    /// `env!("CARGO_MANIFEST_DIR")` to keep paths relative to the crate root.
+    pub fn strip_file_prefix(&mut self, prefix: &str) -> &mut Self {
+        let relative = match &self.file {
+            Some(file) => match file.strip_prefix(prefix) {
+                Some(relative) => file.len() - relative.trim_start_matches(['/', '\\']).len(),
+                None => return self,
+            },
+            None => return self,
+        };
+        self.file = self.file.take().map(|file| match file {
+            Cow::Borrowed(file) => Cow::Borrowed(&file[relative..]),
+            Cow::Owned(file) => Cow::Owned(file[relative..].to_string()),
+        });
+        self
+    }
+
+    /// Adds environment variables with names starting with `prefix` as `env.<name>` fields
+    /// with lowercase names, at most [`MAX_ENV_FIELDS`] of them in name order
+    pub fn snapshot_env(&mut self, prefix: &str) -> &mut Self {
+        let mut vars: Vec<_> = std::env::vars_os()
+            .filter_map(|(name, value)| {
+                let name = name.into_string().ok()?;
+                name.starts_with(prefix).then_some((name, value))
+            })
+            .collect();
+        vars.sort_by(|(a, _), (b, _)| a.cmp(b));
+        for (name, value) in vars.into_iter().take(MAX_ENV_FIELDS) {
+            self.add_env_field(&name, &value);
+        }
+        self
+    }
+
+    /// Adds the listed environment variables which are set as `env.<name>` fields with
+    /// lowercase names, at most [`MAX_ENV_FIELDS`] of them
+    pub fn snapshot_env_keys(&mut self, keys: &[&str]) -> &mut Self {
+        let vars = keys
+            .iter()
+            .filter_map(|key| Some((key, std::env::var_os(key)?)))
+            .take(MAX_ENV_FIELDS);
+        for (name, value) in vars {
+            self.add_env_field(name, &value);
+        }
+        self
+    }
+
+    fn add_env_field(&mut self, name: &str, value: &std::ffi::OsStr) {
+        let key = format!("env.{}", name.to_lowercase());
+        self.add_data(&key, value.to_string_lossy().into_owned().into());
+    }
+
+    /// Adds `level_value` field with the numeric level on the given scale
+    pub fn add_level_value(&mut self, scale: LevelScale) -> &mut Self {
+        self.add_data("level_value", scale.value(self.level).into())
+    }
+
+    /// Adds `module_short` field with the last segment of the module path
+    pub fn add_module_short(&mut self) -> &mut Self {
+        if let Some(short) = self.module.as_deref().and_then(|m| m.rsplit("::").next()) {
+            let short = short.to_string();
+            self.add_data("module_short", short.into());
+        }
+        self
+    }
+
+    /// Adds `location` field joining the file, line and column known, e.g. `src/main.rs:42`.
+    /// Nothing is added without a file.
+    ///
+    /// ```
+    /// use qoollo_logstash_rs::LogStashRecord;
+    ///
+    /// let mut record = LogStashRecord::new();
+    /// record.file = Some("src/main.rs".into());
+    /// record.line = Some(42);
+    /// record.add_location();
+    /// assert_eq!(record.fields["location"], "src/main.rs:42");
+    /// ```
+    pub fn add_location(&mut self) -> &mut Self {
+        if let Some(file) = &self.file {
+            let mut location = file.to_string();
+            if let Some(line) = self.line {
+                location.push_str(&format!(":{}", line));
+                if let Some(column) = self.column {
+                    location.push_str(&format!(":{}", column));
+                }
+            }
+            self.add_data("location", location.into());
+        }
+        self
+    }
+
+    /// Adds the `logger` object with the `name` and `version` of this crate, and the
+    /// `config_hash` of the logger configuration if given, to tell which build and
+    /// configuration produced the record
+    pub fn add_logger_info(&mut self, config_hash: Option<&str>) -> &mut Self {
+        let mut logger = serde_json::Map::new();
+        logger.insert("name".into(), LOGGER_NAME.into());
+        logger.insert("version".into(), env!("CARGO_PKG_VERSION").into());
+        if let Some(config_hash) = config_hash {
+            logger.insert("config_hash".into(), config_hash.into());
+        }
+        self.add_data("logger", Value::Object(logger))
+    }
+
+    /// Adds `duration` as a number of milliseconds with microsecond precision, and `ms` as
+    /// `{key}_unit`
+    ///
+    /// ```
+    /// use qoollo_logstash_rs::LogStashRecord;
+    /// use std::time::Duration;
+    ///
+    /// let mut record = LogStashRecord::new();
+    /// record.add_duration("elapsed", Duration::from_nanos(1_234_567_890));
+    /// assert_eq!(record.fields["elapsed"], 1234.567);
+    /// assert_eq!(record.fields["elapsed_unit"], "ms");
+    /// ```
+    pub fn add_duration(&mut self, key: &str, duration: Duration) -> &mut Self {
+        let millis = duration.as_micros() as f64 / 1000.0;
+        self.add_data(key, millis.into())
+            .add_data(&format!("{}_unit", key), "ms".into())
+    }
+
+    /// Adds `duration` as an integer number of nanoseconds, and `ns` as `{key}_unit`
+    ///
+    /// ```
+    /// use qoollo_logstash_rs::LogStashRecord;
+    /// use std::time::Duration;
+    ///
+    /// let mut record = LogStashRecord::new();
+    /// record.add_duration_ns("elapsed", Duration::from_nanos(1_234_567_890));
+    /// assert_eq!(record.fields["elapsed"], 1_234_567_890u64);
+    /// assert_eq!(record.fields["elapsed_unit"], "ns");
+    /// ```
+    pub fn add_duration_ns(&mut self, key: &str, duration: Duration) -> &mut Self {
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        self.add_data(key, nanos.into())
+            .add_data(&format!("{}_unit", key), "ns".into())
+    }
+
+    /// Adds `duration` as an ISO 8601 duration string, see [`format_duration`]
+    ///
+    /// ```
+    /// use qoollo_logstash_rs::LogStashRecord;
+    /// use std::time::Duration;
+    ///
+    /// let mut record = LogStashRecord::new();
+    /// record.add_duration_human("elapsed", Duration::from_millis(3_723_500));
+    /// assert_eq!(record.fields["elapsed"], "PT1H2M3.5S");
+    /// ```
+    pub fn add_duration_human(&mut self, key: &str, duration: Duration) -> &mut Self {
+        self.add_data(key, format_duration(duration).into())
+    }
+
+    /// Adds `tag` to the `tags` array unless it is already present
+    pub fn add_tag(&mut self, tag: &str) -> &mut Self {
+        if !self.tags.iter().any(|t| t == tag) {
+            self.tags.push(tag.into());
+        }
+        self
+    }
+
+    /// Adds every tag of `tags` with [`add_tag`](Self::add_tag), skipping the ones already
+    /// present
+    pub fn with_tags(mut self, tags: &[String]) -> Self {
+        for tag in tags {
+            self.add_tag(tag);
+        }
+        self
+    }
+
     pub fn with_data_from_map(mut self, extra_fields: &HashMap<String, Value>) -> Self {
         if !extra_fields.is_empty() {
             self.fields.extend(
@@ -80,6 +729,181 @@ impl LogStashRecord {
         }
         self
     }
+
+    /// String value of the `message` field, converting a non-string value to its JSON text
+    fn message_mut(&mut self) -> &mut String {
+        let message = self
+            .fields
+            .entry("message".into())
+            .or_insert_with(|| Value::String(String::new()));
+        if !message.is_string() {
+            *message = Value::String(message.to_string());
+        }
+        match message {
+            Value::String(message) => message,
+            _ => unreachable!("message was converted to a string"),
+        }
+    }
+}
+
+/// Appends to the `message` field, e.g. `write!(record, "status={}", code)`
+impl std::fmt::Write for LogStashRecord {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        self.message_mut().push_str(s);
+        Ok(())
+    }
+
+    fn write_char(&mut self, c: char) -> std::fmt::Result {
+        self.message_mut().push(c);
+        Ok(())
+    }
+}
+
+/// Builder of [`LogStashRecord`] created by [`LogStashRecord::builder`].
+///
+/// The level is taken by the constructor, so a record can't be built without choosing it.
+#[derive(Debug, Clone)]
+pub struct LogStashRecordBuilder {
+    record: LogStashRecord,
+}
+
+impl LogStashRecordBuilder {
+    fn new(level: Level) -> Self {
+        Self {
+            record: LogStashRecord {
+                level,
+                ..LogStashRecord::new()
+            },
+        }
+    }
+
+    /// Sets the timestamp, the current time of the default clock by default
+    pub fn timestamp(mut self, timestamp: DateTime<Utc>) -> Self {
+        self.record.timestamp = timestamp;
+        self
+    }
+
+    pub fn level(mut self, level: Level) -> Self {
+        self.record.level = level;
+        self
+    }
+
+    pub fn target(mut self, target: impl Into<Cow<'static, str>>) -> Self {
+        self.record.target = target.into();
+        self
+    }
+
+    /// Sets the `message` field
+    pub fn message(self, message: impl Into<String>) -> Self {
+        self.field("message", message.into())
+    }
+
+    pub fn field(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.record.add_data(key, value.into());
+        self
+    }
+
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.record.add_tag(tag);
+        self
+    }
+
+    pub fn build(self) -> LogStashRecord {
+        self.record
+    }
+}
+
+/// Formats `duration` as an ISO 8601 duration in hours, minutes and seconds, e.g.
+/// `PT1H2M3.5S`, with up to nanosecond precision
+///
+/// ```
+/// use qoollo_logstash_rs::format_duration;
+/// use std::time::Duration;
+///
+/// assert_eq!(format_duration(Duration::ZERO), "PT0S");
+/// assert_eq!(format_duration(Duration::from_micros(1_500)), "PT0.0015S");
+/// assert_eq!(format_duration(Duration::from_secs(90_000)), "PT25H");
+/// ```
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, minutes, seconds) = (secs / 3600, secs / 60 % 60, secs % 60);
+    let nanos = duration.subsec_nanos();
+    let mut text = String::from("PT");
+    if hours > 0 {
+        text.push_str(&format!("{}H", hours));
+    }
+    if minutes > 0 {
+        text.push_str(&format!("{}M", minutes));
+    }
+    if seconds > 0 || nanos > 0 || secs == 0 {
+        text.push_str(&seconds.to_string());
+        if nanos > 0 {
+            let fraction = format!("{:09}", nanos);
+            text.push('.');
+            text.push_str(fraction.trim_end_matches('0'));
+        }
+        text.push('S');
+    }
+    text
+}
+
+fn static_or_owned(
+    static_str: Option<&'static str>,
+    owned: Option<&str>,
+) -> Option<Cow<'static, str>> {
+    match static_str {
+        Some(s) => Some(Cow::Borrowed(s)),
+        None => owned.map(|s| Cow::Owned(s.to_string())),
+    }
+}
+
+fn omit_null<T>(value: &Option<T>) -> bool {
+    value.is_none() && null_policy() == NullPolicy::OmitIfNull
+}
+
+fn omit_timestamp(_: &DateTime<Utc>) -> bool {
+    !timestamp_enabled()
+}
+
+fn ansi_regex() -> &'static Regex {
+    static ANSI: OnceLock<Regex> = OnceLock::new();
+    ANSI.get_or_init(|| Regex::new(r"\x1b\[[0-9;]*m").expect("valid regex"))
+}
+
+fn estimated_value_size(value: &Value) -> usize {
+    match value {
+        Value::Null => 4,
+        Value::Bool(_) => 5,
+        Value::Number(_) => 20,
+        Value::String(s) => s.len() + 2,
+        Value::Array(values) => {
+            2 + values
+                .iter()
+                .map(|v| estimated_value_size(v) + 1)
+                .sum::<usize>()
+        }
+        Value::Object(map) => {
+            2 + map
+                .iter()
+                .map(|(k, v)| k.len() + 4 + estimated_value_size(v))
+                .sum::<usize>()
+        }
+    }
+}
+
+fn redact_pattern_in(value: &mut Value, pattern: &Regex, mask: &str) {
+    match value {
+        Value::String(s) if pattern.is_match(s) => {
+            *s = pattern.replace_all(s, regex::NoExpand(mask)).into_owned();
+        }
+        Value::Array(values) => values
+            .iter_mut()
+            .for_each(|v| redact_pattern_in(v, pattern, mask)),
+        Value::Object(map) => map
+            .values_mut()
+            .for_each(|v| redact_pattern_in(v, pattern, mask)),
+        _ => {}
+    }
 }
 
 mod logstash_date_format {
@@ -103,6 +927,6 @@ mod level_serializer {
     where
         S: Serializer,
     {
-        serializer.serialize_str(level.as_str())
+        super::with_level_name(*level, |name| serializer.serialize_str(name))
     }
 }
@@ -0,0 +1,134 @@
+//! CBOR serialization of records, for binary payloads without base64 in the wire format.
+//!
+//! ```
+//! use qoollo_logstash_rs::LogStashRecord;
+//!
+//! let mut record = LogStashRecord::builder(log::Level::Info).message("upload").build();
+//! record.add_bytes("digest", &[0xde, 0xad, 0xbe, 0xef]);
+//! let cbor = record.to_cbor().unwrap();
+//! let parsed = LogStashRecord::from_cbor(&cbor).unwrap();
+//! assert_eq!(parsed.fields, record.fields);
+//! assert_eq!(parsed.bytes_fields, ["digest"]);
+//! ```
+
+use super::LogStashRecord;
+use crate::prelude::*;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ciborium::Value as Cbor;
+use serde_json::{Map, Number, Value};
+use std::convert::TryFrom;
+
+/// Tag marking the data as CBOR, written around every record
+pub const SELF_DESCRIBE_TAG: u64 = 55799;
+/// Tag of RFC 3339 date and time strings
+const DATE_TIME_TAG: u64 = 0;
+
+impl LogStashRecord {
+    /// Adds `bytes` as field `key`, base64-encoded in JSON and a byte string in CBOR
+    pub fn add_bytes(&mut self, key: &str, bytes: &[u8]) -> &mut Self {
+        if !self.bytes_fields.iter().any(|field| field == key) {
+            self.bytes_fields.push(key.into());
+        }
+        self.add_data(key, BASE64.encode(bytes).into())
+    }
+
+    /// Serializes the record as a CBOR map with the self-describe tag. `@timestamp` is a
+    /// date and time string with tag 0, and the fields added by
+    /// [`add_bytes`](Self::add_bytes) are byte strings.
+    pub fn to_cbor(&self) -> Result<Vec<u8>> {
+        let mut buffer = Vec::with_capacity(self.estimated_json_size());
+        self.write_cbor(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Serializes the record as [`to_cbor`](Self::to_cbor) into `writer`
+    pub fn write_cbor<W: std::io::Write>(&self, writer: W) -> Result<()> {
+        let entries = match Cbor::serialized(self).map_err(cbor_error)? {
+            Cbor::Map(entries) => entries,
+            _ => return Err(Error::Cbor("record is not serialized as a map".into())),
+        };
+        let entries = entries
+            .into_iter()
+            .map(|(key, value)| {
+                let value = match (key.as_text(), value) {
+                    (Some("@timestamp"), value) => Cbor::Tag(DATE_TIME_TAG, Box::new(value)),
+                    (Some(key), Cbor::Text(text)) if self.bytes_fields.iter().any(|f| f == key) => {
+                        match BASE64.decode(&text) {
+                            Ok(bytes) => Cbor::Bytes(bytes),
+                            Err(_) => Cbor::Text(text),
+                        }
+                    }
+                    (_, value) => value,
+                };
+                (key, value)
+            })
+            .collect();
+        let tagged = Cbor::Tag(SELF_DESCRIBE_TAG, Box::new(Cbor::Map(entries)));
+        ciborium::into_writer(&tagged, writer).map_err(cbor_error)
+    }
+
+    /// Parses a record written by [`to_cbor`](Self::to_cbor), or any CBOR map the same way
+    /// as [`from_json_str`](Self::from_json_str). Byte strings at the top level become
+    /// fields added by [`add_bytes`](Self::add_bytes), nested ones base64 strings.
+    pub fn from_cbor(cbor: &[u8]) -> Result<Self> {
+        let mut value: Cbor = ciborium::from_reader(cbor).map_err(cbor_error)?;
+        while let Cbor::Tag(_, inner) = value {
+            value = *inner;
+        }
+        let entries = match value {
+            Cbor::Map(entries) => entries,
+            _ => return Err(Error::Cbor("record is not a map".into())),
+        };
+        let mut bytes_fields = vec![];
+        let mut fields = Map::new();
+        for (key, value) in entries {
+            let key = match key {
+                Cbor::Text(key) => key,
+                _ => return Err(Error::Cbor("map key is not a string".into())),
+            };
+            if value.is_bytes() {
+                bytes_fields.push(key.clone());
+            }
+            fields.insert(key, to_json(value)?);
+        }
+        let mut record = Self::from_json_map(fields);
+        record.bytes_fields = bytes_fields;
+        Ok(record)
+    }
+}
+
+/// JSON value of a CBOR value, with byte strings base64-encoded and tags left out
+fn to_json(value: Cbor) -> Result<Value> {
+    Ok(match value {
+        Cbor::Null => Value::Null,
+        Cbor::Bool(value) => Value::Bool(value),
+        Cbor::Integer(value) => {
+            let value = i128::from(value);
+            match (u64::try_from(value), i64::try_from(value)) {
+                (Ok(value), _) => value.into(),
+                (_, Ok(value)) => value.into(),
+                _ => return Err(Error::Cbor(format!("integer {} out of range", value))),
+            }
+        }
+        Cbor::Float(value) => Number::from_f64(value).map_or(Value::Null, Value::Number),
+        Cbor::Text(text) => Value::String(text),
+        Cbor::Bytes(bytes) => Value::String(BASE64.encode(bytes)),
+        Cbor::Array(items) => Value::Array(items.into_iter().map(to_json).collect::<Result<_>>()?),
+        Cbor::Map(entries) => Value::Object(
+            entries
+                .into_iter()
+                .map(|(key, value)| match key {
+                    Cbor::Text(key) => Ok((key, to_json(value)?)),
+                    _ => Err(Error::Cbor("map key is not a string".into())),
+                })
+                .collect::<Result<_>>()?,
+        ),
+        Cbor::Tag(_, value) => to_json(*value)?,
+        _ => return Err(Error::Cbor("unsupported value".into())),
+    })
+}
+
+fn cbor_error(err: impl std::fmt::Display) -> Error {
+    Error::Cbor(err.to_string())
+}
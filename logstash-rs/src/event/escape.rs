@@ -0,0 +1,120 @@
+use super::LogStashRecord;
+use serde::Serialize;
+use serde_json::ser::{CompactFormatter, Formatter};
+use std::io::{self, Write};
+
+/// Marks string fields of records to be serialized with non-ASCII characters escaped as
+/// `\uXXXX`, for Logstash codecs mis-parsing UTF-8 in JSON strings
+#[derive(Debug, Clone, Default)]
+pub struct EscapingTransformer {
+    fields: Vec<String>,
+}
+
+impl EscapingTransformer {
+    /// Escapes the values of the top-level `fields`, other fields are serialized as they are
+    pub fn new(fields: Vec<String>) -> Self {
+        Self { fields }
+    }
+
+    pub fn apply(&self, record: &mut LogStashRecord) {
+        for field in &self.fields {
+            if !record.ascii_only_fields.contains(field) {
+                record.ascii_only_fields.push(field.clone());
+            }
+        }
+    }
+}
+
+/// Serializes `record` as compact JSON, escaping its ASCII-only fields
+pub(crate) fn write_escaped<W: Write>(
+    writer: W,
+    record: &LogStashRecord,
+) -> serde_json::Result<()> {
+    let formatter = EscapingFormatter {
+        fields: &record.ascii_only_fields,
+        depth: 0,
+        in_key: false,
+        key: String::new(),
+        escaping: false,
+    };
+    record.serialize(&mut serde_json::Serializer::with_formatter(
+        writer, formatter,
+    ))
+}
+
+/// Compact formatter escaping the strings inside the values of the listed top-level keys
+struct EscapingFormatter<'a> {
+    fields: &'a [String],
+    depth: usize,
+    in_key: bool,
+    /// Top-level key being written
+    key: String,
+    escaping: bool,
+}
+
+impl Formatter for EscapingFormatter<'_> {
+    fn begin_object<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.depth += 1;
+        CompactFormatter.begin_object(writer)
+    }
+
+    fn end_object<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.depth -= 1;
+        CompactFormatter.end_object(writer)
+    }
+
+    fn begin_object_key<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        if self.depth == 1 {
+            self.in_key = true;
+            self.key.clear();
+        }
+        CompactFormatter.begin_object_key(writer, first)
+    }
+
+    fn end_object_key<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.in_key = false;
+        CompactFormatter.end_object_key(writer)
+    }
+
+    fn begin_object_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        if self.depth == 1 {
+            self.escaping = self.fields.contains(&self.key);
+        }
+        CompactFormatter.begin_object_value(writer)
+    }
+
+    fn end_object_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        if self.depth == 1 {
+            self.escaping = false;
+        }
+        CompactFormatter.end_object_value(writer)
+    }
+
+    fn write_string_fragment<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        fragment: &str,
+    ) -> io::Result<()> {
+        if self.in_key {
+            self.key.push_str(fragment);
+        }
+        if !self.escaping {
+            return writer.write_all(fragment.as_bytes());
+        }
+        let mut units = [0u16; 2];
+        for c in fragment.chars() {
+            if c.is_ascii() {
+                writer.write_all(&[c as u8])?;
+            } else {
+                for unit in c.encode_utf16(&mut units) {
+                    write!(writer, "\\u{:04x}", unit)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
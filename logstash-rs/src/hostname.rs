@@ -0,0 +1,102 @@
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::Duration;
+
+/// Source of the `host` field value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HostnameProvider {
+    /// Fixed value, e.g. a Kubernetes pod name
+    Static(String),
+    /// System hostname resolved once and optionally refreshed by the worker thread
+    CachedSystem { refresh: Option<Duration> },
+    /// Do not emit the `host` field
+    Disabled,
+}
+
+impl Default for HostnameProvider {
+    fn default() -> Self {
+        Self::CachedSystem { refresh: None }
+    }
+}
+
+/// Shared handle to the current hostname. Clones observe provider swaps and refreshes.
+#[derive(Debug, Clone)]
+pub struct HostnameCache {
+    state: Arc<RwLock<HostnameState>>,
+}
+
+#[derive(Debug)]
+struct HostnameState {
+    provider: HostnameProvider,
+    value: Option<String>,
+}
+
+impl Default for HostnameCache {
+    fn default() -> Self {
+        Self::new(HostnameProvider::default())
+    }
+}
+
+impl HostnameCache {
+    pub fn new(provider: HostnameProvider) -> Self {
+        let value = resolve(&provider);
+        Self {
+            state: Arc::new(RwLock::new(HostnameState { provider, value })),
+        }
+    }
+
+    /// Current hostname, `None` if disabled
+    pub fn get(&self) -> Option<String> {
+        self.state
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .value
+            .clone()
+    }
+
+    /// Replaces the provider and resolves the hostname again
+    pub fn set_provider(&self, provider: HostnameProvider) {
+        let value = resolve(&provider);
+        let mut state = self.state.write().unwrap_or_else(PoisonError::into_inner);
+        state.provider = provider;
+        state.value = value;
+    }
+
+    /// Resolves the system hostname again
+    pub fn refresh(&self) {
+        let provider = self.provider();
+        if let HostnameProvider::CachedSystem { .. } = provider {
+            let value = resolve(&provider);
+            self.state
+                .write()
+                .unwrap_or_else(PoisonError::into_inner)
+                .value = value;
+        }
+    }
+
+    pub fn provider(&self) -> HostnameProvider {
+        self.state
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .provider
+            .clone()
+    }
+
+    /// Interval of the refresh done by the buffered sender worker
+    #[cfg(feature = "buffered")]
+    pub(crate) fn refresh_interval(&self) -> Option<Duration> {
+        match self.provider() {
+            HostnameProvider::CachedSystem { refresh } => refresh,
+            _ => None,
+        }
+    }
+}
+
+fn resolve(provider: &HostnameProvider) -> Option<String> {
+    match provider {
+        HostnameProvider::Static(hostname) => Some(hostname.clone()),
+        HostnameProvider::CachedSystem { .. } => {
+            Some(gethostname::gethostname().to_string_lossy().into_owned())
+        }
+        HostnameProvider::Disabled => None,
+    }
+}
@@ -1,18 +1,223 @@
+#[cfg(feature = "async")]
+pub mod async_buffer;
+pub mod batch;
+#[cfg(feature = "buffered")]
 pub mod buffer;
+pub mod clock;
+#[cfg(feature = "hot-reload")]
+pub mod config;
+#[cfg(feature = "buffered")]
+mod diagnostics;
 pub mod error;
 pub mod event;
+pub mod hostname;
 pub mod output;
-pub use buffer::BufferedSender;
+#[cfg(feature = "pool")]
+pub mod pool;
+pub mod reconnect;
+#[cfg(feature = "buffered")]
+mod record_buffer;
+#[cfg(feature = "buffered")]
+mod stats;
+#[cfg(feature = "test-utils")]
+pub mod testing;
+#[cfg(feature = "async")]
+pub use async_buffer::{AsyncBufferedSender, AsyncBufferedSenderBuilder};
+pub use batch::{BatchAccumulator, ShouldFlush};
+#[cfg(feature = "buffered")]
+pub use buffer::{
+    flush_all, AdaptiveBatching, BufferPolicy, BufferedSender, BufferedSenderBuilder,
+    PartialWritePolicy, WeakBufferedSender, WorkerDispatch, HEARTBEAT_TARGET, SELF_METRICS_TARGET,
+};
+pub use clock::{ClockSource, SystemClock};
 pub use error::Error;
-pub use event::LogStashRecord;
-pub use output::tcp::TcpSender;
+#[cfg(feature = "cbor")]
+pub use event::SELF_DESCRIBE_TAG;
+pub use event::{
+    format_duration, level_name, set_level_names, set_null_policy, set_timestamp_enabled,
+    EscapingTransformer, LevelScale, LogStashRecord, LogStashRecordBuilder, NullPolicy,
+    LOGGER_NAME,
+};
+pub use hostname::{HostnameCache, HostnameProvider};
+pub use output::ansi::AnsiStrippingSender;
+#[cfg(feature = "buffered")]
+pub use output::chain::ChainedSender;
+pub use output::lumberjack::LumberjackSender;
+pub use output::middleware::{
+    ContextMiddleware, MiddlewarePipeline, RateLimitMiddleware, SenderMiddleware,
+};
+#[cfg(feature = "rayon")]
+pub use output::parallel_fanout::ParallelFanOutSender;
+pub use output::process::ChildProcessSender;
+#[cfg(feature = "prometheus")]
+pub use output::prometheus::{MetricHandles, PrometheusMetricsSender};
+pub use output::proxy::{ProxyKind, ProxyOptions};
+pub use output::ring_buffer::RingBufferSender;
+pub use output::routing::RoutingSender;
+#[cfg(feature = "simple")]
+pub use output::simple::SimpleSender;
+pub use output::tcp::{
+    Resolver, StartupCheck, SystemResolver, TcpSender, TcpSenderBuilder, TlsOptions,
+};
+pub use output::{BatchFormat, BatchId, DelimiterPlacement, DrainSender, Framing};
+#[cfg(feature = "pool")]
+pub use pool::{PooledRecord, RecordPool};
+#[cfg(feature = "derive")]
+pub use qoollo_logstash_derive::LogStashEvent;
+pub use reconnect::{Jitter, ReconnectPolicy};
+#[cfg(feature = "buffered")]
+pub use record_buffer::{OverflowPolicy, OversizedPolicy};
+#[cfg(feature = "buffered")]
+pub use stats::{LatencyHistogram, SenderStats, TargetSize, LATENCY_BUCKET_BOUNDS};
 
 pub type Result<T> = core::result::Result<T, Error>;
 
+/// Features a [`Sender`] implements natively rather than by emulation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SenderCapabilities {
+    /// `send_batch` sends the batch at once instead of looping over `send`
+    pub supports_native_batch: bool,
+    /// `flush` pushes buffered data to the destination instead of doing nothing
+    pub supports_flush: bool,
+    /// Calls return before the records reach the destination
+    pub is_async: bool,
+}
+
+impl SenderCapabilities {
+    /// Capabilities shared by both `self` and `other`
+    pub fn intersect(self, other: Self) -> Self {
+        Self {
+            supports_native_batch: self.supports_native_batch && other.supports_native_batch,
+            supports_flush: self.supports_flush && other.supports_flush,
+            is_async: self.is_async && other.is_async,
+        }
+    }
+}
+
 pub trait Sender: Sync + Send + 'static {
     fn send(&self, event: LogStashRecord) -> Result<()>;
     fn send_batch(&self, events: Vec<LogStashRecord>) -> Result<()>;
     fn flush(&self) -> Result<()>;
+    /// Sends records borrowed from the caller so it can reuse them, clones them unless
+    /// overridden
+    fn send_batch_ref(&self, events: &[LogStashRecord]) -> Result<()> {
+        self.send_batch(events.to_vec())
+    }
+    /// Sends newline-terminated JSON records serialized ahead of time, unsupported unless
+    /// overridden
+    #[cfg(feature = "bytes")]
+    fn send_raw(&self, _frames: &[bytes::Bytes]) -> Result<()> {
+        Err(Error::Config(
+            "sender does not accept serialized records".into(),
+        ))
+    }
+    /// Sends `value` as a record of `level` and `target`. Keys of a JSON object become
+    /// fields of the record, any other value is stored in the `data` field.
+    fn send_typed<T: serde::Serialize>(
+        &self,
+        level: log::Level,
+        target: &str,
+        value: T,
+    ) -> Result<()>
+    where
+        Self: Sized,
+    {
+        let mut record = LogStashRecord::builder(level)
+            .target(target.to_owned())
+            .build();
+        match serde_json::to_value(value)? {
+            serde_json::Value::Object(fields) => record.fields.extend(fields),
+            value => {
+                record.add_data("data", value);
+            }
+        }
+        self.send(record)
+    }
+    /// Flushes and waits up to `timeout` for the records to be delivered, the same as
+    /// `flush` unless overridden
+    fn flush_and_wait(&self, _timeout: std::time::Duration) -> Result<()> {
+        self.flush()
+    }
+    /// Establishes the underlying connection ahead of the first send
+    fn connect(&self) -> Result<()> {
+        Ok(())
+    }
+    /// Verifies the underlying connection is alive without sending a record
+    fn ping(&self) -> Result<()> {
+        Ok(())
+    }
+    /// Human-readable address of the destination, used in diagnostics
+    fn endpoint(&self) -> Option<String> {
+        None
+    }
+    /// Whether records with `metadata` are accepted, checked before building the record so
+    /// filtered records cost nothing
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+    /// Features implemented natively by the sender, none by default
+    fn capabilities(&self) -> SenderCapabilities {
+        SenderCapabilities::default()
+    }
+    /// Identifier of the last batch sent in an envelope, for correlation with the destination
+    fn last_batch_id(&self) -> Option<BatchId> {
+        None
+    }
+    /// Times the connection was re-established after the first one, zero unless overridden
+    fn reconnects(&self) -> u64 {
+        0
+    }
+}
+
+/// Boxed senders, e.g. built by a [`MiddlewarePipeline`], forward every call
+impl<S: Sender + ?Sized> Sender for Box<S> {
+    fn send(&self, event: LogStashRecord) -> Result<()> {
+        (**self).send(event)
+    }
+    fn send_batch(&self, events: Vec<LogStashRecord>) -> Result<()> {
+        (**self).send_batch(events)
+    }
+    fn flush(&self) -> Result<()> {
+        (**self).flush()
+    }
+    fn send_batch_ref(&self, events: &[LogStashRecord]) -> Result<()> {
+        (**self).send_batch_ref(events)
+    }
+    #[cfg(feature = "bytes")]
+    fn send_raw(&self, frames: &[bytes::Bytes]) -> Result<()> {
+        (**self).send_raw(frames)
+    }
+    fn flush_and_wait(&self, timeout: std::time::Duration) -> Result<()> {
+        (**self).flush_and_wait(timeout)
+    }
+    fn connect(&self) -> Result<()> {
+        (**self).connect()
+    }
+    fn ping(&self) -> Result<()> {
+        (**self).ping()
+    }
+    fn endpoint(&self) -> Option<String> {
+        (**self).endpoint()
+    }
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        (**self).enabled(metadata)
+    }
+    fn capabilities(&self) -> SenderCapabilities {
+        (**self).capabilities()
+    }
+    fn last_batch_id(&self) -> Option<BatchId> {
+        (**self).last_batch_id()
+    }
+    fn reconnects(&self) -> u64 {
+        (**self).reconnects()
+    }
+}
+
+/// Paths used by the code generated by `#[derive(LogStashEvent)]`
+#[cfg(feature = "derive")]
+#[doc(hidden)]
+pub mod __private {
+    pub use serde_json;
 }
 
 mod prelude {
@@ -0,0 +1,62 @@
+use crate::prelude::*;
+
+/// Sender removing ANSI color sequences from all string fields before forwarding records
+pub struct AnsiStrippingSender<S> {
+    inner: S,
+}
+
+impl<S: Sender> AnsiStrippingSender<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+}
+
+impl<S: Sender> Sender for AnsiStrippingSender<S> {
+    fn send(&self, mut event: LogStashRecord) -> Result<()> {
+        event.strip_ansi_codes_all();
+        self.inner.send(event)
+    }
+
+    fn send_batch(&self, mut events: Vec<LogStashRecord>) -> Result<()> {
+        for event in &mut events {
+            event.strip_ansi_codes_all();
+        }
+        self.inner.send_batch(events)
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.inner.flush()
+    }
+
+    fn flush_and_wait(&self, timeout: std::time::Duration) -> Result<()> {
+        self.inner.flush_and_wait(timeout)
+    }
+
+    fn connect(&self) -> Result<()> {
+        self.inner.connect()
+    }
+
+    fn ping(&self) -> Result<()> {
+        self.inner.ping()
+    }
+
+    fn endpoint(&self) -> Option<String> {
+        self.inner.endpoint()
+    }
+
+    fn last_batch_id(&self) -> Option<crate::output::BatchId> {
+        self.inner.last_batch_id()
+    }
+
+    fn reconnects(&self) -> u64 {
+        self.inner.reconnects()
+    }
+
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn capabilities(&self) -> SenderCapabilities {
+        self.inner.capabilities()
+    }
+}
@@ -0,0 +1,152 @@
+use super::DrainSender;
+use crate::prelude::*;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Sender handing records to a fast in-memory `Fast` sender on the calling thread, while a
+/// background thread drains it every poll interval and forwards the records to the `Slow`
+/// sender, e.g. a [`TcpSender`]. A batch the slow sender fails to take is retried at the
+/// next poll before more records are drained. Dropping the sender forwards the remaining
+/// records and stops the thread.
+pub struct ChainedSender<Fast: DrainSender, Slow: Sender> {
+    chain: Arc<Chain<Fast, Slow>>,
+    /// Disconnecting it stops the thread
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+struct Chain<Fast, Slow> {
+    fast: Fast,
+    slow: Slow,
+    /// Records drained but not yet taken by the slow sender, locked while forwarding so
+    /// records keep their order
+    pending: Mutex<Vec<LogStashRecord>>,
+}
+
+impl<Fast: DrainSender, Slow: Sender> Chain<Fast, Slow> {
+    /// Sends the pending records and the records drained from the fast sender to the slow one
+    fn forward(&self) -> Result<()> {
+        let mut pending = self.lock();
+        if pending.is_empty() {
+            *pending = self.fast.drain();
+        }
+        if pending.is_empty() {
+            return Ok(());
+        }
+        self.slow.send_batch_ref(&pending)?;
+        pending.clear();
+        Ok(())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<LogStashRecord>> {
+        self.pending.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl<Fast: DrainSender, Slow: Sender> ChainedSender<Fast, Slow> {
+    pub fn new(fast: Fast, slow: Slow, poll_interval: Duration) -> Self {
+        let chain = Arc::new(Chain {
+            fast,
+            slow,
+            pending: Mutex::new(Vec::new()),
+        });
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = {
+            let chain = chain.clone();
+            std::thread::spawn(move || loop {
+                let stopping = !matches!(
+                    stopped.recv_timeout(poll_interval),
+                    Err(RecvTimeoutError::Timeout)
+                );
+                if let Err(err) = chain.forward() {
+                    println!("logstash logger error: {}", err);
+                }
+                if stopping {
+                    return;
+                }
+            })
+        };
+        Self {
+            chain,
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+
+    pub fn fast(&self) -> &Fast {
+        &self.chain.fast
+    }
+
+    pub fn slow(&self) -> &Slow {
+        &self.chain.slow
+    }
+}
+
+impl<Fast: DrainSender, Slow: Sender> Sender for ChainedSender<Fast, Slow> {
+    fn send(&self, event: LogStashRecord) -> Result<()> {
+        self.chain.fast.send(event)
+    }
+
+    fn send_batch(&self, events: Vec<LogStashRecord>) -> Result<()> {
+        self.chain.fast.send_batch(events)
+    }
+
+    /// Forwards the records kept by the fast sender right away and flushes the slow one
+    fn flush(&self) -> Result<()> {
+        self.chain.forward()?;
+        self.chain.slow.flush()
+    }
+
+    /// Forwards the records of the fast sender, then waits for the slow sender with what is
+    /// left of `timeout`
+    fn flush_and_wait(&self, timeout: Duration) -> Result<()> {
+        let deadline = Instant::now() + timeout;
+        self.chain.forward()?;
+        self.chain
+            .slow
+            .flush_and_wait(deadline.saturating_duration_since(Instant::now()))
+    }
+
+    fn connect(&self) -> Result<()> {
+        self.chain.slow.connect()
+    }
+
+    fn ping(&self) -> Result<()> {
+        self.chain.slow.ping()
+    }
+
+    fn endpoint(&self) -> Option<String> {
+        self.chain.slow.endpoint()
+    }
+
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.chain.fast.enabled(metadata)
+    }
+
+    fn capabilities(&self) -> SenderCapabilities {
+        SenderCapabilities {
+            supports_native_batch: self.chain.fast.capabilities().supports_native_batch,
+            supports_flush: true,
+            is_async: true,
+        }
+    }
+
+    fn last_batch_id(&self) -> Option<BatchId> {
+        self.chain.slow.last_batch_id()
+    }
+
+    fn reconnects(&self) -> u64 {
+        self.chain.slow.reconnects()
+    }
+}
+
+impl<Fast: DrainSender, Slow: Sender> Drop for ChainedSender<Fast, Slow> {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
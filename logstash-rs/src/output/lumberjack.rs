@@ -0,0 +1,209 @@
+use crate::output::tcp::{AdvancedTcpStream, Stream, TlsOptions};
+use crate::prelude::*;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::io::Read as IORead;
+use std::io::Write as IOWrite;
+use std::time::Duration;
+
+const PROTOCOL_VERSION: u8 = b'2';
+const FRAME_WINDOW_SIZE: u8 = b'W';
+const FRAME_JSON: u8 = b'J';
+const FRAME_COMPRESSED: u8 = b'C';
+const FRAME_ACK: u8 = b'A';
+
+/// Sender speaking the Beats (Lumberjack v2) protocol.
+///
+/// Every batch is sent in windows: a `W` frame with the window length followed by one `J`
+/// frame per record, optionally wrapped into a zlib `C` frame. A window is considered
+/// delivered once the server acknowledges its last sequence number. Records that were not
+/// acknowledged are resent on a fresh connection.
+pub struct LumberjackSender {
+    stream: AdvancedTcpStream,
+    compression_level: Option<u32>,
+    window_size: Option<usize>,
+}
+
+impl LumberjackSender {
+    pub fn new(
+        hostname: String,
+        port: u16,
+        use_tls: bool,
+        connection_timeout: Option<Duration>,
+    ) -> Self {
+        Self {
+            stream: AdvancedTcpStream::new(hostname, port, use_tls, connection_timeout)
+                .with_read_timeout(Some(Duration::from_secs(30))),
+            compression_level: Some(3),
+            window_size: None,
+        }
+    }
+
+    /// Sets certificates and server name used when TLS is enabled.
+    pub fn with_tls_options(mut self, tls: TlsOptions) -> Self {
+        self.stream = self.stream.with_tls_options(tls);
+        self
+    }
+
+    /// Sets the maximum number of records of a window, larger batches are split into several
+    /// windows. By default a batch is sent as a single window.
+    pub fn with_window_size(mut self, window_size: Option<usize>) -> Self {
+        self.window_size = window_size.map(|size| size.max(1));
+        self
+    }
+
+    /// Sets zlib compression level of the data frames. Level 0 disables compression.
+    pub fn with_compression_level(mut self, level: u32) -> Self {
+        self.compression_level = if level == 0 { None } else { Some(level.min(9)) };
+        self
+    }
+
+    /// Sets the maximum time to wait for an acknowledgement from the server.
+    pub fn with_ack_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.stream = self.stream.with_read_timeout(timeout);
+        self
+    }
+
+    fn send_window(&self, payloads: &[Vec<u8>]) -> Result<usize> {
+        let window = encode_window(payloads, self.compression_level)?;
+        let mut acked = 0;
+        let result = self.stream.exchange(|stream| {
+            stream.write_all(&window)?;
+            stream.flush()?;
+            while acked < payloads.len() {
+                acked = acked.max(read_ack(stream)? as usize);
+            }
+            Ok(())
+        });
+        match result {
+            Err(err) if acked == 0 => Err(err),
+            _ => Ok(acked.min(payloads.len())),
+        }
+    }
+}
+
+impl Sender for LumberjackSender {
+    fn send(&self, event: LogStashRecord) -> Result<()> {
+        self.send_batch(vec![event])
+    }
+
+    fn send_batch(&self, events: Vec<LogStashRecord>) -> Result<()> {
+        let payloads = events
+            .iter()
+            .map(|event| {
+                let mut payload = Vec::with_capacity(event.estimated_json_size());
+                event.write_json(&mut payload).map(|_| payload)
+            })
+            .collect::<serde_json::Result<Vec<_>>>()?;
+        let mut sent = 0;
+        let mut retried = false;
+        while sent < payloads.len() {
+            let end = self
+                .window_size
+                .map_or(payloads.len(), |size| payloads.len().min(sent + size));
+            match self.send_window(&payloads[sent..end]) {
+                Ok(acked) => {
+                    sent += acked;
+                    retried = false;
+                }
+                // The connection may have been closed by the server while idle
+                Err(_) if !retried => retried = true,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.stream.flush()?;
+        Ok(())
+    }
+
+    fn connect(&self) -> Result<()> {
+        self.stream.connect()
+    }
+
+    fn ping(&self) -> Result<()> {
+        self.stream.ping()
+    }
+
+    fn endpoint(&self) -> Option<String> {
+        Some(self.stream.endpoint())
+    }
+
+    fn reconnects(&self) -> u64 {
+        self.stream.reconnects()
+    }
+
+    fn capabilities(&self) -> SenderCapabilities {
+        SenderCapabilities {
+            supports_native_batch: true,
+            supports_flush: true,
+            is_async: false,
+        }
+    }
+}
+
+impl log::Log for LumberjackSender {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        let record = LogStashRecord::from_record(record);
+        let _ = self.send(record);
+    }
+
+    fn flush(&self) {
+        let _ = Sender::flush(self);
+    }
+}
+
+/// Encodes window size frame followed by the data frames of `payloads`
+fn encode_window(payloads: &[Vec<u8>], compression_level: Option<u32>) -> Result<Vec<u8>> {
+    let mut buf = encode_window_size(payloads.len() as u32);
+    let mut frames = vec![];
+    for (i, payload) in payloads.iter().enumerate() {
+        encode_json_frame(&mut frames, i as u32 + 1, payload);
+    }
+    match compression_level {
+        Some(level) => encode_compressed_frame(&mut buf, &frames, level)?,
+        None => buf.extend_from_slice(&frames),
+    }
+    Ok(buf)
+}
+
+fn encode_window_size(size: u32) -> Vec<u8> {
+    let mut buf = vec![PROTOCOL_VERSION, FRAME_WINDOW_SIZE];
+    buf.extend_from_slice(&size.to_be_bytes());
+    buf
+}
+
+fn encode_json_frame(buf: &mut Vec<u8>, seq: u32, payload: &[u8]) {
+    buf.extend_from_slice(&[PROTOCOL_VERSION, FRAME_JSON]);
+    buf.extend_from_slice(&seq.to_be_bytes());
+    buf.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    buf.extend_from_slice(payload);
+}
+
+fn encode_compressed_frame(buf: &mut Vec<u8>, frames: &[u8], level: u32) -> Result<()> {
+    let mut encoder = ZlibEncoder::new(vec![], Compression::new(level));
+    encoder.write_all(frames)?;
+    let compressed = encoder.finish()?;
+    buf.extend_from_slice(&[PROTOCOL_VERSION, FRAME_COMPRESSED]);
+    buf.extend_from_slice(&(compressed.len() as u32).to_be_bytes());
+    buf.extend_from_slice(&compressed);
+    Ok(())
+}
+
+fn read_ack(stream: &mut Stream) -> Result<u32> {
+    let mut frame = [0u8; 6];
+    stream.read_exact(&mut frame)?;
+    if frame[0] != PROTOCOL_VERSION || frame[1] != FRAME_ACK {
+        return Err(Error::Protocol(format!(
+            "unexpected frame header: {:?}",
+            &frame[..2]
+        )));
+    }
+    Ok(u32::from_be_bytes([frame[2], frame[3], frame[4], frame[5]]))
+}
@@ -0,0 +1,274 @@
+use crate::prelude::*;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// Decoration of a sender, composed with others by a [`MiddlewarePipeline`] without
+/// spelling out the nested sender types.
+///
+/// Any `FnOnce(Box<dyn Sender>) -> Box<dyn Sender>` is a middleware, so existing wrappers
+/// such as [`AnsiStrippingSender`](crate::AnsiStrippingSender) fit in a pipeline too.
+pub trait SenderMiddleware {
+    /// Sender forwarding to `inner` with the behavior of the middleware added
+    fn wrap(self, inner: Box<dyn Sender>) -> Box<dyn Sender>;
+}
+
+impl<F: FnOnce(Box<dyn Sender>) -> Box<dyn Sender>> SenderMiddleware for F {
+    fn wrap(self, inner: Box<dyn Sender>) -> Box<dyn Sender> {
+        self(inner)
+    }
+}
+
+/// Builder stacking middleware over a base sender. Each middleware wraps the ones added
+/// before it, so records go through the last added first.
+///
+/// ```
+/// use qoollo_logstash_rs::testing::CapturingSender;
+/// use qoollo_logstash_rs::{ContextMiddleware, MiddlewarePipeline, RateLimitMiddleware};
+/// use std::collections::HashMap;
+///
+/// let context = HashMap::from([("service".to_owned(), "billing".into())]);
+/// let sender = MiddlewarePipeline::new(CapturingSender::new())
+///     .add(RateLimitMiddleware::new(1000))
+///     .add(ContextMiddleware::new(context))
+///     .build();
+/// ```
+pub struct MiddlewarePipeline {
+    sender: Box<dyn Sender>,
+}
+
+impl MiddlewarePipeline {
+    pub fn new<S: Sender>(base: S) -> Self {
+        Self {
+            sender: Box::new(base),
+        }
+    }
+
+    /// Wraps the sender built so far with `middleware`
+    #[allow(clippy::should_implement_trait)]
+    pub fn add(self, middleware: impl SenderMiddleware) -> Self {
+        Self {
+            sender: middleware.wrap(self.sender),
+        }
+    }
+
+    pub fn build(self) -> Box<dyn Sender> {
+        self.sender
+    }
+}
+
+/// Sender forwarding at most `per_second` records per second, with bursts of up to one
+/// second worth of records, and dropping the others. Created without an inner sender as
+/// a middleware, [`around`](Self::around) gives a sender of a known type.
+pub struct RateLimitMiddleware<S = ()> {
+    inner: S,
+    per_second: f64,
+    bucket: Mutex<TokenBucket>,
+    dropped: AtomicU64,
+}
+
+/// Records which may be forwarded right now, refilled continuously
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl RateLimitMiddleware {
+    pub fn new(per_second: u32) -> Self {
+        Self {
+            inner: (),
+            per_second: per_second.into(),
+            bucket: Mutex::new(TokenBucket {
+                tokens: per_second.into(),
+                refilled: Instant::now(),
+            }),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Rate limiting sender forwarding to `inner`
+    pub fn around<S: Sender>(self, inner: S) -> RateLimitMiddleware<S> {
+        RateLimitMiddleware {
+            inner,
+            per_second: self.per_second,
+            bucket: self.bucket,
+            dropped: self.dropped,
+        }
+    }
+}
+
+impl SenderMiddleware for RateLimitMiddleware {
+    fn wrap(self, inner: Box<dyn Sender>) -> Box<dyn Sender> {
+        Box::new(self.around(inner))
+    }
+}
+
+impl<S> RateLimitMiddleware<S> {
+    /// Records dropped over the rate limit
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Takes up to `count` records from the bucket, counting the others as dropped.
+    /// Returns the number of records to forward.
+    fn acquire(&self, count: usize) -> usize {
+        let mut bucket = self.lock();
+        let now = Instant::now();
+        let refill = now.duration_since(bucket.refilled).as_secs_f64() * self.per_second;
+        bucket.tokens = (bucket.tokens + refill).min(self.per_second);
+        bucket.refilled = now;
+        let granted = (bucket.tokens as usize).min(count);
+        bucket.tokens -= granted as f64;
+        self.dropped
+            .fetch_add((count - granted) as u64, Ordering::Relaxed);
+        granted
+    }
+
+    fn lock(&self) -> MutexGuard<'_, TokenBucket> {
+        self.bucket.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl<S: Sender> Sender for RateLimitMiddleware<S> {
+    fn send(&self, event: LogStashRecord) -> Result<()> {
+        match self.acquire(1) {
+            0 => Ok(()),
+            _ => self.inner.send(event),
+        }
+    }
+
+    /// Forwards the first records of the batch within the limit
+    fn send_batch(&self, mut events: Vec<LogStashRecord>) -> Result<()> {
+        events.truncate(self.acquire(events.len()));
+        if events.is_empty() {
+            return Ok(());
+        }
+        self.inner.send_batch(events)
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.inner.flush()
+    }
+
+    fn flush_and_wait(&self, timeout: Duration) -> Result<()> {
+        self.inner.flush_and_wait(timeout)
+    }
+
+    fn connect(&self) -> Result<()> {
+        self.inner.connect()
+    }
+
+    fn ping(&self) -> Result<()> {
+        self.inner.ping()
+    }
+
+    fn endpoint(&self) -> Option<String> {
+        self.inner.endpoint()
+    }
+
+    fn last_batch_id(&self) -> Option<BatchId> {
+        self.inner.last_batch_id()
+    }
+
+    fn reconnects(&self) -> u64 {
+        self.inner.reconnects()
+    }
+
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn capabilities(&self) -> SenderCapabilities {
+        self.inner.capabilities()
+    }
+}
+
+/// Sender adding context fields to every record before forwarding it, fields already set
+/// on the record are kept. Created without an inner sender as a middleware,
+/// [`around`](Self::around) gives a sender of a known type.
+pub struct ContextMiddleware<S = ()> {
+    inner: S,
+    context: HashMap<String, Value>,
+}
+
+impl ContextMiddleware {
+    pub fn new(context: HashMap<String, Value>) -> Self {
+        Self { inner: (), context }
+    }
+
+    /// Sender adding the context fields and forwarding to `inner`
+    pub fn around<S: Sender>(self, inner: S) -> ContextMiddleware<S> {
+        ContextMiddleware {
+            inner,
+            context: self.context,
+        }
+    }
+}
+
+impl SenderMiddleware for ContextMiddleware {
+    fn wrap(self, inner: Box<dyn Sender>) -> Box<dyn Sender> {
+        Box::new(self.around(inner))
+    }
+}
+
+impl<S> ContextMiddleware<S> {
+    fn add_context(&self, event: &mut LogStashRecord) {
+        for (key, value) in &self.context {
+            event
+                .fields
+                .entry(key.clone())
+                .or_insert_with(|| value.clone());
+        }
+    }
+}
+
+impl<S: Sender> Sender for ContextMiddleware<S> {
+    fn send(&self, mut event: LogStashRecord) -> Result<()> {
+        self.add_context(&mut event);
+        self.inner.send(event)
+    }
+
+    fn send_batch(&self, mut events: Vec<LogStashRecord>) -> Result<()> {
+        events.iter_mut().for_each(|event| self.add_context(event));
+        self.inner.send_batch(events)
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.inner.flush()
+    }
+
+    fn flush_and_wait(&self, timeout: Duration) -> Result<()> {
+        self.inner.flush_and_wait(timeout)
+    }
+
+    fn connect(&self) -> Result<()> {
+        self.inner.connect()
+    }
+
+    fn ping(&self) -> Result<()> {
+        self.inner.ping()
+    }
+
+    fn endpoint(&self) -> Option<String> {
+        self.inner.endpoint()
+    }
+
+    fn last_batch_id(&self) -> Option<BatchId> {
+        self.inner.last_batch_id()
+    }
+
+    fn reconnects(&self) -> u64 {
+        self.inner.reconnects()
+    }
+
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn capabilities(&self) -> SenderCapabilities {
+        self.inner.capabilities()
+    }
+}
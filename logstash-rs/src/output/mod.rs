@@ -1 +1,329 @@
+use crate::prelude::*;
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{BufWriter, Write as IOWrite};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub mod ansi;
+#[cfg(feature = "buffered")]
+pub mod chain;
+pub mod lumberjack;
+pub mod middleware;
+#[cfg(feature = "rayon")]
+pub mod parallel_fanout;
+pub mod process;
+#[cfg(feature = "prometheus")]
+pub mod prometheus;
+pub mod proxy;
+pub mod ring_buffer;
+pub mod routing;
+#[cfg(feature = "simple")]
+pub mod simple;
 pub mod tcp;
+
+/// Sender keeping the records it received in memory until they are taken out with
+/// [`drain`](Self::drain), e.g. [`RingBufferSender`](ring_buffer::RingBufferSender)
+pub trait DrainSender: Sender {
+    /// Removes and returns the records kept so far, oldest first
+    fn drain(&self) -> Vec<LogStashRecord>;
+}
+
+/// Where the newline delimiting records is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DelimiterPlacement {
+    /// After every record
+    #[default]
+    Suffix,
+    /// Before every record
+    Prefix,
+}
+
+/// Placement of the newlines between records of newline-delimited JSON
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Framing {
+    pub placement: DelimiterPlacement,
+    /// Whether every batch ends with a newline. Without it consecutive batches written to a
+    /// stream are not separated, so it suits consumers reading a batch per message.
+    pub trailing: bool,
+}
+
+impl Default for Framing {
+    fn default() -> Self {
+        Self {
+            placement: DelimiterPlacement::Suffix,
+            trailing: true,
+        }
+    }
+}
+
+impl Framing {
+    fn before(&self) -> bool {
+        self.placement == DelimiterPlacement::Prefix
+    }
+
+    fn after(&self, last: bool) -> bool {
+        match self.placement {
+            DelimiterPlacement::Suffix => !last || self.trailing,
+            DelimiterPlacement::Prefix => last && self.trailing,
+        }
+    }
+
+    /// Serializes `events` with their delimiters, one buffer per event
+    #[cfg(unix)]
+    pub(crate) fn lines(&self, events: &[LogStashRecord]) -> Result<Vec<Vec<u8>>> {
+        events
+            .iter()
+            .enumerate()
+            .map(|(i, event)| {
+                let mut line = Vec::with_capacity(event.estimated_json_size() + 2);
+                if self.before() {
+                    line.push(b'\n');
+                }
+                event.write_json(&mut line)?;
+                if self.after(i + 1 == events.len()) {
+                    line.push(b'\n');
+                }
+                Ok(line)
+            })
+            .collect()
+    }
+}
+
+/// How a batch of records is laid out on the wire
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchFormat {
+    /// Newline-delimited JSON, every record is a line
+    #[default]
+    NdJson,
+    /// A single JSON object per batch, `{"batch_id":..,"count":..,"events":[..]}`. A record
+    /// sent on its own is wrapped into a one-element envelope.
+    JsonEnvelope {
+        /// Whether to write a [`BatchId`] generated for every batch
+        include_batch_id: bool,
+        /// Whether to write the number of records in the batch
+        include_count: bool,
+    },
+}
+
+/// Identifier of a batch sent in an envelope, a ULID sorting by the time of the batch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BatchId(u128);
+
+impl BatchId {
+    /// New identifier from the current time and 80 random bits
+    pub fn generate() -> Self {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let random = ((crate::reconnect::random_u64() as u128) << 64)
+            | crate::reconnect::random_u64() as u128;
+        Self(((millis & ((1 << 48) - 1)) << 80) | (random & ((1 << 80) - 1)))
+    }
+
+    pub fn as_u128(self) -> u128 {
+        self.0
+    }
+}
+
+/// Formats the identifier in the 26 characters of the canonical ULID encoding
+impl fmt::Display for BatchId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+        (0..26).rev().try_for_each(|i| {
+            let digit = (self.0 >> (i * 5)) as usize & 31;
+            fmt::Write::write_char(f, ALPHABET[digit] as char)
+        })
+    }
+}
+
+/// Writes an envelope with the given fields around the records written by `write_events`,
+/// which separates them with commas
+pub(crate) fn write_envelope<W: IOWrite + ?Sized>(
+    writer: &mut W,
+    batch_id: Option<BatchId>,
+    count: Option<usize>,
+    framing: Framing,
+    write_events: impl FnOnce(&mut dyn IOWrite) -> Result<()>,
+) -> Result<()> {
+    let mut writer = BufWriter::new(writer);
+    let result = write_envelope_parts(&mut writer, batch_id, count, framing, write_events)
+        .and_then(|()| Ok(writer.flush()?));
+    discard_buffered(writer);
+    result
+}
+
+fn write_envelope_parts(
+    writer: &mut dyn IOWrite,
+    batch_id: Option<BatchId>,
+    count: Option<usize>,
+    framing: Framing,
+    write_events: impl FnOnce(&mut dyn IOWrite) -> Result<()>,
+) -> Result<()> {
+    if framing.before() {
+        writer.write_all(b"\n")?;
+    }
+    writer.write_all(b"{")?;
+    if let Some(batch_id) = batch_id {
+        write!(writer, "\"batch_id\":\"{}\",", batch_id)?;
+    }
+    if let Some(count) = count {
+        write!(writer, "\"count\":{},", count)?;
+    }
+    writer.write_all(b"\"events\":[")?;
+    write_events(writer)?;
+    writer.write_all(b"]}")?;
+    if framing.after(true) {
+        writer.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// Drops `writer` without writing out the data it still buffers after a failure, which
+/// would otherwise follow the partially written data on the connection
+fn discard_buffered<W: IOWrite>(writer: BufWriter<W>) {
+    let _ = writer.into_parts();
+}
+
+/// Serializes `events` into `writer` separated by commas, as the items of a JSON array
+pub(crate) fn write_json_items(writer: &mut dyn IOWrite, events: &[LogStashRecord]) -> Result<()> {
+    events.iter().enumerate().try_for_each(|(i, event)| {
+        if i > 0 {
+            writer.write_all(b",")?;
+        }
+        event.write_json(&mut *writer).map_err(json_write_error)
+    })
+}
+
+/// Writes newline-terminated records serialized ahead of time the same way as
+/// [`write_json_items`]
+#[cfg(feature = "bytes")]
+pub(crate) fn write_frame_items(writer: &mut dyn IOWrite, frames: &[bytes::Bytes]) -> Result<()> {
+    frames.iter().enumerate().try_for_each(|(i, frame)| {
+        if i > 0 {
+            writer.write_all(b",")?;
+        }
+        Ok(writer.write_all(frame.strip_suffix(b"\n").unwrap_or(frame))?)
+    })
+}
+
+/// Reports failures of the underlying writer as IO errors
+fn json_write_error(err: serde_json::Error) -> Error {
+    if err.is_io() {
+        Error::IO(err.into())
+    } else {
+        Error::Serde(err)
+    }
+}
+
+/// Serializes `events` as newline-delimited JSON straight into `writer` through a small
+/// buffer, without materializing the whole batch in memory
+pub(crate) fn write_lines<W: IOWrite + ?Sized>(
+    writer: &mut W,
+    events: &[LogStashRecord],
+    framing: Framing,
+) -> Result<()> {
+    write_lines_tracked(writer, events, framing, &Cell::new(0))
+}
+
+/// Same as [`write_lines`], incrementing `written` for every event whose bytes were all
+/// accepted by `writer`, so after a failure only the remaining events need to be resent
+pub(crate) fn write_lines_tracked<W: IOWrite + ?Sized>(
+    writer: &mut W,
+    events: &[LogStashRecord],
+    framing: Framing,
+    written: &Cell<usize>,
+) -> Result<()> {
+    let mut writer = BufWriter::new(CountingWriter::new(writer));
+    // Offsets of the ends of events not yet fully accepted by `writer`
+    let mut ends = VecDeque::with_capacity(events.len());
+    let mut serialized = 0;
+    let result = events
+        .iter()
+        .enumerate()
+        .try_for_each(|(i, event)| {
+            let mut line = CountingWriter::new(&mut writer);
+            if framing.before() {
+                line.write_all(b"\n")?;
+            }
+            event.write_json(&mut line).map_err(json_write_error)?;
+            if framing.after(i + 1 == events.len()) {
+                line.write_all(b"\n")?;
+            }
+            serialized += line.bytes;
+            ends.push_back(serialized);
+            confirm_written(&mut ends, writer.get_ref().bytes, written);
+            Ok(())
+        })
+        .and_then(|()| Ok(writer.flush()?));
+    confirm_written(&mut ends, writer.get_ref().bytes, written);
+    discard_buffered(writer);
+    result
+}
+
+/// Writes records serialized ahead of time, counting them in `written` the same way as
+/// [`write_lines_tracked`]
+#[cfg(feature = "bytes")]
+pub(crate) fn write_frames_tracked<W: IOWrite + ?Sized>(
+    writer: &mut W,
+    frames: &[bytes::Bytes],
+    written: &Cell<usize>,
+) -> Result<()> {
+    let mut writer = BufWriter::new(CountingWriter::new(writer));
+    let mut ends = VecDeque::with_capacity(frames.len());
+    let mut serialized = 0;
+    let result = frames
+        .iter()
+        .try_for_each(|frame| {
+            writer.write_all(frame)?;
+            serialized += frame.len();
+            ends.push_back(serialized);
+            confirm_written(&mut ends, writer.get_ref().bytes, written);
+            Ok(())
+        })
+        .and_then(|()| Ok(writer.flush()?));
+    confirm_written(&mut ends, writer.get_ref().bytes, written);
+    discard_buffered(writer);
+    result
+}
+
+fn confirm_written(ends: &mut VecDeque<usize>, accepted: usize, written: &Cell<usize>) {
+    while ends.front().is_some_and(|&end| end <= accepted) {
+        ends.pop_front();
+        written.set(written.get() + 1);
+    }
+}
+
+/// Writer counting the bytes accepted by the inner writer
+struct CountingWriter<W> {
+    inner: W,
+    bytes: usize,
+}
+
+impl<W> CountingWriter<W> {
+    fn new(inner: W) -> Self {
+        Self { inner, bytes: 0 }
+    }
+}
+
+impl<W: IOWrite> IOWrite for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.bytes += written;
+        Ok(written)
+    }
+
+    fn write_vectored(&mut self, bufs: &[std::io::IoSlice<'_>]) -> std::io::Result<usize> {
+        let written = self.inner.write_vectored(bufs)?;
+        self.bytes += written;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,75 @@
+use crate::error::combine;
+use crate::prelude::*;
+use rayon::prelude::*;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Sender forwarding every record to all inner senders concurrently on the rayon thread pool
+pub struct ParallelFanOutSender {
+    senders: Vec<Box<dyn Sender>>,
+}
+
+impl ParallelFanOutSender {
+    pub fn new(senders: Vec<Box<dyn Sender>>) -> Self {
+        Self { senders }
+    }
+
+    fn for_each(&self, f: impl Fn(&dyn Sender) -> Result<()> + Sync + Send) -> Result<()> {
+        let errors = Mutex::new(vec![]);
+        self.senders.par_iter().for_each(|sender| {
+            if let Err(err) = f(sender.as_ref()) {
+                errors
+                    .lock()
+                    .unwrap_or_else(std::sync::PoisonError::into_inner)
+                    .push(err);
+            }
+        });
+        combine(
+            errors
+                .into_inner()
+                .unwrap_or_else(std::sync::PoisonError::into_inner),
+        )
+    }
+}
+
+impl Sender for ParallelFanOutSender {
+    fn send(&self, event: LogStashRecord) -> Result<()> {
+        self.for_each(|sender| sender.send(event.clone()))
+    }
+
+    fn send_batch(&self, events: Vec<LogStashRecord>) -> Result<()> {
+        self.for_each(|sender| sender.send_batch(events.clone()))
+    }
+
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.senders.iter().any(|sender| sender.enabled(metadata))
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.for_each(|sender| sender.flush())
+    }
+
+    /// Waits for the senders concurrently, up to `timeout` for all of them
+    fn flush_and_wait(&self, timeout: Duration) -> Result<()> {
+        let deadline = Instant::now() + timeout;
+        self.for_each(|sender| {
+            sender.flush_and_wait(deadline.saturating_duration_since(Instant::now()))
+        })
+    }
+
+    fn connect(&self) -> Result<()> {
+        self.for_each(|sender| sender.connect())
+    }
+
+    fn ping(&self) -> Result<()> {
+        self.for_each(|sender| sender.ping())
+    }
+
+    fn capabilities(&self) -> SenderCapabilities {
+        self.senders
+            .iter()
+            .map(|sender| sender.capabilities())
+            .reduce(SenderCapabilities::intersect)
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,196 @@
+#[cfg(feature = "bytes")]
+use crate::output::write_frames_tracked;
+use crate::output::{write_lines, Framing};
+use crate::prelude::*;
+#[cfg(feature = "bytes")]
+use std::cell::Cell;
+use std::io::Write as IOWrite;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Sender writing newline-delimited JSON to the stdin of a spawned forwarder process.
+///
+/// The child is spawned on first use and respawned if it exits or closes its stdin.
+pub struct ChildProcessSender {
+    program: String,
+    args: Vec<String>,
+    child: Mutex<Option<Child>>,
+    framing: Framing,
+    stdout: Option<Box<dyn Fn() -> Stdio + Send + Sync>>,
+    shutdown_timeout: Duration,
+}
+
+impl ChildProcessSender {
+    pub fn new(program: impl Into<String>, args: Vec<String>) -> Self {
+        Self {
+            program: program.into(),
+            args,
+            child: Mutex::new(None),
+            framing: Framing::default(),
+            stdout: None,
+            shutdown_timeout: Duration::from_secs(2),
+        }
+    }
+
+    /// Sets where the newlines between records are written
+    pub fn with_framing(mut self, framing: Framing) -> Self {
+        self.framing = framing;
+        self
+    }
+
+    /// Sets where the output of every spawned child goes, by default it is inherited from
+    /// this process
+    pub fn with_stdout(mut self, stdout: impl Fn() -> Stdio + Send + Sync + 'static) -> Self {
+        self.stdout = Some(Box::new(stdout));
+        self
+    }
+
+    /// Maximum time the drop of the sender waits for the child to exit after closing its
+    /// stdin. A child still running then is killed.
+    pub fn with_shutdown_timeout(mut self, shutdown_timeout: Duration) -> Self {
+        self.shutdown_timeout = shutdown_timeout;
+        self
+    }
+
+    /// Runs `write` on the stdin of the child, respawning it and repeating `write` once if
+    /// the child has gone away
+    fn send_with(&self, write: impl Fn(&mut ChildStdin) -> Result<()>) -> Result<()> {
+        let mut child = self.child.lock()?;
+        let should_repeat = self.send_inner(&mut child, &write)?;
+        if should_repeat {
+            self.send_inner(&mut child, &write)?;
+        }
+        Ok(())
+    }
+
+    fn send_inner(
+        &self,
+        child: &mut Option<Child>,
+        write: &impl Fn(&mut ChildStdin) -> Result<()>,
+    ) -> Result<bool> {
+        let respawned = self.respawn_if_needed(child)?;
+        let stdin = child
+            .as_mut()
+            .and_then(|c| c.stdin.as_mut())
+            .expect("should be some");
+        if let Err(err) = write(stdin) {
+            Self::reap(child);
+            if !respawned {
+                return Ok(true);
+            }
+            return Err(err);
+        }
+        Ok(false)
+    }
+
+    fn respawn_if_needed(&self, child: &mut Option<Child>) -> Result<bool> {
+        if let Some(running) = child.as_mut() {
+            if running.try_wait()?.is_none() {
+                return Ok(false);
+            }
+            Self::reap(child);
+        }
+        let mut command = Command::new(&self.program);
+        command.args(&self.args).stdin(Stdio::piped());
+        if let Some(stdout) = &self.stdout {
+            command.stdout(stdout());
+        }
+        *child = Some(command.spawn()?);
+        Ok(true)
+    }
+
+    fn reap(child: &mut Option<Child>) {
+        if let Some(mut child) = child.take() {
+            drop(child.stdin.take());
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+impl Sender for ChildProcessSender {
+    fn send(&self, event: LogStashRecord) -> Result<()> {
+        self.send_with(|stdin| write_lines(stdin, std::slice::from_ref(&event), self.framing))
+    }
+
+    fn send_batch(&self, events: Vec<LogStashRecord>) -> Result<()> {
+        self.send_batch_ref(&events)
+    }
+
+    fn send_batch_ref(&self, events: &[LogStashRecord]) -> Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        self.send_with(|stdin| write_lines(stdin, events, self.framing))
+    }
+
+    #[cfg(feature = "bytes")]
+    fn send_raw(&self, frames: &[bytes::Bytes]) -> Result<()> {
+        if frames.is_empty() {
+            return Ok(());
+        }
+        self.send_with(|stdin| write_frames_tracked(stdin, frames, &Cell::new(0)))
+    }
+
+    fn flush(&self) -> Result<()> {
+        let mut child = self.child.lock()?;
+        if let Some(stdin) = child.as_mut().and_then(|c| c.stdin.as_mut()) {
+            stdin.flush()?;
+        }
+        Ok(())
+    }
+
+    fn connect(&self) -> Result<()> {
+        let mut child = self.child.lock()?;
+        self.respawn_if_needed(&mut child)?;
+        Ok(())
+    }
+
+    fn endpoint(&self) -> Option<String> {
+        Some(self.program.clone())
+    }
+
+    fn capabilities(&self) -> SenderCapabilities {
+        SenderCapabilities {
+            supports_native_batch: true,
+            supports_flush: true,
+            is_async: false,
+        }
+    }
+}
+
+impl Drop for ChildProcessSender {
+    fn drop(&mut self) {
+        // Closing stdin lets the forwarder drain and exit on its own
+        if let Ok(mut child) = self.child.lock() {
+            if let Some(mut child) = child.take() {
+                drop(child.stdin.take());
+                let deadline = Instant::now() + self.shutdown_timeout;
+                while let Ok(None) = child.try_wait() {
+                    if Instant::now() >= deadline {
+                        let _ = child.kill();
+                        let _ = child.wait();
+                        return;
+                    }
+                    std::thread::sleep(Duration::from_millis(5));
+                }
+            }
+        }
+    }
+}
+
+impl log::Log for ChildProcessSender {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        let record = LogStashRecord::from_record(record);
+        let _ = self.send(record);
+    }
+
+    fn flush(&self) {
+        let _ = Sender::flush(self);
+    }
+}
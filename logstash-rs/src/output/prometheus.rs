@@ -0,0 +1,148 @@
+use crate::prelude::*;
+use prometheus::{Counter, Histogram, HistogramOpts, Registry};
+use std::time::Instant;
+
+/// Metrics updated by [`PrometheusMetricsSender`]
+#[derive(Debug, Clone)]
+pub struct MetricHandles {
+    /// Records accepted by the wrapped sender
+    pub records_sent_total: Counter,
+    /// Records the wrapped sender failed to send
+    pub records_failed_total: Counter,
+    /// Records rejected because the wrapped sender's buffer was full
+    pub records_dropped_total: Counter,
+    /// Duration of `send` and `send_batch` calls in seconds
+    pub send_latency_seconds: Histogram,
+}
+
+impl MetricHandles {
+    /// Creates unregistered metrics with the default names
+    pub fn new() -> prometheus::Result<Self> {
+        Ok(Self {
+            records_sent_total: Counter::new(
+                "records_sent_total",
+                "Records accepted by the logstash sender",
+            )?,
+            records_failed_total: Counter::new(
+                "records_failed_total",
+                "Records the logstash sender failed to send",
+            )?,
+            records_dropped_total: Counter::new(
+                "records_dropped_total",
+                "Records dropped because the logstash sender buffer was full",
+            )?,
+            send_latency_seconds: Histogram::with_opts(HistogramOpts::new(
+                "send_latency_seconds",
+                "Duration of logstash sender calls",
+            ))?,
+        })
+    }
+
+    /// Registers every metric in `registry`
+    pub fn register_with(&self, registry: &Registry) -> prometheus::Result<()> {
+        registry.register(Box::new(self.records_sent_total.clone()))?;
+        registry.register(Box::new(self.records_failed_total.clone()))?;
+        registry.register(Box::new(self.records_dropped_total.clone()))?;
+        registry.register(Box::new(self.send_latency_seconds.clone()))
+    }
+
+    fn observe(&self, count: usize, started: Instant, result: &Result<()>) {
+        self.send_latency_seconds
+            .observe(started.elapsed().as_secs_f64());
+        let counter = match result {
+            Ok(()) => &self.records_sent_total,
+            Err(Error::BufferFull()) => &self.records_dropped_total,
+            Err(_) => &self.records_failed_total,
+        };
+        counter.inc_by(count as f64);
+    }
+}
+
+/// Sender updating Prometheus metrics for every record passed to the wrapped sender
+pub struct PrometheusMetricsSender<S> {
+    inner: S,
+    metrics: MetricHandles,
+}
+
+impl<S: Sender> PrometheusMetricsSender<S> {
+    /// Wraps `inner`, updating already constructed `metrics`
+    pub fn new(inner: S, metrics: MetricHandles) -> Self {
+        Self { inner, metrics }
+    }
+
+    /// Registers the metrics of this sender in `registry`
+    pub fn register_with(&self, registry: &Registry) -> prometheus::Result<()> {
+        self.metrics.register_with(registry)
+    }
+
+    pub fn metrics(&self) -> &MetricHandles {
+        &self.metrics
+    }
+}
+
+impl<S: Sender> Sender for PrometheusMetricsSender<S> {
+    fn send(&self, event: LogStashRecord) -> Result<()> {
+        let started = Instant::now();
+        let result = self.inner.send(event);
+        self.metrics.observe(1, started, &result);
+        result
+    }
+
+    fn send_batch(&self, events: Vec<LogStashRecord>) -> Result<()> {
+        let (count, started) = (events.len(), Instant::now());
+        let result = self.inner.send_batch(events);
+        self.metrics.observe(count, started, &result);
+        result
+    }
+
+    fn send_batch_ref(&self, events: &[LogStashRecord]) -> Result<()> {
+        let started = Instant::now();
+        let result = self.inner.send_batch_ref(events);
+        self.metrics.observe(events.len(), started, &result);
+        result
+    }
+
+    #[cfg(feature = "bytes")]
+    fn send_raw(&self, frames: &[bytes::Bytes]) -> Result<()> {
+        let started = Instant::now();
+        let result = self.inner.send_raw(frames);
+        self.metrics.observe(frames.len(), started, &result);
+        result
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.inner.flush()
+    }
+
+    fn flush_and_wait(&self, timeout: std::time::Duration) -> Result<()> {
+        self.inner.flush_and_wait(timeout)
+    }
+
+    fn connect(&self) -> Result<()> {
+        self.inner.connect()
+    }
+
+    fn ping(&self) -> Result<()> {
+        self.inner.ping()
+    }
+
+    fn endpoint(&self) -> Option<String> {
+        self.inner.endpoint()
+    }
+
+    fn last_batch_id(&self) -> Option<crate::output::BatchId> {
+        self.inner.last_batch_id()
+    }
+
+    fn reconnects(&self) -> u64 {
+        self.inner.reconnects()
+    }
+
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn capabilities(&self) -> SenderCapabilities {
+        self.inner.capabilities()
+    }
+}
@@ -0,0 +1,210 @@
+//! Tunnels of a [`TcpSender`](crate::TcpSender) connection through an HTTP or SOCKS5 proxy
+
+use crate::prelude::*;
+use base64::Engine;
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs};
+
+/// Protocol spoken with a proxy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProxyKind {
+    /// HTTP proxy supporting the `CONNECT` method
+    Http,
+    /// SOCKS5 proxy, resolving the hostname of the server itself
+    Socks5,
+}
+
+/// Proxy the connection to the server goes through
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProxyOptions {
+    pub kind: ProxyKind,
+    /// `host:port` of the proxy
+    pub addr: String,
+    /// Username authenticating to the proxy, requires `password`
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+/// Largest response header of an HTTP proxy read before giving up
+const MAX_HTTP_RESPONSE: usize = 8 * 1024;
+
+impl ProxyOptions {
+    pub fn new(kind: ProxyKind, addr: impl Into<String>) -> Self {
+        Self {
+            kind,
+            addr: addr.into(),
+            username: None,
+            password: None,
+        }
+    }
+
+    /// Authenticates to the proxy with `username` and `password`
+    pub fn with_credentials(
+        mut self,
+        username: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Self {
+        self.username = Some(username.into());
+        self.password = Some(password.into());
+        self
+    }
+
+    pub(crate) fn resolve(&self) -> Result<SocketAddr> {
+        self.addr
+            .to_socket_addrs()
+            .map_err(|err| Error::Config(format!("proxy address {}: {}", self.addr, err)))?
+            .next()
+            .ok_or_else(|| Error::Config(format!("proxy address {}: no address", self.addr)))
+    }
+
+    fn credentials(&self) -> Result<Option<(&str, &str)>> {
+        match (&self.username, &self.password) {
+            (Some(username), Some(password)) => Ok(Some((username, password))),
+            (None, None) => Ok(None),
+            _ => Err(Error::Config(
+                "proxy username and password must be set together".into(),
+            )),
+        }
+    }
+
+    /// Asks the proxy connected with `stream` to open a tunnel to `hostname` and `port`
+    pub(crate) fn open_tunnel(
+        &self,
+        stream: &mut TcpStream,
+        hostname: &str,
+        port: u16,
+    ) -> Result<()> {
+        match self.kind {
+            ProxyKind::Http => self.http_connect(stream, hostname, port),
+            ProxyKind::Socks5 => self.socks5_connect(stream, hostname, port),
+        }
+    }
+
+    fn http_connect(&self, stream: &mut TcpStream, hostname: &str, port: u16) -> Result<()> {
+        let authority = match hostname.parse::<IpAddr>() {
+            Ok(IpAddr::V6(ip)) => format!("[{}]:{}", ip, port),
+            _ => format!("{}:{}", hostname, port),
+        };
+        let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", authority);
+        if let Some((username, password)) = self.credentials()? {
+            let token = base64::engine::general_purpose::STANDARD
+                .encode(format!("{}:{}", username, password));
+            request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", token));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes())?;
+
+        // Read byte by byte so nothing the server sends through the tunnel is consumed
+        let mut response = Vec::new();
+        let mut byte = [0u8];
+        while !response.ends_with(b"\r\n\r\n") {
+            if response.len() == MAX_HTTP_RESPONSE {
+                return Err(Error::Protocol("proxy response header is too long".into()));
+            }
+            stream.read_exact(&mut byte)?;
+            response.push(byte[0]);
+        }
+        let status_line = String::from_utf8_lossy(&response);
+        let status_line = status_line.lines().next().unwrap_or_default();
+        match status_line.split_whitespace().nth(1) {
+            Some(status) if status.starts_with('2') => Ok(()),
+            _ => Err(Error::Connection(format!(
+                "proxy refused to connect to {}: {}",
+                authority, status_line
+            ))),
+        }
+    }
+
+    fn socks5_connect(&self, stream: &mut TcpStream, hostname: &str, port: u16) -> Result<()> {
+        const VERSION: u8 = 5;
+        const NO_AUTH: u8 = 0;
+        const USERNAME_PASSWORD: u8 = 2;
+
+        let credentials = self.credentials()?;
+        let method = if credentials.is_some() {
+            USERNAME_PASSWORD
+        } else {
+            NO_AUTH
+        };
+        stream.write_all(&[VERSION, 1, method])?;
+        let mut reply = [0u8; 2];
+        stream.read_exact(&mut reply)?;
+        if reply != [VERSION, method] {
+            return Err(Error::Connection(
+                "SOCKS5 proxy rejected the authentication method".into(),
+            ));
+        }
+        if let Some((username, password)) = credentials {
+            let mut request = vec![1];
+            for value in [username, password] {
+                let len = u8::try_from(value.len()).map_err(|_| {
+                    Error::Config("SOCKS5 username and password are limited to 255 bytes".into())
+                })?;
+                request.push(len);
+                request.extend_from_slice(value.as_bytes());
+            }
+            stream.write_all(&request)?;
+            stream.read_exact(&mut reply)?;
+            if reply[1] != 0 {
+                return Err(Error::Connection(
+                    "SOCKS5 proxy rejected the credentials".into(),
+                ));
+            }
+        }
+
+        let mut request = vec![VERSION, 1, 0];
+        match hostname.parse::<IpAddr>() {
+            Ok(IpAddr::V4(ip)) => {
+                request.push(1);
+                request.extend_from_slice(&ip.octets());
+            }
+            Ok(IpAddr::V6(ip)) => {
+                request.push(4);
+                request.extend_from_slice(&ip.octets());
+            }
+            Err(_) => {
+                let len = u8::try_from(hostname.len()).map_err(|_| {
+                    Error::Config("SOCKS5 hostnames are limited to 255 bytes".into())
+                })?;
+                request.push(3);
+                request.push(len);
+                request.extend_from_slice(hostname.as_bytes());
+            }
+        }
+        request.extend_from_slice(&port.to_be_bytes());
+        stream.write_all(&request)?;
+
+        let mut header = [0u8; 4];
+        stream.read_exact(&mut header)?;
+        if header[1] != 0 {
+            return Err(Error::Connection(format!(
+                "SOCKS5 proxy failed to connect to {}:{} with code {}",
+                hostname, port, header[1]
+            )));
+        }
+        // The address the proxy bound for the tunnel, unused
+        let address_len = match header[3] {
+            1 => 4,
+            4 => 16,
+            3 => {
+                let mut len = [0u8];
+                stream.read_exact(&mut len)?;
+                usize::from(len[0])
+            }
+            kind => {
+                return Err(Error::Protocol(format!(
+                    "SOCKS5 proxy replied with address type {}",
+                    kind
+                )))
+            }
+        };
+        let mut bound = vec![0u8; address_len + 2];
+        stream.read_exact(&mut bound)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,84 @@
+use super::DrainSender;
+use crate::prelude::*;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+/// Sender keeping the last `capacity` records in memory until they are drained, dropping
+/// the oldest record when full
+pub struct RingBufferSender {
+    records: Mutex<VecDeque<LogStashRecord>>,
+    capacity: usize,
+    dropped: AtomicU64,
+}
+
+impl RingBufferSender {
+    /// Fails if `capacity` is 0
+    pub fn new(capacity: usize) -> Result<Self> {
+        if capacity == 0 {
+            return Err(Error::Config("ring buffer capacity is 0".into()));
+        }
+        Ok(Self {
+            records: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+            dropped: AtomicU64::new(0),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Records dropped to make room for newer ones
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, VecDeque<LogStashRecord>> {
+        self.records.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn push(&self, records: &mut VecDeque<LogStashRecord>, event: LogStashRecord) {
+        if records.len() == self.capacity {
+            records.pop_front();
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        records.push_back(event);
+    }
+}
+
+impl Sender for RingBufferSender {
+    fn send(&self, event: LogStashRecord) -> Result<()> {
+        self.push(&mut self.lock(), event);
+        Ok(())
+    }
+
+    fn send_batch(&self, events: Vec<LogStashRecord>) -> Result<()> {
+        let mut records = self.lock();
+        for event in events {
+            self.push(&mut records, event);
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn capabilities(&self) -> SenderCapabilities {
+        SenderCapabilities {
+            supports_native_batch: true,
+            ..Default::default()
+        }
+    }
+}
+
+impl DrainSender for RingBufferSender {
+    fn drain(&self) -> Vec<LogStashRecord> {
+        self.lock().drain(..).collect()
+    }
+}
@@ -0,0 +1,104 @@
+use crate::error::combine;
+use crate::prelude::*;
+use std::time::{Duration, Instant};
+
+/// Sender dispatching records to inner senders by target prefix.
+///
+/// The route with the longest prefix matching the record target wins, records matching no
+/// route go to the default sender. Batches are split per destination keeping the order of
+/// records, so every inner sender still receives a single batch.
+pub struct RoutingSender {
+    routes: Vec<(String, Box<dyn Sender>)>,
+    default: Box<dyn Sender>,
+}
+
+impl RoutingSender {
+    pub fn new(default: Box<dyn Sender>) -> Self {
+        Self {
+            routes: vec![],
+            default,
+        }
+    }
+
+    /// Routes records with target starting with `target_prefix` to `sender`.
+    pub fn with_route(mut self, target_prefix: impl Into<String>, sender: Box<dyn Sender>) -> Self {
+        self.routes.push((target_prefix.into(), sender));
+        self.routes
+            .sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+        self
+    }
+
+    /// Index of the route for `target`, `routes.len()` standing for the default sender
+    fn route_for(&self, target: &str) -> usize {
+        self.routes
+            .iter()
+            .position(|(prefix, _)| target.starts_with(prefix.as_str()))
+            .unwrap_or(self.routes.len())
+    }
+
+    fn sender(&self, route: usize) -> &dyn Sender {
+        match self.routes.get(route) {
+            Some((_, sender)) => sender.as_ref(),
+            None => self.default.as_ref(),
+        }
+    }
+
+    fn for_each(&self, f: impl Fn(&dyn Sender) -> Result<()>) -> Result<()> {
+        let errors = (0..=self.routes.len())
+            .filter_map(|route| f(self.sender(route)).err())
+            .collect();
+        combine(errors)
+    }
+}
+
+impl Sender for RoutingSender {
+    fn send(&self, event: LogStashRecord) -> Result<()> {
+        self.sender(self.route_for(&event.target)).send(event)
+    }
+
+    fn send_batch(&self, events: Vec<LogStashRecord>) -> Result<()> {
+        let mut batches = vec![vec![]; self.routes.len() + 1];
+        for event in events {
+            batches[self.route_for(&event.target)].push(event);
+        }
+        let errors = batches
+            .into_iter()
+            .enumerate()
+            .filter(|(_, batch)| !batch.is_empty())
+            .filter_map(|(route, batch)| self.sender(route).send_batch(batch).err())
+            .collect();
+        combine(errors)
+    }
+
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.sender(self.route_for(metadata.target()))
+            .enabled(metadata)
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.for_each(|sender| sender.flush())
+    }
+
+    /// Waits for the routes one after the other, up to `timeout` for all of them
+    fn flush_and_wait(&self, timeout: Duration) -> Result<()> {
+        let deadline = Instant::now() + timeout;
+        self.for_each(|sender| {
+            sender.flush_and_wait(deadline.saturating_duration_since(Instant::now()))
+        })
+    }
+
+    fn connect(&self) -> Result<()> {
+        self.for_each(|sender| sender.connect())
+    }
+
+    fn ping(&self) -> Result<()> {
+        self.for_each(|sender| sender.ping())
+    }
+
+    fn capabilities(&self) -> SenderCapabilities {
+        self.routes
+            .iter()
+            .map(|(_, sender)| sender.capabilities())
+            .fold(self.default.capabilities(), SenderCapabilities::intersect)
+    }
+}
@@ -0,0 +1,43 @@
+use crate::prelude::*;
+use log::LevelFilter;
+
+/// Unbuffered `log::Log` implementation sending every record from the logging thread.
+///
+/// Spawns no threads, so it suits targets without thread support when built without the
+/// `buffered` feature. Errors of the wrapped sender are ignored.
+pub struct SimpleSender<S> {
+    sender: S,
+    level_filter: LevelFilter,
+}
+
+impl<S: Sender> SimpleSender<S> {
+    pub fn new(sender: S) -> Self {
+        Self {
+            sender,
+            level_filter: LevelFilter::Trace,
+        }
+    }
+
+    /// Maximum level reported as enabled by `log::Log::enabled`.
+    pub fn with_level_filter(mut self, level_filter: LevelFilter) -> Self {
+        self.level_filter = level_filter;
+        self
+    }
+}
+
+impl<S: Sender> log::Log for SimpleSender<S> {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= self.level_filter && self.sender.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !log::Log::enabled(self, record.metadata()) {
+            return;
+        }
+        let _ = self.sender.send(LogStashRecord::from_record(record));
+    }
+
+    fn flush(&self) {
+        let _ = self.sender.flush();
+    }
+}
@@ -1,19 +1,100 @@
+use crate::output::proxy::ProxyOptions;
+use crate::output::{
+    write_envelope, write_json_items, write_lines, write_lines_tracked, BatchFormat, BatchId,
+    CountingWriter, Framing,
+};
+#[cfg(feature = "bytes")]
+use crate::output::{write_frame_items, write_frames_tracked};
 use crate::prelude::*;
-use std::fmt::Write as FMTWrite;
+use crate::reconnect::{Backoff, ReconnectPolicy};
+use std::cell::Cell;
+#[cfg(unix)]
+use std::io::IoSlice;
+use std::io::Read as IORead;
 use std::io::Write as IOWrite;
 use std::net::TcpStream;
-use std::net::ToSocketAddrs;
-use std::sync::Mutex;
-use std::time::Duration;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
 
-type Stream = Box<dyn IOWrite + Sync + Send>;
+pub(crate) trait ReadWrite: IORead + IOWrite + Sync + Send {}
+
+impl<T: IORead + IOWrite + Sync + Send> ReadWrite for T {}
+
+pub(crate) type Stream = Box<dyn ReadWrite>;
+
+/// TLS settings of the connection, used when TLS is enabled
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsOptions {
+    /// PEM file with an additional trusted root certificate
+    pub ca_cert: Option<PathBuf>,
+    /// PEM file with the client certificate, requires `client_key`
+    pub client_cert: Option<PathBuf>,
+    /// PEM file with the PKCS#8 private key of the client certificate
+    pub client_key: Option<PathBuf>,
+    /// Name used to verify the server certificate instead of the hostname
+    pub server_name: Option<String>,
+    /// Accept any server certificate. Supported by the `tls` backend only.
+    pub insecure_skip_verify: bool,
+}
+
+/// Connection attempt made when a sender is set up, catching a wrong address right away
+/// instead of at the first flush
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StartupCheck {
+    /// Connect on the first send
+    #[default]
+    Off,
+    /// Connect right away, printing an error if it fails
+    Warn,
+    /// Connect right away, failing the setup if it fails
+    Fail,
+}
+
+/// Resolves the hostname of a [`TcpSender`] into the addresses to connect to
+pub trait Resolver: Send + Sync {
+    fn resolve(&self, hostname: &str, port: u16) -> std::io::Result<Vec<SocketAddr>>;
+}
+
+/// Resolver asking the system with [`ToSocketAddrs`]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    fn resolve(&self, hostname: &str, port: u16) -> std::io::Result<Vec<SocketAddr>> {
+        Ok((hostname, port).to_socket_addrs()?.collect())
+    }
+}
 
 pub(crate) struct AdvancedTcpStream {
     hostname: String,
     port: u16,
     use_tls: bool,
+    tls: TlsOptions,
+    proxy: Option<ProxyOptions>,
     stream: Mutex<Option<Stream>>,
+    /// Handle to the raw socket of `stream`, used to probe the connection state
+    socket: Mutex<Option<TcpStream>>,
     connection_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    nodelay: bool,
+    reconnect: ReconnectPolicy,
+    backoff: Mutex<Backoff>,
+    /// Connections established so far
+    connections: AtomicU64,
+    /// Consecutive failed writes on the current connection, across sends
+    write_errors: AtomicU32,
+    dns_cache_ttl: Option<Duration>,
+    resolver: Arc<dyn Resolver>,
+    /// Last resolved address of `hostname` and when it was resolved
+    resolved: Mutex<Option<(SocketAddr, Instant)>>,
+    #[cfg(feature = "test-utils")]
+    write_faults: Option<crate::testing::WriteFaults>,
 }
 
 impl AdvancedTcpStream {
@@ -27,38 +108,201 @@ impl AdvancedTcpStream {
             hostname,
             port,
             use_tls,
+            tls: TlsOptions::default(),
+            proxy: None,
             stream: Mutex::new(None),
+            socket: Mutex::new(None),
             connection_timeout,
+            read_timeout: None,
+            write_timeout: None,
+            nodelay: false,
+            reconnect: ReconnectPolicy::default(),
+            backoff: Default::default(),
+            connections: AtomicU64::new(0),
+            write_errors: AtomicU32::new(0),
+            dns_cache_ttl: None,
+            resolver: Arc::new(SystemResolver),
+            resolved: Mutex::new(None),
+            #[cfg(feature = "test-utils")]
+            write_faults: None,
         }
     }
 
-    pub(crate) fn send_bytes(&self, bytes: &[u8]) -> Result<()> {
+    pub(crate) fn with_write_timeout(mut self, write_timeout: Option<Duration>) -> Self {
+        self.write_timeout = write_timeout;
+        self
+    }
+
+    pub(crate) fn with_reconnect_policy(mut self, reconnect: ReconnectPolicy) -> Self {
+        self.reconnect = reconnect;
+        self
+    }
+
+    /// Connections established after the first one
+    pub(crate) fn reconnects(&self) -> u64 {
+        self.connections.load(Ordering::Relaxed).saturating_sub(1)
+    }
+
+    pub(crate) fn with_nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = nodelay;
+        self
+    }
+
+    pub(crate) fn with_tls_options(mut self, tls: TlsOptions) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    pub(crate) fn with_proxy(mut self, proxy: Option<ProxyOptions>) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    pub(crate) fn with_dns_cache_ttl(mut self, dns_cache_ttl: Option<Duration>) -> Self {
+        self.dns_cache_ttl = dns_cache_ttl;
+        self
+    }
+
+    pub(crate) fn with_resolver(mut self, resolver: Arc<dyn Resolver>) -> Self {
+        self.resolver = resolver;
+        self
+    }
+
+    pub(crate) fn with_read_timeout(mut self, read_timeout: Option<Duration>) -> Self {
+        self.read_timeout = read_timeout;
+        self
+    }
+
+    #[cfg(feature = "test-utils")]
+    pub(crate) fn with_write_faults(mut self, faults: crate::testing::WriteFaults) -> Self {
+        self.write_faults = Some(faults);
+        self
+    }
+
+    /// Runs `write` on the connected stream, repeating it once on a fresh connection if the
+    /// existing one turned out to be broken after the write errors tolerated by the reconnect
+    /// policy
+    pub(crate) fn send_with(&self, write: impl Fn(&mut dyn IOWrite) -> Result<()>) -> Result<()> {
         let mut stream = self.stream.lock()?;
-        let should_repeat = self.send_bytes_inner(&mut stream, bytes)?;
+        let should_repeat = self.send_inner(&mut stream, &write)?;
         if should_repeat {
-            self.send_bytes_inner(&mut stream, bytes)?;
+            self.send_inner(&mut stream, &write)?;
         }
         Ok(())
     }
 
-    fn send_bytes_inner(&self, stream: &mut Option<Stream>, bytes: &[u8]) -> Result<bool> {
+    /// Runs `write` and confirms the connection survived it by waiting up to `timeout` for
+    /// a reset from the peer. Retries once on a fresh connection.
+    pub(crate) fn send_confirmed_with(
+        &self,
+        write: impl Fn(&mut dyn IOWrite) -> Result<()>,
+        timeout: Duration,
+    ) -> Result<()> {
+        let mut stream = self.stream.lock()?;
+        let mut retried = false;
+        loop {
+            let recreated = self.recreate_stream_if_needed(&mut stream)?;
+            let stream_ref = stream.as_mut().expect("should be some");
+            match self.write_confirmed(stream_ref, &write, timeout) {
+                Ok(()) => return Ok(()),
+                Err(err) => {
+                    *stream = None;
+                    if recreated || retried {
+                        return Err(err);
+                    }
+                    retried = true;
+                }
+            }
+        }
+    }
+
+    fn write_confirmed(
+        &self,
+        stream: &mut Stream,
+        write: &impl Fn(&mut dyn IOWrite) -> Result<()>,
+        timeout: Duration,
+    ) -> Result<()> {
+        write(stream)?;
+        stream.flush()?;
+        if self.probe(timeout)? {
+            Ok(())
+        } else {
+            Err(Error::Connection(
+                "connection closed by peer after write".into(),
+            ))
+        }
+    }
+
+    /// Runs `f` on the connected stream, dropping the connection if `f` fails
+    pub(crate) fn exchange<T>(&self, f: impl FnOnce(&mut Stream) -> Result<T>) -> Result<T> {
+        let mut stream = self.stream.lock()?;
+        self.recreate_stream_if_needed(&mut stream)?;
+        let result = f(stream.as_mut().expect("should be some"));
+        if result.is_err() {
+            *stream = None;
+        }
+        result
+    }
+
+    fn send_inner(
+        &self,
+        stream: &mut Option<Stream>,
+        write: &impl Fn(&mut dyn IOWrite) -> Result<()>,
+    ) -> Result<bool> {
         let recreated = self.recreate_stream_if_needed(stream)?;
-        if let Err(err) = stream.as_mut().expect("should be some").write_all(bytes) {
+        let connected = stream.as_mut().expect("should be some");
+        loop {
+            let mut counted = CountingWriter::new(&mut *connected);
+            let err = match write(&mut counted) {
+                Ok(()) => {
+                    self.write_errors.store(0, Ordering::Relaxed);
+                    return Ok(false);
+                }
+                Err(err) => err,
+            };
+            // Repeating a write the stream accepted a part of would garble the framing
+            let errors = self.write_errors.fetch_add(1, Ordering::Relaxed) + 1;
+            if counted.bytes == 0 && errors <= self.reconnect.tolerated_write_errors {
+                continue;
+            }
             *stream = None;
             if !recreated {
                 return Ok(true);
             }
-            return Err(err.into());
+            return Err(err);
         }
-        Ok(false)
     }
 
     fn recreate_stream_if_needed(&self, stream: &mut Option<Stream>) -> Result<bool> {
         if stream.is_none() {
-            *stream = Some(if self.use_tls {
-                self.create_tls_connection()?
+            let mut backoff = self.backoff.lock()?;
+            if let Some(remaining) = backoff.remaining() {
+                return Err(Error::Connection(format!(
+                    "waiting {:?} before reconnecting",
+                    remaining
+                )));
+            }
+            let connection = if self.use_tls {
+                self.create_tls_connection()
             } else {
-                self.create_tcp_connection()?
+                self.create_tcp_connection()
+            };
+            #[cfg(feature = "test-utils")]
+            let connection = match &self.write_faults {
+                Some(faults) => connection.map(|connection| faults.wrap(connection)),
+                None => connection,
+            };
+            *stream = Some(match connection {
+                Ok(connection) => {
+                    backoff.succeeded();
+                    self.connections.fetch_add(1, Ordering::Relaxed);
+                    self.write_errors.store(0, Ordering::Relaxed);
+                    connection
+                }
+                Err(err) => {
+                    backoff.failed(&self.reconnect);
+                    return Err(err);
+                }
             });
             Ok(true)
         } else {
@@ -66,16 +310,56 @@ impl AdvancedTcpStream {
         }
     }
 
-    fn create_connection(&self) -> Result<TcpStream> {
-        let addr = (self.hostname.as_str(), self.port)
-            .to_socket_addrs()?
+    /// Resolves the hostname, reusing the previously resolved address while it is younger
+    /// than the DNS cache TTL
+    fn resolve(&self) -> Result<SocketAddr> {
+        let mut resolved = self.resolved.lock()?;
+        if let (Some(ttl), Some((addr, resolved_at))) = (self.dns_cache_ttl, *resolved) {
+            if resolved_at.elapsed() < ttl {
+                return Ok(addr);
+            }
+        }
+        let addr = self
+            .resolver
+            .resolve(&self.hostname, self.port)?
+            .into_iter()
             .next()
             .ok_or_else(|| Error::AddressResolution(self.hostname.clone(), self.port))?;
-        let stream = if let Some(timeout) = self.connection_timeout {
-            TcpStream::connect_timeout(&addr, timeout)?
+        *resolved = Some((addr, Instant::now()));
+        Ok(addr)
+    }
+
+    fn create_connection(&self) -> Result<TcpStream> {
+        // The proxy resolves the hostname of the server
+        let addr = match &self.proxy {
+            Some(proxy) => proxy.resolve()?,
+            None => self.resolve()?,
+        };
+        let connected = if let Some(timeout) = self.connection_timeout {
+            TcpStream::connect_timeout(&addr, timeout)
         } else {
-            TcpStream::connect(addr)?
+            TcpStream::connect(addr)
+        };
+        let mut stream = match connected {
+            Ok(stream) => stream,
+            Err(err) => {
+                // The endpoint may have moved, resolve the hostname again on the next attempt
+                *self.resolved.lock()? = None;
+                return Err(err.into());
+            }
         };
+        if let Some(proxy) = &self.proxy {
+            // The connection timeout also bounds the exchange opening the tunnel
+            stream.set_read_timeout(self.connection_timeout)?;
+            stream.set_write_timeout(self.connection_timeout)?;
+            proxy.open_tunnel(&mut stream, &self.hostname, self.port)?;
+        }
+        stream.set_read_timeout(self.read_timeout)?;
+        stream.set_write_timeout(self.write_timeout)?;
+        if self.nodelay {
+            stream.set_nodelay(true)?;
+        }
+        *self.socket.lock()? = Some(stream.try_clone()?);
         Ok(stream)
     }
 
@@ -92,9 +376,21 @@ impl AdvancedTcpStream {
     #[cfg(all(feature = "tls", not(feature = "rustls")))]
     fn create_tls_connection(&self) -> Result<Stream> {
         use native_tls::HandshakeError;
-        let conn = native_tls::TlsConnector::new()?;
+        let mut builder = native_tls::TlsConnector::builder();
+        if let Some(ca_cert) = &self.tls.ca_cert {
+            builder
+                .add_root_certificate(native_tls::Certificate::from_pem(&std::fs::read(ca_cert)?)?);
+        }
+        if let Some((cert, key)) = self.client_identity_files()? {
+            builder.identity(native_tls::Identity::from_pkcs8(
+                &std::fs::read(cert)?,
+                &std::fs::read(key)?,
+            )?);
+        }
+        builder.danger_accept_invalid_certs(self.tls.insecure_skip_verify);
+        let conn = builder.build()?;
         let stream = self.create_connection()?;
-        let mut stream = conn.connect(self.hostname.as_str(), stream);
+        let mut stream = conn.connect(self.server_name(), stream);
         while let Err(err) = stream {
             match err {
                 HandshakeError::Failure(err) => return Err(err.into()),
@@ -118,78 +414,523 @@ impl AdvancedTcpStream {
                 ta.name_constraints,
             )
         }));
+        if self.tls.insecure_skip_verify {
+            return Err(Error::Config(
+                "insecure_skip_verify is not supported by the rustls backend".into(),
+            ));
+        }
+        if let Some(ca_cert) = &self.tls.ca_cert {
+            for cert in read_pem_certs(ca_cert)? {
+                root_store
+                    .add(&cert)
+                    .map_err(|err| Error::Config(format!("ca_cert: {}", err)))?;
+            }
+        }
         let config = rustls_crate::ClientConfig::builder()
             .with_safe_defaults()
-            .with_root_certificates(root_store)
-            .with_no_client_auth();
-        let session = rustls_crate::ClientConnection::new(
-            Arc::new(config),
-            self.hostname.as_str().try_into()?,
-        )?;
+            .with_root_certificates(root_store);
+        let config = match self.client_identity_files()? {
+            Some((cert, key)) => {
+                let key = rustls_pemfile::pkcs8_private_keys(&mut std::io::BufReader::new(
+                    std::fs::File::open(key)?,
+                ))?
+                .into_iter()
+                .next()
+                .ok_or_else(|| Error::Config("client_key: no PKCS#8 private key found".into()))?;
+                config.with_single_cert(read_pem_certs(cert)?, rustls_crate::PrivateKey(key))?
+            }
+            None => config.with_no_client_auth(),
+        };
+        let session =
+            rustls_crate::ClientConnection::new(Arc::new(config), self.server_name().try_into()?)?;
         let stream = self.create_connection()?;
         let stream = rustls_crate::StreamOwned::new(session, stream);
         Ok(Box::new(stream))
     }
 
+    #[cfg(any(feature = "tls", feature = "rustls"))]
+    fn server_name(&self) -> &str {
+        self.tls.server_name.as_deref().unwrap_or(&self.hostname)
+    }
+
+    #[cfg(any(feature = "tls", feature = "rustls"))]
+    fn client_identity_files(&self) -> Result<Option<(&PathBuf, &PathBuf)>> {
+        match (&self.tls.client_cert, &self.tls.client_key) {
+            (Some(cert), Some(key)) => Ok(Some((cert, key))),
+            (None, None) => Ok(None),
+            _ => Err(Error::Config(
+                "client_cert and client_key must be set together".into(),
+            )),
+        }
+    }
+
     #[cfg(all(not(feature = "tls"), not(feature = "rustls")))]
     fn create_tls_connection(&self) -> Result<Stream> {
         panic!("TLS is not supported. Please enable 'tls' feature")
     }
 
-    fn flush(&self) -> Result<()> {
+    pub(crate) fn connect(&self) -> Result<()> {
         let mut stream = self.stream.lock()?;
-        let recreated = self.recreate_stream_if_needed(&mut stream)?;
-        if !recreated {
-            stream.as_mut().expect("should be some").flush()?;
+        self.recreate_stream_if_needed(&mut stream)?;
+        Ok(())
+    }
+
+    pub(crate) fn endpoint(&self) -> String {
+        format!("{}:{}", self.hostname, self.port)
+    }
+
+    /// Reconnects if the connection was closed by the peer
+    pub(crate) fn ping(&self) -> Result<()> {
+        let mut stream = self.stream.lock()?;
+        if stream.is_some() && !self.is_alive()? {
+            *stream = None;
+        }
+        self.recreate_stream_if_needed(&mut stream)?;
+        Ok(())
+    }
+
+    fn is_alive(&self) -> Result<bool> {
+        let socket = self.socket.lock()?;
+        let socket = match socket.as_ref() {
+            Some(socket) => socket,
+            None => return Ok(false),
+        };
+        socket.set_nonblocking(true)?;
+        let alive = match socket.peek(&mut [0u8; 1]) {
+            Ok(0) => false,
+            Ok(_) => true,
+            Err(err) => err.kind() == std::io::ErrorKind::WouldBlock,
+        };
+        socket.set_nonblocking(false)?;
+        Ok(alive)
+    }
+
+    /// Waits up to `timeout` for the peer to close or reset the connection
+    fn probe(&self, timeout: Duration) -> Result<bool> {
+        let socket = self.socket.lock()?;
+        let socket = match socket.as_ref() {
+            Some(socket) => socket,
+            None => return Ok(false),
+        };
+        socket.set_read_timeout(Some(timeout))?;
+        let alive = match socket.peek(&mut [0u8; 1]) {
+            Ok(0) => false,
+            Ok(_) => true,
+            Err(err) => matches!(
+                err.kind(),
+                std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+            ),
+        };
+        socket.set_read_timeout(self.read_timeout)?;
+        Ok(alive)
+    }
+
+    /// Pushes data buffered by the stream. Without a connection there is nothing to push,
+    /// so no connection is opened.
+    pub(crate) fn flush(&self) -> Result<()> {
+        let mut stream = self.stream.lock()?;
+        if let Some(stream) = stream.as_mut() {
+            stream.flush()?;
         }
         Ok(())
     }
 }
 
+/// Sender writing newline-delimited JSON to a TCP (optionally TLS) connection.
+///
+/// The connection is guarded by a mutex, so the sender can be shared between threads and
+/// used directly as a `log::Log` implementation.
 pub struct TcpSender {
     stream: AdvancedTcpStream,
+    audit: Option<Duration>,
+    framing: Framing,
+    batch_format: BatchFormat,
+    last_batch_id: Mutex<Option<BatchId>>,
+}
+
+/// Builder of a [`TcpSender`] connecting to `hostname` and `port`, both required
+#[derive(Debug, Clone, Default)]
+pub struct TcpSenderBuilder {
+    hostname: String,
+    port: u16,
+    connect_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    nodelay: bool,
+    reconnect: ReconnectPolicy,
+    framing: Framing,
+    tls: Option<TlsOptions>,
+    proxy: Option<ProxyOptions>,
+}
+
+impl TcpSenderBuilder {
+    pub fn hostname(mut self, hostname: impl Into<String>) -> Self {
+        self.hostname = hostname.into();
+        self
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Maximum time a connection attempt may take, unlimited by default
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Maximum time a write to the socket may block, unlimited by default
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.write_timeout = Some(timeout);
+        self
+    }
+
+    /// Disables Nagle's algorithm, so small batches are sent without delay
+    pub fn nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = nodelay;
+        self
+    }
+
+    pub fn reconnect(mut self, reconnect: ReconnectPolicy) -> Self {
+        self.reconnect = reconnect;
+        self
+    }
+
+    pub fn framing(mut self, framing: Framing) -> Self {
+        self.framing = framing;
+        self
+    }
+
+    /// Connects over TLS with `tls` settings
+    pub fn tls(mut self, tls: TlsOptions) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Connects through the `proxy`, which resolves the hostname of the server
+    pub fn proxy(mut self, proxy: ProxyOptions) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Fails if the hostname is empty or the port is 0
+    pub fn build(self) -> Result<TcpSender> {
+        if self.hostname.is_empty() {
+            return Err(Error::Config("hostname is empty".into()));
+        }
+        if self.port == 0 {
+            return Err(Error::Config("port is 0".into()));
+        }
+        let stream = AdvancedTcpStream::new(
+            self.hostname,
+            self.port,
+            self.tls.is_some(),
+            self.connect_timeout,
+        )
+        .with_tls_options(self.tls.unwrap_or_default())
+        .with_proxy(self.proxy)
+        .with_write_timeout(self.write_timeout)
+        .with_nodelay(self.nodelay)
+        .with_reconnect_policy(self.reconnect);
+        Ok(TcpSender::from_stream(stream).with_framing(self.framing))
+    }
 }
 
 impl TcpSender {
+    pub fn builder() -> TcpSenderBuilder {
+        TcpSenderBuilder::default()
+    }
+
+    #[deprecated(since = "0.2.0", note = "use TcpSenderBuilder")]
     pub fn new(
         hostname: String,
         port: u16,
         use_tls: bool,
         connection_timeout: Option<Duration>,
     ) -> Self {
+        Self::from_stream(AdvancedTcpStream::new(
+            hostname,
+            port,
+            use_tls,
+            connection_timeout,
+        ))
+    }
+
+    fn from_stream(stream: AdvancedTcpStream) -> Self {
         Self {
-            stream: AdvancedTcpStream::new(hostname, port, use_tls, connection_timeout),
+            stream,
+            audit: None,
+            framing: Framing::default(),
+            batch_format: BatchFormat::default(),
+            last_batch_id: Mutex::new(None),
+        }
+    }
+
+    /// Sets certificates and server name used when TLS is enabled.
+    pub fn with_tls_options(mut self, tls: TlsOptions) -> Self {
+        self.stream = self.stream.with_tls_options(tls);
+        self
+    }
+
+    /// Sets the proxy the connection goes through.
+    pub fn with_proxy(mut self, proxy: Option<ProxyOptions>) -> Self {
+        self.stream = self.stream.with_proxy(proxy);
+        self
+    }
+
+    /// Sets the maximum time a write to the socket may block.
+    pub fn with_write_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.stream = self.stream.with_write_timeout(timeout);
+        self
+    }
+
+    /// Sets the backoff between failed connection attempts.
+    pub fn with_reconnect_policy(mut self, reconnect: ReconnectPolicy) -> Self {
+        self.stream = self.stream.with_reconnect_policy(reconnect);
+        self
+    }
+
+    /// Reuse the resolved address of the hostname for reconnects within `ttl`. By default the
+    /// hostname is resolved on every connection attempt, so a changed address is picked up
+    /// at the next reconnect. A failed attempt always discards the cached address.
+    pub fn with_dns_cache_ttl(mut self, ttl: Option<Duration>) -> Self {
+        self.stream = self.stream.with_dns_cache_ttl(ttl);
+        self
+    }
+
+    /// Resolves the hostname with `resolver` instead of asking the system, e.g. to look it
+    /// up in service discovery
+    pub fn with_resolver(mut self, resolver: Arc<dyn Resolver>) -> Self {
+        self.stream = self.stream.with_resolver(resolver);
+        self
+    }
+
+    /// Fails the writes requested by `faults` to exercise the handling of write errors
+    #[cfg(feature = "test-utils")]
+    pub fn with_write_faults(mut self, faults: crate::testing::WriteFaults) -> Self {
+        self.stream = self.stream.with_write_faults(faults);
+        self
+    }
+
+    /// Audit mode: after every write wait up to `timeout` for the peer to reset the
+    /// connection, and resend on a fresh one if it did. Failing that the send returns an
+    /// error instead of reporting data handed to a dead socket as sent.
+    pub fn with_audit(mut self, timeout: Option<Duration>) -> Self {
+        self.audit = timeout;
+        self
+    }
+
+    /// Sets where the newlines between records are written. Records serialized early by
+    /// the buffered sender already end with a newline and are written as they are.
+    pub fn with_framing(mut self, framing: Framing) -> Self {
+        self.framing = framing;
+        self
+    }
+
+    /// Sets how the records of a batch are laid out, newline-delimited JSON by default. An
+    /// envelope is written and resent as a whole, records serialized early included.
+    pub fn with_batch_format(mut self, batch_format: BatchFormat) -> Self {
+        self.batch_format = batch_format;
+        self
+    }
+
+    /// Identifier of the last batch sent in an envelope with a batch ID
+    pub fn last_batch_id(&self) -> Option<BatchId> {
+        *self
+            .last_batch_id
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Sends `count` records written by `write_events` in an envelope, `None` if the batch
+    /// format has no envelope
+    fn send_enveloped(
+        &self,
+        count: usize,
+        write_events: impl Fn(&mut dyn IOWrite) -> Result<()>,
+    ) -> Option<Result<()>> {
+        let (include_batch_id, include_count) = match self.batch_format {
+            BatchFormat::NdJson => return None,
+            BatchFormat::JsonEnvelope {
+                include_batch_id,
+                include_count,
+            } => (include_batch_id, include_count),
+        };
+        let batch_id = include_batch_id.then(BatchId::generate);
+        let count = include_count.then_some(count);
+        let result = self.send_with(|stream| {
+            write_envelope(stream, batch_id, count, self.framing, &write_events)
+        });
+        if result.is_ok() && batch_id.is_some() {
+            *self
+                .last_batch_id
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner) = batch_id;
+        }
+        Some(result)
+    }
+
+    fn send_with(&self, write: impl Fn(&mut dyn IOWrite) -> Result<()>) -> Result<()> {
+        match self.audit {
+            Some(timeout) => self.stream.send_confirmed_with(write, timeout),
+            None => self.stream.send_with(write),
         }
     }
+
+    /// Sends the batch serializing every record into its own buffer and writing them all with
+    /// scatter-gather IO, instead of copying them into a single buffer first.
+    #[cfg(unix)]
+    pub fn send_batch_vectored(&self, events: &[LogStashRecord]) -> Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        if self.batch_format != BatchFormat::NdJson {
+            return self.send_batch_ref(events);
+        }
+        let lines = self.framing.lines(events)?;
+        self.send_with(|stream| write_all_vectored(stream, &lines))
+    }
+
+    /// Establishes the connection before the first event is sent.
+    pub fn pre_connect(&self) -> Result<()> {
+        self.stream.connect()
+    }
+
+    /// Connects within the connection timeout as required by `check`. The connection is kept
+    /// for the first send.
+    pub fn check_on_start(self, check: StartupCheck) -> Result<Self> {
+        if check == StartupCheck::Off {
+            return Ok(self);
+        }
+        if let Err(err) = self.pre_connect() {
+            let message = format!("cannot connect to {}: {}", self.stream.endpoint(), err);
+            if check == StartupCheck::Fail {
+                return Err(Error::Config(message));
+            }
+            println!("logstash logger error: {}", message);
+        }
+        Ok(self)
+    }
+
+    /// Checks that the connection is still alive without sending a record,
+    /// reconnecting if it was closed by the peer.
+    pub fn ping(&self) -> Result<()> {
+        self.stream.ping()
+    }
 }
 
 impl Sender for TcpSender {
+    #[cfg(not(feature = "bytes"))]
     fn send(&self, event: LogStashRecord) -> Result<()> {
-        let mut event = serde_json::to_string(&event)?;
-        event.write_char('\n')?;
-        self.stream.send_bytes(event.as_bytes())?;
-        Ok(())
+        let events = std::slice::from_ref(&event);
+        if let Some(result) = self.send_enveloped(1, |w| write_json_items(w, events)) {
+            return result;
+        }
+        self.send_with(|stream| write_lines(stream, events, self.framing))
+    }
+
+    #[cfg(feature = "bytes")]
+    fn send(&self, event: LogStashRecord) -> Result<()> {
+        let events = std::slice::from_ref(&event);
+        if let Some(result) = self.send_enveloped(1, |w| write_json_items(w, events)) {
+            return result;
+        }
+        if self.framing != Framing::default() {
+            return self.send_with(|stream| {
+                write_lines(stream, std::slice::from_ref(&event), self.framing)
+            });
+        }
+        let bytes = event.to_json_bytes_with_newline()?;
+        self.send_with(|stream| Ok(stream.write_all(&bytes)?))
     }
 
     fn send_batch(&self, events: Vec<LogStashRecord>) -> Result<()> {
+        self.send_batch_ref(&events)
+    }
+
+    fn send_batch_ref(&self, events: &[LogStashRecord]) -> Result<()> {
         if events.is_empty() {
             return Ok(());
         }
-        let mut buf = vec![];
-        for event in events {
-            serde_json::to_writer(&mut buf, &event)?;
-            buf.push('\n' as u8);
+        if let Some(result) = self.send_enveloped(events.len(), |w| write_json_items(w, events)) {
+            return result;
         }
-        self.stream.send_bytes(&buf)?;
-        Ok(())
+        if self.audit.is_some() {
+            // Written bytes are not confirmed until the probe, resend the whole batch
+            return self.send_with(|stream| write_lines(stream, events, self.framing));
+        }
+        // A retry on a fresh connection resends only the events not written before the failure
+        let written = Cell::new(0);
+        let attempted = Cell::new(false);
+        self.send_with(|stream| {
+            attempted.set(true);
+            write_lines_tracked(stream, &events[written.get()..], self.framing, &written)
+        })
+        .map_err(|err| {
+            if !attempted.get() {
+                return err;
+            }
+            // The stream was dropped, the next batch starts on a fresh connection
+            Error::PartialWrite {
+                confirmed: written.get(),
+                unconfirmed: events.len() - written.get(),
+                source: Box::new(err),
+            }
+        })
+    }
+
+    #[cfg(feature = "bytes")]
+    fn send_raw(&self, frames: &[bytes::Bytes]) -> Result<()> {
+        if frames.is_empty() {
+            return Ok(());
+        }
+        if let Some(result) = self.send_enveloped(frames.len(), |w| write_frame_items(w, frames)) {
+            return result;
+        }
+        if self.audit.is_some() {
+            return self.send_with(|stream| write_frames_tracked(stream, frames, &Cell::new(0)));
+        }
+        let written = Cell::new(0);
+        self.send_with(|stream| write_frames_tracked(stream, &frames[written.get()..], &written))
     }
 
     fn flush(&self) -> Result<()> {
         self.stream.flush()?;
         Ok(())
     }
+
+    fn connect(&self) -> Result<()> {
+        self.pre_connect()
+    }
+
+    fn ping(&self) -> Result<()> {
+        TcpSender::ping(self)
+    }
+
+    fn endpoint(&self) -> Option<String> {
+        Some(self.stream.endpoint())
+    }
+
+    fn last_batch_id(&self) -> Option<BatchId> {
+        TcpSender::last_batch_id(self)
+    }
+
+    fn reconnects(&self) -> u64 {
+        self.stream.reconnects()
+    }
+
+    fn capabilities(&self) -> SenderCapabilities {
+        SenderCapabilities {
+            supports_native_batch: true,
+            supports_flush: true,
+            is_async: false,
+        }
+    }
 }
 
+/// Synchronous unbuffered logger: every `log` call serializes the record and blocks until it
+/// is written to the socket, silently dropping it on errors. Fine for scripts and tests,
+/// wrap the sender into [`BufferedSender`] in production.
 impl log::Log for TcpSender {
     fn enabled(&self, _metadata: &log::Metadata) -> bool {
         true
@@ -204,3 +945,24 @@ impl log::Log for TcpSender {
         let _ = Sender::flush(self);
     }
 }
+
+#[cfg(unix)]
+fn write_all_vectored<W: IOWrite + ?Sized>(writer: &mut W, lines: &[Vec<u8>]) -> Result<()> {
+    let mut slices: Vec<_> = lines.iter().map(|line| IoSlice::new(line)).collect();
+    let mut slices = &mut slices[..];
+    while !slices.is_empty() {
+        match writer.write_vectored(slices) {
+            Ok(0) => return Err(std::io::Error::from(std::io::ErrorKind::WriteZero).into()),
+            Ok(written) => IoSlice::advance_slices(&mut slices, written),
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(not(feature = "tls"), feature = "rustls"))]
+fn read_pem_certs(path: &std::path::Path) -> Result<Vec<rustls_crate::Certificate>> {
+    let certs = rustls_pemfile::certs(&mut std::io::BufReader::new(std::fs::File::open(path)?))?;
+    Ok(certs.into_iter().map(rustls_crate::Certificate).collect())
+}
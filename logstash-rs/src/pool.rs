@@ -0,0 +1,93 @@
+use crate::clock::default_clock;
+use crate::event::LogStashRecord;
+use crossbeam_queue::ArrayQueue;
+use std::ops::{Deref, DerefMut};
+
+/// Bounded pool of records reusing their allocations
+#[derive(Debug)]
+pub struct RecordPool {
+    records: ArrayQueue<LogStashRecord>,
+}
+
+impl RecordPool {
+    /// Pool keeping at most `capacity` idle records
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            records: ArrayQueue::new(capacity.max(1)),
+        }
+    }
+
+    /// Takes an empty record stamped with the current time from the pool, or allocates a new
+    /// one if the pool is empty
+    pub fn acquire(&self) -> PooledRecord<'_> {
+        let record = match self.records.pop() {
+            Some(mut record) => {
+                record.timestamp = default_clock().now();
+                record
+            }
+            None => LogStashRecord::new(),
+        };
+        PooledRecord {
+            record: Some(record),
+            pool: self,
+        }
+    }
+
+    /// Clears `record` and keeps it for reuse. The record is dropped if the pool is full.
+    pub fn release(&self, mut record: LogStashRecord) {
+        record.module = None;
+        record.file = None;
+        record.line = None;
+        record.column = None;
+        record.target = Default::default();
+        record.tags.clear();
+        record.fields.clear();
+        record.ascii_only_fields.clear();
+        record.bytes_fields.clear();
+        let _ = self.records.push(record);
+    }
+
+    /// Number of idle records in the pool
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+}
+
+/// Record taken from a [`RecordPool`], returned to it on drop
+pub struct PooledRecord<'a> {
+    record: Option<LogStashRecord>,
+    pool: &'a RecordPool,
+}
+
+impl PooledRecord<'_> {
+    /// Detaches the record from the pool, e.g. to pass it to a sender
+    pub fn into_inner(mut self) -> LogStashRecord {
+        self.record.take().expect("pooled record taken")
+    }
+}
+
+impl Deref for PooledRecord<'_> {
+    type Target = LogStashRecord;
+
+    fn deref(&self) -> &LogStashRecord {
+        self.record.as_ref().expect("pooled record taken")
+    }
+}
+
+impl DerefMut for PooledRecord<'_> {
+    fn deref_mut(&mut self) -> &mut LogStashRecord {
+        self.record.as_mut().expect("pooled record taken")
+    }
+}
+
+impl Drop for PooledRecord<'_> {
+    fn drop(&mut self) {
+        if let Some(record) = self.record.take() {
+            self.pool.release(record);
+        }
+    }
+}
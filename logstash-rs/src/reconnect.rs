@@ -0,0 +1,97 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{Duration, Instant};
+
+/// Randomization of the reconnect delay, spreading reconnects of many instances which lost
+/// the connection at the same time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Jitter {
+    /// Wait exactly the backoff delay
+    #[default]
+    None,
+    /// Wait a random time between zero and the backoff delay
+    Full,
+    /// Wait half of the backoff delay plus a random time up to the other half
+    Equal,
+}
+
+/// Exponential backoff between failed connection attempts.
+///
+/// The delay starts at `initial_delay` and doubles after every failed attempt up to
+/// `max_delay`. Sends during the delay fail without connecting. The default policy
+/// reconnects immediately on the first failed write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub jitter: Jitter,
+    /// Consecutive failed writes repeated on the same connection before it is dropped and
+    /// reconnected, riding out momentary network blips. Only writes which failed before the
+    /// connection accepted any byte are repeated, a partial write always reconnects.
+    pub tolerated_write_errors: u32,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::ZERO,
+            max_delay: Duration::from_secs(30),
+            jitter: Jitter::None,
+            tolerated_write_errors: 0,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Delay before the next attempt after `failures` consecutive failed attempts
+    pub fn delay(&self, failures: u32) -> Duration {
+        let exponent = failures.saturating_sub(1).min(31);
+        let delay = self
+            .initial_delay
+            .saturating_mul(1 << exponent)
+            .min(self.max_delay);
+        match self.jitter {
+            Jitter::None => delay,
+            Jitter::Full => random_up_to(delay),
+            Jitter::Equal => delay / 2 + random_up_to(delay - delay / 2),
+        }
+    }
+}
+
+/// Uniformly distributed duration between zero and `max`
+fn random_up_to(max: Duration) -> Duration {
+    let nanos = max.as_nanos().min(u64::MAX as u128) as u64;
+    Duration::from_nanos(random_u64() % nanos.saturating_add(1))
+}
+
+pub(crate) fn random_u64() -> u64 {
+    // Every `RandomState` is seeded differently, no need for a random number generator
+    RandomState::new().build_hasher().finish()
+}
+
+/// Failed connection attempts tracked by a connection
+#[derive(Debug, Default)]
+pub(crate) struct Backoff {
+    failures: u32,
+    next_attempt: Option<Instant>,
+}
+
+impl Backoff {
+    /// Time left until the next connection attempt is allowed
+    pub(crate) fn remaining(&self) -> Option<Duration> {
+        self.next_attempt
+            .map(|next| next.saturating_duration_since(Instant::now()))
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    pub(crate) fn failed(&mut self, policy: &ReconnectPolicy) {
+        self.failures = self.failures.saturating_add(1);
+        self.next_attempt = Some(Instant::now() + policy.delay(self.failures));
+    }
+
+    pub(crate) fn succeeded(&mut self) {
+        self.failures = 0;
+        self.next_attempt = None;
+    }
+}
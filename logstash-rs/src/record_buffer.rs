@@ -0,0 +1,184 @@
+use crate::prelude::*;
+
+/// What to do with buffered records once the memory budget is exceeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// Drop the oldest buffered records to make room for the new one
+    #[default]
+    DropOldest,
+    /// Drop the new record
+    DropNewest,
+}
+
+/// What the worker does with a record above the maximum event size
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OversizedPolicy {
+    /// Cut its largest fields down, see [`LogStashRecord::truncate_to`]
+    Truncate,
+    /// Drop the record
+    Drop,
+    /// Send it unchanged, only counting it
+    #[default]
+    SendAnyway,
+}
+
+impl OversizedPolicy {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            OversizedPolicy::Truncate => "truncate",
+            OversizedPolicy::Drop => "drop",
+            OversizedPolicy::SendAnyway => "send_anyway",
+        }
+    }
+}
+
+/// Upper bound on the estimated size of buffered records
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct MemoryBudget {
+    pub(crate) max_bytes: usize,
+    pub(crate) policy: OverflowPolicy,
+}
+
+/// Item kept in a [`RecordBuffer`]
+pub(crate) trait Buffered {
+    /// Cheap estimate of the size on the wire
+    fn wire_size(&self) -> usize;
+}
+
+impl Buffered for LogStashRecord {
+    fn wire_size(&self) -> usize {
+        self.estimated_json_size()
+    }
+}
+
+/// Record serialized ahead of time, its exact size is known
+#[cfg(feature = "bytes")]
+impl Buffered for bytes::Bytes {
+    fn wire_size(&self) -> usize {
+        self.len()
+    }
+}
+
+/// Records waiting in the worker along with their cached size estimates
+#[derive(Debug)]
+pub(crate) struct RecordBuffer<T = LogStashRecord> {
+    records: Vec<T>,
+    sizes: Vec<usize>,
+    bytes: usize,
+    budget: MemoryBudget,
+}
+
+impl<T: Buffered> RecordBuffer<T> {
+    pub(crate) fn new(capacity: usize, budget: MemoryBudget) -> Self {
+        Self {
+            records: Vec::with_capacity(capacity),
+            sizes: Vec::with_capacity(capacity),
+            bytes: 0,
+            budget,
+        }
+    }
+
+    /// Appends `record` applying the overflow policy, returns the number of dropped records
+    pub(crate) fn push(&mut self, record: T) -> usize {
+        let size = record.wire_size();
+        if self.bytes + size > self.budget.max_bytes {
+            // A record larger than the whole budget never fits, keep the buffered ones
+            if size > self.budget.max_bytes || self.budget.policy == OverflowPolicy::DropNewest {
+                return 1;
+            }
+            let mut freed = 0;
+            let mut count = 0;
+            while self.bytes - freed + size > self.budget.max_bytes {
+                freed += self.sizes[count];
+                count += 1;
+            }
+            self.records.drain(..count);
+            self.sizes.drain(..count);
+            self.bytes -= freed;
+            self.push_unchecked(record, size);
+            return count;
+        }
+        self.push_unchecked(record, size);
+        0
+    }
+
+    fn push_unchecked(&mut self, record: T, size: usize) {
+        self.records.push(record);
+        self.sizes.push(size);
+        self.bytes += size;
+    }
+
+    /// Drops up to `count` oldest records, returns the number of dropped records
+    pub(crate) fn drop_oldest(&mut self, count: usize) -> usize {
+        let count = count.min(self.records.len());
+        self.records.drain(..count);
+        self.bytes -= self.sizes.drain(..count).sum::<usize>();
+        count
+    }
+
+    /// Puts `records` back in front of the buffered ones, then drops the oldest records
+    /// beyond the memory budget. Returns the number of dropped records.
+    pub(crate) fn restore(&mut self, records: Vec<T>) -> usize {
+        let sizes: Vec<usize> = records.iter().map(Buffered::wire_size).collect();
+        self.bytes += sizes.iter().sum::<usize>();
+        self.records.splice(0..0, records);
+        self.sizes.splice(0..0, sizes);
+        let mut excess = 0;
+        let mut freed = 0;
+        while self.bytes - freed > self.budget.max_bytes {
+            freed += self.sizes[excess];
+            excess += 1;
+        }
+        self.drop_oldest(excess)
+    }
+
+    /// Takes all buffered records leaving an empty buffer with `capacity`
+    pub(crate) fn take(&mut self, capacity: usize) -> Vec<T> {
+        self.sizes.clear();
+        self.bytes = 0;
+        std::mem::replace(&mut self.records, Vec::with_capacity(capacity))
+    }
+
+    /// Most recently buffered record. Its cached size estimate is not updated.
+    pub(crate) fn last_mut(&mut self) -> Option<&mut T> {
+        self.records.last_mut()
+    }
+
+    /// Buffered records, oldest first
+    pub(crate) fn records(&self) -> &[T] {
+        &self.records
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub(crate) fn bytes(&self) -> usize {
+        self.bytes
+    }
+}
+
+/// Numbers runs of consecutive records sharing the same millisecond timestamp with a
+/// `sub_ms_seq` field, starting from 0. Records with a unique millisecond are left untouched.
+pub(crate) fn add_sub_ms_seq(records: &mut [LogStashRecord]) {
+    let mut start = 0;
+    while start < records.len() {
+        let millis = records[start].timestamp.timestamp_millis();
+        let end = records[start..]
+            .iter()
+            .position(|r| r.timestamp.timestamp_millis() != millis)
+            .map_or(records.len(), |len| start + len);
+        if end - start > 1 {
+            for (seq, record) in records[start..end].iter_mut().enumerate() {
+                record.add_data("sub_ms_seq", seq.into());
+            }
+        }
+        start = end;
+    }
+}
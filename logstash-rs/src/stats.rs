@@ -0,0 +1,309 @@
+use crate::output::BatchId;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+#[cfg(feature = "metrics")]
+use std::sync::Arc;
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+/// Snapshot of the buffered sender counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SenderStats {
+    /// Buffer size the workers flush at, 0 without buffering. The largest over the workers
+    /// when it changes with adaptive batching.
+    pub batch_size: usize,
+    /// Records dropped because of buffer overflow
+    pub dropped: u64,
+    /// Estimated size of records currently held in the worker buffer
+    pub buffered_bytes: u64,
+    /// Records accepted by the downstream sender
+    pub sent: u64,
+    /// Records currently held in the worker buffer
+    pub buffered: u64,
+    /// Duration of the last successful send to the downstream sender
+    pub last_send_latency: Duration,
+    /// Identifier of the last batch sent in an envelope
+    pub last_batch_id: Option<BatchId>,
+    /// Wall time of the calls to the downstream sender
+    pub latency: LatencyHistogram,
+}
+
+/// Upper bounds of the [`LatencyHistogram`] buckets, the last bucket holds longer calls
+pub const LATENCY_BUCKET_BOUNDS: [Duration; 4] = [
+    Duration::from_millis(1),
+    Duration::from_millis(10),
+    Duration::from_millis(100),
+    Duration::from_secs(1),
+];
+
+/// Number of calls to the downstream sender by wall time
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    /// Calls under 1ms, 10ms, 100ms and 1s, then calls of 1s or more
+    pub buckets: [u64; LATENCY_BUCKET_BOUNDS.len() + 1],
+}
+
+impl LatencyHistogram {
+    /// Index of the bucket counting a call of `latency`
+    pub fn bucket(latency: Duration) -> usize {
+        LATENCY_BUCKET_BOUNDS
+            .iter()
+            .position(|bound| latency < *bound)
+            .unwrap_or(LATENCY_BUCKET_BOUNDS.len())
+    }
+
+    pub fn total(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    /// Sum of the counts of both histograms
+    pub fn merge(mut self, other: LatencyHistogram) -> Self {
+        self.buckets
+            .iter_mut()
+            .zip(other.buckets)
+            .for_each(|(count, other)| *count += other);
+        self
+    }
+}
+
+/// Estimated JSON sizes of the records of one target, see
+/// [`BufferedSender::target_sizes`](crate::BufferedSender::target_sizes)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TargetSize {
+    pub count: u64,
+    pub total_bytes: u64,
+    pub max_bytes: u64,
+    /// Records above the maximum event size
+    pub violations: u64,
+}
+
+impl TargetSize {
+    pub fn avg_bytes(&self) -> u64 {
+        self.total_bytes.checked_div(self.count).unwrap_or(0)
+    }
+
+    /// Sums of the counts of both targets, the larger maximum
+    pub fn merge(self, other: TargetSize) -> Self {
+        Self {
+            count: self.count + other.count,
+            total_bytes: self.total_bytes + other.total_bytes,
+            max_bytes: self.max_bytes.max(other.max_bytes),
+            violations: self.violations + other.violations,
+        }
+    }
+
+    fn add(&mut self, size: usize, violation: bool) {
+        self.count += 1;
+        self.total_bytes += size as u64;
+        self.max_bytes = self.max_bytes.max(size as u64);
+        self.violations += u64::from(violation);
+    }
+}
+
+/// Record sizes of up to `capacity` targets. Once full, a new target replaces the one with
+/// the smallest maximum if its record is larger, so the largest targets are kept.
+#[derive(Debug, Default)]
+pub(crate) struct TargetSizes {
+    targets: HashMap<String, TargetSize>,
+    capacity: usize,
+}
+
+impl TargetSizes {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            targets: HashMap::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    fn add(&mut self, target: &str, size: usize, violation: bool) {
+        if let Some(entry) = self.targets.get_mut(target) {
+            entry.add(size, violation);
+            return;
+        }
+        if self.targets.len() == self.capacity {
+            let smallest = self
+                .targets
+                .iter()
+                .min_by_key(|(_, entry)| entry.max_bytes)
+                .filter(|(_, entry)| entry.max_bytes < size as u64)
+                .map(|(target, _)| target.clone());
+            match smallest {
+                Some(smallest) => self.targets.remove(&smallest),
+                None => return,
+            };
+        }
+        let mut entry = TargetSize::default();
+        entry.add(size, violation);
+        self.targets.insert(target.to_owned(), entry);
+    }
+}
+
+impl SenderStats {
+    /// Formats the counters in the Prometheus text exposition format, every metric name
+    /// starting with `prefix`
+    pub fn render_prometheus_text(&self, prefix: &str) -> String {
+        let metrics = [
+            ("events_sent_total", "counter", self.sent as f64),
+            ("dropped_total", "counter", self.dropped as f64),
+            ("buffer_fill", "gauge", self.buffered as f64),
+            ("buffered_bytes", "gauge", self.buffered_bytes as f64),
+            (
+                "last_send_latency_seconds",
+                "gauge",
+                self.last_send_latency.as_secs_f64(),
+            ),
+        ];
+        let mut text = String::new();
+        for (name, kind, value) in metrics {
+            let _ = writeln!(text, "# TYPE {}_{} {}", prefix, name, kind);
+            let _ = writeln!(text, "{}_{} {}", prefix, name, value);
+        }
+        text
+    }
+}
+
+/// Names of the metrics published through the `metrics` facade
+#[cfg(feature = "metrics")]
+#[derive(Debug)]
+struct MetricNames {
+    sent: Arc<str>,
+    dropped: Arc<str>,
+    buffered: Arc<str>,
+    buffered_bytes: Arc<str>,
+    last_send_latency: Arc<str>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct StatsCounters {
+    pub(crate) dropped: AtomicU64,
+    pub(crate) buffered_bytes: AtomicU64,
+    sent: AtomicU64,
+    buffered: AtomicU64,
+    last_send_latency_nanos: AtomicU64,
+    last_batch_id: Mutex<Option<BatchId>>,
+    latency_buckets: [AtomicU64; LATENCY_BUCKET_BOUNDS.len() + 1],
+    batch_size: AtomicUsize,
+    target_sizes: Mutex<TargetSizes>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<MetricNames>,
+}
+
+impl StatsCounters {
+    /// Counters also published through the `metrics` facade under names starting with
+    /// `prefix`
+    #[cfg(feature = "metrics")]
+    pub(crate) fn with_metrics(prefix: &str) -> Self {
+        let name = |name: &str| Arc::from(format!("{}_{}", prefix, name));
+        Self {
+            metrics: Some(MetricNames {
+                sent: name("events_sent_total"),
+                dropped: name("dropped_total"),
+                buffered: name("buffer_fill"),
+                buffered_bytes: name("buffered_bytes"),
+                last_send_latency: name("last_send_latency_seconds"),
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// Counters keeping the record sizes of up to `capacity` targets
+    pub(crate) fn with_tracked_targets(mut self, capacity: usize) -> Self {
+        self.target_sizes = Mutex::new(TargetSizes::new(capacity));
+        self
+    }
+
+    pub(crate) fn snapshot(&self) -> SenderStats {
+        SenderStats {
+            batch_size: self.batch_size.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            buffered_bytes: self.buffered_bytes.load(Ordering::Relaxed),
+            sent: self.sent.load(Ordering::Relaxed),
+            buffered: self.buffered.load(Ordering::Relaxed),
+            last_send_latency: Duration::from_nanos(
+                self.last_send_latency_nanos.load(Ordering::Relaxed),
+            ),
+            last_batch_id: *self
+                .last_batch_id
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+            latency: LatencyHistogram {
+                buckets: self
+                    .latency_buckets
+                    .each_ref()
+                    .map(|count| count.load(Ordering::Relaxed)),
+            },
+        }
+    }
+
+    /// Counts a call to the downstream sender in the latency histogram
+    pub(crate) fn add_latency(&self, latency: Duration) {
+        self.latency_buckets[LatencyHistogram::bucket(latency)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn reset_latency(&self) {
+        self.latency_buckets
+            .iter()
+            .for_each(|count| count.store(0, Ordering::Relaxed));
+    }
+
+    pub(crate) fn add_dropped(&self, count: usize) {
+        if count > 0 {
+            self.dropped.fetch_add(count as u64, Ordering::Relaxed);
+            #[cfg(feature = "metrics")]
+            if let Some(names) = &self.metrics {
+                metrics::counter!(names.dropped.clone()).increment(count as u64);
+            }
+        }
+    }
+
+    pub(crate) fn add_sent(&self, count: usize, latency: Duration) {
+        self.sent.fetch_add(count as u64, Ordering::Relaxed);
+        self.last_send_latency_nanos
+            .store(latency.as_nanos() as u64, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        if let Some(names) = &self.metrics {
+            metrics::counter!(names.sent.clone()).increment(count as u64);
+            metrics::gauge!(names.last_send_latency.clone()).set(latency.as_secs_f64());
+        }
+    }
+
+    /// Counts a record of `target` in the sizes of its target
+    pub(crate) fn add_record_size(&self, target: &str, size: usize, violation: bool) {
+        let mut sizes = self.target_sizes.lock().unwrap();
+        if sizes.capacity > 0 {
+            sizes.add(target, size, violation);
+        }
+    }
+
+    pub(crate) fn target_sizes(&self) -> HashMap<String, TargetSize> {
+        self.target_sizes.lock().unwrap().targets.clone()
+    }
+
+    pub(crate) fn set_batch_size(&self, size: usize) {
+        self.batch_size.store(size, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_last_batch_id(&self, batch_id: BatchId) {
+        *self
+            .last_batch_id
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(batch_id);
+    }
+
+    pub(crate) fn set_buffered(&self, records: usize, bytes: usize) {
+        let previous_records = self.buffered.swap(records as u64, Ordering::Relaxed);
+        let previous_bytes = self.buffered_bytes.swap(bytes as u64, Ordering::Relaxed);
+        // Gauges are shared by all workers, each of them adds its own change
+        #[cfg(feature = "metrics")]
+        if let Some(names) = &self.metrics {
+            metrics::gauge!(names.buffered.clone())
+                .increment(records as f64 - previous_records as f64);
+            metrics::gauge!(names.buffered_bytes.clone())
+                .increment(bytes as f64 - previous_bytes as f64);
+        }
+        #[cfg(not(feature = "metrics"))]
+        let _ = (previous_records, previous_bytes);
+    }
+}
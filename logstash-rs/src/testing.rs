@@ -0,0 +1,141 @@
+//! Helpers for tests of code logging through this crate.
+//!
+//! ```
+//! use qoollo_logstash_rs::testing::install_capturing_logger;
+//!
+//! let captured = install_capturing_logger(log::LevelFilter::Info).unwrap();
+//! log::info!(target: "orders", "order {} accepted", 42);
+//! log::debug!("filtered out");
+//!
+//! captured.assert_logged(log::Level::Info, "order 42 accepted");
+//! let record = captured.find(|r| r.target == "orders").unwrap();
+//! assert_eq!(record.fields["message"], "order 42 accepted");
+//! assert_eq!(captured.len(), 1);
+//! ```
+
+use crate::prelude::*;
+use log::{Level, LevelFilter};
+use std::ops::Range;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+mod faults;
+mod lumberjack;
+mod mock;
+
+pub use faults::WriteFaults;
+pub use lumberjack::{LumberjackAck, MockLumberjack, ReceivedWindow};
+pub use mock::{MockBehavior, MockLogstash, ReceivedLine};
+
+/// Sender keeping every record in memory. Clones share the captured records.
+#[derive(Debug, Clone, Default)]
+pub struct CapturingSender {
+    records: Arc<Mutex<Vec<LogStashRecord>>>,
+}
+
+impl CapturingSender {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Copies of the records captured so far
+    pub fn records(&self) -> Vec<LogStashRecord> {
+        self.lock().clone()
+    }
+
+    /// Removes and returns the records captured so far
+    pub fn take(&self) -> Vec<LogStashRecord> {
+        std::mem::take(&mut *self.lock())
+    }
+
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// First captured record matching `predicate`
+    pub fn find(&self, predicate: impl Fn(&LogStashRecord) -> bool) -> Option<LogStashRecord> {
+        self.lock().iter().find(|r| predicate(r)).cloned()
+    }
+
+    /// Panics listing the captured records unless one has `level` and `message`
+    pub fn assert_logged(&self, level: Level, message: &str) {
+        let records = self.lock();
+        let found = records.iter().any(|r| {
+            r.level == level && r.fields.get("message").and_then(|m| m.as_str()) == Some(message)
+        });
+        assert!(
+            found,
+            "no {} record with message {:?} among captured records: {:#?}",
+            level, message, *records
+        );
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<LogStashRecord>> {
+        // A test panicking while holding the lock must not hide the records from others
+        self.records.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl Sender for CapturingSender {
+    fn send(&self, event: LogStashRecord) -> Result<()> {
+        self.lock().push(event);
+        Ok(())
+    }
+
+    fn send_batch(&self, events: Vec<LogStashRecord>) -> Result<()> {
+        self.lock().extend(events);
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl log::Log for CapturingSender {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        self.lock().push(LogStashRecord::from_record(record));
+    }
+
+    fn flush(&self) {}
+}
+
+/// Installs a new [`CapturingSender`] as the global logger with the `level` max level and
+/// returns it. Fails if a global logger is already set, so call it once per test binary.
+pub fn install_capturing_logger(level: LevelFilter) -> Result<CapturingSender> {
+    let sender = CapturingSender::new();
+    log::set_logger(Box::leak(Box::new(sender.clone())))
+        .map_err(|err| Error::Config(err.to_string()))?;
+    log::set_max_level(level);
+    Ok(sender)
+}
+
+/// Info record of `target` with the `record <seq>` message and a `seq` field
+pub fn record(target: &str, seq: u64) -> LogStashRecord {
+    leveled_record(Level::Info, target, seq)
+}
+
+/// Same as [`record`] with `level`
+pub fn leveled_record(level: Level, target: &str, seq: u64) -> LogStashRecord {
+    LogStashRecord::builder(level)
+        .target(target.to_owned())
+        .message(format!("record {}", seq))
+        .field("seq", seq)
+        .build()
+}
+
+/// One [`record`] of `target` per sequence number of `seqs`
+pub fn records(target: &str, seqs: Range<u64>) -> Vec<LogStashRecord> {
+    seqs.map(|seq| record(target, seq)).collect()
+}
@@ -0,0 +1,75 @@
+use crate::output::tcp::Stream;
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+/// Write failures injected into the connections of a `TcpSender` with
+/// `TcpSender::with_write_faults`. Clones share the pending failures.
+#[derive(Debug, Clone, Default)]
+pub struct WriteFaults {
+    pending: Arc<AtomicU32>,
+    injected: Arc<AtomicU32>,
+}
+
+impl WriteFaults {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fails the next `count` writes before they accept any byte, the connection stays usable
+    pub fn fail_next(&self, count: u32) {
+        self.pending.store(count, Ordering::SeqCst);
+    }
+
+    /// Writes failed so far
+    pub fn injected(&self) -> u32 {
+        self.injected.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn wrap(&self, stream: Stream) -> Stream {
+        Box::new(FaultyStream {
+            inner: stream,
+            faults: self.clone(),
+        })
+    }
+
+    fn take(&self) -> bool {
+        let taken = self
+            .pending
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |pending| {
+                pending.checked_sub(1)
+            })
+            .is_ok();
+        if taken {
+            self.injected.fetch_add(1, Ordering::SeqCst);
+        }
+        taken
+    }
+}
+
+struct FaultyStream {
+    inner: Stream,
+    faults: WriteFaults,
+}
+
+impl Read for FaultyStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl Write for FaultyStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.faults.take() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "injected write failure",
+            ));
+        }
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
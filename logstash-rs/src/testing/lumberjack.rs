@@ -0,0 +1,321 @@
+use crate::prelude::*;
+use flate2::read::ZlibDecoder;
+use serde_json::Value;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How often the acceptor thread checks whether the server was dropped
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Acknowledgement of a window by the [`MockLumberjack`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LumberjackAck {
+    /// Acknowledge the whole window at once
+    All,
+    /// Acknowledge every record of the window separately
+    Each,
+    /// Acknowledge the first records of the window, then close the connection
+    Partial(u32),
+}
+
+/// Window received by a [`MockLumberjack`]
+#[derive(Debug, Clone)]
+pub struct ReceivedWindow {
+    /// Index of the connection the window came on, starting at 0
+    pub connection: usize,
+    /// Length announced by the `W` frame
+    pub size: u32,
+    /// Whether the data frames came in a `C` frame
+    pub compressed: bool,
+    /// Sequence numbers of the `J` frames
+    pub seqs: Vec<u32>,
+    /// Payloads of the `J` frames parsed as JSON
+    pub events: Vec<Value>,
+    /// Last sequence number acknowledged
+    pub acked: u32,
+}
+
+#[derive(Default)]
+struct Received {
+    windows: Mutex<Vec<ReceivedWindow>>,
+    changed: Condvar,
+}
+
+/// Beats input speaking Lumberjack v2 on a local port, recording every window it receives.
+/// Windows are acknowledged following successive entries of the script, windows past the
+/// script are acknowledged at once. Stops accepting connections when dropped.
+///
+/// ```
+/// use qoollo_logstash_rs::testing::MockLumberjack;
+/// use qoollo_logstash_rs::{LogStashRecord, LumberjackSender, Sender};
+/// use std::time::Duration;
+///
+/// let server = MockLumberjack::start().unwrap();
+/// let sender = LumberjackSender::new("127.0.0.1".into(), server.port(), false, None);
+/// sender.send(LogStashRecord::builder(log::Level::Info).message("hello").build()).unwrap();
+///
+/// let events = server.wait_for_acked_events(1, Duration::from_secs(5));
+/// assert_eq!(events[0]["message"], "hello");
+/// ```
+pub struct MockLumberjack {
+    addr: SocketAddr,
+    received: Arc<Received>,
+    connections: Arc<AtomicUsize>,
+    stopped: Arc<AtomicBool>,
+    acceptor: Option<JoinHandle<()>>,
+}
+
+impl MockLumberjack {
+    /// Starts a server acknowledging every window at once
+    pub fn start() -> Result<Self> {
+        Self::start_with_script(Vec::new())
+    }
+
+    /// Starts a server acknowledging the successive windows it receives as told by `script`
+    pub fn start_with_script(script: Vec<LumberjackAck>) -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        let received = Arc::new(Received::default());
+        let connections = Arc::new(AtomicUsize::new(0));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let acceptor = {
+            let received = received.clone();
+            let connections = connections.clone();
+            let stopped = stopped.clone();
+            let script = Arc::new(script);
+            thread::spawn(move || {
+                while !stopped.load(Ordering::Relaxed) {
+                    let stream = match listener.accept() {
+                        Ok((stream, _)) => stream,
+                        Err(_) => {
+                            thread::sleep(POLL_INTERVAL);
+                            continue;
+                        }
+                    };
+                    let connection = Connection {
+                        index: connections.fetch_add(1, Ordering::SeqCst),
+                        script: script.clone(),
+                        received: received.clone(),
+                    };
+                    // Served until the client disconnects, the server does not wait for it
+                    thread::spawn(move || connection.serve(stream));
+                }
+            })
+        };
+        Ok(Self {
+            addr,
+            received,
+            connections,
+            stopped,
+            acceptor: Some(acceptor),
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn port(&self) -> u16 {
+        self.addr.port()
+    }
+
+    /// Number of connections accepted so far
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+
+    /// Copies of the windows received so far
+    pub fn windows(&self) -> Vec<ReceivedWindow> {
+        self.lock().clone()
+    }
+
+    /// Records acknowledged so far, in the order they were received
+    pub fn acked_events(&self) -> Vec<Value> {
+        acked_events(&self.lock())
+    }
+
+    /// Waits until at least `count` records were acknowledged and returns them, panics listing
+    /// the received windows if `timeout` passes first
+    pub fn wait_for_acked_events(&self, count: usize, timeout: Duration) -> Vec<Value> {
+        let deadline = Instant::now() + timeout;
+        let mut windows = self.lock();
+        loop {
+            let events = acked_events(&windows);
+            if events.len() >= count {
+                return events;
+            }
+            let now = Instant::now();
+            assert!(
+                now < deadline,
+                "{} of {} records acknowledged within {:?}: {:#?}",
+                events.len(),
+                count,
+                timeout,
+                *windows
+            );
+            windows = self
+                .received
+                .changed
+                .wait_timeout(windows, deadline - now)
+                .unwrap_or_else(|err| err.into_inner())
+                .0;
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<ReceivedWindow>> {
+        // A test panicking while holding the lock must not hide the windows from others
+        self.received
+            .windows
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl Drop for MockLumberjack {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        if let Some(acceptor) = self.acceptor.take() {
+            let _ = acceptor.join();
+        }
+    }
+}
+
+fn acked_events(windows: &[ReceivedWindow]) -> Vec<Value> {
+    windows
+        .iter()
+        .flat_map(|window| {
+            let acked = window.seqs.iter().take_while(|&&seq| seq <= window.acked);
+            window.events[..acked.count()].iter().cloned()
+        })
+        .collect()
+}
+
+struct Connection {
+    index: usize,
+    script: Arc<Vec<LumberjackAck>>,
+    received: Arc<Received>,
+}
+
+impl Connection {
+    fn serve(self, mut stream: TcpStream) {
+        let _ = stream.set_nonblocking(false);
+        while let Ok(window) = self.read_window(&mut stream) {
+            let size = window.size;
+            let (index, ack) = self.record(window);
+            let (acks, close) = match ack {
+                LumberjackAck::All => (vec![size], false),
+                LumberjackAck::Each => ((1..=size).collect(), false),
+                LumberjackAck::Partial(0) => (vec![], true),
+                LumberjackAck::Partial(count) => (vec![count.min(size)], true),
+            };
+            for seq in acks {
+                // Recorded first, so the records are there once the client sees the ack
+                self.acknowledge(index, seq);
+                if stream.write_all(&ack_frame(seq)).is_err() {
+                    return;
+                }
+            }
+            if close {
+                return;
+            }
+        }
+    }
+
+    /// Reads a `W` frame and the data frames of the window it announces
+    fn read_window(&self, stream: &mut TcpStream) -> std::io::Result<ReceivedWindow> {
+        let (version, kind) = read_header(stream)?;
+        if version != b'2' || kind != b'W' {
+            return Err(invalid(format!("expected a window frame, got {:?}", kind)));
+        }
+        let mut window = ReceivedWindow {
+            connection: self.index,
+            size: read_u32(stream)?,
+            compressed: false,
+            seqs: Vec::new(),
+            events: Vec::new(),
+            acked: 0,
+        };
+        while (window.seqs.len() as u32) < window.size {
+            match read_header(stream)? {
+                (b'2', b'J') => read_json_frame(stream, &mut window)?,
+                (b'2', b'C') => {
+                    window.compressed = true;
+                    let mut compressed = vec![0u8; read_u32(stream)? as usize];
+                    stream.read_exact(&mut compressed)?;
+                    let mut decompressed = Vec::new();
+                    ZlibDecoder::new(&compressed[..]).read_to_end(&mut decompressed)?;
+                    let mut frames = &decompressed[..];
+                    while !frames.is_empty() {
+                        match read_header(&mut frames)? {
+                            (b'2', b'J') => read_json_frame(&mut frames, &mut window)?,
+                            header => return Err(invalid(format!("unexpected {:?}", header))),
+                        }
+                    }
+                }
+                header => return Err(invalid(format!("unexpected frame {:?}", header))),
+            }
+        }
+        Ok(window)
+    }
+
+    /// Stores a new window, returns its index and how to acknowledge it
+    fn record(&self, window: ReceivedWindow) -> (usize, LumberjackAck) {
+        let mut windows = self.lock();
+        let index = windows.len();
+        let ack = self
+            .script
+            .get(index)
+            .copied()
+            .unwrap_or(LumberjackAck::All);
+        windows.push(window);
+        self.received.changed.notify_all();
+        (index, ack)
+    }
+
+    fn acknowledge(&self, index: usize, seq: u32) {
+        self.lock()[index].acked = seq;
+        self.received.changed.notify_all();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<ReceivedWindow>> {
+        self.received
+            .windows
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+fn read_header(stream: &mut impl Read) -> std::io::Result<(u8, u8)> {
+    let mut header = [0u8; 2];
+    stream.read_exact(&mut header)?;
+    Ok((header[0], header[1]))
+}
+
+fn read_u32(stream: &mut impl Read) -> std::io::Result<u32> {
+    let mut value = [0u8; 4];
+    stream.read_exact(&mut value)?;
+    Ok(u32::from_be_bytes(value))
+}
+
+fn read_json_frame(stream: &mut impl Read, window: &mut ReceivedWindow) -> std::io::Result<()> {
+    let seq = read_u32(stream)?;
+    let mut payload = vec![0u8; read_u32(stream)? as usize];
+    stream.read_exact(&mut payload)?;
+    window.seqs.push(seq);
+    window.events.push(serde_json::from_slice(&payload)?);
+    Ok(())
+}
+
+fn ack_frame(seq: u32) -> [u8; 6] {
+    let seq = seq.to_be_bytes();
+    [b'2', b'A', seq[0], seq[1], seq[2], seq[3]]
+}
+
+fn invalid(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,350 @@
+use crate::prelude::*;
+use serde_json::Value;
+use std::io::{BufRead, BufReader, Read};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How often the server threads check whether the server was dropped
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Behavior of the [`MockLogstash`] on one connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MockBehavior {
+    /// Read every line until the client disconnects
+    Accept,
+    /// Read the first bytes, keeping the complete lines among them, then close the connection
+    CloseAfterBytes(usize),
+    /// Read nothing for the duration, then read like [`MockBehavior::Accept`]
+    Stall(Duration),
+    /// Wait for the first bytes and close without reading them, resetting the connection
+    Reset,
+}
+
+/// Line received by a [`MockLogstash`]
+#[derive(Debug, Clone)]
+pub struct ReceivedLine {
+    pub line: String,
+    pub received_at: Instant,
+    /// Index of the connection the line came on, starting at 0
+    pub connection: usize,
+}
+
+impl ReceivedLine {
+    /// The line parsed as JSON, `None` if it is not valid JSON
+    pub fn json(&self) -> Option<Value> {
+        serde_json::from_str(&self.line).ok()
+    }
+}
+
+#[derive(Default)]
+struct Received {
+    lines: Mutex<Vec<ReceivedLine>>,
+    changed: Condvar,
+}
+
+#[cfg(all(feature = "tls", not(feature = "rustls")))]
+type Acceptor = Option<native_tls::TlsAcceptor>;
+#[cfg(not(all(feature = "tls", not(feature = "rustls"))))]
+type Acceptor = Option<NoTls>;
+
+/// Acceptor of the builds without `native-tls`, never constructed
+#[cfg(not(all(feature = "tls", not(feature = "rustls"))))]
+#[derive(Clone)]
+enum NoTls {}
+
+/// Logstash `json_lines` TCP input bound to a local port, recording every received line for
+/// assertions. Connections are served one after another as the client reconnects, each
+/// following the next behavior of the script. Stops when dropped.
+///
+/// ```
+/// use qoollo_logstash_rs::testing::MockLogstash;
+/// use qoollo_logstash_rs::{LogStashRecord, Sender, TcpSender};
+/// use std::time::Duration;
+///
+/// let server = MockLogstash::start().unwrap();
+/// let tcp = TcpSender::builder().hostname("127.0.0.1").port(server.port()).build().unwrap();
+/// tcp.send(LogStashRecord::builder(log::Level::Error).message("failed").build()).unwrap();
+///
+/// server.wait_for_events(1, Duration::from_secs(5));
+/// server.assert_json_field(0, "level", "ERROR");
+/// server.assert_json_field(0, "message", "failed");
+/// assert_eq!(server.connections(), 1);
+/// ```
+pub struct MockLogstash {
+    addr: SocketAddr,
+    received: Arc<Received>,
+    connections: Arc<AtomicUsize>,
+    stopped: Arc<AtomicBool>,
+    acceptor: Option<JoinHandle<()>>,
+}
+
+impl MockLogstash {
+    /// Starts a server reading every connection until the client disconnects
+    pub fn start() -> Result<Self> {
+        Self::start_with_script(Vec::new())
+    }
+
+    /// Starts a server applying the behaviors of `script` to successive connections, the
+    /// connections past the script are read until the client disconnects
+    pub fn start_with_script(script: Vec<MockBehavior>) -> Result<Self> {
+        Self::spawn(script, None)
+    }
+
+    /// Starts a server speaking TLS with `identity`, e.g. a self-signed certificate accepted
+    /// by a client with `TlsOptions::insecure_skip_verify`
+    #[cfg(all(feature = "tls", not(feature = "rustls")))]
+    pub fn start_tls(identity: native_tls::Identity, script: Vec<MockBehavior>) -> Result<Self> {
+        Self::spawn(script, Some(native_tls::TlsAcceptor::new(identity)?))
+    }
+
+    fn spawn(script: Vec<MockBehavior>, tls: Acceptor) -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        let received = Arc::new(Received::default());
+        let connections = Arc::new(AtomicUsize::new(0));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let acceptor = {
+            let received = received.clone();
+            let connections = connections.clone();
+            let stopped = stopped.clone();
+            thread::spawn(move || {
+                let mut workers = Vec::new();
+                while !stopped.load(Ordering::Relaxed) {
+                    let stream = match listener.accept() {
+                        Ok((stream, _)) => stream,
+                        Err(_) => {
+                            thread::sleep(POLL_INTERVAL);
+                            continue;
+                        }
+                    };
+                    let index = connections.fetch_add(1, Ordering::SeqCst);
+                    let behavior = script.get(index).copied().unwrap_or(MockBehavior::Accept);
+                    let connection = Connection {
+                        index,
+                        behavior,
+                        received: received.clone(),
+                        stopped: stopped.clone(),
+                    };
+                    let tls = tls.clone();
+                    workers.push(thread::spawn(move || connection.serve(stream, tls)));
+                }
+                for worker in workers {
+                    let _ = worker.join();
+                }
+            })
+        };
+        Ok(Self {
+            addr,
+            received,
+            connections,
+            stopped,
+            acceptor: Some(acceptor),
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn port(&self) -> u16 {
+        self.addr.port()
+    }
+
+    /// Number of connections accepted so far
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+
+    /// Copies of the lines received so far
+    pub fn lines(&self) -> Vec<ReceivedLine> {
+        self.lock().clone()
+    }
+
+    /// Lines received so far parsed as JSON, skipping lines that are not valid JSON
+    pub fn events(&self) -> Vec<Value> {
+        self.lock().iter().filter_map(ReceivedLine::json).collect()
+    }
+
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    /// Waits until at least `count` lines were received and returns them, panics listing the
+    /// received lines if `timeout` passes first
+    pub fn wait_for_events(&self, count: usize, timeout: Duration) -> Vec<ReceivedLine> {
+        let deadline = Instant::now() + timeout;
+        let mut lines = self.lock();
+        while lines.len() < count {
+            let now = Instant::now();
+            assert!(
+                now < deadline,
+                "received {} of {} lines within {:?}: {:#?}",
+                lines.len(),
+                count,
+                timeout,
+                *lines
+            );
+            lines = self
+                .received
+                .changed
+                .wait_timeout(lines, deadline - now)
+                .unwrap_or_else(|err| err.into_inner())
+                .0;
+        }
+        lines.clone()
+    }
+
+    /// Panics unless the line at `index` is a JSON object with `field` equal to `expected`
+    pub fn assert_json_field(&self, index: usize, field: &str, expected: impl Into<Value>) {
+        let lines = self.lock();
+        let line = lines
+            .get(index)
+            .unwrap_or_else(|| panic!("no line {} among {} received lines", index, lines.len()));
+        let json = line
+            .json()
+            .unwrap_or_else(|| panic!("line {} is not JSON: {:?}", index, line.line));
+        let expected = expected.into();
+        assert_eq!(
+            json.get(field),
+            Some(&expected),
+            "field {:?} of line {}: {}",
+            field,
+            index,
+            line.line
+        );
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<ReceivedLine>> {
+        // A test panicking while holding the lock must not hide the lines from others
+        self.received
+            .lines
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl Drop for MockLogstash {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        if let Some(acceptor) = self.acceptor.take() {
+            let _ = acceptor.join();
+        }
+    }
+}
+
+struct Connection {
+    index: usize,
+    behavior: MockBehavior,
+    received: Arc<Received>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl Connection {
+    fn serve(self, stream: TcpStream, _tls: Acceptor) {
+        if let MockBehavior::Stall(duration) = self.behavior {
+            let until = Instant::now() + duration;
+            while Instant::now() < until && !self.stopped.load(Ordering::Relaxed) {
+                thread::sleep(POLL_INTERVAL.min(until.saturating_duration_since(Instant::now())));
+            }
+        }
+        if stream.set_nonblocking(false).is_err()
+            || stream.set_read_timeout(Some(POLL_INTERVAL)).is_err()
+        {
+            return;
+        }
+        if self.behavior == MockBehavior::Reset {
+            self.wait_for_data(&stream);
+            return;
+        }
+        let limit = match self.behavior {
+            MockBehavior::CloseAfterBytes(bytes) => bytes as u64,
+            _ => u64::MAX,
+        };
+        #[cfg(all(feature = "tls", not(feature = "rustls")))]
+        if let Some(tls) = _tls {
+            if let Ok(stream) = self.handshake(&tls, stream) {
+                self.read_lines(stream.take(limit));
+            }
+            return;
+        }
+        self.read_lines(stream.take(limit));
+    }
+
+    #[cfg(all(feature = "tls", not(feature = "rustls")))]
+    fn handshake(
+        &self,
+        tls: &native_tls::TlsAcceptor,
+        stream: TcpStream,
+    ) -> std::result::Result<native_tls::TlsStream<TcpStream>, ()> {
+        let mut handshake = tls.accept(stream);
+        loop {
+            match handshake {
+                Ok(stream) => return Ok(stream),
+                Err(native_tls::HandshakeError::WouldBlock(mid))
+                    if !self.stopped.load(Ordering::Relaxed) =>
+                {
+                    handshake = mid.handshake()
+                }
+                Err(_) => return Err(()),
+            }
+        }
+    }
+
+    /// Waits until data arrives without consuming it, closing a socket with unread data
+    /// makes the kernel reset the connection
+    fn wait_for_data(&self, stream: &TcpStream) {
+        while !self.stopped.load(Ordering::Relaxed) {
+            match stream.peek(&mut [0u8; 1]) {
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                    ) => {}
+                _ => return,
+            }
+        }
+    }
+
+    /// Records the lines read from `stream` until it ends or the server stops, a trailing
+    /// incomplete line is dropped
+    fn read_lines(&self, stream: impl Read) {
+        let mut reader = BufReader::new(stream);
+        let mut line = Vec::new();
+        while !self.stopped.load(Ordering::Relaxed) {
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) => return,
+                Ok(_) if line.ends_with(b"\n") => {
+                    line.pop();
+                    self.record(String::from_utf8_lossy(&line).into_owned());
+                    line.clear();
+                }
+                Ok(_) => return,
+                // Timed out waiting for data, bytes read so far stay in `line`
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                    ) => {}
+                Err(_) => return,
+            }
+        }
+    }
+
+    fn record(&self, line: String) {
+        let mut lines = self
+            .received
+            .lines
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        lines.push(ReceivedLine {
+            line,
+            received_at: Instant::now(),
+            connection: self.index,
+        });
+        self.received.changed.notify_all();
+    }
+}
@@ -0,0 +1,106 @@
+//! `AsyncBufferedSender` task sending records to the `MockLogstash` of the `testing` module.
+
+use log::{Level, Log, Record};
+use qoollo_logstash_rs::testing::{record, MockLogstash};
+use qoollo_logstash_rs::AsyncBufferedSender;
+use std::io::Read;
+use std::net::TcpListener;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[tokio::test]
+async fn records_flow_through_the_task_and_are_flushed_on_shutdown() {
+    let server = MockLogstash::start().unwrap();
+    let sender = AsyncBufferedSender::new("127.0.0.1".into(), server.port(), Some(10), None, 100);
+    for seq in 0..15 {
+        sender.send(record("async", seq)).await.unwrap();
+    }
+    sender.try_send(record("async", 15)).unwrap();
+    sender.log(
+        &Record::builder()
+            .args(format_args!("from the log shim"))
+            .level(Level::Warn)
+            .target("async")
+            .build(),
+    );
+
+    // The first 10 records fill the buffer, the rest are sent by the shutdown
+    sender.shutdown().await.unwrap();
+    let lines = server.wait_for_events(17, TIMEOUT);
+    let events: Vec<_> = lines.iter().filter_map(|line| line.json()).collect();
+    let seqs: Vec<_> = events[..16]
+        .iter()
+        .map(|event| event["seq"].as_u64().unwrap())
+        .collect();
+    assert_eq!(seqs, (0..16).collect::<Vec<_>>());
+    assert_eq!(events[16]["message"], "from the log shim");
+    assert_eq!(events[16]["level"], "WARN");
+    assert_eq!(server.connections(), 1);
+}
+
+/// Seqs of the records sent to a listener bound on `port` by a flush of `sender` and its shutdown
+async fn flushed_seqs(sender: AsyncBufferedSender, port: u16) -> Vec<u64> {
+    let listener = TcpListener::bind(("127.0.0.1", port)).unwrap();
+    sender.flush().await.unwrap();
+    sender.shutdown().await.unwrap();
+    // The flush connected before it returned, so the connection is waiting to be accepted
+    listener.set_nonblocking(true).unwrap();
+    let (mut stream, _) = listener
+        .accept()
+        .expect("records should be sent by the flush");
+    stream.set_nonblocking(false).unwrap();
+    stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+    let mut received = String::new();
+    stream.read_to_string(&mut received).unwrap();
+
+    received
+        .lines()
+        .map(|line| {
+            serde_json::from_str::<serde_json::Value>(line).unwrap()["seq"]
+                .as_u64()
+                .unwrap()
+        })
+        .collect()
+}
+
+/// Port nothing listens on until a listener is bound on it again
+fn unused_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+#[tokio::test]
+async fn failed_flush_keeps_the_buffered_records() {
+    let port = unused_port();
+    let sender = AsyncBufferedSender::new("127.0.0.1".into(), port, Some(10), None, 100);
+    for seq in 0..3 {
+        sender.send(record("async", seq)).await.unwrap();
+    }
+    assert!(sender.flush().await.is_err());
+
+    let seqs = flushed_seqs(sender, port).await;
+    assert_eq!(seqs, [0, 1, 2]);
+}
+
+#[tokio::test]
+async fn records_retained_beyond_the_maximum_drop_the_oldest() {
+    let port = unused_port();
+    let sender = AsyncBufferedSender::builder("127.0.0.1", port)
+        .with_buffer_size(Some(2))
+        .with_buffer_lifetime(None)
+        .with_max_retained(5)
+        .build();
+    // Every record from the second on fills the buffer and fails to be flushed
+    for seq in 0..12 {
+        sender.send(record("async", seq)).await.unwrap();
+    }
+    assert!(sender.flush().await.is_err());
+    assert_eq!(sender.dropped(), 7);
+
+    let seqs = flushed_seqs(sender, port).await;
+    assert_eq!(seqs, [7, 8, 9, 10, 11]);
+}
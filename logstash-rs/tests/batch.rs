@@ -0,0 +1,199 @@
+//! Batches passed by `BufferedSender` workers to the wrapped sender.
+
+use chrono::{TimeZone, Utc};
+use log::Level;
+use qoollo_logstash_rs::testing::{records, CapturingSender};
+use qoollo_logstash_rs::{BufferedSender, LogStashRecord, Result, Sender};
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Sender recording the sequence numbers of every call
+#[derive(Clone, Default)]
+struct BatchRecorder {
+    calls: Arc<Mutex<Vec<Vec<u64>>>>,
+}
+
+impl BatchRecorder {
+    fn calls(&self) -> Vec<Vec<u64>> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    fn record_call(&self, events: &[LogStashRecord]) -> Result<()> {
+        let seqs = events
+            .iter()
+            .filter_map(|event| event.fields.get("seq").and_then(|seq| seq.as_u64()))
+            .collect();
+        self.calls.lock().unwrap().push(seqs);
+        Ok(())
+    }
+}
+
+impl Sender for BatchRecorder {
+    fn send(&self, event: LogStashRecord) -> Result<()> {
+        self.record_call(std::slice::from_ref(&event))
+    }
+
+    fn send_batch(&self, events: Vec<LogStashRecord>) -> Result<()> {
+        self.record_call(&events)
+    }
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn send_records_is_delivered_as_one_batch() {
+    let recorder = BatchRecorder::default();
+    let sender = BufferedSender::builder()
+        .with_buffer_size(None)
+        .with_diagnostics(false)
+        .build(recorder.clone());
+
+    sender.send_records(records("batch", 0..50)).unwrap();
+    sender.send_records(records("batch", 50..53)).unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+    assert_eq!(
+        recorder.calls(),
+        [(0..50).collect::<Vec<_>>(), (50..53).collect()]
+    );
+    assert_eq!(sender.stats().sent, 53);
+}
+
+#[test]
+fn send_records_goes_through_the_buffer() {
+    let recorder = BatchRecorder::default();
+    let sender = BufferedSender::builder()
+        .with_buffer_size(Some(4))
+        .with_buffer_lifetime(None)
+        .with_ignore_buffer_level(Level::Trace)
+        .with_diagnostics(false)
+        .build(recorder.clone());
+
+    sender.send_records(records("batch", 0..10)).unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+    assert_eq!(
+        recorder.calls(),
+        [vec![0, 1, 2, 3], vec![4, 5, 6, 7], vec![8, 9]]
+    );
+}
+
+#[test]
+fn same_millisecond_records_are_numbered_within_a_batch() {
+    let captured = CapturingSender::new();
+    let sender = BufferedSender::builder()
+        .with_buffer_size(Some(10))
+        .with_buffer_lifetime(None)
+        .with_ignore_buffer_level(Level::Trace)
+        .with_sub_ms_seq(true)
+        .with_diagnostics(false)
+        .build(captured.clone());
+
+    let millis = Utc.timestamp_millis_opt(1_700_000_000_123).unwrap();
+    let timestamps = [
+        millis,
+        millis + chrono::Duration::microseconds(300),
+        millis + chrono::Duration::microseconds(999),
+        millis + chrono::Duration::milliseconds(1),
+        millis + chrono::Duration::milliseconds(2),
+        millis + chrono::Duration::milliseconds(2),
+    ];
+    let records = timestamps
+        .iter()
+        .zip(0..)
+        .map(|(timestamp, seq)| {
+            LogStashRecord::builder(Level::Info)
+                .timestamp(*timestamp)
+                .field("seq", seq)
+                .build()
+        })
+        .collect();
+    sender.send_records(records).unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+
+    let sent: Vec<_> = captured
+        .records()
+        .iter()
+        .map(|record| serde_json::to_value(record).unwrap())
+        .collect();
+    let order: Vec<_> = sent
+        .iter()
+        .map(|record| {
+            (
+                record["@timestamp"].clone(),
+                record.get("sub_ms_seq").cloned(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        order,
+        [
+            (json!("2023-11-14T22:13:20.123Z"), Some(json!(0))),
+            (json!("2023-11-14T22:13:20.123Z"), Some(json!(1))),
+            (json!("2023-11-14T22:13:20.123Z"), Some(json!(2))),
+            (json!("2023-11-14T22:13:20.124Z"), None),
+            (json!("2023-11-14T22:13:20.125Z"), Some(json!(0))),
+            (json!("2023-11-14T22:13:20.125Z"), Some(json!(1))),
+        ]
+    );
+    // Sorting by timestamp then sequence gives back the logging order
+    let mut sorted: Vec<_> = sent.iter().rev().cloned().collect();
+    sorted.sort_by_key(|record| {
+        (
+            record["@timestamp"].as_str().unwrap().to_string(),
+            record["sub_ms_seq"].as_u64(),
+        )
+    });
+    assert_eq!(sorted, sent);
+}
+
+#[test]
+fn target_overrides_use_the_longest_matching_prefix() {
+    let recorder = BatchRecorder::default();
+    let sender = BufferedSender::builder()
+        .with_buffer_size(Some(100))
+        .with_buffer_lifetime(None)
+        .with_ignore_buffer_level(Level::Trace)
+        .with_target_override("myapp", Level::Debug)
+        .with_target_override("myapp::db", Level::Trace)
+        .with_target_override("myapp::auth", Level::Warn)
+        .with_diagnostics(false)
+        .build(recorder.clone());
+
+    let records = vec![
+        ("myapp::db::pool", Level::Debug),
+        ("myapp::web", Level::Debug),
+        ("myapp::auth", Level::Info),
+        ("myapp::auth", Level::Error),
+        ("other", Level::Debug),
+        ("other", Level::Trace),
+        ("myapp::database", Level::Debug),
+        ("myapp", Level::Trace),
+        ("myap", Level::Debug),
+    ];
+    for (seq, (target, level)) in records.into_iter().enumerate() {
+        let record = LogStashRecord::builder(level)
+            .target(target)
+            .field("seq", seq)
+            .build();
+        sender.send(record).unwrap();
+    }
+    sender.flush_and_wait(TIMEOUT).unwrap();
+
+    // Records at or past the level of their override go out one by one, the others wait
+    // in the buffer. Targets without a matching prefix fall through to the default level.
+    assert_eq!(
+        recorder.calls(),
+        [
+            vec![1],
+            vec![2],
+            vec![5],
+            vec![6],
+            vec![7],
+            vec![0, 3, 4, 8]
+        ]
+    );
+}
@@ -0,0 +1,40 @@
+//! The `testing` helpers as a downstream crate uses them: the capturing sender installed as the
+//! global logger. A process has a single global logger, so this file holds a single test.
+
+use log::{Level, LevelFilter};
+use qoollo_logstash_rs::testing::install_capturing_logger;
+
+#[test]
+fn log_macros_are_captured_with_their_fields() {
+    let captured = install_capturing_logger(LevelFilter::Info).unwrap();
+    assert!(install_capturing_logger(LevelFilter::Info).is_err());
+
+    log::info!(target: "orders", "order {} accepted", 42);
+    log::info!("cart emptied");
+    log::warn!(target: "orders", "order {} delayed", 43);
+    log::debug!("filtered out by the max level");
+    assert_eq!(captured.len(), 3);
+
+    captured.assert_logged(Level::Info, "order 42 accepted");
+    captured.assert_logged(Level::Warn, "order 43 delayed");
+    let record = captured
+        .find(|record| record.fields["message"] == "cart emptied")
+        .unwrap();
+    assert_eq!(record.level, Level::Info);
+    assert_eq!(record.target, module_path!());
+    assert_eq!(record.module.as_deref(), Some(module_path!()));
+    assert_eq!(record.file.as_deref(), Some(file!()));
+    assert!(record.line.is_some());
+
+    let orders: Vec<_> = captured
+        .take()
+        .into_iter()
+        .filter(|record| record.target == "orders")
+        .map(|record| record.level)
+        .collect();
+    assert_eq!(orders, [Level::Info, Level::Warn]);
+    assert!(captured.is_empty());
+
+    log::error!("captured after take");
+    captured.assert_logged(Level::Error, "captured after take");
+}
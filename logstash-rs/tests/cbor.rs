@@ -0,0 +1,107 @@
+//! CBOR encoding of records: self-describe tag, tagged timestamp, binary fields as byte
+//! strings, and round trips through `from_cbor`.
+
+use chrono::{DateTime, Utc};
+use ciborium::Value;
+use log::Level;
+use qoollo_logstash_rs::{LogStashRecord, SELF_DESCRIBE_TAG};
+
+/// Timestamp with millisecond precision, the precision kept by the serialized form
+fn timestamp() -> DateTime<Utc> {
+    DateTime::from_timestamp_millis(Utc::now().timestamp_millis()).unwrap()
+}
+
+fn record() -> LogStashRecord {
+    let mut record = LogStashRecord::builder(Level::Error)
+        .target("cbor")
+        .message("upload failed")
+        .field("attempt", 3)
+        .field("ratio", 0.5)
+        .field("labels", serde_json::json!(["a", "b"]))
+        .build();
+    record.timestamp = timestamp();
+    record.add_bytes("payload", &[0, 1, 2, 0xff]);
+    record
+}
+
+fn decode(cbor: &[u8]) -> Vec<(Value, Value)> {
+    match ciborium::from_reader(cbor).unwrap() {
+        Value::Tag(SELF_DESCRIBE_TAG, map) => map.into_map().unwrap(),
+        other => panic!("missing self-describe tag: {:?}", other),
+    }
+}
+
+fn get<'a>(map: &'a [(Value, Value)], key: &str) -> &'a Value {
+    map.iter()
+        .find(|(k, _)| k.as_text() == Some(key))
+        .map(|(_, v)| v)
+        .unwrap_or_else(|| panic!("missing key {}", key))
+}
+
+#[test]
+fn encoding_starts_with_self_describe_tag() {
+    let cbor = record().to_cbor().unwrap();
+    assert_eq!(&cbor[..3], &[0xd9, 0xd9, 0xf7]);
+}
+
+#[test]
+fn timestamp_is_tagged_date_time() {
+    let record = record();
+    let map = decode(&record.to_cbor().unwrap());
+    match get(&map, "@timestamp") {
+        Value::Tag(0, text) => {
+            let parsed: DateTime<Utc> = text.as_text().unwrap().parse().unwrap();
+            assert_eq!(parsed, record.timestamp);
+        }
+        other => panic!("untagged timestamp: {:?}", other),
+    }
+}
+
+#[test]
+fn binary_fields_are_byte_strings() {
+    let record = record();
+    let map = decode(&record.to_cbor().unwrap());
+    assert_eq!(get(&map, "payload"), &Value::Bytes(vec![0, 1, 2, 0xff]));
+    assert_eq!(get(&map, "message"), &Value::Text("upload failed".into()));
+    assert_eq!(get(&map, "attempt"), &Value::Integer(3.into()));
+
+    let json = serde_json::to_value(&record).unwrap();
+    assert_eq!(json["payload"], "AAEC/w==");
+}
+
+#[test]
+fn round_trip_keeps_record() {
+    let record = record();
+    let mut buffer = vec![];
+    record.write_cbor(&mut buffer).unwrap();
+    assert_eq!(buffer, record.to_cbor().unwrap());
+
+    let parsed = LogStashRecord::from_cbor(&buffer).unwrap();
+    assert_eq!(parsed, record);
+    assert_eq!(parsed.bytes_fields, vec!["payload".to_string()]);
+    let mut reencoded = decode(&parsed.to_cbor().unwrap());
+    let mut decoded = decode(&buffer);
+    let key = |(k, _): &(Value, Value)| k.as_text().unwrap().to_owned();
+    reencoded.sort_by_key(key);
+    decoded.sort_by_key(key);
+    assert_eq!(reencoded, decoded);
+}
+
+#[test]
+fn round_trip_without_binary_fields() {
+    let mut record = LogStashRecord::builder(Level::Debug)
+        .message("plain")
+        .build();
+    record.timestamp = timestamp();
+    let parsed = LogStashRecord::from_cbor(&record.to_cbor().unwrap()).unwrap();
+    assert_eq!(parsed, record);
+    assert!(parsed.bytes_fields.is_empty());
+}
+
+#[test]
+fn rejects_non_map() {
+    let mut buffer = vec![];
+    ciborium::into_writer(&Value::Array(vec![]), &mut buffer).unwrap();
+    assert!(LogStashRecord::from_cbor(&buffer).is_err());
+    assert!(LogStashRecord::from_cbor(&[0xff]).is_err());
+}
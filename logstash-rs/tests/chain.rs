@@ -0,0 +1,91 @@
+//! Records sent through `ChainedSender` from a `RingBufferSender` to a `TcpSender` and the
+//! `MockLogstash` of the `testing` module.
+
+use qoollo_logstash_rs::testing::{record, CapturingSender, MockLogstash};
+use qoollo_logstash_rs::{BufferedSender, ChainedSender, RingBufferSender, Sender, TcpSender};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn chained(
+    server: &MockLogstash,
+    poll_interval: Duration,
+) -> ChainedSender<RingBufferSender, TcpSender> {
+    let tcp = TcpSender::builder()
+        .hostname("127.0.0.1")
+        .port(server.port())
+        .build()
+        .unwrap();
+    ChainedSender::new(RingBufferSender::new(1000).unwrap(), tcp, poll_interval)
+}
+
+fn seqs(server: &MockLogstash) -> Vec<u64> {
+    server
+        .events()
+        .iter()
+        .map(|e| e["seq"].as_u64().unwrap())
+        .collect()
+}
+
+#[test]
+fn background_thread_forwards_records() {
+    let server = MockLogstash::start().unwrap();
+    let sender = chained(&server, Duration::from_millis(20));
+    for seq in 0..50 {
+        sender.send(record("chain", seq)).unwrap();
+    }
+
+    server.wait_for_events(50, TIMEOUT);
+    assert_eq!(seqs(&server), (0..50).collect::<Vec<_>>());
+    assert!(sender.fast().is_empty());
+}
+
+#[test]
+fn flush_forwards_without_waiting_for_poll() {
+    let server = MockLogstash::start().unwrap();
+    let sender = chained(&server, Duration::from_secs(600));
+    sender
+        .send_batch((0..5).map(|seq| record("chain", seq)).collect())
+        .unwrap();
+    assert_eq!(sender.fast().len(), 5);
+
+    sender.flush().unwrap();
+    assert!(sender.fast().is_empty());
+    server.wait_for_events(5, TIMEOUT);
+    assert_eq!(seqs(&server), [0, 1, 2, 3, 4]);
+}
+
+#[test]
+fn drop_forwards_remaining_records() {
+    let server = MockLogstash::start().unwrap();
+    let sender = chained(&server, Duration::from_secs(600));
+    for seq in 0..3 {
+        sender.send(record("chain", seq)).unwrap();
+    }
+    drop(sender);
+
+    server.wait_for_events(3, TIMEOUT);
+    assert_eq!(seqs(&server), [0, 1, 2]);
+}
+
+#[test]
+fn flush_and_wait_waits_for_the_slow_sender() {
+    let captured = CapturingSender::new();
+    let buffered = BufferedSender::builder()
+        .with_buffer_size(Some(1000))
+        .with_buffer_lifetime(Some(Duration::from_secs(600)))
+        .with_diagnostics(false)
+        .build(captured.clone());
+    let sender = ChainedSender::new(
+        RingBufferSender::new(1000).unwrap(),
+        buffered,
+        Duration::from_secs(600),
+    );
+    for seq in 0..5 {
+        sender.send(record("chain", seq)).unwrap();
+    }
+
+    // The buffered sender only delivers once its workers confirm the flush
+    sender.flush_and_wait(TIMEOUT).unwrap();
+    assert_eq!(captured.len(), 5);
+}
@@ -0,0 +1,67 @@
+//! Record timestamps taken from a `MockClock`. The default clock is global, so this file
+//! holds the only test installing it.
+
+use chrono::{DateTime, TimeZone, Utc};
+use log::{Level, Record};
+use qoollo_logstash_rs::clock::{default_clock, set_default_clock, MockClock};
+use qoollo_logstash_rs::LogStashRecord;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn start() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 2, 29, 23, 59, 59).unwrap()
+}
+
+fn timestamp(record: &LogStashRecord) -> serde_json::Value {
+    serde_json::to_value(record).unwrap()["@timestamp"].clone()
+}
+
+#[test]
+fn records_are_stamped_with_the_mock_clock() {
+    let clock = MockClock::new(start());
+    let record = LogStashRecord::new_with_clock(&clock);
+    assert_eq!(record.timestamp, start());
+    assert_eq!(timestamp(&record), "2024-02-29T23:59:59.000Z");
+
+    let log_record = Record::builder()
+        .args(format_args!("hello"))
+        .level(Level::Info)
+        .build();
+    clock.advance(Duration::from_millis(1_250));
+    let record = LogStashRecord::from_record_with_clock(&log_record, &clock);
+    assert_eq!(timestamp(&record), "2024-03-01T00:00:00.250Z");
+    assert_eq!(record.fields["message"], "hello");
+}
+
+#[test]
+fn mock_clock_stays_fixed_until_changed() {
+    let clock = MockClock::new(start());
+    let first = LogStashRecord::new_with_clock(&clock);
+    std::thread::sleep(Duration::from_millis(5));
+    let second = LogStashRecord::new_with_clock(&clock);
+    assert_eq!(first.timestamp, second.timestamp);
+
+    let later = Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap();
+    clock.set(later);
+    assert_eq!(LogStashRecord::new_with_clock(&clock).timestamp, later);
+}
+
+#[test]
+fn default_clock_stamps_new_records() {
+    let clock = Arc::new(MockClock::new(start()));
+    set_default_clock(clock.clone()).unwrap_or_else(|_| panic!("default clock already set"));
+    assert_eq!(LogStashRecord::new().timestamp, start());
+    assert_eq!(
+        LogStashRecord::builder(Level::Warn).build().timestamp,
+        start()
+    );
+    assert_eq!(default_clock().now(), start());
+
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(
+        LogStashRecord::new().timestamp,
+        start() + chrono::Duration::seconds(1)
+    );
+    // Installed once, later clocks are handed back
+    assert!(set_default_clock(Arc::new(MockClock::new(start()))).is_err());
+}
@@ -0,0 +1,131 @@
+//! Identical consecutive records coalesced into the last buffered one by `BufferedSender`.
+
+use log::Level;
+use qoollo_logstash_rs::testing::CapturingSender;
+use qoollo_logstash_rs::{BufferedSender, LogStashRecord, Sender};
+use serde_json::Value;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const CAP: u64 = 1000;
+const RECORDS: u64 = 10_000;
+
+fn coalescing(captured: &CapturingSender) -> BufferedSender {
+    BufferedSender::builder()
+        .with_buffer_size(Some(100))
+        .with_buffer_lifetime(None)
+        .with_ignore_buffer_level(Level::Trace)
+        .with_log_queue_len(RECORDS as usize + 10)
+        .with_coalesce_repeats(Some(CAP))
+        .with_diagnostics(false)
+        .build(captured.clone())
+}
+
+fn record(level: Level, message: &str) -> LogStashRecord {
+    LogStashRecord::builder(level)
+        .target("retry")
+        .message(message)
+        .build()
+}
+
+/// Message and repeat count of every captured record
+fn captured_runs(captured: &CapturingSender) -> Vec<(String, Option<u64>)> {
+    captured
+        .take()
+        .iter()
+        .map(|record| {
+            (
+                record.fields["message"].as_str().unwrap().to_owned(),
+                record.fields.get("repeat_count").and_then(Value::as_u64),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn identical_records_fill_a_handful_of_entries() {
+    let captured = CapturingSender::new();
+    let sender = coalescing(&captured);
+
+    sender.pause().unwrap();
+    for _ in 0..RECORDS {
+        sender
+            .send(record(Level::Warn, "connection refused"))
+            .unwrap();
+    }
+    sender.send(record(Level::Info, "request served")).unwrap();
+    // Fails while paused, once the records queued before are buffered
+    let err = sender.flush_and_wait(TIMEOUT).unwrap_err();
+    assert_eq!(err.kind(), "paused");
+    assert_eq!(sender.stats().buffered, RECORDS / CAP + 1);
+
+    sender.resume().unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+    let runs = captured_runs(&captured);
+    let mut expected = vec![("connection refused".to_owned(), Some(CAP)); (RECORDS / CAP) as usize];
+    expected.push(("request served".to_owned(), None));
+    assert_eq!(runs, expected);
+    assert_eq!(sender.stats().dropped, 0);
+}
+
+#[test]
+fn only_consecutive_matches_are_coalesced() {
+    let captured = CapturingSender::new();
+    let sender = coalescing(&captured);
+
+    let sends = [
+        (Level::Warn, "timeout"),
+        (Level::Warn, "timeout"),
+        (Level::Error, "timeout"),
+        (Level::Warn, "timeout"),
+        (Level::Warn, "refused"),
+        (Level::Warn, "refused"),
+        (Level::Warn, "refused"),
+    ];
+    for &(level, message) in sends.iter() {
+        sender.send(record(level, message)).unwrap();
+    }
+    // Same message from another target starts a new entry
+    sender
+        .send(
+            LogStashRecord::builder(Level::Warn)
+                .target("other")
+                .message("refused")
+                .build(),
+        )
+        .unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+
+    let runs = captured_runs(&captured);
+    let expected = [
+        ("timeout", Some(2)),
+        ("timeout", None),
+        ("timeout", None),
+        ("refused", Some(3)),
+        ("refused", None),
+    ];
+    let expected: Vec<_> = expected
+        .iter()
+        .map(|&(message, count)| (message.to_owned(), count))
+        .collect();
+    assert_eq!(runs, expected);
+}
+
+#[test]
+fn records_are_not_coalesced_by_default() {
+    let captured = CapturingSender::new();
+    let sender = BufferedSender::builder()
+        .with_buffer_size(Some(100))
+        .with_ignore_buffer_level(Level::Trace)
+        .with_diagnostics(false)
+        .build(captured.clone());
+
+    for _ in 0..3 {
+        sender.send(record(Level::Warn, "timeout")).unwrap();
+    }
+    sender.flush_and_wait(TIMEOUT).unwrap();
+    assert_eq!(
+        captured_runs(&captured),
+        vec![("timeout".to_owned(), None); 3]
+    );
+}
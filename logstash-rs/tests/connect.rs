@@ -0,0 +1,123 @@
+//! Connection handling of `BufferedSender` workers: records logged while the sender connects
+//! in the background are buffered and delivered once the connection is ready.
+
+use log::Level;
+use qoollo_logstash_rs::testing::{leveled_record, CapturingSender};
+use qoollo_logstash_rs::{
+    BufferedSender, BufferedSenderBuilder, Error, LogStashRecord, Result, Sender,
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Sender whose `connect` blocks until released, failing any send made before it returns
+#[derive(Clone)]
+struct SlowConnectSender {
+    release: Arc<Mutex<mpsc::Receiver<Result<()>>>>,
+    connected: Arc<AtomicBool>,
+    captured: CapturingSender,
+}
+
+impl SlowConnectSender {
+    fn new() -> (Self, mpsc::Sender<Result<()>>) {
+        let (release, released) = mpsc::channel();
+        let sender = Self {
+            release: Arc::new(Mutex::new(released)),
+            connected: Default::default(),
+            captured: CapturingSender::new(),
+        };
+        (sender, release)
+    }
+
+    fn check_connected(&self) -> Result<()> {
+        if self.connected.load(Ordering::SeqCst) {
+            Ok(())
+        } else {
+            Err(Error::Connection("sent before connecting".into()))
+        }
+    }
+}
+
+impl Sender for SlowConnectSender {
+    fn send(&self, event: LogStashRecord) -> Result<()> {
+        self.check_connected()?;
+        self.captured.send(event)
+    }
+
+    fn send_batch(&self, events: Vec<LogStashRecord>) -> Result<()> {
+        self.check_connected()?;
+        self.captured.send_batch(events)
+    }
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn connect(&self) -> Result<()> {
+        let result = self.release.lock().unwrap().recv().unwrap_or(Ok(()));
+        // Sends are accepted after a failed attempt too, as a sender reconnecting lazily would
+        self.connected.store(true, Ordering::SeqCst);
+        result
+    }
+}
+
+fn pre_connect() -> BufferedSenderBuilder {
+    BufferedSender::builder()
+        .with_pre_connect(true)
+        .with_buffer_size(Some(2))
+        .with_buffer_lifetime(None)
+        .with_diagnostics(false)
+}
+
+fn seqs(captured: &CapturingSender) -> Vec<u64> {
+    captured
+        .records()
+        .iter()
+        .filter_map(|record| record.fields.get("seq").and_then(|seq| seq.as_u64()))
+        .collect()
+}
+
+/// Sends records of every level, including ones above the ignore buffer level, while the
+/// worker is still connecting
+fn send_early_records(sender: &BufferedSender) {
+    for seq in 0..7 {
+        let level = if seq % 3 == 0 {
+            Level::Error
+        } else {
+            Level::Info
+        };
+        sender.send(leveled_record(level, "connect", seq)).unwrap();
+    }
+}
+
+#[test]
+fn early_records_survive_connecting() {
+    let (slow, release) = SlowConnectSender::new();
+    let sender = pre_connect().build(slow.clone());
+
+    send_early_records(&sender);
+    // Flushes are held back as well until the connection is ready
+    Sender::flush(&sender).unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+    assert!(slow.captured.records().is_empty());
+
+    release.send(Ok(())).unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+    assert_eq!(seqs(&slow.captured), (0..7).collect::<Vec<_>>());
+    assert_eq!(sender.stats().dropped, 0);
+}
+
+#[test]
+fn early_records_are_sent_after_failed_connect() {
+    let (slow, release) = SlowConnectSender::new();
+    let sender = pre_connect().build(slow.clone());
+
+    send_early_records(&sender);
+    release
+        .send(Err(Error::Connection("refused".into())))
+        .unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+    assert_eq!(seqs(&slow.captured), (0..7).collect::<Vec<_>>());
+}
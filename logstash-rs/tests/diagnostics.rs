@@ -0,0 +1,173 @@
+//! Self-diagnostic records sent by `BufferedSender` workers once the wrapped sender recovers
+//! from errors.
+
+use log::Level;
+use qoollo_logstash_rs::testing::{record, CapturingSender};
+use qoollo_logstash_rs::{BufferedSender, Error, LogStashRecord, Result, Sender};
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const DIAGNOSTICS_TARGET: &str = "logstash_rs::internal";
+const ENDPOINT: &str = "mock:5044";
+
+/// Sender failing every call while down, counting the failed calls
+#[derive(Clone, Default)]
+struct FlakySender {
+    down: Arc<AtomicBool>,
+    failed_calls: Arc<AtomicUsize>,
+    /// Number of batches holding diagnostic records to reject while up
+    reject_diagnostics: Arc<AtomicUsize>,
+    captured: CapturingSender,
+}
+
+impl FlakySender {
+    fn set_down(&self, down: bool) {
+        self.down.store(down, Ordering::SeqCst);
+    }
+
+    /// Takes the number of calls failed so far
+    fn take_failed_calls(&self) -> usize {
+        self.failed_calls.swap(0, Ordering::SeqCst)
+    }
+
+    fn check(&self) -> Result<()> {
+        if self.down.load(Ordering::SeqCst) {
+            self.failed_calls.fetch_add(1, Ordering::SeqCst);
+            return Err(io::Error::new(io::ErrorKind::ConnectionRefused, "refused").into());
+        }
+        Ok(())
+    }
+
+    fn diagnostics(&self) -> Vec<LogStashRecord> {
+        self.captured
+            .records()
+            .into_iter()
+            .filter(|record| record.target == DIAGNOSTICS_TARGET)
+            .collect()
+    }
+}
+
+impl Sender for FlakySender {
+    fn send(&self, event: LogStashRecord) -> Result<()> {
+        self.check()?;
+        self.captured.send(event)
+    }
+
+    fn send_batch(&self, events: Vec<LogStashRecord>) -> Result<()> {
+        self.check()?;
+        if events.iter().any(|e| e.target == DIAGNOSTICS_TARGET)
+            && self
+                .reject_diagnostics
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok()
+        {
+            return Err(Error::Connection("diagnostics rejected".into()));
+        }
+        self.captured.send_batch(events)
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.check()
+    }
+
+    fn endpoint(&self) -> Option<String> {
+        Some(ENDPOINT.into())
+    }
+}
+
+fn unbuffered(flaky: &FlakySender) -> BufferedSender {
+    BufferedSender::builder()
+        .with_buffer_size(None)
+        .with_error_period(Duration::from_secs(3600))
+        .build(flaky.clone())
+}
+
+/// Sends `count` records while the sender is down and returns the number of failed calls
+fn fail(sender: &BufferedSender, flaky: &FlakySender, count: usize) -> usize {
+    flaky.set_down(true);
+    for seq in 0..count as u64 {
+        sender.send(record("diagnostics", seq)).unwrap();
+    }
+    assert!(sender.flush_and_wait(TIMEOUT).is_err());
+    flaky.set_down(false);
+    flaky.take_failed_calls()
+}
+
+fn assert_recovery(record: &LogStashRecord, failures: usize) {
+    assert_eq!(record.level, Level::Warn);
+    assert_eq!(record.fields["error_kind"], "io");
+    assert_eq!(record.fields["error"], "refused");
+    assert_eq!(record.fields["retry_count"], failures);
+    assert_eq!(record.fields["endpoint"], ENDPOINT);
+    assert_eq!(
+        record.fields["message"],
+        format!(
+            "logstash sender recovered after {} failed attempts: refused",
+            failures
+        )
+    );
+}
+
+#[test]
+fn recovery_is_reported_once_with_failure_count() {
+    let flaky = FlakySender::default();
+    let sender = unbuffered(&flaky);
+
+    let failures = fail(&sender, &flaky, 3);
+    assert_eq!(failures, 4, "three sends and the flush should have failed");
+    // Nothing is reported while the sender is failing
+    assert!(flaky.diagnostics().is_empty());
+
+    for seq in 0..3 {
+        sender.send(record("diagnostics", seq)).unwrap();
+        sender.flush_and_wait(TIMEOUT).unwrap();
+    }
+    let diagnostics = flaky.diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_recovery(&diagnostics[0], failures);
+
+    // Every new failure streak is reported on its own
+    let failures = fail(&sender, &flaky, 1);
+    // Diagnostics are sent after the flush is confirmed, the second flush waits for them
+    sender.flush_and_wait(TIMEOUT).unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+    let diagnostics = flaky.diagnostics();
+    assert_eq!(diagnostics.len(), 2);
+    assert_recovery(&diagnostics[1], failures);
+}
+
+#[test]
+fn failed_diagnostics_are_retried_without_further_diagnostics() {
+    let flaky = FlakySender::default();
+    flaky.reject_diagnostics.store(1, Ordering::SeqCst);
+    let sender = unbuffered(&flaky);
+
+    let failures = fail(&sender, &flaky, 2);
+    sender.flush_and_wait(TIMEOUT).unwrap();
+    assert!(flaky.diagnostics().is_empty());
+
+    sender.send(record("diagnostics", 0)).unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+    let diagnostics = flaky.diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_recovery(&diagnostics[0], failures);
+}
+
+#[test]
+fn disabled_diagnostics_send_nothing() {
+    let flaky = FlakySender::default();
+    let sender = BufferedSender::builder()
+        .with_buffer_size(None)
+        .with_diagnostics(false)
+        .with_error_period(Duration::from_secs(3600))
+        .build(flaky.clone());
+
+    fail(&sender, &flaky, 2);
+    sender.send(record("diagnostics", 0)).unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+    assert!(flaky.diagnostics().is_empty());
+    assert_eq!(flaky.captured.len(), 1);
+}
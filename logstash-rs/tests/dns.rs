@@ -0,0 +1,138 @@
+//! Hostname resolution of `TcpSender` on reconnects, with and without a DNS cache TTL.
+
+use log::Level;
+use qoollo_logstash_rs::testing::{MockBehavior, MockLogstash};
+use qoollo_logstash_rs::{LogStashRecord, Resolver, Sender, TcpSender};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+const AUDIT_TIMEOUT: Duration = Duration::from_millis(200);
+const HOSTNAME: &str = "logstash.internal";
+
+/// Resolver returning an address set by the test, counting the lookups
+#[derive(Default)]
+struct MockResolver {
+    addr: Mutex<Option<SocketAddr>>,
+    lookups: AtomicUsize,
+}
+
+impl MockResolver {
+    fn pointing_to(addr: SocketAddr) -> Arc<Self> {
+        let resolver = Arc::new(Self::default());
+        resolver.point_to(addr);
+        resolver
+    }
+
+    fn point_to(&self, addr: SocketAddr) {
+        *self.addr.lock().unwrap() = Some(addr);
+    }
+
+    fn lookups(&self) -> usize {
+        self.lookups.load(Ordering::SeqCst)
+    }
+}
+
+impl Resolver for MockResolver {
+    fn resolve(&self, hostname: &str, port: u16) -> std::io::Result<Vec<SocketAddr>> {
+        assert_eq!((hostname, port), (HOSTNAME, 5044));
+        self.lookups.fetch_add(1, Ordering::SeqCst);
+        Ok(self.addr.lock().unwrap().iter().copied().collect())
+    }
+}
+
+fn tcp(resolver: &Arc<MockResolver>, dns_cache_ttl: Option<Duration>) -> TcpSender {
+    TcpSender::builder()
+        .hostname(HOSTNAME)
+        .port(5044)
+        .build()
+        .unwrap()
+        .with_resolver(resolver.clone())
+        .with_dns_cache_ttl(dns_cache_ttl)
+        // Resends a batch reset by the peer on a fresh connection, making the reconnect
+        // observable within the call
+        .with_audit(Some(AUDIT_TIMEOUT))
+}
+
+fn record() -> LogStashRecord {
+    LogStashRecord::builder(Level::Info).target("dns").build()
+}
+
+/// Connects to `old`, which resets the connection, and moves the hostname to `new`
+/// before the next send reconnects
+fn move_after_connect(tcp: &TcpSender, resolver: &MockResolver, new: &MockLogstash) {
+    tcp.pre_connect().unwrap();
+    resolver.point_to(new.addr());
+    tcp.send(record()).unwrap();
+}
+
+#[test]
+fn hostname_is_resolved_again_on_every_reconnect() {
+    let old = MockLogstash::start_with_script(vec![MockBehavior::Reset]).unwrap();
+    let new = MockLogstash::start().unwrap();
+    let resolver = MockResolver::pointing_to(old.addr());
+    let tcp = tcp(&resolver, None);
+
+    move_after_connect(&tcp, &resolver, &new);
+    new.wait_for_events(1, TIMEOUT);
+    assert_eq!(resolver.lookups(), 2);
+    assert_eq!(old.connections(), 1);
+    assert_eq!(tcp.reconnects(), 1);
+}
+
+#[test]
+fn cached_address_is_reused_within_the_ttl() {
+    let old = MockLogstash::start_with_script(vec![MockBehavior::Reset]).unwrap();
+    let new = MockLogstash::start().unwrap();
+    let resolver = MockResolver::pointing_to(old.addr());
+    let tcp = tcp(&resolver, Some(Duration::from_secs(3600)));
+
+    move_after_connect(&tcp, &resolver, &new);
+    old.wait_for_events(1, TIMEOUT);
+    assert_eq!(resolver.lookups(), 1);
+    assert_eq!(old.connections(), 2);
+    assert_eq!(new.connections(), 0);
+}
+
+#[test]
+fn expired_address_is_resolved_again() {
+    let old = MockLogstash::start_with_script(vec![MockBehavior::Reset]).unwrap();
+    let new = MockLogstash::start().unwrap();
+    let resolver = MockResolver::pointing_to(old.addr());
+    let tcp = tcp(&resolver, Some(Duration::from_millis(50)));
+
+    tcp.pre_connect().unwrap();
+    resolver.point_to(new.addr());
+    std::thread::sleep(Duration::from_millis(100));
+    tcp.send(record()).unwrap();
+    new.wait_for_events(1, TIMEOUT);
+    assert_eq!(resolver.lookups(), 2);
+}
+
+#[test]
+fn failed_connection_discards_the_cached_address() {
+    // Nothing listens on the address the hostname resolves to at first
+    let closed = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let new = MockLogstash::start().unwrap();
+    let resolver = MockResolver::pointing_to(closed);
+    let tcp = tcp(&resolver, Some(Duration::from_secs(3600)));
+
+    assert!(tcp.send(record()).is_err());
+    resolver.point_to(new.addr());
+    tcp.send(record()).unwrap();
+    new.wait_for_events(1, TIMEOUT);
+    assert_eq!(resolver.lookups(), 2);
+}
+
+#[test]
+fn hostname_without_addresses_is_an_error() {
+    let resolver = Arc::new(MockResolver::default());
+    let tcp = tcp(&resolver, None);
+    let err = tcp.send(record()).unwrap_err();
+    assert!(err.to_string().contains(HOSTNAME), "{}", err);
+}
@@ -0,0 +1,101 @@
+//! `BufferedSender::drain_pending` with workers that don't hand their records back within
+//! the shutdown timeout.
+
+use log::Level;
+use qoollo_logstash_rs::testing::{record, CapturingSender};
+use qoollo_logstash_rs::{BufferedSender, LogStashRecord, Result, Sender};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Sender holding every call until released, capturing the records
+#[derive(Clone)]
+struct GatedSender {
+    release: Arc<Mutex<mpsc::Receiver<()>>>,
+    captured: CapturingSender,
+}
+
+impl Sender for GatedSender {
+    fn send(&self, event: LogStashRecord) -> Result<()> {
+        let _ = self.release.lock().unwrap().recv();
+        self.captured.send(event)
+    }
+
+    fn send_batch(&self, events: Vec<LogStashRecord>) -> Result<()> {
+        let _ = self.release.lock().unwrap().recv();
+        self.captured.send_batch(events)
+    }
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Drains a sender whose worker is stuck delivering records 0 to 2 while records 3 and 4
+/// are queued behind it, in a queue of `log_queue_len`. Returns the drained records and
+/// the records captured once the worker is released.
+fn drain_stuck_worker(log_queue_len: usize) -> (Vec<LogStashRecord>, Vec<u64>) {
+    let (release, released) = mpsc::channel();
+    let captured = CapturingSender::new();
+    let gated = GatedSender {
+        release: Arc::new(Mutex::new(released)),
+        captured: captured.clone(),
+    };
+    let sender = BufferedSender::builder()
+        .with_buffer_size(Some(100))
+        .with_buffer_lifetime(None)
+        .with_ignore_buffer_level(Level::Trace)
+        .with_log_queue_len(log_queue_len)
+        .with_diagnostics(false)
+        .with_shutdown_timeout(SHUTDOWN_TIMEOUT)
+        .build(gated);
+    // Pauses let the worker empty the queue before more is queued
+    for seq in 0..3 {
+        sender.send(record("drain", seq)).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    sender.flush().unwrap();
+    std::thread::sleep(Duration::from_millis(50));
+    for seq in 3..5 {
+        sender.send(record("drain", seq)).unwrap();
+    }
+
+    let started = Instant::now();
+    let drained = sender.drain_pending();
+    let elapsed = started.elapsed();
+    assert!(elapsed >= SHUTDOWN_TIMEOUT, "{:?}", elapsed);
+    // The drop of the sender waits for the stuck worker another shutdown timeout
+    assert!(
+        elapsed < SHUTDOWN_TIMEOUT * 2 + TIMEOUT / 10,
+        "{:?}",
+        elapsed
+    );
+
+    drop(release);
+    let started = Instant::now();
+    while captured.len() < 5 && started.elapsed() < TIMEOUT {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let seqs = captured
+        .records()
+        .iter()
+        .map(|record| record.fields["seq"].as_u64().unwrap())
+        .collect();
+    (drained, seqs)
+}
+
+#[test]
+fn worker_with_a_full_queue_delivers_its_records_when_it_stops() {
+    let (drained, seqs) = drain_stuck_worker(2);
+    assert!(drained.is_empty());
+    assert_eq!(seqs, [0, 1, 2, 3, 4]);
+}
+
+#[test]
+fn worker_draining_too_late_delivers_its_records_instead() {
+    let (drained, seqs) = drain_stuck_worker(10);
+    assert!(drained.is_empty());
+    assert_eq!(seqs, [0, 1, 2, 3, 4]);
+}
@@ -0,0 +1,167 @@
+//! `log::Log::enabled` of `BufferedSender`, driven by the level filters and by the
+//! saturation of the worker queue.
+
+use log::{Level, LevelFilter, Log, Metadata};
+use qoollo_logstash_rs::testing::{record, CapturingSender};
+use qoollo_logstash_rs::{BufferedSender, LogStashRecord, Result, Sender};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const SATURATION_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// Sender holding every send until released
+#[derive(Clone)]
+struct GatedSender {
+    release: Arc<Mutex<mpsc::Receiver<()>>>,
+    captured: CapturingSender,
+}
+
+impl GatedSender {
+    fn new() -> (Self, mpsc::Sender<()>) {
+        let (release, released) = mpsc::channel();
+        let sender = Self {
+            release: Arc::new(Mutex::new(released)),
+            captured: CapturingSender::new(),
+        };
+        (sender, release)
+    }
+}
+
+impl Sender for GatedSender {
+    fn send(&self, event: LogStashRecord) -> Result<()> {
+        let _ = self.release.lock().unwrap().recv();
+        self.captured.send(event)
+    }
+
+    fn send_batch(&self, events: Vec<LogStashRecord>) -> Result<()> {
+        let _ = self.release.lock().unwrap().recv();
+        self.captured.send_batch(events)
+    }
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+fn enabled(sender: &BufferedSender, level: Level, target: &str) -> bool {
+    Log::enabled(
+        sender,
+        &Metadata::builder().level(level).target(target).build(),
+    )
+}
+
+/// Worker blocked in its first send with a queue of two records, so the next send finds
+/// the queue full
+fn saturated_sender() -> (BufferedSender, GatedSender, mpsc::Sender<()>) {
+    let (gated, release) = GatedSender::new();
+    let sender = BufferedSender::builder()
+        .with_buffer_size(None)
+        .with_log_queue_len(2)
+        .with_saturation_timeout(Some(SATURATION_TIMEOUT))
+        .with_diagnostics(false)
+        .build(gated.clone());
+    sender.send(record("enabled", 0)).unwrap();
+    // Let the worker take the first record before filling the queue
+    std::thread::sleep(Duration::from_millis(50));
+    sender.send(record("enabled", 1)).unwrap();
+    sender.send(record("enabled", 2)).unwrap();
+    (sender, gated, release)
+}
+
+/// Keeps sending to the full queue for longer than `duration`
+fn keep_full(sender: &BufferedSender, duration: Duration) {
+    let until = Instant::now() + duration;
+    while Instant::now() < until {
+        // Records not above `Warn` are dropped silently on a full queue
+        sender.send(record("enabled", 99)).unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+    }
+}
+
+fn wait_until(condition: impl Fn() -> bool) {
+    let deadline = Instant::now() + TIMEOUT;
+    while !condition() {
+        assert!(
+            Instant::now() < deadline,
+            "condition not met in {:?}",
+            TIMEOUT
+        );
+        std::thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+fn level_filters_disable_records() {
+    let sender = BufferedSender::builder()
+        .with_level_filter(LevelFilter::Info)
+        .with_target_level_filter("noisy", LevelFilter::Warn)
+        .with_target_level_filter("noisy::debugged", LevelFilter::Trace)
+        .with_diagnostics(false)
+        .build(CapturingSender::new());
+
+    assert!(enabled(&sender, Level::Info, "app"));
+    assert!(!enabled(&sender, Level::Debug, "app"));
+    assert!(enabled(&sender, Level::Warn, "noisy::module"));
+    assert!(!enabled(&sender, Level::Info, "noisy::module"));
+    // The longest matching prefix wins
+    assert!(enabled(&sender, Level::Trace, "noisy::debugged"));
+}
+
+#[test]
+fn off_filter_disables_every_level() {
+    let sender = BufferedSender::builder()
+        .with_target_level_filter("quiet", LevelFilter::Off)
+        .with_diagnostics(false)
+        .build(CapturingSender::new());
+
+    assert!(!enabled(&sender, Level::Error, "quiet"));
+    assert!(enabled(&sender, Level::Trace, "loud"));
+}
+
+#[test]
+fn saturation_disables_every_record_until_drained() {
+    let (sender, gated, release) = saturated_sender();
+    keep_full(&sender, SATURATION_TIMEOUT * 2);
+    assert!(!enabled(&sender, Level::Error, "enabled"));
+    assert!(!enabled(&sender, Level::Info, "other"));
+
+    // Still saturated while the worker works through the queue
+    release.send(()).unwrap();
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(!enabled(&sender, Level::Info, "enabled"));
+
+    for _ in 0..2 {
+        release.send(()).unwrap();
+    }
+    wait_until(|| enabled(&sender, Level::Info, "enabled"));
+    assert_eq!(gated.captured.len(), 3);
+}
+
+#[test]
+fn short_saturation_keeps_records_enabled() {
+    let (sender, gated, release) = saturated_sender();
+    // Full for less than the saturation timeout
+    sender.send(record("enabled", 3)).unwrap();
+    assert!(enabled(&sender, Level::Info, "enabled"));
+
+    for _ in 0..3 {
+        release.send(()).unwrap();
+    }
+    wait_until(|| gated.captured.len() == 3);
+    assert!(enabled(&sender, Level::Info, "enabled"));
+}
+
+#[test]
+fn without_saturation_timeout_a_full_queue_keeps_records_enabled() {
+    let (gated, release) = GatedSender::new();
+    let sender = BufferedSender::builder()
+        .with_buffer_size(None)
+        .with_log_queue_len(1)
+        .with_diagnostics(false)
+        .build(gated);
+    sender.send(record("enabled", 0)).unwrap();
+    keep_full(&sender, SATURATION_TIMEOUT * 2);
+    assert!(enabled(&sender, Level::Info, "enabled"));
+    drop(release);
+}
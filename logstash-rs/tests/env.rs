@@ -0,0 +1,121 @@
+//! Environment variables added to records by `snapshot_env` and `snapshot_env_keys`. Each
+//! test sets variables of its own prefix, so the tests don't see each other's variables.
+
+use log::Level;
+use qoollo_logstash_rs::event::MAX_ENV_FIELDS;
+use qoollo_logstash_rs::LogStashRecord;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+fn record() -> LogStashRecord {
+    LogStashRecord::builder(Level::Info).message("env").build()
+}
+
+/// `env.` fields of `record` by name
+fn env_fields(record: &LogStashRecord) -> BTreeMap<String, Value> {
+    record
+        .fields
+        .iter()
+        .filter(|(name, _)| name.starts_with("env."))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect()
+}
+
+#[test]
+fn prefix_selects_variables_as_lowercase_fields() {
+    std::env::set_var("SNAPSHOT_PREFIX_REGION", "eu-west-1");
+    std::env::set_var("SNAPSHOT_PREFIX_Pod_Name", "billing-7");
+    std::env::set_var("SNAPSHOT_PREFIXED_NOT", "other prefix");
+    std::env::set_var("OTHER_SNAPSHOT_PREFIX_X", "prefix not at the start");
+
+    let mut record = record();
+    record.snapshot_env("SNAPSHOT_PREFIX_");
+    let fields: Vec<_> = env_fields(&record).into_iter().collect();
+    assert_eq!(
+        fields,
+        [
+            (
+                "env.snapshot_prefix_pod_name".to_string(),
+                Value::from("billing-7")
+            ),
+            (
+                "env.snapshot_prefix_region".to_string(),
+                Value::from("eu-west-1")
+            ),
+        ]
+    );
+    assert_eq!(record.fields["message"], "env");
+}
+
+#[test]
+fn prefix_snapshot_is_capped_in_name_order() {
+    for index in 0..MAX_ENV_FIELDS + 10 {
+        std::env::set_var(format!("SNAPSHOT_CAP_{:03}", index), index.to_string());
+    }
+
+    let mut record = record();
+    record.snapshot_env("SNAPSHOT_CAP_");
+    let fields = env_fields(&record);
+    assert_eq!(fields.len(), MAX_ENV_FIELDS);
+    let expected: Vec<_> = (0..MAX_ENV_FIELDS)
+        .map(|index| format!("env.snapshot_cap_{:03}", index))
+        .collect();
+    assert_eq!(fields.keys().cloned().collect::<Vec<_>>(), expected);
+    assert_eq!(fields["env.snapshot_cap_049"], "49");
+}
+
+#[test]
+fn listed_keys_which_are_set_are_added() {
+    std::env::set_var("SNAPSHOT_KEYS_VERSION", "1.4.2");
+    std::env::set_var("SNAPSHOT_KEYS_UNLISTED", "left out");
+    std::env::remove_var("SNAPSHOT_KEYS_MISSING");
+
+    let mut record = record();
+    record.snapshot_env_keys(&["SNAPSHOT_KEYS_VERSION", "SNAPSHOT_KEYS_MISSING"]);
+    let fields: Vec<_> = env_fields(&record).into_iter().collect();
+    assert_eq!(
+        fields,
+        [(
+            "env.snapshot_keys_version".to_string(),
+            Value::from("1.4.2")
+        )]
+    );
+}
+
+#[test]
+fn listed_keys_are_capped() {
+    let keys: Vec<_> = (0..MAX_ENV_FIELDS + 5)
+        .map(|index| format!("SNAPSHOT_KEYS_CAP_{:03}", index))
+        .collect();
+    for key in &keys {
+        std::env::set_var(key, "set");
+    }
+    let keys: Vec<_> = keys.iter().map(String::as_str).collect();
+
+    let mut record = record();
+    record.snapshot_env_keys(&keys);
+    assert_eq!(env_fields(&record).len(), MAX_ENV_FIELDS);
+}
+
+#[cfg(unix)]
+#[test]
+fn non_utf8_values_are_added_lossily() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    std::env::set_var("SNAPSHOT_LOSSY_NAME", OsStr::from_bytes(b"caf\xe9 au lait"));
+
+    let mut by_prefix = record();
+    by_prefix.snapshot_env("SNAPSHOT_LOSSY_");
+    assert_eq!(
+        by_prefix.fields["env.snapshot_lossy_name"],
+        "caf\u{fffd} au lait"
+    );
+
+    let mut by_key = record();
+    by_key.snapshot_env_keys(&["SNAPSHOT_LOSSY_NAME"]);
+    assert_eq!(
+        by_key.fields["env.snapshot_lossy_name"],
+        "caf\u{fffd} au lait"
+    );
+}
@@ -0,0 +1,128 @@
+//! Batches wrapped by `TcpSender` in a JSON envelope, parsed from a `MockLogstash`.
+
+use log::Level;
+use qoollo_logstash_rs::testing::{record, records, MockLogstash};
+use qoollo_logstash_rs::{BatchFormat, BufferedSender, Sender, TcpSender};
+use serde_json::Value;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn enveloping(server: &MockLogstash, include_batch_id: bool, include_count: bool) -> TcpSender {
+    TcpSender::builder()
+        .hostname("127.0.0.1")
+        .port(server.port())
+        .build()
+        .unwrap()
+        .with_batch_format(BatchFormat::JsonEnvelope {
+            include_batch_id,
+            include_count,
+        })
+}
+
+/// Envelopes received once `count` of them arrived
+fn envelopes(server: &MockLogstash, count: usize) -> Vec<Value> {
+    server
+        .wait_for_events(count, TIMEOUT)
+        .iter()
+        .map(|line| line.json().unwrap())
+        .collect()
+}
+
+fn seqs(envelope: &Value) -> Vec<u64> {
+    envelope["events"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|event| event["seq"].as_u64().unwrap())
+        .collect()
+}
+
+fn is_ulid(id: &str) -> bool {
+    id.len() == 26
+        && id
+            .chars()
+            .all(|c| "0123456789ABCDEFGHJKMNPQRSTVWXYZ".contains(c))
+}
+
+#[test]
+fn batch_is_wrapped_with_its_id_and_count() {
+    let server = MockLogstash::start().unwrap();
+    let tcp = enveloping(&server, true, true);
+
+    tcp.send_batch(records("envelope", 0..3)).unwrap();
+    let envelope = &envelopes(&server, 1)[0];
+    let batch_id = envelope["batch_id"].as_str().unwrap();
+    assert!(is_ulid(batch_id), "{}", batch_id);
+    assert_eq!(envelope["count"], 3);
+    assert_eq!(seqs(envelope), [0, 1, 2]);
+    assert_eq!(envelope["events"][0]["target"], "envelope");
+    assert_eq!(tcp.last_batch_id().unwrap().to_string(), batch_id);
+}
+
+#[test]
+fn single_record_is_a_one_element_envelope() {
+    let server = MockLogstash::start().unwrap();
+    let tcp = enveloping(&server, false, true);
+
+    tcp.send(record("envelope", 0)).unwrap();
+    let envelope = &envelopes(&server, 1)[0];
+    assert_eq!(envelope["count"], 1);
+    assert_eq!(seqs(envelope), [0]);
+    assert!(envelope.get("batch_id").is_none());
+    assert!(tcp.last_batch_id().is_none());
+}
+
+#[test]
+fn envelope_without_metadata_holds_the_events_only() {
+    let server = MockLogstash::start().unwrap();
+    let tcp = enveloping(&server, false, false);
+
+    tcp.send_batch_ref(&records("envelope", 0..2)).unwrap();
+    let envelope = &envelopes(&server, 1)[0];
+    let keys: Vec<_> = envelope.as_object().unwrap().keys().collect();
+    assert_eq!(keys, ["events"]);
+    assert_eq!(seqs(envelope), [0, 1]);
+}
+
+#[test]
+fn every_batch_gets_a_new_id_sorting_by_time() {
+    let server = MockLogstash::start().unwrap();
+    let tcp = enveloping(&server, true, false);
+
+    for batch in 0..3 {
+        tcp.send_batch(records("envelope", batch * 2..batch * 2 + 2))
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(2));
+    }
+    let ids: Vec<_> = envelopes(&server, 3)
+        .iter()
+        .map(|envelope| envelope["batch_id"].as_str().unwrap().to_owned())
+        .collect();
+    let mut sorted = ids.clone();
+    sorted.sort();
+    sorted.dedup();
+    assert_eq!(ids, sorted);
+}
+
+#[test]
+fn buffered_sender_exposes_the_last_batch_id_in_its_stats() {
+    let server = MockLogstash::start().unwrap();
+    let sender = BufferedSender::builder()
+        .with_buffer_size(Some(10))
+        .with_ignore_buffer_level(Level::Trace)
+        .with_diagnostics(false)
+        .build(enveloping(&server, true, true));
+
+    records("envelope", 0..4)
+        .into_iter()
+        .try_for_each(|record| sender.send(record))
+        .unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+    let envelope = &envelopes(&server, 1)[0];
+    assert_eq!(envelope["count"], 4);
+    assert_eq!(
+        sender.stats().last_batch_id.unwrap().to_string(),
+        envelope["batch_id"]
+    );
+}
@@ -0,0 +1,88 @@
+//! Non-ASCII characters of chosen fields escaped as `\uXXXX` on the wire.
+
+use log::Level;
+use qoollo_logstash_rs::{EscapingTransformer, LogStashRecord, Sender, TcpSender};
+use serde_json::{json, Value};
+use std::io::Read;
+use std::net::TcpListener;
+
+/// Lines received from a `TcpSender` sending `records` as a batch
+fn wire(records: Vec<LogStashRecord>) -> Vec<String> {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let reader = std::thread::spawn(move || {
+        let mut bytes = String::new();
+        listener
+            .accept()
+            .unwrap()
+            .0
+            .read_to_string(&mut bytes)
+            .unwrap();
+        bytes
+    });
+
+    let tcp = TcpSender::builder()
+        .hostname("127.0.0.1")
+        .port(port)
+        .build()
+        .unwrap();
+    tcp.send_batch(records).unwrap();
+    drop(tcp);
+    reader.join().unwrap().lines().map(str::to_owned).collect()
+}
+
+fn record() -> LogStashRecord {
+    let mut record = LogStashRecord::builder(Level::Info)
+        .target("escape")
+        .message("café crème 😀")
+        .field("city", "Zürich")
+        .build();
+    record.add_data(
+        "order",
+        json!({ "client": "Ærø", "items": ["naïve", 1], "ascii": "plain" }),
+    );
+    record
+}
+
+#[test]
+fn listed_fields_are_escaped_others_are_not() {
+    // Same timestamp for both records
+    let plain = record();
+    let mut escaped = plain.clone();
+    escaped.escape_non_ascii(&["message", "order"]);
+    let lines = wire(vec![escaped, plain]);
+
+    let line = &lines[0];
+    assert!(!line.is_ascii());
+    assert!(line.contains(r#""message":"caf\u00e9 cr\u00e8me \ud83d\ude00""#));
+    // Strings nested in an escaped field are escaped, its keys and ASCII strings unchanged
+    assert!(line.contains(r#""client":"\u00c6r\u00f8""#));
+    assert!(line.contains(r#""items":["na\u00efve",1]"#));
+    assert!(line.contains(r#""ascii":"plain""#));
+    // Fields not listed are written as UTF-8
+    assert!(line.contains(r#""city":"Zürich""#));
+
+    // Escaping changes the bytes only, both records parse to the same values
+    let parsed: Vec<Value> = lines
+        .iter()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(parsed[0], parsed[1]);
+    assert_eq!(parsed[0]["message"], "café crème 😀");
+    assert!(!lines[1].contains("\\u"));
+}
+
+#[test]
+fn transformer_escapes_every_field_it_lists() {
+    let transformer = EscapingTransformer::new(vec!["message".into(), "city".into()]);
+    let mut escaped = record();
+    transformer.apply(&mut escaped);
+    // Applying it again changes nothing
+    transformer.apply(&mut escaped);
+    let lines = wire(vec![escaped]);
+
+    let line = &lines[0];
+    assert!(line.contains(r#""message":"caf\u00e9 cr\u00e8me \ud83d\ude00""#));
+    assert!(line.contains(r#""city":"Z\u00fcrich""#));
+    assert!(line.contains(r#""client":"Ærø""#));
+}
@@ -0,0 +1,157 @@
+//! `ParallelFanOutSender` calling its senders concurrently, compared with calling the same
+//! senders one after the other.
+
+use qoollo_logstash_rs::testing::{record, CapturingSender};
+use qoollo_logstash_rs::{Error, LogStashRecord, ParallelFanOutSender, Result, Sender};
+use std::sync::{Arc, Mutex, Once};
+use std::time::{Duration, Instant};
+
+const SENDERS: usize = 4;
+const DELAY: Duration = Duration::from_millis(1);
+const RECORDS: usize = 20;
+
+/// Sender adding an artificial delay to every call, optionally failing
+#[derive(Clone)]
+struct SlowSender {
+    captured: CapturingSender,
+    /// Timeouts given to `flush_and_wait`
+    timeouts: Arc<Mutex<Vec<Duration>>>,
+    fail: bool,
+}
+
+impl SlowSender {
+    fn new(fail: bool) -> Self {
+        Self {
+            captured: CapturingSender::new(),
+            timeouts: Default::default(),
+            fail,
+        }
+    }
+
+    fn call(&self, f: impl FnOnce() -> Result<()>) -> Result<()> {
+        std::thread::sleep(DELAY);
+        if self.fail {
+            return Err(Error::Connection("unreachable".into()));
+        }
+        f()
+    }
+}
+
+impl Sender for SlowSender {
+    fn send(&self, event: LogStashRecord) -> Result<()> {
+        self.call(|| self.captured.send(event))
+    }
+
+    fn send_batch(&self, events: Vec<LogStashRecord>) -> Result<()> {
+        self.call(|| self.captured.send_batch(events))
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.call(|| Ok(()))
+    }
+
+    fn flush_and_wait(&self, timeout: Duration) -> Result<()> {
+        self.timeouts.lock().unwrap().push(timeout);
+        self.call(|| Ok(()))
+    }
+}
+
+/// The global rayon pool sizes itself by the CPUs, which may be fewer than the senders
+fn init_thread_pool() {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        let _ = rayon::ThreadPoolBuilder::new()
+            .num_threads(SENDERS)
+            .build_global();
+    });
+}
+
+fn boxed(senders: &[SlowSender]) -> Vec<Box<dyn Sender>> {
+    senders
+        .iter()
+        .map(|sender| Box::new(sender.clone()) as Box<dyn Sender>)
+        .collect()
+}
+
+fn time(f: impl FnOnce()) -> Duration {
+    let started = Instant::now();
+    f();
+    started.elapsed()
+}
+
+#[test]
+fn parallel_fan_out_beats_sequential_sends() {
+    init_thread_pool();
+    let senders: Vec<_> = (0..SENDERS).map(|_| SlowSender::new(false)).collect();
+    let parallel = ParallelFanOutSender::new(boxed(&senders));
+
+    let sequential_time = time(|| {
+        for seq in 0..RECORDS as u64 {
+            for sender in &senders {
+                sender.send(record("fanout", seq)).unwrap();
+            }
+        }
+    });
+    let parallel_time = time(|| {
+        for seq in 0..RECORDS as u64 {
+            parallel.send(record("fanout", seq)).unwrap();
+        }
+    });
+    println!(
+        "{} records to {} senders with {:?} delay: sequential {:?}, parallel {:?}",
+        RECORDS, SENDERS, DELAY, sequential_time, parallel_time
+    );
+
+    assert!(sequential_time >= DELAY * (RECORDS * SENDERS) as u32);
+    assert!(
+        parallel_time * 2 < sequential_time,
+        "parallel {:?} not faster than sequential {:?}",
+        parallel_time,
+        sequential_time
+    );
+    for sender in &senders {
+        assert_eq!(sender.captured.len(), RECORDS * 2);
+    }
+}
+
+#[test]
+fn errors_of_every_sender_are_collected() {
+    init_thread_pool();
+    let senders = vec![
+        SlowSender::new(true),
+        SlowSender::new(false),
+        SlowSender::new(true),
+    ];
+    let parallel = ParallelFanOutSender::new(boxed(&senders));
+
+    match parallel.send_batch(vec![record("fanout", 0), record("fanout", 1)]) {
+        Err(Error::Multiple(errors)) => {
+            assert_eq!(errors.len(), 2);
+            assert!(errors.iter().all(|err| err.kind() == "connection"));
+        }
+        other => panic!("expected two errors, got {:?}", other),
+    }
+    // The healthy sender is not held back by the failing ones
+    assert_eq!(senders[1].captured.len(), 2);
+
+    let parallel = ParallelFanOutSender::new(boxed(&senders[..2]));
+    assert_eq!(parallel.flush().unwrap_err().kind(), "connection");
+}
+
+#[test]
+fn flush_and_wait_reaches_every_sender_within_the_timeout() {
+    init_thread_pool();
+    let senders = vec![SlowSender::new(false), SlowSender::new(true)];
+    let parallel = ParallelFanOutSender::new(boxed(&senders));
+    let timeout = Duration::from_secs(1);
+
+    assert_eq!(
+        parallel.flush_and_wait(timeout).unwrap_err().kind(),
+        "connection"
+    );
+    for sender in &senders {
+        let timeouts = sender.timeouts.lock().unwrap();
+        assert_eq!(timeouts.len(), 1);
+        assert!(timeouts[0] <= timeout, "{:?}", timeouts[0]);
+    }
+}
@@ -0,0 +1,182 @@
+//! Flushes of the wrapped sender by `BufferedSender` workers: flush commands queued
+//! back-to-back collapse into a single flush, a flush with nothing buffered still reaches
+//! the wrapped sender, and a record of the flush level pushes out the records before it.
+
+use log::Level;
+use qoollo_logstash_rs::testing::{leveled_record, record, CapturingSender};
+use qoollo_logstash_rs::{BufferedSender, LogStashRecord, Result, Sender};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Sender holding every send until released, counting the flushes
+#[derive(Clone)]
+struct GatedSender {
+    release: Arc<Mutex<mpsc::Receiver<()>>>,
+    flushes: Arc<AtomicUsize>,
+    captured: CapturingSender,
+}
+
+impl GatedSender {
+    fn new() -> (Self, mpsc::Sender<()>) {
+        let (release, released) = mpsc::channel();
+        let sender = Self {
+            release: Arc::new(Mutex::new(released)),
+            flushes: Default::default(),
+            captured: CapturingSender::new(),
+        };
+        (sender, release)
+    }
+
+    fn flushes(&self) -> usize {
+        self.flushes.load(Ordering::Relaxed)
+    }
+}
+
+impl Sender for GatedSender {
+    fn send(&self, event: LogStashRecord) -> Result<()> {
+        let _ = self.release.lock().unwrap().recv();
+        self.captured.send(event)
+    }
+
+    fn send_batch(&self, events: Vec<LogStashRecord>) -> Result<()> {
+        let _ = self.release.lock().unwrap().recv();
+        self.captured.send_batch(events)
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.flushes.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+#[test]
+fn queued_flushes_are_coalesced() {
+    let (gated, release) = GatedSender::new();
+    let sender = BufferedSender::builder()
+        .with_buffer_size(None)
+        .with_diagnostics(false)
+        .build(gated.clone());
+
+    // The worker blocks in this send while the flushes queue up behind it
+    sender.send(record("flush", 0)).unwrap();
+    for _ in 0..5 {
+        Sender::flush(&sender).unwrap();
+    }
+    release.send(()).unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+    assert_eq!(gated.flushes(), 2);
+    assert_eq!(gated.captured.len(), 1);
+
+    // A record between flushes is sent and flushed on its own
+    release.send(()).unwrap();
+    sender.send(record("flush", 1)).unwrap();
+    Sender::flush(&sender).unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+    assert_eq!(gated.flushes(), 4);
+    assert_eq!(gated.captured.len(), 2);
+}
+
+#[test]
+fn empty_flush_reaches_the_wrapped_sender_once() {
+    let (gated, release) = GatedSender::new();
+    let sender = BufferedSender::builder()
+        .with_buffer_size(Some(10))
+        .with_buffer_lifetime(Some(Duration::from_millis(50)))
+        .with_ignore_buffer_level(Level::Trace)
+        .with_diagnostics(false)
+        .build(gated.clone());
+
+    sender.flush_and_wait(TIMEOUT).unwrap();
+    assert_eq!(gated.flushes(), 1);
+    assert!(gated.captured.records().is_empty());
+
+    // The buffer lifetime flushes the record once, an empty buffer arms no further timeouts
+    release.send(()).unwrap();
+    sender.send(record("flush", 0)).unwrap();
+    let started = Instant::now();
+    while gated.flushes() < 2 {
+        assert!(started.elapsed() < TIMEOUT, "buffer lifetime did not flush");
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    std::thread::sleep(Duration::from_millis(200));
+    assert_eq!(gated.flushes(), 2);
+    assert_eq!(gated.captured.len(), 1);
+}
+
+/// Sequence numbers and levels captured once `count` records arrived, without flushing
+fn wait_for(captured: &CapturingSender, count: usize) -> Vec<(u64, Level)> {
+    let started = Instant::now();
+    while captured.len() < count {
+        assert!(started.elapsed() < TIMEOUT, "{} records", captured.len());
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    captured
+        .take()
+        .iter()
+        .map(|record| (record.fields["seq"].as_u64().unwrap(), record.level))
+        .collect()
+}
+
+fn flushing_on_error(captured: &CapturingSender, ignore_buffer: Level) -> BufferedSender {
+    BufferedSender::builder()
+        .with_buffer_size(Some(100))
+        .with_buffer_lifetime(Some(Duration::from_secs(3600)))
+        .with_ignore_buffer_level(ignore_buffer)
+        .with_flush_on_level(Some(Level::Error))
+        .with_diagnostics(false)
+        .build(captured.clone())
+}
+
+#[test]
+fn buffered_error_flushes_the_records_before_it() {
+    let captured = CapturingSender::new();
+    let sender = flushing_on_error(&captured, Level::Trace);
+
+    sender
+        .send(leveled_record(Level::Info, "flush", 0))
+        .unwrap();
+    sender
+        .send(leveled_record(Level::Warn, "flush", 1))
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(captured.is_empty());
+
+    sender
+        .send(leveled_record(Level::Error, "flush", 2))
+        .unwrap();
+    assert_eq!(
+        wait_for(&captured, 3),
+        [(0, Level::Info), (1, Level::Warn), (2, Level::Error)]
+    );
+
+    // Later records are buffered again until the next error
+    sender
+        .send(leveled_record(Level::Info, "flush", 3))
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(captured.is_empty());
+}
+
+#[test]
+fn unbuffered_error_is_sent_after_the_flushed_context() {
+    let captured = CapturingSender::new();
+    // Errors bypass the buffer, the buffered records are flushed ahead of them
+    let sender = flushing_on_error(&captured, Level::Error);
+
+    sender
+        .send(leveled_record(Level::Info, "flush", 0))
+        .unwrap();
+    sender
+        .send(leveled_record(Level::Debug, "flush", 1))
+        .unwrap();
+    sender
+        .send(leveled_record(Level::Error, "flush", 2))
+        .unwrap();
+    assert_eq!(
+        wait_for(&captured, 3),
+        [(0, Level::Info), (1, Level::Debug), (2, Level::Error)]
+    );
+}
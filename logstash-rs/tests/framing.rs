@@ -0,0 +1,96 @@
+//! Bytes written by `TcpSender` for every placement of the newlines between records.
+
+use chrono::{TimeZone, Utc};
+use log::Level;
+use qoollo_logstash_rs::{DelimiterPlacement, Framing, LogStashRecord, Sender, TcpSender};
+use std::io::Read;
+use std::net::TcpListener;
+
+fn record(seq: u64) -> LogStashRecord {
+    LogStashRecord::builder(Level::Info)
+        .target("framing")
+        .timestamp(Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap())
+        .field("seq", seq)
+        .build()
+}
+
+/// Bytes received from a sender with `framing` sending two batches and a single record
+fn wire(framing: Framing) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let reader = std::thread::spawn(move || {
+        let mut bytes = String::new();
+        listener
+            .accept()
+            .unwrap()
+            .0
+            .read_to_string(&mut bytes)
+            .unwrap();
+        bytes
+    });
+
+    let tcp = TcpSender::builder()
+        .hostname("127.0.0.1")
+        .port(port)
+        .framing(framing)
+        .build()
+        .unwrap();
+    tcp.send_batch(vec![record(0), record(1)]).unwrap();
+    tcp.send_batch_ref(&[record(2), record(3)]).unwrap();
+    tcp.send(record(4)).unwrap();
+    tcp.flush().unwrap();
+    drop(tcp);
+    reader.join().unwrap()
+}
+
+/// JSON of the records, `a` to `e` in the expected output
+fn json() -> [String; 5] {
+    std::array::from_fn(|seq| serde_json::to_string(&record(seq as u64)).unwrap())
+}
+
+fn framing(placement: DelimiterPlacement, trailing: bool) -> Framing {
+    Framing {
+        placement,
+        trailing,
+    }
+}
+
+#[test]
+fn suffix_with_trailing_newline_ends_every_record() {
+    let [a, b, c, d, e] = json();
+    assert_eq!(
+        Framing::default(),
+        framing(DelimiterPlacement::Suffix, true)
+    );
+    assert_eq!(
+        wire(Framing::default()),
+        format!("{}\n{}\n{}\n{}\n{}\n", a, b, c, d, e)
+    );
+}
+
+#[test]
+fn suffix_without_trailing_newline_separates_records_within_a_batch() {
+    let [a, b, c, d, e] = json();
+    assert_eq!(
+        wire(framing(DelimiterPlacement::Suffix, false)),
+        format!("{}\n{}{}\n{}{}", a, b, c, d, e)
+    );
+}
+
+#[test]
+fn prefix_with_trailing_newline_wraps_every_batch() {
+    let [a, b, c, d, e] = json();
+    assert_eq!(
+        wire(framing(DelimiterPlacement::Prefix, true)),
+        format!("\n{}\n{}\n\n{}\n{}\n\n{}\n", a, b, c, d, e)
+    );
+}
+
+#[test]
+fn prefix_without_trailing_newline_starts_every_record() {
+    let [a, b, c, d, e] = json();
+    assert_eq!(
+        wire(framing(DelimiterPlacement::Prefix, false)),
+        format!("\n{}\n{}\n{}\n{}\n{}", a, b, c, d, e)
+    );
+}
@@ -0,0 +1,118 @@
+//! Heartbeat records sent by `BufferedSender` workers at a fixed interval, next to records
+//! waiting for the buffer lifetime.
+
+use log::Level;
+use qoollo_logstash_rs::{BufferedSender, LogStashRecord, Result, Sender, HEARTBEAT_TARGET};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const INTERVAL: Duration = Duration::from_millis(100);
+const LIFETIME: Duration = Duration::from_millis(550);
+/// Allowed lateness of the worker waking up on a busy machine
+const SLACK: Duration = Duration::from_millis(80);
+
+/// Sender capturing records with the time each arrived
+#[derive(Clone, Default)]
+struct TimedSender {
+    received: Arc<Mutex<Vec<(Instant, LogStashRecord)>>>,
+}
+
+impl TimedSender {
+    fn received(&self) -> Vec<(Instant, LogStashRecord)> {
+        self.received.lock().unwrap().clone()
+    }
+}
+
+impl Sender for TimedSender {
+    fn send(&self, event: LogStashRecord) -> Result<()> {
+        self.received.lock().unwrap().push((Instant::now(), event));
+        Ok(())
+    }
+
+    fn send_batch(&self, events: Vec<LogStashRecord>) -> Result<()> {
+        let now = Instant::now();
+        let mut received = self.received.lock().unwrap();
+        received.extend(events.into_iter().map(|event| (now, event)));
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+fn is_heartbeat(record: &LogStashRecord) -> bool {
+    record.target == HEARTBEAT_TARGET
+}
+
+#[test]
+fn heartbeats_keep_their_cadence_and_leave_the_buffer_lifetime_alone() {
+    let timed = TimedSender::default();
+    let sender = BufferedSender::builder()
+        .with_buffer_size(Some(100))
+        .with_buffer_lifetime(Some(LIFETIME))
+        .with_ignore_buffer_level(Level::Trace)
+        .with_heartbeat_interval(Some(INTERVAL))
+        .with_diagnostics(false)
+        .build(timed.clone());
+
+    let started = Instant::now();
+    for seq in 0..3 {
+        sender
+            .send(
+                LogStashRecord::builder(Level::Info)
+                    .target("app")
+                    .field("seq", seq)
+                    .build(),
+            )
+            .unwrap();
+    }
+    thread::sleep(LIFETIME + INTERVAL * 3);
+    drop(sender);
+
+    let received = timed.received();
+    let (heartbeats, records): (Vec<_>, Vec<_>) = received
+        .iter()
+        .partition(|(_, record)| is_heartbeat(record));
+
+    // The records wait for the lifetime started by the first of them, the heartbeats on the
+    // way neither flush them early nor push the deadline back
+    assert_eq!(records.len(), 3);
+    let flushed = records[0].0.duration_since(started);
+    assert!(
+        flushed >= LIFETIME && flushed < LIFETIME + SLACK,
+        "records flushed after {:?}",
+        flushed
+    );
+
+    // One heartbeat per interval since the worker started
+    let beats = heartbeats.len() as u32;
+    assert!((7..=9).contains(&beats), "{} heartbeats", beats);
+    for pair in heartbeats.windows(2) {
+        let gap = pair[1].0.duration_since(pair[0].0);
+        assert!(
+            gap >= INTERVAL - SLACK / 2 && gap < INTERVAL + SLACK,
+            "heartbeats {:?} apart",
+            gap
+        );
+    }
+
+    // Counters of the heartbeats before and after the flush
+    let mut uptime = 0.0;
+    for (at, heartbeat) in &heartbeats {
+        let fields = &heartbeat.fields;
+        let before_flush = *at < records[0].0;
+        assert_eq!(fields["buffer_fill"], if before_flush { 3 } else { 0 });
+        let next_uptime = fields["uptime_seconds"].as_f64().unwrap();
+        assert!(next_uptime > uptime);
+        uptime = next_uptime;
+    }
+    let sent: Vec<_> = heartbeats
+        .iter()
+        .map(|(_, heartbeat)| heartbeat.fields["events_sent"].as_u64().unwrap())
+        .collect();
+    // Records sent since the previous heartbeat, not counting the heartbeats themselves
+    assert_eq!(sent.iter().sum::<u64>(), 3);
+    assert_eq!(sent.iter().filter(|&&count| count > 0).count(), 1);
+}
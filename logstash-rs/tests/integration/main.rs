@@ -0,0 +1,82 @@
+//! End-to-end test sending records through `BufferedSender` and `TcpSender` to a real
+//! Logstash with a `json_lines` TCP input, reading them back from its stdout.
+//!
+//! Requires Docker, run with `cargo test -p qoollo-logstash-rs --features integration-tests`.
+
+use qoollo_logstash_rs::{BufferedSender, LogStashRecord, Sender, TcpSender};
+use serde_json::Value;
+use std::process::Command;
+use std::time::{Duration, Instant};
+use testcontainers::{clients::Cli, core::WaitFor, GenericImage, RunnableImage};
+
+const LOGSTASH_IMAGE: &str = "docker.elastic.co/logstash/logstash";
+const LOGSTASH_TAG: &str = "8.11.1";
+const TCP_PORT: u16 = 5000;
+const PIPELINE: &str = "input { tcp { port => 5000 codec => json_lines } } \
+                        output { stdout { codec => json_lines } }";
+const RECORDS: usize = 100;
+
+#[test]
+fn records_reach_logstash() {
+    let docker = Cli::default();
+    let image = GenericImage::new(LOGSTASH_IMAGE, LOGSTASH_TAG)
+        .with_env_var("XPACK_MONITORING_ENABLED", "false")
+        .with_exposed_port(TCP_PORT)
+        .with_wait_for(WaitFor::message_on_stdout("Pipelines running"));
+    let args = vec!["-e".to_string(), PIPELINE.to_string()];
+    let container = docker.run(RunnableImage::from((image, args)));
+    let port = container.get_host_port_ipv4(TCP_PORT);
+
+    let run_id = format!("{}", std::process::id());
+    let tcp = TcpSender::builder()
+        .hostname("127.0.0.1")
+        .port(port)
+        .connect_timeout(Duration::from_secs(10))
+        .build()
+        .unwrap();
+    let sender = BufferedSender::builder().build(tcp);
+    for i in 0..RECORDS {
+        let record = LogStashRecord::builder(log::Level::Info)
+            .target("integration")
+            .message(format!("record {}", i))
+            .field("run_id", run_id.as_str())
+            .field("seq", i)
+            .build();
+        sender.send(record).unwrap();
+    }
+    sender.flush_and_wait(Duration::from_secs(10)).unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(60);
+    let events = loop {
+        let events = received_events(container.id(), &run_id);
+        if events.len() >= RECORDS || Instant::now() > deadline {
+            break events;
+        }
+        std::thread::sleep(Duration::from_millis(500));
+    };
+
+    assert_eq!(events.len(), RECORDS);
+    let mut seqs: Vec<u64> = events.iter().map(|e| e["seq"].as_u64().unwrap()).collect();
+    seqs.sort_unstable();
+    assert_eq!(seqs, (0..RECORDS as u64).collect::<Vec<_>>());
+    for event in &events {
+        assert_eq!(event["level"], "INFO");
+        assert_eq!(event["target"], "integration");
+        let seq = event["seq"].as_u64().unwrap();
+        assert_eq!(event["message"], format!("record {}", seq));
+        assert!(event["@timestamp"].is_string());
+    }
+}
+
+/// Events of this run printed by the stdout output of the container
+fn received_events(container_id: &str, run_id: &str) -> Vec<Value> {
+    let output = Command::new("docker")
+        .args(["logs", container_id])
+        .output()
+        .expect("docker logs");
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .filter(|event| event["run_id"] == run_id)
+        .collect()
+}
@@ -0,0 +1,180 @@
+//! Latency histogram of `BufferedSender`, slow-send diagnostics and adaptive batching,
+//! against a sender sleeping in every send.
+
+use log::Level;
+use qoollo_logstash_rs::testing::{record, CapturingSender};
+use qoollo_logstash_rs::{
+    AdaptiveBatching, BufferedSender, BufferedSenderBuilder, LatencyHistogram, LogStashRecord,
+    Result, Sender,
+};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const DIAGNOSTICS_TARGET: &str = "logstash_rs::internal";
+
+/// Sender capturing records after sleeping for the current delay
+#[derive(Clone, Default)]
+struct SlowSender {
+    delay_ms: Arc<AtomicU64>,
+    captured: CapturingSender,
+    /// Sizes of the received batches
+    batches: Arc<Mutex<Vec<usize>>>,
+}
+
+impl SlowSender {
+    fn set_delay(&self, delay: Duration) {
+        self.delay_ms
+            .store(delay.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    fn sleep(&self) {
+        thread::sleep(Duration::from_millis(self.delay_ms.load(Ordering::Relaxed)));
+    }
+}
+
+impl Sender for SlowSender {
+    fn send(&self, event: LogStashRecord) -> Result<()> {
+        self.sleep();
+        self.captured.send(event)
+    }
+
+    fn send_batch(&self, events: Vec<LogStashRecord>) -> Result<()> {
+        self.sleep();
+        self.batches.lock().unwrap().push(events.len());
+        self.captured.send_batch(events)
+    }
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Every record is sent on its own, one call to the sender per record
+fn unbuffered() -> BufferedSenderBuilder {
+    BufferedSender::builder().with_buffer_size(None)
+}
+
+#[test]
+fn histogram_counts_calls_by_latency() {
+    let slow = SlowSender::default();
+    let sender = unbuffered().with_diagnostics(false).build(slow.clone());
+    sender.send(record("latency", 0)).unwrap();
+    sender.send(record("latency", 1)).unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+    let latency = sender.stats().latency;
+    assert_eq!(latency.total(), 3);
+    assert_eq!(latency.buckets[3..], [0, 0]);
+
+    sender.reset_latency_histogram();
+    assert_eq!(sender.stats().latency, LatencyHistogram::default());
+
+    slow.set_delay(Duration::from_millis(20));
+    sender.send(record("latency", 2)).unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+    slow.set_delay(Duration::from_millis(150));
+    sender.send(record("latency", 3)).unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+
+    let latency = sender.stats().latency;
+    assert_eq!(latency.total(), 4);
+    // The flushes return right away
+    assert_eq!(latency.buckets[0], 2);
+    assert_eq!(latency.buckets[2], 1);
+    assert_eq!(latency.buckets[3], 1);
+    assert_eq!(LatencyHistogram::bucket(Duration::from_secs(2)), 4);
+}
+
+#[test]
+fn slow_send_emits_diagnostic_warning() {
+    let slow = SlowSender::default();
+    let sender = unbuffered()
+        .with_slow_send_threshold(Some(Duration::from_millis(50)))
+        .build(slow.clone());
+    sender.send(record("latency", 0)).unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+    assert!(slow
+        .captured
+        .find(|r| r.target == DIAGNOSTICS_TARGET)
+        .is_none());
+
+    slow.set_delay(Duration::from_millis(100));
+    sender.send(record("latency", 1)).unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+
+    let warning = slow
+        .captured
+        .find(|r| r.target == DIAGNOSTICS_TARGET)
+        .expect("no slow send diagnostic");
+    assert_eq!(warning.level, Level::Warn);
+    assert!(warning.fields["latency_ms"].as_f64().unwrap() >= 100.0);
+    assert_eq!(warning.fields["threshold_ms"], 50);
+    assert!(warning.fields["message"]
+        .as_str()
+        .unwrap()
+        .contains("slow send threshold"));
+}
+
+fn adaptive(buffer_size: usize) -> BufferedSenderBuilder {
+    BufferedSender::builder()
+        .with_buffer_size(Some(buffer_size))
+        .with_buffer_lifetime(None)
+        .with_ignore_buffer_level(Level::Trace)
+        .with_diagnostics(false)
+        .with_adaptive_batching(Some(AdaptiveBatching {
+            min_size: 2,
+            max_size: 64,
+            target_latency: Duration::from_millis(10),
+        }))
+}
+
+#[test]
+fn batch_size_grows_with_slow_sends() {
+    let slow = SlowSender::default();
+    slow.set_delay(Duration::from_millis(30));
+    let sender = adaptive(4).build(slow.clone());
+    assert_eq!(sender.stats().batch_size, 4);
+    sender
+        .send_records((0..200).map(|seq| record("latency", seq)).collect())
+        .unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+
+    let batches = slow.batches.lock().unwrap().clone();
+    assert_eq!(batches[..5], [4, 8, 16, 32, 64]);
+    assert_eq!(batches.iter().sum::<usize>(), 200);
+    assert_eq!(sender.stats().batch_size, 64);
+}
+
+#[test]
+fn batch_size_shrinks_with_fast_sends() {
+    let fast = SlowSender::default();
+    // Out of the bounds, starts from the maximum
+    let sender = adaptive(100).build(fast.clone());
+    assert_eq!(sender.stats().batch_size, 64);
+    sender
+        .send_records((0..200).map(|seq| record("latency", seq)).collect())
+        .unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+
+    let batches = fast.batches.lock().unwrap().clone();
+    assert_eq!(batches[..6], [64, 32, 16, 8, 4, 2]);
+    assert!(batches[6..].iter().all(|&size| size == 2));
+    assert_eq!(fast.captured.len(), 200);
+    assert_eq!(sender.stats().batch_size, 2);
+}
+
+#[test]
+fn batch_size_follows_latency_changes() {
+    let adaptive = AdaptiveBatching {
+        min_size: 10,
+        max_size: 1000,
+        target_latency: Duration::from_millis(100),
+    };
+    assert_eq!(adaptive.adjust(100, Duration::from_millis(150)), 200);
+    assert_eq!(adaptive.adjust(800, Duration::from_millis(150)), 1000);
+    assert_eq!(adaptive.adjust(100, Duration::from_millis(75)), 100);
+    assert_eq!(adaptive.adjust(100, Duration::from_millis(10)), 50);
+    assert_eq!(adaptive.adjust(15, Duration::from_millis(10)), 10);
+}
@@ -0,0 +1,142 @@
+//! Buffer lifetimes and sizes overridden per level in the buffer shared by all levels.
+
+use log::Level;
+use qoollo_logstash_rs::{BufferPolicy, BufferedSender, LogStashRecord, Result, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const ERROR_LIFETIME: Duration = Duration::from_millis(100);
+const LIFETIME: Duration = Duration::from_millis(600);
+/// Allowed lateness of the worker waking up on a busy machine
+const SLACK: Duration = Duration::from_millis(80);
+
+/// Sequence numbers of a batch with the time it arrived
+type Batch = (Instant, Vec<u64>);
+
+/// Sender capturing every batch
+#[derive(Clone, Default)]
+struct TimedSender {
+    batches: Arc<Mutex<Vec<Batch>>>,
+}
+
+impl TimedSender {
+    fn batches(&self) -> Vec<Batch> {
+        self.batches.lock().unwrap().clone()
+    }
+}
+
+impl Sender for TimedSender {
+    fn send(&self, event: LogStashRecord) -> Result<()> {
+        self.send_batch(vec![event])
+    }
+
+    fn send_batch(&self, events: Vec<LogStashRecord>) -> Result<()> {
+        let seqs = events
+            .iter()
+            .map(|event| event.fields["seq"].as_u64().unwrap())
+            .collect();
+        self.batches.lock().unwrap().push((Instant::now(), seqs));
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+fn buffered(timed: &TimedSender) -> BufferedSender {
+    BufferedSender::builder()
+        .with_buffer_size(Some(100))
+        .with_buffer_lifetime(Some(LIFETIME))
+        .with_ignore_buffer_level(Level::Trace)
+        .with_level_buffer_policy(
+            Level::Error,
+            BufferPolicy {
+                size: None,
+                lifetime: Some(ERROR_LIFETIME),
+            },
+        )
+        .with_level_buffer_policy(
+            Level::Warn,
+            BufferPolicy {
+                size: Some(2),
+                lifetime: None,
+            },
+        )
+        .with_diagnostics(false)
+        .build(timed.clone())
+}
+
+fn send(sender: &BufferedSender, level: Level, seq: u64) {
+    sender
+        .send(LogStashRecord::builder(level).field("seq", seq).build())
+        .unwrap();
+}
+
+fn assert_flushed_after(at: Instant, started: Instant, lifetime: Duration) {
+    let waited = at.duration_since(started);
+    assert!(
+        waited >= lifetime && waited < lifetime + SLACK,
+        "flushed after {:?}, expected {:?}",
+        waited,
+        lifetime
+    );
+}
+
+#[test]
+fn levels_without_override_wait_for_the_global_lifetime() {
+    let timed = TimedSender::default();
+    let sender = buffered(&timed);
+
+    let started = Instant::now();
+    send(&sender, Level::Info, 0);
+    send(&sender, Level::Debug, 1);
+    thread::sleep(LIFETIME + SLACK * 2);
+
+    let batches = timed.batches();
+    assert_eq!(batches.len(), 1);
+    assert_eq!(batches[0].1, [0, 1]);
+    assert_flushed_after(batches[0].0, started, LIFETIME);
+}
+
+#[test]
+fn shorter_level_lifetime_flushes_the_records_buffered_before() {
+    let timed = TimedSender::default();
+    let sender = buffered(&timed);
+
+    send(&sender, Level::Info, 0);
+    thread::sleep(ERROR_LIFETIME);
+    let error_sent = Instant::now();
+    send(&sender, Level::Error, 1);
+    send(&sender, Level::Info, 2);
+    thread::sleep(ERROR_LIFETIME + SLACK);
+
+    // One buffer for all levels keeps the records in order
+    let batches = timed.batches();
+    assert_eq!(batches.len(), 1);
+    assert_eq!(batches[0].1, [0, 1, 2]);
+    assert_flushed_after(batches[0].0, error_sent, ERROR_LIFETIME);
+}
+
+#[test]
+fn level_size_caps_the_buffer_holding_a_record_of_that_level() {
+    let timed = TimedSender::default();
+    let sender = buffered(&timed);
+
+    let started = Instant::now();
+    send(&sender, Level::Info, 0);
+    send(&sender, Level::Warn, 1);
+    send(&sender, Level::Info, 2);
+    send(&sender, Level::Warn, 3);
+    send(&sender, Level::Info, 4);
+    thread::sleep(LIFETIME + SLACK * 2);
+
+    // A buffered warning flushes the buffer at two records of any level, the last info
+    // record waits for the global lifetime
+    let batches = timed.batches();
+    let seqs: Vec<_> = batches.iter().map(|(_, seqs)| seqs.clone()).collect();
+    assert_eq!(seqs, [vec![0, 1], vec![2, 3], vec![4]]);
+    assert!(batches[1].0.duration_since(started) < SLACK);
+    assert_flushed_after(batches[2].0, started, LIFETIME);
+}
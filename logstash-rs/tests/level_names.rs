@@ -0,0 +1,72 @@
+//! Custom names of the `level` field. The names are shared by all records of the process, so
+//! this file holds a single test.
+
+use log::Level;
+use qoollo_logstash_rs::{level_name, set_level_names, LogStashRecord};
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+fn names(names: &[(Level, &str)]) -> HashMap<Level, String> {
+    names
+        .iter()
+        .map(|&(level, name)| (level, name.to_owned()))
+        .collect()
+}
+
+/// Serialized `level` field of a record of every level, most severe first
+fn serialized_levels() -> Vec<String> {
+    [
+        Level::Error,
+        Level::Warn,
+        Level::Info,
+        Level::Debug,
+        Level::Trace,
+    ]
+    .iter()
+    .map(|&level| {
+        let json = serde_json::to_value(LogStashRecord::builder(level).build()).unwrap();
+        json["level"].as_str().unwrap().to_owned()
+    })
+    .collect()
+}
+
+fn parsed_level(name: &str) -> Level {
+    let json = format!(r#"{{"level":"{}","message":"parsed"}}"#, name);
+    LogStashRecord::from_json_str(&json).unwrap().level
+}
+
+#[test]
+fn partial_mapping_renames_mapped_levels_and_can_be_replaced() {
+    assert_eq!(
+        serialized_levels(),
+        ["ERROR", "WARN", "INFO", "DEBUG", "TRACE"]
+    );
+    // The names of `log::Level` are used without copying them
+    assert!(matches!(level_name(Level::Info), Cow::Borrowed("INFO")));
+
+    // Unmapped levels keep the names of `log::Level`
+    set_level_names(names(&[(Level::Error, "ERR"), (Level::Warn, "WRN")]));
+    assert_eq!(
+        serialized_levels(),
+        ["ERR", "WRN", "INFO", "DEBUG", "TRACE"]
+    );
+    assert_eq!(level_name(Level::Warn), "WRN");
+    assert!(matches!(level_name(Level::Info), Cow::Borrowed("INFO")));
+    assert_eq!(parsed_level("WRN"), Level::Warn);
+    assert_eq!(parsed_level("DEBUG"), Level::Debug);
+
+    // New names replace the previous mapping as a whole
+    set_level_names(names(&[(Level::Info, "INF")]));
+    assert_eq!(
+        serialized_levels(),
+        ["ERROR", "WARN", "INF", "DEBUG", "TRACE"]
+    );
+    assert_eq!(parsed_level("INF"), Level::Info);
+    assert_eq!(parsed_level("ERROR"), Level::Error);
+
+    set_level_names(HashMap::new());
+    assert_eq!(
+        serialized_levels(),
+        ["ERROR", "WARN", "INFO", "DEBUG", "TRACE"]
+    );
+}
@@ -0,0 +1,140 @@
+//! Frames written by `LumberjackSender` and its handling of the acknowledgements of the
+//! `MockLumberjack` Beats input.
+
+use qoollo_logstash_rs::testing::{records, LumberjackAck, MockLumberjack};
+use qoollo_logstash_rs::{LumberjackSender, Sender};
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::thread;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn sender(server: &MockLumberjack) -> LumberjackSender {
+    LumberjackSender::new("127.0.0.1".into(), server.port(), false, Some(TIMEOUT))
+        .with_ack_timeout(Some(TIMEOUT))
+}
+
+fn seqs(events: &[serde_json::Value]) -> Vec<u64> {
+    events.iter().map(|e| e["seq"].as_u64().unwrap()).collect()
+}
+
+#[test]
+fn uncompressed_window_is_a_window_frame_followed_by_json_frames() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let events = records("lumberjack", 0..2);
+    let payloads: Vec<String> = events
+        .iter()
+        .map(|event| serde_json::to_string(event).unwrap())
+        .collect();
+    let client = thread::spawn(move || {
+        LumberjackSender::new("127.0.0.1".into(), port, false, None)
+            .with_compression_level(0)
+            .with_ack_timeout(Some(TIMEOUT))
+            .send_batch(events)
+    });
+
+    let mut expected = b"2W\x00\x00\x00\x02".to_vec();
+    for (seq, payload) in payloads.iter().enumerate() {
+        expected.extend_from_slice(b"2J");
+        expected.extend_from_slice(&(seq as u32 + 1).to_be_bytes());
+        expected.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        expected.extend_from_slice(payload.as_bytes());
+    }
+    let (mut stream, _) = listener.accept().unwrap();
+    let mut received = vec![0u8; expected.len()];
+    stream.read_exact(&mut received).unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&received),
+        String::from_utf8_lossy(&expected)
+    );
+    stream.write_all(b"2A\x00\x00\x00\x02").unwrap();
+    client.join().unwrap().unwrap();
+}
+
+#[test]
+fn compressed_window_wraps_the_json_frames() {
+    let server = MockLumberjack::start().unwrap();
+    sender(&server)
+        .send_batch(records("lumberjack", 0..3))
+        .unwrap();
+
+    let windows = server.windows();
+    assert_eq!(windows.len(), 1);
+    assert!(windows[0].compressed);
+    assert_eq!(windows[0].size, 3);
+    assert_eq!(windows[0].seqs, vec![1, 2, 3]);
+    assert_eq!(windows[0].acked, 3);
+    assert_eq!(seqs(&windows[0].events), vec![0, 1, 2]);
+    assert_eq!(windows[0].events[0]["level"], "INFO");
+}
+
+#[test]
+fn window_size_splits_batches_into_windows() {
+    let server = MockLumberjack::start().unwrap();
+    sender(&server)
+        .with_window_size(Some(2))
+        .send_batch(records("lumberjack", 0..5))
+        .unwrap();
+
+    let windows = server.windows();
+    let sizes: Vec<u32> = windows.iter().map(|w| w.size).collect();
+    assert_eq!(sizes, vec![2, 2, 1]);
+    for window in &windows {
+        assert_eq!(window.seqs, (1..=window.size).collect::<Vec<_>>());
+    }
+    assert_eq!(seqs(&server.acked_events()), vec![0, 1, 2, 3, 4]);
+    assert_eq!(server.connections(), 1);
+}
+
+#[test]
+fn acks_of_every_record_complete_the_window() {
+    let server = MockLumberjack::start_with_script(vec![LumberjackAck::Each]).unwrap();
+    sender(&server)
+        .send_batch(records("lumberjack", 0..4))
+        .unwrap();
+
+    assert_eq!(seqs(&server.acked_events()), vec![0, 1, 2, 3]);
+    assert_eq!(server.windows().len(), 1);
+}
+
+#[test]
+fn partially_acknowledged_window_resends_only_the_rest() {
+    let server = MockLumberjack::start_with_script(vec![LumberjackAck::Partial(2)]).unwrap();
+    let sender = sender(&server);
+    sender.send_batch(records("lumberjack", 0..5)).unwrap();
+
+    let windows = server.windows();
+    assert_eq!(windows.len(), 2);
+    assert_eq!((windows[0].size, windows[0].acked), (5, 2));
+    assert_eq!(windows[1].connection, 1);
+    assert_eq!(windows[1].size, 3);
+    assert_eq!(seqs(&windows[1].events), vec![2, 3, 4]);
+    assert_eq!(seqs(&server.acked_events()), vec![0, 1, 2, 3, 4]);
+    assert_eq!(sender.reconnects(), 1);
+}
+
+#[test]
+fn partial_acks_keep_progressing_across_connections() {
+    let script = vec![LumberjackAck::Partial(1), LumberjackAck::Partial(1)];
+    let server = MockLumberjack::start_with_script(script).unwrap();
+    sender(&server)
+        .send_batch(records("lumberjack", 0..3))
+        .unwrap();
+
+    let sizes: Vec<u32> = server.windows().iter().map(|w| w.size).collect();
+    assert_eq!(sizes, vec![3, 2, 1]);
+    assert_eq!(seqs(&server.acked_events()), vec![0, 1, 2]);
+}
+
+#[test]
+fn unacknowledged_window_fails_after_one_retry() {
+    let script = vec![LumberjackAck::Partial(0), LumberjackAck::Partial(0)];
+    let server = MockLumberjack::start_with_script(script).unwrap();
+    let result = sender(&server).send_batch(records("lumberjack", 0..2));
+
+    assert!(result.is_err());
+    assert_eq!(server.windows().len(), 2);
+    assert!(server.acked_events().is_empty());
+}
@@ -0,0 +1,311 @@
+//! Memory budget of `BufferedSender` workers holding records the wrapped sender can't take, the
+//! flushes bounding batches of large records by size, and the cap on records queued and
+//! buffered in total.
+
+use log::Level;
+use qoollo_logstash_rs::testing::CapturingSender;
+use qoollo_logstash_rs::{
+    BufferedSender, BufferedSenderBuilder, Error, LogStashRecord, OverflowPolicy, Result, Sender,
+};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_BUFFER_BYTES: usize = 32 * 1024;
+const PAYLOAD: usize = 4 * 1024;
+const RECORDS: u64 = 100;
+
+/// Sender rejecting everything, as a destination down for the whole test
+struct WedgedSender;
+
+impl Sender for WedgedSender {
+    fn send(&self, _event: LogStashRecord) -> Result<()> {
+        Err(Error::Connection("wedged".into()))
+    }
+
+    fn send_batch(&self, _events: Vec<LogStashRecord>) -> Result<()> {
+        Err(Error::Connection("wedged".into()))
+    }
+
+    fn flush(&self) -> Result<()> {
+        Err(Error::Connection("wedged".into()))
+    }
+}
+
+fn record(seq: u64) -> LogStashRecord {
+    LogStashRecord::builder(Level::Info)
+        .target("memory")
+        .message("x".repeat(PAYLOAD))
+        .field("seq", seq)
+        .build()
+}
+
+fn builder() -> BufferedSenderBuilder {
+    BufferedSender::builder()
+        .with_buffer_size(Some(4))
+        .with_buffer_lifetime(None)
+        .with_ignore_buffer_level(Level::Trace)
+        .with_max_buffer_bytes(MAX_BUFFER_BYTES)
+        .with_error_period(Duration::from_secs(3600))
+        .with_diagnostics(false)
+}
+
+fn seqs(records: &[LogStashRecord]) -> Vec<u64> {
+    records
+        .iter()
+        .map(|record| record.fields["seq"].as_u64().unwrap())
+        .collect()
+}
+
+/// Sends the records, checking the tracked bytes stay within the budget all along
+fn send_all(sender: &BufferedSender) {
+    for seq in 0..RECORDS {
+        sender.send(record(seq)).unwrap();
+        let stats = sender.stats();
+        assert!(
+            stats.buffered_bytes <= MAX_BUFFER_BYTES as u64,
+            "{} bytes buffered after record {}",
+            stats.buffered_bytes,
+            seq
+        );
+    }
+}
+
+#[test]
+fn wedged_sender_keeps_newest_records_within_budget() {
+    // Ordered mode keeps the failed batches, so they pile up behind the wedged sender
+    let sender = builder().with_ordered(true).build(WedgedSender);
+    send_all(&sender);
+    assert!(sender.flush_and_wait(TIMEOUT).is_err());
+
+    let stats = sender.stats();
+    assert!(stats.buffered_bytes <= MAX_BUFFER_BYTES as u64);
+    assert!(stats.buffered > 0);
+    assert_eq!(stats.sent, 0);
+    assert_eq!(stats.buffered + stats.dropped, RECORDS);
+    assert!(stats.dropped >= RECORDS - (MAX_BUFFER_BYTES / PAYLOAD) as u64);
+
+    // The oldest records were dropped
+    let pending = sender.drain_pending();
+    let first = RECORDS - pending.len() as u64;
+    assert_eq!(seqs(&pending), (first..RECORDS).collect::<Vec<_>>());
+}
+
+#[test]
+fn drop_newest_keeps_oldest_records_while_paused() {
+    let captured = CapturingSender::new();
+    let sender = builder()
+        .with_overflow_policy(OverflowPolicy::DropNewest)
+        .build(captured.clone());
+    sender.pause().unwrap();
+    send_all(&sender);
+    // Fails while paused, once the records queued before are buffered
+    assert_eq!(sender.flush_and_wait(TIMEOUT).unwrap_err().kind(), "paused");
+
+    let stats = sender.stats();
+    assert!(stats.dropped > 0);
+    assert_eq!(stats.buffered + stats.dropped, RECORDS);
+
+    sender.resume().unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+    let kept = captured.records();
+    assert_eq!(seqs(&kept), (0..kept.len() as u64).collect::<Vec<_>>());
+    assert_eq!(kept.len() as u64 + stats.dropped, RECORDS);
+}
+
+const MAX_IN_FLIGHT: usize = 10;
+const OVER_CAP: u64 = 25;
+
+/// Paused sender capped at `MAX_IN_FLIGHT` records, with room for all of them in the channel
+fn capped(policy: OverflowPolicy, captured: &CapturingSender) -> BufferedSender {
+    let sender = BufferedSender::builder()
+        .with_buffer_size(Some(100))
+        .with_buffer_lifetime(None)
+        .with_ignore_buffer_level(Level::Trace)
+        .with_log_queue_len(1000)
+        .with_max_in_flight(Some(MAX_IN_FLIGHT))
+        .with_overflow_policy(policy)
+        .with_diagnostics(false)
+        .build(captured.clone());
+    sender.pause().unwrap();
+    for seq in 0..OVER_CAP {
+        sender
+            .send(
+                LogStashRecord::builder(Level::Info)
+                    .field("seq", seq)
+                    .build(),
+            )
+            .unwrap();
+    }
+    // Fails while paused, once the records queued before are buffered
+    assert_eq!(sender.flush_and_wait(TIMEOUT).unwrap_err().kind(), "paused");
+    sender
+}
+
+#[test]
+fn drop_newest_rejects_records_above_the_in_flight_cap() {
+    let captured = CapturingSender::new();
+    let sender = capped(OverflowPolicy::DropNewest, &captured);
+
+    // The paused worker holds the first records, the channel is empty but the cap is reached
+    let stats = sender.stats();
+    assert_eq!(stats.buffered, MAX_IN_FLIGHT as u64);
+    assert_eq!(stats.dropped, OVER_CAP - MAX_IN_FLIGHT as u64);
+
+    sender.resume().unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+    assert_eq!(
+        seqs(&captured.take()),
+        (0..MAX_IN_FLIGHT as u64).collect::<Vec<_>>()
+    );
+
+    // Sent records no longer count against the cap
+    sender
+        .send(
+            LogStashRecord::builder(Level::Info)
+                .field("seq", OVER_CAP)
+                .build(),
+        )
+        .unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+    assert_eq!(seqs(&captured.take()), [OVER_CAP]);
+}
+
+#[test]
+fn drop_oldest_keeps_the_newest_records_within_the_in_flight_cap() {
+    let captured = CapturingSender::new();
+    let sender = capped(OverflowPolicy::DropOldest, &captured);
+
+    let stats = sender.stats();
+    assert_eq!(stats.buffered, MAX_IN_FLIGHT as u64);
+    assert_eq!(stats.dropped, OVER_CAP - MAX_IN_FLIGHT as u64);
+
+    sender.resume().unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+    let first = OVER_CAP - MAX_IN_FLIGHT as u64;
+    assert_eq!(
+        seqs(&captured.take()),
+        (first..OVER_CAP).collect::<Vec<_>>()
+    );
+}
+
+const THREADS: u64 = 4;
+const LARGE_RECORDS: u64 = 50;
+const FLUSH_BYTES: usize = 256 * 1024;
+
+/// Record with a stack trace of 1KB to 64KB
+fn large_record(seq: u64) -> LogStashRecord {
+    LogStashRecord::builder(Level::Error)
+        .target("memory")
+        .message("failed")
+        .field("seq", seq)
+        .field(
+            "stack_trace",
+            "at frame\n".repeat((seq * 7919 % 64 + 1) as usize * 100),
+        )
+        .build()
+}
+
+/// Sender keeping the estimated size of every batch
+#[derive(Clone, Default)]
+struct BatchBytesSender {
+    batches: Arc<Mutex<Vec<usize>>>,
+    captured: CapturingSender,
+}
+
+impl Sender for BatchBytesSender {
+    fn send(&self, event: LogStashRecord) -> Result<()> {
+        self.send_batch(vec![event])
+    }
+
+    fn send_batch(&self, events: Vec<LogStashRecord>) -> Result<()> {
+        let bytes = events.iter().map(LogStashRecord::estimated_json_size).sum();
+        self.batches.lock().unwrap().push(bytes);
+        self.captured.send_batch(events)
+    }
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Sends large records from several threads while sampling the buffered bytes, returns the
+/// largest sample
+fn send_concurrently(sender: &Arc<BufferedSender>) -> u64 {
+    let done = Arc::new(AtomicBool::new(false));
+    let largest = Arc::new(AtomicU64::new(0));
+    let sampler = {
+        let (sender, done, largest) = (sender.clone(), done.clone(), largest.clone());
+        thread::spawn(move || {
+            while !done.load(Ordering::Relaxed) {
+                largest.fetch_max(sender.stats().buffered_bytes, Ordering::Relaxed);
+                thread::yield_now();
+            }
+        })
+    };
+    let senders: Vec<_> = (0..THREADS)
+        .map(|thread| {
+            let sender = sender.clone();
+            thread::spawn(move || {
+                for seq in thread * LARGE_RECORDS..(thread + 1) * LARGE_RECORDS {
+                    sender.send(large_record(seq)).unwrap();
+                }
+            })
+        })
+        .collect();
+    senders.into_iter().for_each(|t| t.join().unwrap());
+    done.store(true, Ordering::Relaxed);
+    sampler.join().unwrap();
+    largest.load(Ordering::Relaxed)
+}
+
+#[test]
+fn concurrent_large_records_stay_within_the_memory_budget() {
+    // Ordered mode keeps the failed batches, so they pile up behind the wedged sender
+    let sender = Arc::new(
+        builder()
+            .with_ordered(true)
+            .with_max_buffer_bytes(FLUSH_BYTES)
+            .with_log_queue_len(1000)
+            .build(WedgedSender),
+    );
+    let largest = send_concurrently(&sender);
+    assert!(sender.flush_and_wait(TIMEOUT).is_err());
+
+    let stats = sender.stats();
+    assert!(largest <= FLUSH_BYTES as u64, "{} bytes buffered", largest);
+    assert!(stats.buffered_bytes <= FLUSH_BYTES as u64);
+    assert!(stats.dropped > 0);
+}
+
+#[test]
+fn flush_bytes_bound_every_batch_without_dropping_records() {
+    let batches = BatchBytesSender::default();
+    let sender = Arc::new(
+        BufferedSender::builder()
+            .with_buffer_size(Some(1000))
+            .with_buffer_lifetime(None)
+            .with_ignore_buffer_level(Level::Trace)
+            .with_flush_bytes(Some(FLUSH_BYTES))
+            .with_log_queue_len(1000)
+            .with_diagnostics(false)
+            .build(batches.clone()),
+    );
+    let largest = send_concurrently(&sender);
+    sender.flush_and_wait(TIMEOUT).unwrap();
+
+    assert!(largest <= FLUSH_BYTES as u64, "{} bytes buffered", largest);
+    let sizes = batches.batches.lock().unwrap().clone();
+    assert!(sizes.len() > 1);
+    assert!(
+        sizes.iter().all(|&bytes| bytes <= FLUSH_BYTES),
+        "{:?}",
+        sizes
+    );
+    let mut sent = seqs(&batches.captured.take());
+    sent.sort_unstable();
+    assert_eq!(sent, (0..THREADS * LARGE_RECORDS).collect::<Vec<_>>());
+    assert_eq!(sender.stats().dropped, 0);
+}
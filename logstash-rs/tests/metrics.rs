@@ -0,0 +1,87 @@
+//! `SenderStats` published through the `metrics` facade and rendered in the Prometheus text
+//! format. The workers update the metrics on their own threads, so the debugging recorder is
+//! installed globally and this file holds a single test.
+
+use log::Level;
+use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+use qoollo_logstash_rs::testing::CapturingSender;
+use qoollo_logstash_rs::{BufferedSender, LogStashRecord, OverflowPolicy, Sender};
+use std::collections::HashMap;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_IN_FLIGHT: usize = 4;
+const RECORDS: u64 = 10;
+
+#[test]
+fn worker_counters_are_published_under_the_prefix() {
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+    recorder.install().unwrap();
+
+    let captured = CapturingSender::new();
+    let sender = BufferedSender::builder()
+        .with_buffer_size(Some(MAX_IN_FLIGHT))
+        .with_buffer_lifetime(None)
+        .with_ignore_buffer_level(Level::Trace)
+        .with_max_in_flight(Some(MAX_IN_FLIGHT))
+        .with_overflow_policy(OverflowPolicy::DropNewest)
+        .with_diagnostics(false)
+        .with_metrics_prefix("app_logstash")
+        .build(captured.clone());
+
+    // The paused worker holds the first records, the others are dropped
+    sender.pause().unwrap();
+    for seq in 0..RECORDS {
+        sender
+            .send(
+                LogStashRecord::builder(Level::Info)
+                    .field("seq", seq)
+                    .build(),
+            )
+            .unwrap();
+    }
+    // Fails while paused, once the records queued before are buffered
+    assert_eq!(sender.flush_and_wait(TIMEOUT).unwrap_err().kind(), "paused");
+    sender.resume().unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+    assert_eq!(captured.take().len(), MAX_IN_FLIGHT);
+
+    let metrics: HashMap<_, _> = snapshotter
+        .snapshot()
+        .into_vec()
+        .into_iter()
+        .map(|(key, _, _, value)| (key.key().name().to_owned(), value))
+        .collect();
+    assert_eq!(
+        metrics["app_logstash_events_sent_total"],
+        DebugValue::Counter(MAX_IN_FLIGHT as u64)
+    );
+    assert_eq!(
+        metrics["app_logstash_dropped_total"],
+        DebugValue::Counter(RECORDS - MAX_IN_FLIGHT as u64)
+    );
+    assert_eq!(
+        metrics["app_logstash_buffer_fill"],
+        DebugValue::Gauge(0.0.into())
+    );
+    assert_eq!(
+        metrics["app_logstash_buffered_bytes"],
+        DebugValue::Gauge(0.0.into())
+    );
+    assert!(metrics.contains_key("app_logstash_last_send_latency_seconds"));
+    assert!(metrics.keys().all(|name| name.starts_with("app_logstash_")));
+
+    // The same counters in the text format, for services without an exporter
+    let text = sender.stats().render_prometheus_text("app_logstash");
+    assert!(text.contains("# TYPE app_logstash_events_sent_total counter\n"));
+    assert!(text.contains(&format!(
+        "\napp_logstash_events_sent_total {}\n",
+        MAX_IN_FLIGHT
+    )));
+    assert!(text.contains(&format!(
+        "\napp_logstash_dropped_total {}\n",
+        RECORDS - MAX_IN_FLIGHT as u64
+    )));
+    assert!(text.contains("# TYPE app_logstash_buffer_fill gauge\napp_logstash_buffer_fill 0\n"));
+}
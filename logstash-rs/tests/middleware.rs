@@ -0,0 +1,81 @@
+//! Senders composed from middleware by `MiddlewarePipeline`.
+
+use log::Level;
+use qoollo_logstash_rs::testing::CapturingSender;
+use qoollo_logstash_rs::{
+    AnsiStrippingSender, ContextMiddleware, LogStashRecord, MiddlewarePipeline,
+    RateLimitMiddleware, Sender,
+};
+use serde_json::Value;
+use std::collections::HashMap;
+
+fn record(message: &str) -> LogStashRecord {
+    LogStashRecord::builder(Level::Info)
+        .target("middleware")
+        .message(message)
+        .build()
+}
+
+fn context() -> HashMap<String, Value> {
+    HashMap::from([
+        ("service".to_owned(), "billing".into()),
+        ("region".to_owned(), "eu".into()),
+    ])
+}
+
+#[test]
+fn pipeline_applies_every_middleware() {
+    let captured = CapturingSender::new();
+    let sender = MiddlewarePipeline::new(captured.clone())
+        .add(RateLimitMiddleware::new(3))
+        .add(ContextMiddleware::new(context()))
+        .add(|inner| Box::new(AnsiStrippingSender::new(inner)) as Box<dyn Sender>)
+        .build();
+
+    let mut own_region = record("\u{1b}[31mred\u{1b}[0m");
+    own_region.add_data("region", "us".into());
+    sender.send(own_region).unwrap();
+    sender
+        .send_batch((0..5).map(|i| record(&i.to_string())).collect())
+        .unwrap();
+    sender.flush().unwrap();
+
+    let records = captured.records();
+    let messages: Vec<_> = records
+        .iter()
+        .map(|r| r.fields["message"].clone())
+        .collect();
+    assert_eq!(messages, ["red", "0", "1"]);
+    assert!(records.iter().all(|r| r.fields["service"] == "billing"));
+    assert_eq!(records[0].fields["region"], "us");
+    assert_eq!(records[1].fields["region"], "eu");
+}
+
+#[test]
+fn rate_limit_counts_dropped_records() {
+    let captured = CapturingSender::new();
+    let sender = RateLimitMiddleware::new(10).around(captured.clone());
+    for i in 0..15 {
+        sender.send(record(&i.to_string())).unwrap();
+    }
+    assert_eq!(captured.len(), 10);
+    assert_eq!(sender.dropped(), 5);
+
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    sender
+        .send_batch((0..5).map(|i| record(&i.to_string())).collect())
+        .unwrap();
+    assert!((12..=14).contains(&captured.len()), "{}", captured.len());
+}
+
+#[test]
+fn middleware_senders_can_be_used_unboxed() {
+    let captured = CapturingSender::new();
+    let sender = ContextMiddleware::new(context()).around(captured.clone());
+    sender.send(record("typed")).unwrap();
+    assert_eq!(captured.records()[0].fields["service"], "billing");
+
+    let boxed: Box<dyn Sender> = Box::new(sender);
+    boxed.send(record("boxed")).unwrap();
+    assert_eq!(captured.len(), 2);
+}
@@ -0,0 +1,51 @@
+//! Serialization of unset optional fields under each `NullPolicy`. The policy is global, so
+//! this file holds a single test.
+
+use log::Level;
+use qoollo_logstash_rs::event::null_policy;
+use qoollo_logstash_rs::{set_null_policy, LogStashRecord, NullPolicy};
+use serde_json::Value;
+
+fn to_json(record: &LogStashRecord) -> Value {
+    serde_json::to_value(record).unwrap()
+}
+
+#[test]
+fn null_policies_omit_or_serialize_unset_fields() {
+    let unset = LogStashRecord::builder(Level::Info)
+        .message("hello")
+        .build();
+    let mut located = unset.clone();
+    located.module = Some("my_crate::net".into());
+    located.file = Some("src/net.rs".into());
+    located.line = Some(42);
+    located.column = Some(7);
+    const OPTIONAL: [&str; 4] = ["module", "file", "line", "column"];
+
+    // Omitted by default
+    assert_eq!(null_policy(), NullPolicy::OmitIfNull);
+    let json = to_json(&unset);
+    for field in OPTIONAL.iter() {
+        assert!(json.get(field).is_none(), "{} in {}", field, json);
+    }
+    assert_eq!(json["message"], "hello");
+
+    set_null_policy(NullPolicy::SerializeAsNull);
+    assert_eq!(null_policy(), NullPolicy::SerializeAsNull);
+    let json = to_json(&unset);
+    for field in OPTIONAL.iter() {
+        assert_eq!(json[field], Value::Null, "{} in {}", field, json);
+    }
+    assert!(json["@timestamp"].is_string());
+
+    // Set fields are serialized under both policies
+    for &policy in [NullPolicy::SerializeAsNull, NullPolicy::OmitIfNull].iter() {
+        set_null_policy(policy);
+        let json = to_json(&located);
+        assert_eq!(json["module"], "my_crate::net");
+        assert_eq!(json["file"], "src/net.rs");
+        assert_eq!(json["line"], 42);
+        assert_eq!(json["column"], 7);
+    }
+    assert!(to_json(&unset).get("module").is_none());
+}
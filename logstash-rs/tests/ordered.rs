@@ -0,0 +1,124 @@
+//! Ordered mode of `BufferedSender`: records above the ignore buffer level and batches
+//! retried after a failure keep the order the records were sent in.
+
+use log::Level;
+use qoollo_logstash_rs::{BufferedSender, Error, LogStashRecord, Result, Sender};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+const LEVELS: [Level; 3] = [Level::Info, Level::Error, Level::Warn];
+
+/// Sender recording the sequence numbers of every call, failing the first `failures` ones
+#[derive(Clone, Default)]
+struct SeqSender {
+    attempts: Arc<Mutex<Vec<Vec<u64>>>>,
+    delivered: Arc<Mutex<Vec<Vec<u64>>>>,
+    failures: Arc<AtomicUsize>,
+}
+
+impl SeqSender {
+    fn failing(failures: usize) -> Self {
+        Self {
+            failures: Arc::new(AtomicUsize::new(failures)),
+            ..Default::default()
+        }
+    }
+
+    fn attempts(&self) -> Vec<Vec<u64>> {
+        self.attempts.lock().unwrap().clone()
+    }
+
+    fn delivered(&self) -> Vec<Vec<u64>> {
+        self.delivered.lock().unwrap().clone()
+    }
+
+    fn record_call(&self, events: &[LogStashRecord]) -> Result<()> {
+        let seqs: Vec<_> = events
+            .iter()
+            .map(|event| event.fields["seq"].as_u64().unwrap())
+            .collect();
+        self.attempts.lock().unwrap().push(seqs.clone());
+        let failing = self
+            .failures
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| {
+                left.checked_sub(1)
+            })
+            .is_ok();
+        if failing {
+            return Err(Error::Connection("unreachable".into()));
+        }
+        self.delivered.lock().unwrap().push(seqs);
+        Ok(())
+    }
+}
+
+impl Sender for SeqSender {
+    fn send(&self, event: LogStashRecord) -> Result<()> {
+        self.record_call(std::slice::from_ref(&event))
+    }
+
+    fn send_batch(&self, events: Vec<LogStashRecord>) -> Result<()> {
+        self.record_call(&events)
+    }
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+fn sender(seqs: &SeqSender, ordered: bool) -> BufferedSender {
+    BufferedSender::builder()
+        .with_buffer_size(Some(4))
+        .with_ordered(ordered)
+        .with_diagnostics(false)
+        .build(seqs.clone())
+}
+
+/// Sends records cycling through info, error and warn levels, errors bypass the buffer
+fn send_interleaved(sender: &BufferedSender, count: u64) {
+    for seq in 0..count {
+        let record = LogStashRecord::builder(LEVELS[seq as usize % LEVELS.len()])
+            .target("ordered")
+            .field("seq", seq)
+            .build();
+        sender.send(record).unwrap();
+    }
+}
+
+#[test]
+fn interleaved_levels_are_delivered_in_order() {
+    let seqs = SeqSender::default();
+    let sender = sender(&seqs, true);
+
+    send_interleaved(&sender, 30);
+    sender.flush_and_wait(TIMEOUT).unwrap();
+    let delivered: Vec<_> = seqs.delivered().concat();
+    assert_eq!(delivered, (0..30).collect::<Vec<_>>());
+}
+
+#[test]
+fn failed_batch_blocks_later_batches_until_delivered() {
+    let seqs = SeqSender::failing(2);
+    let sender = sender(&seqs, true);
+
+    send_interleaved(&sender, 12);
+    sender.flush_and_wait(TIMEOUT).unwrap();
+
+    let delivered: Vec<_> = seqs.delivered().concat();
+    assert_eq!(delivered, (0..12).collect::<Vec<_>>());
+    // The failed batch stays in front, every retry starts with its first record
+    let attempts = seqs.attempts();
+    assert_eq!(attempts.len(), seqs.delivered().len() + 2);
+    assert_eq!(attempts[0][0], 0);
+    assert_eq!(attempts[1][0], 0);
+    assert_eq!(attempts[2][0], 0);
+    for attempt in &attempts {
+        let first = attempt[0];
+        assert_eq!(
+            *attempt,
+            (first..first + attempt.len() as u64).collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,190 @@
+//! Pausing `BufferedSender` workers: records are held without calls to the wrapped sender and
+//! delivered in order on resume, in batches of the buffer size.
+
+use log::Level;
+use qoollo_logstash_rs::testing::CapturingSender;
+use qoollo_logstash_rs::{BufferedSender, Error, LogStashRecord, Result, Sender};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const BUFFER_SIZE: usize = 10;
+const LIFETIME: Duration = Duration::from_millis(50);
+
+/// Sender counting every call, failing the batches it is told to
+#[derive(Clone, Default)]
+struct CountingSender {
+    calls: Arc<AtomicUsize>,
+    failures: Arc<AtomicUsize>,
+    batches: Arc<Mutex<Vec<usize>>>,
+    captured: CapturingSender,
+}
+
+impl CountingSender {
+    fn calls(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+
+    fn fail_next(&self, batches: usize) {
+        self.failures.store(batches, Ordering::SeqCst);
+    }
+
+    fn batches(&self) -> Vec<usize> {
+        self.batches.lock().unwrap().clone()
+    }
+
+    fn seqs(&self) -> Vec<u64> {
+        self.captured
+            .take()
+            .iter()
+            .map(|record| record.fields["seq"].as_u64().unwrap())
+            .collect()
+    }
+}
+
+impl Sender for CountingSender {
+    fn send(&self, event: LogStashRecord) -> Result<()> {
+        self.send_batch(vec![event])
+    }
+
+    fn send_batch(&self, events: Vec<LogStashRecord>) -> Result<()> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        let fail = self
+            .failures
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok();
+        if fail {
+            return Err(Error::Connection("down".into()));
+        }
+        self.batches.lock().unwrap().push(events.len());
+        self.captured.send_batch(events)
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn connect(&self) -> Result<()> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn ping(&self) -> Result<()> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+fn buffered(counting: &CountingSender) -> BufferedSender {
+    BufferedSender::builder()
+        .with_buffer_size(Some(BUFFER_SIZE))
+        .with_buffer_lifetime(Some(LIFETIME))
+        .with_ignore_buffer_level(Level::Trace)
+        .with_ping_interval(Some(LIFETIME))
+        .with_error_period(Duration::from_secs(3600))
+        .with_diagnostics(false)
+        .build(counting.clone())
+}
+
+/// Pauses the sender and sends it `count` records
+fn pause_and_send(sender: &BufferedSender, count: u64) {
+    sender.pause().unwrap();
+    assert!(sender.is_paused());
+    for seq in 0..count {
+        let level = if seq % 7 == 0 {
+            Level::Error
+        } else {
+            Level::Info
+        };
+        sender
+            .send(LogStashRecord::builder(level).field("seq", seq).build())
+            .unwrap();
+    }
+}
+
+#[test]
+fn paused_records_are_held_and_delivered_in_order_on_resume() {
+    let counting = CountingSender::default();
+    let sender = buffered(&counting);
+    pause_and_send(&sender, 35);
+
+    // Neither the buffer size, the lifetime, pings nor flushes reach the wrapped sender, and
+    // a flush waiting for the records to be delivered fails
+    std::thread::sleep(LIFETIME * 3);
+    let err = sender.flush_and_wait(TIMEOUT).unwrap_err();
+    assert_eq!(err.kind(), "paused");
+    assert_eq!(counting.calls(), 0);
+    assert_eq!(sender.stats().buffered, 35);
+
+    sender.resume().unwrap();
+    assert!(!sender.is_paused());
+    sender.flush_and_wait(TIMEOUT).unwrap();
+    assert_eq!(counting.seqs(), (0..35).collect::<Vec<_>>());
+    assert_eq!(counting.batches(), [10, 10, 10, 5]);
+    assert_eq!(sender.stats().buffered, 0);
+}
+
+#[test]
+fn failed_backlog_batch_keeps_the_batches_after_it() {
+    let counting = CountingSender::default();
+    let sender = buffered(&counting);
+    pause_and_send(&sender, 30);
+
+    // The first batch of the backlog fails, it is dropped outside of ordered mode
+    counting.fail_next(1);
+    sender.resume().unwrap();
+    let _ = sender.flush_and_wait(TIMEOUT);
+    sender.flush_and_wait(TIMEOUT).unwrap();
+    assert_eq!(counting.seqs(), (10..30).collect::<Vec<_>>());
+    assert_eq!(sender.stats().buffered, 0);
+}
+
+/// Sender blocking every call until the test ends
+struct StuckSender(Mutex<mpsc::Receiver<()>>);
+
+impl Sender for StuckSender {
+    fn send(&self, _event: LogStashRecord) -> Result<()> {
+        let _ = self.0.lock().unwrap().recv();
+        Ok(())
+    }
+
+    fn send_batch(&self, _events: Vec<LogStashRecord>) -> Result<()> {
+        let _ = self.0.lock().unwrap().recv();
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn pause_and_resume_do_not_wait_for_room_in_the_queue() {
+    let (release, stuck) = mpsc::channel();
+    let sender = BufferedSender::builder()
+        .with_buffer_size(None)
+        .with_log_queue_len(2)
+        .with_diagnostics(false)
+        .with_shutdown_timeout(Duration::ZERO)
+        .build(StuckSender(Mutex::new(stuck)));
+    // The worker is stuck in the first send, the next records fill the queue
+    for seq in 0..3 {
+        let _ = sender.send(
+            LogStashRecord::builder(Level::Info)
+                .field("seq", seq)
+                .build(),
+        );
+    }
+
+    let started = Instant::now();
+    sender.pause().unwrap();
+    sender.resume().unwrap();
+    assert!(
+        started.elapsed() < Duration::from_secs(1),
+        "{:?}",
+        started.elapsed()
+    );
+    drop(release);
+}
@@ -0,0 +1,159 @@
+//! Records persisted by `BufferedSender` when the shutdown flush does not finish in time,
+//! and replayed by the next sender using the same file.
+
+use log::Level;
+use qoollo_logstash_rs::testing::{record, MockLogstash, ReceivedLine};
+use qoollo_logstash_rs::{
+    BufferedSender, BufferedSenderBuilder, Error, LogStashRecord, Result, Sender, TcpSender,
+};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const TARGET: &str = "persist";
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn persist_path(test: &str) -> PathBuf {
+    let path =
+        std::env::temp_dir().join(format!("logstash-{}-{}.ndjson", test, std::process::id()));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+fn builder(path: &Path) -> BufferedSenderBuilder {
+    BufferedSender::builder()
+        .with_buffer_size(Some(100))
+        .with_buffer_lifetime(None)
+        .with_ignore_buffer_level(Level::Trace)
+        .with_diagnostics(false)
+        .with_persist_on_shutdown(Some(path.to_owned()))
+}
+
+fn connect(server: &MockLogstash, builder: BufferedSenderBuilder) -> BufferedSender {
+    let tcp = TcpSender::builder()
+        .hostname("127.0.0.1")
+        .port(server.port())
+        .connect_timeout(Duration::from_secs(5))
+        .build()
+        .unwrap();
+    builder.build(tcp)
+}
+
+/// Records of this test among the received lines
+fn events(lines: &[ReceivedLine]) -> Vec<Value> {
+    lines
+        .iter()
+        .filter_map(ReceivedLine::json)
+        .filter(|event| event["target"] == TARGET)
+        .collect()
+}
+
+/// Sender blocking every call until `release` is dropped, then failing
+struct Stalled {
+    release: Mutex<mpsc::Receiver<()>>,
+}
+
+impl Stalled {
+    fn new() -> (Self, mpsc::Sender<()>) {
+        let (release, released) = mpsc::channel();
+        let sender = Self {
+            release: Mutex::new(released),
+        };
+        (sender, release)
+    }
+
+    fn stall(&self) -> Result<()> {
+        let _ = self.release.lock().unwrap().recv();
+        Err(Error::Connection("stalled".into()))
+    }
+}
+
+impl Sender for Stalled {
+    fn send(&self, _event: LogStashRecord) -> Result<()> {
+        self.stall()
+    }
+
+    fn send_batch(&self, _events: Vec<LogStashRecord>) -> Result<()> {
+        self.stall()
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.stall()
+    }
+}
+
+#[test]
+fn records_left_at_shutdown_timeout_are_replayed_on_restart() {
+    let path = persist_path("timeout");
+    let (stalled, release) = Stalled::new();
+    let sender = builder(&path)
+        .with_shutdown_timeout(Duration::from_millis(200))
+        .build(stalled);
+    for seq in 0..10 {
+        sender.send(record(TARGET, seq)).unwrap();
+    }
+    let dropped = Instant::now();
+    drop(sender);
+    assert!(
+        dropped.elapsed() < Duration::from_secs(2),
+        "shutdown did not time out"
+    );
+    let persisted = fs::read_to_string(&path).unwrap();
+    assert_eq!(persisted.lines().count(), 10);
+
+    let server = MockLogstash::start().unwrap();
+    let sender = connect(&server, builder(&path));
+    sender.send(record(TARGET, 10)).unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+
+    let events = events(&server.wait_for_events(11, TIMEOUT));
+    let seqs: Vec<u64> = events.iter().map(|e| e["seq"].as_u64().unwrap()).collect();
+    assert_eq!(seqs, (0..11).collect::<Vec<_>>());
+    for event in &events[..10] {
+        assert_eq!(event["replayed"], true);
+        assert_eq!(event["level"], "INFO");
+    }
+    assert!(events[10].get("replayed").is_none());
+    assert!(!path.exists());
+    drop(release);
+}
+
+#[test]
+fn corrupt_lines_are_skipped_on_replay() {
+    let path = persist_path("corrupt");
+    let valid = serde_json::to_string(&record(TARGET, 0)).unwrap();
+    fs::write(
+        &path,
+        format!("{}\nnot json\n[1, 2]\n{}", valid, &valid[..valid.len() / 2]),
+    )
+    .unwrap();
+
+    let server = MockLogstash::start().unwrap();
+    let sender = connect(&server, builder(&path));
+    sender.send(record(TARGET, 1)).unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+
+    let events = events(&server.wait_for_events(2, TIMEOUT));
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0]["seq"], 0);
+    assert_eq!(events[0]["replayed"], true);
+    assert_eq!(events[1]["seq"], 1);
+    assert!(!path.exists());
+}
+
+#[test]
+fn file_is_removed_once_records_are_delivered() {
+    let path = persist_path("delivered");
+    let server = MockLogstash::start().unwrap();
+    let sender = connect(&server, builder(&path));
+    for seq in 0..5 {
+        sender.send(record(TARGET, seq)).unwrap();
+    }
+    drop(sender);
+
+    assert_eq!(events(&server.wait_for_events(5, TIMEOUT)).len(), 5);
+    assert!(!path.exists());
+}
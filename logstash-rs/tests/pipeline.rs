@@ -0,0 +1,321 @@
+//! Records sent through `BufferedSender` and `TcpSender` to the `MockLogstash` of the
+//! `testing` module, covering buffering, reconnects and stalled servers without Docker.
+
+use chrono::{DateTime, SubsecRound, Utc};
+use log::Level;
+use qoollo_logstash_rs::testing::{leveled_record, MockBehavior, MockLogstash, ReceivedLine};
+use qoollo_logstash_rs::{
+    BufferedSender, BufferedSenderBuilder, LogStashRecord, Sender, TcpSender,
+};
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const TARGET: &str = "pipeline";
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn buffered(server: &MockLogstash, builder: BufferedSenderBuilder) -> BufferedSender {
+    let tcp = TcpSender::builder()
+        .hostname("127.0.0.1")
+        .port(server.port())
+        .connect_timeout(Duration::from_secs(5))
+        .build()
+        .unwrap();
+    builder.build(tcp)
+}
+
+/// Records of this test among the received lines, leaving out diagnostics records
+fn events(lines: &[ReceivedLine]) -> Vec<Value> {
+    lines
+        .iter()
+        .filter_map(ReceivedLine::json)
+        .filter(|event| event["target"] == TARGET)
+        .collect()
+}
+
+fn seqs(events: &[Value]) -> Vec<u64> {
+    events.iter().map(|e| e["seq"].as_u64().unwrap()).collect()
+}
+
+#[test]
+fn buffered_records_arrive_as_json_lines() {
+    let server = MockLogstash::start().unwrap();
+    let sender = buffered(
+        &server,
+        BufferedSender::builder()
+            .with_buffer_size(Some(10))
+            .with_buffer_lifetime(None)
+            .with_ignore_buffer_level(Level::Trace),
+    );
+    for seq in 0..25 {
+        sender
+            .send(leveled_record(Level::Info, TARGET, seq))
+            .unwrap();
+    }
+    sender.flush_and_wait(TIMEOUT).unwrap();
+
+    let events = events(&server.wait_for_events(25, TIMEOUT));
+    assert_eq!(seqs(&events), (0..25).collect::<Vec<_>>());
+    for event in &events {
+        assert_eq!(event["level"], "INFO");
+        assert_eq!(event["message"], format!("record {}", event["seq"]));
+        assert!(event["@timestamp"].is_string());
+    }
+    server.assert_json_field(0, "target", TARGET);
+    assert_eq!(server.connections(), 1);
+}
+
+#[test]
+fn manually_built_records_arrive_with_their_creation_timestamp() {
+    let server = MockLogstash::start().unwrap();
+    let sender = buffered(
+        &server,
+        BufferedSender::builder().with_buffer_size(Some(10)),
+    );
+    let before = Utc::now();
+    let mut event = LogStashRecord::new();
+    event.target = TARGET.into();
+    event.add_data("seq", 0.into());
+    let stamped = event.timestamp;
+    sender.send(event).unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+
+    server.wait_for_events(1, TIMEOUT);
+    let events = events(&server.lines());
+    let timestamp = events[0]["@timestamp"]
+        .as_str()
+        .expect("@timestamp should be a string");
+    let timestamp = DateTime::parse_from_rfc3339(timestamp).unwrap();
+    assert!(stamped >= before);
+    assert_eq!(timestamp, stamped.trunc_subsecs(3));
+}
+
+#[test]
+fn redactor_runs_on_the_worker_before_records_reach_the_wire() {
+    let server = MockLogstash::start().unwrap();
+    let test_thread = std::thread::current().id();
+    let redacted_on = Arc::new(Mutex::new(vec![]));
+    let on = redacted_on.clone();
+    let sender = buffered(
+        &server,
+        BufferedSender::builder()
+            .with_buffer_size(Some(10))
+            .with_ignore_buffer_level(Level::Warn)
+            .with_redactor(move |event| {
+                on.lock().unwrap().push(std::thread::current().id());
+                event.fields.remove("password");
+                event.redact_value("user", "***");
+            }),
+    );
+    // The warning is sent right away, the error goes through the buffer
+    for &(seq, level) in [(0, Level::Warn), (1, Level::Error)].iter() {
+        let mut event = leveled_record(level, TARGET, seq);
+        event.add_data("password", "hunter2".into());
+        event.add_data("user", "alice".into());
+        sender.send(event).unwrap();
+    }
+    sender.flush_and_wait(TIMEOUT).unwrap();
+
+    let events = events(&server.wait_for_events(2, TIMEOUT));
+    assert_eq!(seqs(&events), [0, 1]);
+    for event in &events {
+        assert!(event.get("password").is_none(), "{}", event);
+        assert_eq!(event["user"], "***");
+        assert_eq!(
+            event["level"],
+            if event["seq"] == 0 { "WARN" } else { "ERROR" }
+        );
+    }
+    let redacted_on = redacted_on.lock().unwrap();
+    assert_eq!(redacted_on.len(), 2);
+    assert!(redacted_on.iter().all(|&thread| thread != test_thread));
+}
+
+#[test]
+fn buffer_lifetime_sends_records_without_flush() {
+    let lifetime = Duration::from_millis(200);
+    let server = MockLogstash::start().unwrap();
+    let sender = buffered(
+        &server,
+        BufferedSender::builder()
+            .with_buffer_size(Some(100))
+            .with_buffer_lifetime(Some(lifetime))
+            .with_ignore_buffer_level(Level::Trace),
+    );
+    let sent = Instant::now();
+    for seq in 0..3 {
+        sender
+            .send(leveled_record(Level::Info, TARGET, seq))
+            .unwrap();
+    }
+
+    let lines = server.wait_for_events(3, TIMEOUT);
+    assert!(lines[0].received_at.duration_since(sent) >= lifetime);
+    assert_eq!(seqs(&events(&lines)), [0, 1, 2]);
+}
+
+#[test]
+fn reconnects_after_server_closes_connection() {
+    let server = MockLogstash::start_with_script(vec![MockBehavior::CloseAfterBytes(0)]).unwrap();
+    let sender = buffered(&server, BufferedSender::builder().with_buffer_size(None));
+
+    // Records written before the client notices the closed connection are lost
+    let deadline = Instant::now() + TIMEOUT;
+    let mut seq = 0;
+    while server.connections() < 2 {
+        assert!(Instant::now() < deadline, "sender did not reconnect");
+        sender
+            .send(leveled_record(Level::Info, TARGET, seq))
+            .unwrap();
+        sender.flush_and_wait(TIMEOUT).unwrap();
+        seq += 1;
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    sender
+        .send(leveled_record(Level::Info, TARGET, seq))
+        .unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+
+    let lines = loop {
+        let lines = server.wait_for_events(1, TIMEOUT);
+        if events(&lines).last().map(|event| event["seq"] == seq) == Some(true) {
+            break lines;
+        }
+        assert!(Instant::now() < deadline, "last record not received");
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    assert!(lines.iter().all(|line| line.connection == 1));
+    assert_eq!(server.connections(), 2);
+}
+
+#[test]
+fn stalled_server_receives_records_once_reading() {
+    let stall = Duration::from_millis(500);
+    let server = MockLogstash::start_with_script(vec![MockBehavior::Stall(stall)]).unwrap();
+    let started = Instant::now();
+    let sender = buffered(
+        &server,
+        BufferedSender::builder()
+            .with_buffer_size(Some(5))
+            .with_ignore_buffer_level(Level::Trace),
+    );
+    for seq in 0..20 {
+        sender
+            .send(leveled_record(Level::Info, TARGET, seq))
+            .unwrap();
+    }
+    sender.flush_and_wait(TIMEOUT).unwrap();
+
+    let lines = server.wait_for_events(20, TIMEOUT);
+    assert!(lines[0].received_at.duration_since(started) >= stall);
+    assert_eq!(seqs(&events(&lines)), (0..20).collect::<Vec<_>>());
+    assert_eq!(server.connections(), 1);
+}
+
+#[test]
+fn drain_pending_returns_records_not_sent() {
+    let server = MockLogstash::start().unwrap();
+    let sender = buffered(
+        &server,
+        BufferedSender::builder()
+            .with_buffer_size(Some(100))
+            .with_buffer_lifetime(None)
+            .with_ignore_buffer_level(Level::Trace),
+    );
+    for seq in 0..3 {
+        sender
+            .send(leveled_record(Level::Info, TARGET, seq))
+            .unwrap();
+    }
+    sender.flush_and_wait(TIMEOUT).unwrap();
+    for seq in 3..10 {
+        sender
+            .send(leveled_record(Level::Info, TARGET, seq))
+            .unwrap();
+    }
+    let clone = sender.clone();
+
+    let drained = sender.drain_pending();
+    let drained_seqs: Vec<u64> = drained
+        .iter()
+        .map(|record| record.fields["seq"].as_u64().unwrap())
+        .collect();
+    assert_eq!(drained_seqs, (3..10).collect::<Vec<_>>());
+    assert!(drained.iter().all(|record| record.target == TARGET));
+    assert!(clone.send(leveled_record(Level::Info, TARGET, 10)).is_err());
+
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(seqs(&events(&server.lines())), [0, 1, 2]);
+}
+
+#[test]
+fn ensure_connected_reports_whether_the_server_is_reachable() {
+    let server = MockLogstash::start().unwrap();
+    let sender = buffered(&server, BufferedSender::builder().with_diagnostics(false));
+    sender.ensure_connected(TIMEOUT).unwrap();
+    let started = Instant::now();
+    while server.connections() == 0 && started.elapsed() < TIMEOUT {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(server.connections(), 1);
+
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let tcp = TcpSender::builder()
+        .hostname("127.0.0.1")
+        .port(port)
+        .connect_timeout(Duration::from_millis(500))
+        .build()
+        .unwrap();
+    let sender = BufferedSender::builder().with_diagnostics(false).build(tcp);
+    assert!(sender.ensure_connected(TIMEOUT).is_err());
+}
+
+#[test]
+fn clones_share_one_worker_and_connection() {
+    let server = MockLogstash::start().unwrap();
+    let app = buffered(
+        &server,
+        BufferedSender::builder()
+            .with_buffer_size(Some(100))
+            .with_buffer_lifetime(None)
+            .with_ignore_buffer_level(Level::Trace)
+            .with_diagnostics(false),
+    );
+    let access = app.clone();
+    let threads: Vec<_> = vec![app.clone(), access.clone()]
+        .into_iter()
+        .enumerate()
+        .map(|(i, sender)| {
+            std::thread::spawn(move || {
+                for seq in 0..10 {
+                    sender
+                        .send(leveled_record(Level::Info, TARGET, i as u64 * 10 + seq))
+                        .unwrap();
+                }
+            })
+        })
+        .collect();
+    threads
+        .into_iter()
+        .for_each(|thread| thread.join().unwrap());
+    // Dropping a clone keeps the worker running for the others
+    drop(app);
+    access.flush_and_wait(TIMEOUT).unwrap();
+
+    let mut seqs = seqs(&events(&server.wait_for_events(20, TIMEOUT)));
+    seqs.sort_unstable();
+    assert_eq!(seqs, (0..20).collect::<Vec<_>>());
+    assert_eq!(server.connections(), 1);
+
+    // The last clone dropped flushes what is left
+    access
+        .send(leveled_record(Level::Info, TARGET, 20))
+        .unwrap();
+    drop(access);
+    assert_eq!(events(&server.wait_for_events(21, TIMEOUT)).len(), 21);
+    assert_eq!(server.connections(), 1);
+}
@@ -0,0 +1,134 @@
+//! Records recycled by the `RecordPool` of a `BufferedSender`: the output is the same as
+//! with fresh records, and records sent on their own are not recycled.
+
+use chrono::{TimeZone, Utc};
+use log::Level;
+use qoollo_logstash_rs::{BufferedSender, LogStashRecord, RecordPool, Result, Sender};
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+const RECORDS: usize = 250;
+
+/// Sender serializing the records it gets and recording the method used for each call
+#[derive(Clone, Default)]
+struct SerializingSender {
+    calls: Arc<Mutex<Vec<&'static str>>>,
+    output: Arc<Mutex<Vec<u8>>>,
+}
+
+impl SerializingSender {
+    fn write(&self, call: &'static str, events: &[LogStashRecord]) -> Result<()> {
+        self.calls.lock().unwrap().push(call);
+        let mut output = self.output.lock().unwrap();
+        for event in events {
+            serde_json::to_writer(&mut *output, event)?;
+            output.push(b'\n');
+        }
+        Ok(())
+    }
+
+    fn calls(&self) -> Vec<&'static str> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// Serialized records parsed back, the order of their fields depends on the map
+    fn events(&self) -> Vec<Value> {
+        let output = self.output.lock().unwrap();
+        serde_json::Deserializer::from_slice(&output)
+            .into_iter()
+            .collect::<serde_json::Result<_>>()
+            .unwrap()
+    }
+}
+
+impl Sender for SerializingSender {
+    fn send(&self, event: LogStashRecord) -> Result<()> {
+        self.write("send", std::slice::from_ref(&event))
+    }
+
+    fn send_batch(&self, events: Vec<LogStashRecord>) -> Result<()> {
+        self.write("send_batch", &events)
+    }
+
+    fn send_batch_ref(&self, events: &[LogStashRecord]) -> Result<()> {
+        self.write("send_batch_ref", events)
+    }
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+fn from_record(pool: Option<&Arc<RecordPool>>, record: &log::Record) -> LogStashRecord {
+    match pool {
+        Some(pool) => LogStashRecord::from_record_pooled(record, pool).into_inner(),
+        None => LogStashRecord::from_record(record),
+    }
+}
+
+/// Sends records of varying shape, taken from `pool` if there is one, through a sender
+/// buffering records less verbose than `ignore_buffer`
+fn send_records(pool: Option<&Arc<RecordPool>>, ignore_buffer: Level) -> SerializingSender {
+    let captured = SerializingSender::default();
+    let mut builder = BufferedSender::builder()
+        .with_buffer_size(Some(16))
+        .with_buffer_lifetime(None)
+        .with_ignore_buffer_level(ignore_buffer)
+        .with_diagnostics(false);
+    if let Some(pool) = pool {
+        builder = builder.with_record_pool(pool.clone());
+    }
+    let sender = builder.build(captured.clone());
+
+    let timestamp = Utc.with_ymd_and_hms(2024, 5, 17, 8, 30, 0).unwrap();
+    for seq in 0..RECORDS {
+        let mut event = from_record(
+            pool,
+            &log::Record::builder()
+                .args(format_args!("record {}", seq))
+                .level(Level::Info)
+                .target("pool")
+                .module_path_static(Some("pool::records"))
+                .line(if seq % 3 == 0 { Some(seq as u32) } else { None })
+                .build(),
+        );
+        event.timestamp = timestamp;
+        event.add_data("seq", seq.into());
+        // Fields and tags of earlier records must not leak into reused ones
+        if seq % 2 == 0 {
+            event.add_data("even", true.into());
+        }
+        if seq % 5 == 0 {
+            event.add_tag("fifth");
+        }
+        sender.send(event).unwrap();
+    }
+    sender.flush_and_wait(TIMEOUT).unwrap();
+    captured
+}
+
+#[test]
+fn pooled_records_serialize_like_fresh_ones() {
+    let pool = Arc::new(RecordPool::new(64));
+    let pooled = send_records(Some(&pool), Level::Trace);
+    let fresh = send_records(None, Level::Trace);
+
+    assert_eq!(pooled.events().len(), RECORDS);
+    assert_eq!(pooled.events(), fresh.events());
+    // Batches are sent by reference and their records returned to the pool
+    assert!(pooled.calls().iter().all(|&call| call == "send_batch_ref"));
+    assert!(!pool.is_empty());
+}
+
+#[test]
+fn records_sent_on_their_own_use_send_and_are_not_recycled() {
+    let pool = Arc::new(RecordPool::new(64));
+    let pooled = send_records(Some(&pool), Level::Error);
+    let fresh = send_records(None, Level::Error);
+
+    assert_eq!(pooled.calls(), vec!["send"; RECORDS]);
+    assert_eq!(pooled.events(), fresh.events());
+    assert!(pool.is_empty());
+}
@@ -0,0 +1,120 @@
+//! Allocations saved by recycling records through a `RecordPool`. Every allocation of the
+//! process is counted, so this file holds a single test.
+
+use log::Level;
+use qoollo_logstash_rs::{BufferedSender, LogStashRecord, RecordPool, Result, Sender};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Allocator counting allocations
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+const BUFFER_SIZE: usize = 100;
+const RECORDS: usize = 10_000;
+
+/// Sender serializing batches into nothing, allocating nothing itself
+struct SinkSender;
+
+impl Sender for SinkSender {
+    fn send(&self, event: LogStashRecord) -> Result<()> {
+        self.send_batch_ref(std::slice::from_ref(&event))
+    }
+
+    fn send_batch(&self, events: Vec<LogStashRecord>) -> Result<()> {
+        self.send_batch_ref(&events)
+    }
+
+    fn send_batch_ref(&self, events: &[LogStashRecord]) -> Result<()> {
+        for event in events {
+            serde_json::to_writer(std::io::sink(), event)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Allocations of sending `RECORDS` records with fields, after a warm-up round filling the
+/// pool if there is one
+fn allocations(pool: Option<Arc<RecordPool>>) -> usize {
+    let mut builder = BufferedSender::builder()
+        .with_buffer_size(Some(BUFFER_SIZE))
+        .with_buffer_lifetime(None)
+        .with_ignore_buffer_level(Level::Trace)
+        .with_diagnostics(false);
+    if let Some(pool) = &pool {
+        builder = builder.with_record_pool(pool.clone());
+    }
+    let sender = builder.build(SinkSender);
+
+    let send_all = || {
+        for seq in 0..RECORDS {
+            let record = log::Record::builder()
+                .args(format_args!("request handled"))
+                .level(Level::Info)
+                .target("pool")
+                .module_path_static(Some("pool"))
+                .build();
+            let mut event = match &pool {
+                Some(pool) => LogStashRecord::from_record_pooled(&record, pool).into_inner(),
+                None => LogStashRecord::from_record(&record),
+            };
+            event.add_data("seq", seq.into());
+            event.add_data("status", 200.into());
+            sender.send(event).unwrap();
+            // A steady rate, the worker keeps up and returns the records of each batch
+            if seq % BUFFER_SIZE == BUFFER_SIZE - 1 {
+                sender.flush_and_wait(TIMEOUT).unwrap();
+            }
+        }
+    };
+    send_all();
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    send_all();
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+#[test]
+fn pooled_records_allocate_less() {
+    let fresh = allocations(None);
+    let pooled = allocations(Some(Arc::new(RecordPool::new(4 * BUFFER_SIZE))));
+    println!(
+        "{} records: {} allocations with fresh records, {} with pooled records",
+        RECORDS, fresh, pooled
+    );
+
+    // Records reuse the maps of their fields, the keys and the messages are allocated. A few
+    // may be taken while the worker still holds the records of the previous batch.
+    assert!(
+        pooled + RECORDS * 9 / 10 <= fresh,
+        "{} allocations pooled, {} fresh",
+        pooled,
+        fresh
+    );
+}
@@ -0,0 +1,145 @@
+//! Records written by `ChildProcessSender` to the stdin of a forwarder and read back from
+//! its stdout.
+#![cfg(unix)]
+
+use log::Level;
+use qoollo_logstash_rs::testing::record;
+use qoollo_logstash_rs::{ChildProcessSender, LogStashRecord, Sender};
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, PipeReader};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Sender spawning `sh -c script`, with the stdout of its children piped to the returned
+/// reader
+fn piped(script: &str, args: &[&str]) -> (ChildProcessSender, BufReader<PipeReader>) {
+    let (reader, writer) = std::io::pipe().unwrap();
+    let mut sh_args = vec!["-c".to_owned(), script.to_owned()];
+    sh_args.extend(args.iter().map(|arg| arg.to_string()));
+    let sender = ChildProcessSender::new("sh", sh_args)
+        .with_stdout(move || writer.try_clone().unwrap().into());
+    (sender, BufReader::new(reader))
+}
+
+fn read_events(reader: &mut BufReader<PipeReader>, count: usize) -> Vec<Value> {
+    (0..count)
+        .map(|_| {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            serde_json::from_str(&line).unwrap()
+        })
+        .collect()
+}
+
+fn marker_path(test: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("logstash-{}-{}", test, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+#[test]
+fn records_round_trip_through_cat() {
+    let (sender, mut stdout) = piped("exec cat", &[]);
+    sender.send(record("process", 0)).unwrap();
+    sender
+        .send_batch(vec![record("process", 1), record("process", 2)])
+        .unwrap();
+    sender.flush().unwrap();
+
+    let events = read_events(&mut stdout, 3);
+    for (seq, event) in events.iter().enumerate() {
+        assert_eq!(event["seq"], seq);
+        assert_eq!(event["message"], format!("record {}", seq));
+        assert_eq!(event["level"], "INFO");
+        assert_eq!(event["target"], "process");
+    }
+}
+
+#[test]
+fn child_closing_its_stdin_is_respawned() {
+    // The first child closes its stdin and lingers, the next ones echo their input
+    let marker = marker_path("respawn");
+    let script = r#"if [ -e "$0" ]; then exec cat; fi; exec 0<&-; touch "$0"; sleep 10"#;
+    let (sender, mut stdout) = piped(script, &[marker.to_str().unwrap()]);
+    sender.connect().unwrap();
+    let started = Instant::now();
+    while !marker.exists() {
+        assert!(
+            started.elapsed() < Duration::from_secs(10),
+            "child did not start"
+        );
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    sender.send(record("process", 0)).unwrap();
+    sender.send(record("process", 1)).unwrap();
+    sender.flush().unwrap();
+
+    let events = read_events(&mut stdout, 2);
+    assert_eq!(events[0]["seq"], 0);
+    assert_eq!(events[1]["seq"], 1);
+    let _ = std::fs::remove_file(&marker);
+}
+
+/// Record with a nested document of about `items` × 100 bytes of JSON
+fn large_record(seq: usize, items: usize) -> LogStashRecord {
+    let document: Vec<Value> = (0..items)
+        .map(|id| json!({ "id": id, "name": format!("item-{}", id), "payload": "x".repeat(64) }))
+        .collect();
+    LogStashRecord::builder(Level::Info)
+        .target("process")
+        .field("seq", seq)
+        .field("document", document)
+        .build()
+}
+
+#[test]
+fn large_records_are_streamed_intact() {
+    let (sender, mut stdout) = piped("exec cat", &[]);
+    // The pipe holds less than a record, read while the records are written
+    let reader = std::thread::spawn(move || read_events(&mut stdout, 3));
+    let records: Vec<_> = (0..3).map(|seq| large_record(seq, 10_000)).collect();
+    let expected: Vec<_> = records
+        .iter()
+        .map(|record| serde_json::to_value(record).unwrap())
+        .collect();
+    sender.send_batch(records).unwrap();
+    sender.flush().unwrap();
+
+    let events = reader.join().unwrap();
+    assert_eq!(events, expected);
+    assert_eq!(events[2]["document"][9_999]["name"], "item-9999");
+}
+
+#[test]
+fn drop_kills_a_child_not_exiting_within_the_shutdown_timeout() {
+    // The child ignores its closed stdin and keeps running
+    let marker = marker_path("kill");
+    let script = r#"echo $$ > "$0"; exec sleep 30"#;
+    let (sender, _stdout) = piped(script, &[marker.to_str().unwrap()]);
+    let sender = sender.with_shutdown_timeout(Duration::from_millis(200));
+    sender.connect().unwrap();
+    let started = Instant::now();
+    let pid = loop {
+        match std::fs::read_to_string(&marker) {
+            Ok(pid) if pid.ends_with('\n') => break pid.trim().to_owned(),
+            _ => {}
+        }
+        assert!(
+            started.elapsed() < Duration::from_secs(10),
+            "child did not start"
+        );
+        std::thread::sleep(Duration::from_millis(10));
+    };
+
+    let dropped = Instant::now();
+    drop(sender);
+    assert!(dropped.elapsed() < Duration::from_secs(5));
+    let alive = std::process::Command::new("kill")
+        .args(["-0", &pid])
+        .stderr(std::process::Stdio::null())
+        .status()
+        .unwrap();
+    assert!(!alive.success(), "child {} still running", pid);
+    let _ = std::fs::remove_file(&marker);
+}
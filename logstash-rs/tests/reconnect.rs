@@ -0,0 +1,97 @@
+//! Reconnect delays computed by `ReconnectPolicy` with and without jitter.
+
+use qoollo_logstash_rs::{Jitter, ReconnectPolicy};
+use std::collections::HashSet;
+use std::time::Duration;
+
+const SAMPLES: usize = 200;
+
+fn policy(jitter: Jitter) -> ReconnectPolicy {
+    ReconnectPolicy {
+        initial_delay: Duration::from_millis(100),
+        max_delay: Duration::from_secs(5),
+        jitter,
+        ..Default::default()
+    }
+}
+
+/// Delays after `failures` failed attempts, checking each lies within `min..=max`
+fn sample_delays(
+    policy: &ReconnectPolicy,
+    failures: u32,
+    min: Duration,
+    max: Duration,
+) -> HashSet<Duration> {
+    (0..SAMPLES)
+        .map(|_| {
+            let delay = policy.delay(failures);
+            assert!(
+                min <= delay && delay <= max,
+                "{:?} not within {:?}..={:?}",
+                delay,
+                min,
+                max
+            );
+            delay
+        })
+        .collect()
+}
+
+#[test]
+fn delays_double_up_to_the_maximum_without_jitter() {
+    let policy = policy(Jitter::None);
+    let delays: Vec<_> = (1..=8).map(|failures| policy.delay(failures)).collect();
+    let millis: Vec<_> = delays.iter().map(Duration::as_millis).collect();
+    assert_eq!(millis, [100, 200, 400, 800, 1600, 3200, 5000, 5000]);
+    assert_eq!(policy.delay(u32::MAX), Duration::from_secs(5));
+}
+
+#[test]
+fn full_jitter_varies_between_zero_and_the_delay() {
+    let policy = policy(Jitter::Full);
+    for &(failures, delay) in [(1, 100), (3, 400), (10, 5000)].iter() {
+        let delays = sample_delays(
+            &policy,
+            failures,
+            Duration::ZERO,
+            Duration::from_millis(delay),
+        );
+        assert!(
+            delays.len() > SAMPLES / 2,
+            "{} distinct delays",
+            delays.len()
+        );
+    }
+}
+
+#[test]
+fn equal_jitter_varies_between_half_the_delay_and_the_delay() {
+    let policy = policy(Jitter::Equal);
+    for &(failures, delay) in [(1, 100), (3, 400), (10, 5000)].iter() {
+        let delays = sample_delays(
+            &policy,
+            failures,
+            Duration::from_millis(delay / 2),
+            Duration::from_millis(delay),
+        );
+        assert!(
+            delays.len() > SAMPLES / 2,
+            "{} distinct delays",
+            delays.len()
+        );
+    }
+}
+
+#[test]
+fn jitter_of_a_zero_delay_is_zero() {
+    let policy = ReconnectPolicy {
+        jitter: Jitter::Full,
+        ..Default::default()
+    };
+    assert_eq!(policy.delay(1), Duration::ZERO);
+    let policy = ReconnectPolicy {
+        jitter: Jitter::Equal,
+        ..Default::default()
+    };
+    assert_eq!(policy.delay(1), Duration::ZERO);
+}
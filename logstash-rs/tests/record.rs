@@ -0,0 +1,487 @@
+//! JSON serialization, equality and hashing of `LogStashRecord`.
+
+use chrono::{TimeZone, Utc};
+use log::Level;
+use qoollo_logstash_rs::testing::CapturingSender;
+use qoollo_logstash_rs::{
+    format_duration, AnsiStrippingSender, LevelScale, LogStashRecord, Sender,
+};
+use serde_json::{json, Value};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::time::Duration;
+
+fn to_json(record: &LogStashRecord) -> Value {
+    serde_json::to_value(record).unwrap()
+}
+
+#[test]
+fn empty_tags_are_omitted() {
+    let record = LogStashRecord::builder(Level::Info)
+        .message("hello")
+        .build();
+    assert!(to_json(&record).get("tags").is_none());
+}
+
+#[test]
+fn single_tag_is_an_array() {
+    let record = LogStashRecord::builder(Level::Info).tag("canary").build();
+    assert_eq!(to_json(&record)["tags"], json!(["canary"]));
+}
+
+#[test]
+fn duplicate_tags_are_kept_once_in_order() {
+    let mut record = LogStashRecord::builder(Level::Error)
+        .tag("beta")
+        .tag("error")
+        .build()
+        .with_tags(&["canary".into(), "beta".into()]);
+    record.add_tag("error").add_tag("canary");
+    assert_eq!(to_json(&record)["tags"], json!(["beta", "error", "canary"]));
+}
+
+#[test]
+fn redact_value_masks_string_fields_only() {
+    let mut record = LogStashRecord::builder(Level::Info)
+        .field("password", "hunter2")
+        .field("pin", 1234)
+        .message("login")
+        .build();
+    record
+        .redact_value("password", "***")
+        .redact_value("pin", "***")
+        .redact_value("missing", "***");
+
+    let json = to_json(&record);
+    assert_eq!(json["password"], "***");
+    assert_eq!(json["pin"], 1234);
+    assert_eq!(json["message"], "login");
+    assert!(json.get("missing").is_none());
+}
+
+#[test]
+fn redact_pattern_leaves_non_matching_fields_intact() {
+    let card = regex::Regex::new(r"\b\d{4}-\d{4}-\d{4}-\d{4}\b").unwrap();
+    let mut record = LogStashRecord::builder(Level::Info)
+        .message("paid with 1234-5678-9012-3456 and 1111-2222-3333-4444")
+        .field("order", "order 1234-5678")
+        .field(
+            "nested",
+            json!({"cards": ["1234-5678-9012-3456"], "count": 2}),
+        )
+        .field("amount", 12.5)
+        .field("mask_chars", "$1 stays")
+        .build();
+    let untouched = to_json(&record);
+    record.redact_pattern(&card, "$1-****");
+
+    let json = to_json(&record);
+    // The mask is inserted literally, `$1` is not a capture group reference
+    assert_eq!(json["message"], "paid with $1-**** and $1-****");
+    assert_eq!(json["nested"], json!({"cards": ["$1-****"], "count": 2}));
+    for field in [
+        "order",
+        "amount",
+        "mask_chars",
+        "level",
+        "target",
+        "@timestamp",
+    ] {
+        assert_eq!(json[field], untouched[field], "{} changed", field);
+    }
+}
+
+#[test]
+fn builder_keeps_the_level_it_was_given() {
+    for level in [
+        Level::Error,
+        Level::Warn,
+        Level::Info,
+        Level::Debug,
+        Level::Trace,
+    ] {
+        let record = LogStashRecord::builder(level).message("hello").build();
+        assert_eq!(record.level, level);
+        assert_eq!(to_json(&record)["level"], level.as_str().to_uppercase());
+    }
+    // Records built without the builder keep the documented `Warn` default
+    assert_eq!(LogStashRecord::new().level, Level::Warn);
+}
+
+#[test]
+fn full_build_sets_every_field() {
+    let timestamp = Utc.with_ymd_and_hms(2024, 5, 17, 8, 30, 0).unwrap();
+    let record = LogStashRecord::builder(Level::Debug)
+        .timestamp(timestamp)
+        .level(Level::Error)
+        .target("app::billing")
+        .message("payment declined")
+        .field("order_id", 42)
+        .field("retryable", false)
+        .tag("billing")
+        .build();
+
+    assert_eq!(record.timestamp, timestamp);
+    assert_eq!(record.level, Level::Error);
+    assert_eq!(record.target, "app::billing");
+    let json = to_json(&record);
+    assert_eq!(json["@timestamp"], "2024-05-17T08:30:00.000Z");
+    assert_eq!(json["level"], "ERROR");
+    assert_eq!(json["target"], "app::billing");
+    assert_eq!(json["message"], "payment declined");
+    assert_eq!(json["order_id"], 42);
+    assert_eq!(json["retryable"], false);
+    assert_eq!(json["tags"], json!(["billing"]));
+}
+
+#[test]
+fn builder_timestamp_defaults_to_now() {
+    let before = Utc::now();
+    let record = LogStashRecord::builder(Level::Info).build();
+    let after = Utc::now();
+    assert!(before <= record.timestamp && record.timestamp <= after);
+}
+
+#[test]
+fn borrowed_and_owned_locations_serialize_identically() {
+    let timestamp = Utc.with_ymd_and_hms(2024, 5, 17, 8, 30, 0).unwrap();
+    let (module, file) = (String::from("app::billing"), String::from("src/billing.rs"));
+    let args = format_args!("charged");
+    let borrowed = log::Record::builder()
+        .args(args)
+        .level(Level::Info)
+        .target("app::billing")
+        .module_path_static(Some("app::billing"))
+        .file_static(Some("src/billing.rs"))
+        .line(Some(12))
+        .build();
+    let owned = log::Record::builder()
+        .args(args)
+        .level(Level::Info)
+        .target(&module)
+        .module_path(Some(&module))
+        .file(Some(&file))
+        .line(Some(12))
+        .build();
+
+    let serialize = |record: &log::Record| {
+        let mut event = LogStashRecord::from_record(record);
+        event.timestamp = timestamp;
+        (event.clone(), serde_json::to_string(&event).unwrap())
+    };
+    let (borrowed, borrowed_json) = serialize(&borrowed);
+    let (owned, owned_json) = serialize(&owned);
+    assert!(matches!(borrowed.module, Some(Cow::Borrowed(_))));
+    assert!(matches!(borrowed.target, Cow::Borrowed(_)));
+    assert!(matches!(owned.file, Some(Cow::Owned(_))));
+    assert!(matches!(owned.target, Cow::Owned(_)));
+
+    assert_eq!(borrowed_json, owned_json);
+    assert_eq!(
+        borrowed_json,
+        r#"{"@timestamp":"2024-05-17T08:30:00.000Z","module":"app::billing","file":"src/billing.rs","line":12,"level":"INFO","target":"app::billing","message":"charged"}"#
+    );
+}
+
+/// Message as printed by a colored terminal formatter
+const COLORED: &str =
+    "\x1b[1;31mERROR\x1b[0m request \x1b[2mid=\x1b[0m\x1b[38;5;208m42\x1b[0m failed";
+
+#[test]
+fn strip_ansi_codes_cleans_the_given_field_only() {
+    let mut record = LogStashRecord::builder(Level::Error)
+        .message(COLORED)
+        .field("span", "\x1b[3mhandle\x1b[0m")
+        .build();
+    record
+        .strip_ansi_codes("message")
+        .strip_ansi_codes("missing");
+
+    let json = to_json(&record);
+    assert_eq!(json["message"], "ERROR request id=42 failed");
+    assert_eq!(json["span"], "\x1b[3mhandle\x1b[0m");
+}
+
+#[test]
+fn strip_ansi_codes_all_cleans_every_string_field() {
+    let mut record = LogStashRecord::builder(Level::Info)
+        .message(COLORED)
+        .field("span", "\x1b[3mhandle\x1b[0m")
+        .field("status", 500)
+        // Only color sequences are removed, other control sequences are kept
+        .field("progress", "\x1b[2K50%")
+        .build();
+    record.strip_ansi_codes_all();
+
+    let json = to_json(&record);
+    assert_eq!(json["message"], "ERROR request id=42 failed");
+    assert_eq!(json["span"], "handle");
+    assert_eq!(json["status"], 500);
+    assert_eq!(json["progress"], "\x1b[2K50%");
+}
+
+#[test]
+fn ansi_stripping_sender_cleans_records_before_forwarding() {
+    let captured = CapturingSender::new();
+    let sender = AnsiStrippingSender::new(captured.clone());
+    sender
+        .send(
+            LogStashRecord::builder(Level::Error)
+                .message(COLORED)
+                .build(),
+        )
+        .unwrap();
+    sender
+        .send_batch(vec![LogStashRecord::builder(Level::Warn)
+            .message("\x1b[33mslow\x1b[0m")
+            .build()])
+        .unwrap();
+
+    let messages: Vec<_> = captured
+        .records()
+        .iter()
+        .map(|record| to_json(record)["message"].clone())
+        .collect();
+    assert_eq!(messages, ["ERROR request id=42 failed", "slow"]);
+}
+
+#[test]
+fn level_value_follows_the_documented_scales() {
+    let expected = vec![
+        (Level::Error, "ERROR", 1, 5),
+        (Level::Warn, "WARN", 2, 4),
+        (Level::Info, "INFO", 3, 3),
+        (Level::Debug, "DEBUG", 4, 2),
+        (Level::Trace, "TRACE", 5, 1),
+    ];
+    for (level, name, log_value, inverted_value) in expected {
+        let mut record = LogStashRecord::builder(level).build();
+        let json = to_json(record.add_level_value(LevelScale::Log));
+        assert_eq!(json["level"], name);
+        assert_eq!(json["level_value"], log_value);
+
+        let json = to_json(record.add_level_value(LevelScale::Inverted));
+        assert_eq!(json["level"], name);
+        assert_eq!(json["level_value"], inverted_value);
+    }
+}
+
+#[test]
+fn write_appends_to_the_message() {
+    let mut record = LogStashRecord::builder(Level::Info).build();
+    record.write_str("status=").unwrap();
+    record.write_str("200").unwrap();
+    record.write_char(' ').unwrap();
+    write!(record, "latency={}ms", 12).unwrap();
+    assert_eq!(to_json(&record)["message"], "status=200 latency=12ms");
+}
+
+#[test]
+fn write_keeps_the_initial_message() {
+    let mut record = LogStashRecord::builder(Level::Error)
+        .message("request failed")
+        .build();
+    let err = "timeout";
+    write!(record, ": err={}", err).unwrap();
+    assert_eq!(to_json(&record)["message"], "request failed: err=timeout");
+
+    // A non-string message is kept as its JSON text
+    let mut record = LogStashRecord::builder(Level::Info).build();
+    record.add_data("message", json!(42));
+    write!(record, " retries").unwrap();
+    assert_eq!(to_json(&record)["message"], "42 retries");
+}
+
+#[test]
+fn from_json_str_round_trips_a_serialized_record() {
+    let mut record = LogStashRecord::builder(Level::Error)
+        .timestamp(Utc.with_ymd_and_hms(2024, 5, 6, 7, 8, 9).unwrap())
+        .target("app::db")
+        .message("query failed")
+        .field("rows", 3)
+        .tag("replayed")
+        .build();
+    record.module = Some(Cow::Borrowed("app::db"));
+    record.file = Some(Cow::Borrowed("src/db.rs"));
+    record.line = Some(42);
+    let json = serde_json::to_string(&record).unwrap();
+
+    let parsed = LogStashRecord::from_json_str(&json).unwrap();
+    assert_eq!(to_json(&parsed), to_json(&record));
+    assert_eq!(parsed.level, Level::Error);
+    assert_eq!(parsed.timestamp, record.timestamp);
+    assert_eq!(parsed.line, Some(42));
+}
+
+#[test]
+fn from_json_str_defaults_missing_fields_and_keeps_unknown_ones() {
+    let before = Utc::now();
+    let parsed = LogStashRecord::from_json_str(
+        r#"{"message":"replayed","level":"LOUD","line":"12","trace":{"id":"abc"},"retries":2}"#,
+    )
+    .unwrap();
+
+    assert_eq!(parsed.level, Level::Warn);
+    assert!(parsed.timestamp >= before);
+    assert_eq!(parsed.line, None);
+    assert_eq!(parsed.target, "");
+    assert_eq!(parsed.fields["message"], "replayed");
+    assert_eq!(parsed.fields["trace"], json!({ "id": "abc" }));
+    assert_eq!(parsed.fields["retries"], 2);
+    // Known keys are taken out of the fields even when their value is unusable
+    assert!(!parsed.fields.contains_key("level"));
+    assert!(!parsed.fields.contains_key("line"));
+}
+
+#[test]
+fn from_json_str_fails_only_without_a_json_object() {
+    assert!(LogStashRecord::from_json_str("{}").is_ok());
+    assert!(LogStashRecord::from_json_str(r#"{"message":"cut"#).is_err());
+    assert!(LogStashRecord::from_json_str(r#"["not", "an", "object"]"#).is_err());
+}
+
+fn with_fields(fields: &[(&'static str, Value)]) -> LogStashRecord {
+    let timestamp = Utc.with_ymd_and_hms(2024, 5, 17, 8, 30, 0).unwrap();
+    let mut builder = LogStashRecord::builder(Level::Info)
+        .timestamp(timestamp)
+        .target("dedup");
+    for (key, value) in fields {
+        builder = builder.field(key, value.clone());
+    }
+    builder.build()
+}
+
+#[test]
+fn equal_fields_hash_the_same_regardless_of_insertion_order() {
+    let first = with_fields(&[("order_id", json!(42)), ("status", json!("paid"))]);
+    let second = with_fields(&[("status", json!("paid")), ("order_id", json!(42))]);
+
+    assert_eq!(first, second);
+    assert_eq!(first.content_hash(), second.content_hash());
+    let set: HashSet<_> = vec![first, second].into_iter().collect();
+    assert_eq!(set.len(), 1);
+}
+
+#[test]
+fn changed_field_changes_the_hash() {
+    let paid = with_fields(&[("order_id", json!(42)), ("status", json!("paid"))]);
+    let refunded = with_fields(&[("order_id", json!(42)), ("status", json!("refunded"))]);
+    let mut later = paid.clone();
+    later.timestamp += chrono::Duration::milliseconds(1);
+
+    assert_ne!(paid, refunded);
+    assert_ne!(paid.content_hash(), refunded.content_hash());
+    assert_ne!(paid.content_hash(), later.content_hash());
+    assert_eq!(paid.content_hash(), paid.clone().content_hash());
+}
+
+#[test]
+fn location_joins_the_file_line_and_column_known() {
+    let located = |file: Option<&'static str>, line, column| {
+        let mut record = LogStashRecord::new();
+        record.file = file.map(Cow::Borrowed);
+        record.line = line;
+        record.column = column;
+        record.add_location();
+        record.fields.get("location").cloned()
+    };
+
+    assert_eq!(
+        located(Some("src/main.rs"), Some(42), None),
+        Some(json!("src/main.rs:42"))
+    );
+    assert_eq!(
+        located(Some("src/main.rs"), Some(42), Some(7)),
+        Some(json!("src/main.rs:42:7"))
+    );
+    assert_eq!(
+        located(Some("src/main.rs"), None, Some(7)),
+        Some(json!("src/main.rs"))
+    );
+    assert_eq!(located(None, Some(42), None), None);
+}
+
+#[test]
+fn column_is_serialized_when_known() {
+    let mut record = LogStashRecord::new();
+    assert!(to_json(&record).get("column").is_none());
+    record.column = Some(7);
+    assert_eq!(to_json(&record)["column"], 7);
+}
+
+#[derive(serde::Serialize)]
+struct Request {
+    method: &'static str,
+    path: String,
+    client: Client,
+}
+
+#[derive(serde::Serialize)]
+struct Client {
+    ip: [u8; 4],
+    user: Option<String>,
+}
+
+#[test]
+fn serializable_struct_is_added_as_nested_json() {
+    let request = Request {
+        method: "GET",
+        path: "/orders/42".into(),
+        client: Client {
+            ip: [10, 0, 0, 1],
+            user: None,
+        },
+    };
+    let mut record = LogStashRecord::new();
+    record.try_add_data("request", &request).unwrap();
+
+    let expected = json!({
+        "method": "GET",
+        "path": "/orders/42",
+        "client": { "ip": [10, 0, 0, 1], "user": null }
+    });
+    assert_eq!(to_json(&record)["request"], expected);
+
+    // A value which cannot be converted leaves the record as it was
+    let by_pair: std::collections::HashMap<(u8, u8), u8> = [((1, 2), 3)].into();
+    let err = record.try_add_data("by_pair", &by_pair).unwrap_err();
+    assert_eq!(err.kind(), "serde");
+    assert!(!record.fields.contains_key("by_pair"));
+    assert_eq!(to_json(&record)["request"], expected);
+}
+
+#[test]
+fn duration_is_milliseconds_with_microsecond_precision() {
+    let mut record = LogStashRecord::new();
+    record
+        .add_duration("elapsed", Duration::from_nanos(1_234_567_890))
+        .add_duration("fast", Duration::from_nanos(999))
+        .add_duration("slow", Duration::from_secs(3 * 3600));
+
+    let json = to_json(&record);
+    // Nanoseconds below a microsecond are dropped
+    assert_eq!(json["elapsed"], 1234.567);
+    assert_eq!(json["fast"], 0.0);
+    assert_eq!(json["slow"], 10_800_000.0);
+    assert_eq!(json["elapsed_unit"], "ms");
+    assert_eq!(json["fast_unit"], "ms");
+    assert_eq!(json["slow_unit"], "ms");
+}
+
+#[test]
+fn duration_ns_and_human_keep_full_precision() {
+    let duration = Duration::new(3_723, 500_000_001);
+    let mut record = LogStashRecord::new();
+    record
+        .add_duration_ns("elapsed_ns", duration)
+        .add_duration_human("elapsed_iso", duration);
+
+    let json = to_json(&record);
+    assert_eq!(json["elapsed_ns"], 3_723_500_000_001u64);
+    assert_eq!(json["elapsed_ns_unit"], "ns");
+    assert_eq!(json["elapsed_iso"], "PT1H2M3.500000001S");
+    assert!(json.get("elapsed_iso_unit").is_none());
+    assert_eq!(format_duration(Duration::from_secs(60)), "PT1M");
+    assert_eq!(format_duration(Duration::from_nanos(1)), "PT0.000000001S");
+}
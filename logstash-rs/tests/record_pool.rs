@@ -0,0 +1,75 @@
+//! `RecordPool` handing out records and taking them back cleared.
+
+use chrono::{TimeZone, Utc};
+use log::Level;
+use qoollo_logstash_rs::{LogStashRecord, RecordPool};
+
+/// Record built by `f` from a `log::Record` with every metadata set
+fn order_record(f: impl FnOnce(&log::Record) -> LogStashRecord) -> LogStashRecord {
+    f(&log::Record::builder()
+        .args(format_args!("order {} accepted", 42))
+        .level(Level::Warn)
+        .target("orders")
+        .module_path_static(Some("shop::orders"))
+        .file_static(Some("src/orders.rs"))
+        .line(Some(12))
+        .build())
+}
+
+#[test]
+fn dropped_records_return_to_the_pool_cleared() {
+    let pool = RecordPool::new(4);
+    {
+        let mut record = pool.acquire();
+        record.target = "pool".into();
+        record.module = Some("pool::records".into());
+        record.line = Some(7);
+        record.add_data("seq", 1.into());
+        record.add_tag("reused");
+    }
+    assert_eq!(pool.len(), 1);
+
+    let record = pool.acquire();
+    assert!(pool.is_empty());
+    assert_eq!(record.target, "");
+    assert_eq!(record.module, None);
+    assert_eq!(record.line, None);
+    assert!(record.fields.is_empty());
+    assert!(record.tags.is_empty());
+}
+
+#[test]
+fn detached_records_are_not_returned() {
+    let pool = RecordPool::new(4);
+    let record = pool.acquire().into_inner();
+    drop(record);
+    assert!(pool.is_empty());
+}
+
+#[test]
+fn pool_keeps_at_most_its_capacity() {
+    let pool = RecordPool::new(2);
+    for _ in 0..3 {
+        pool.release(LogStashRecord::new());
+    }
+    assert_eq!(pool.len(), 2);
+}
+
+#[test]
+fn pooled_records_are_filled_like_fresh_ones() {
+    let pool = RecordPool::new(4);
+    // A used record, so the pooled one is a reused allocation
+    pool.acquire().add_data("stale", true.into());
+
+    let mut pooled =
+        order_record(|record| LogStashRecord::from_record_pooled(record, &pool).into_inner());
+    let mut fresh = order_record(LogStashRecord::from_record);
+    let timestamp = Utc.with_ymd_and_hms(2024, 5, 17, 8, 30, 0).unwrap();
+    pooled.timestamp = timestamp;
+    fresh.timestamp = timestamp;
+
+    assert_eq!(
+        serde_json::to_value(&pooled).unwrap(),
+        serde_json::to_value(&fresh).unwrap()
+    );
+}
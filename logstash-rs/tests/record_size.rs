@@ -0,0 +1,148 @@
+//! Record sizes by target and the oversized record policies of `BufferedSender`.
+
+use log::Level;
+use qoollo_logstash_rs::testing::CapturingSender;
+use qoollo_logstash_rs::{
+    BufferedSender, BufferedSenderBuilder, LogStashRecord, OversizedPolicy, Sender,
+};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const DIAGNOSTICS_TARGET: &str = "logstash_rs::internal";
+const MAX_EVENT_BYTES: usize = 1000;
+
+fn record(target: &str, payload_len: usize) -> LogStashRecord {
+    LogStashRecord::builder(Level::Info)
+        .target(target.to_owned())
+        .message("sized")
+        .field("payload", "x".repeat(payload_len))
+        .build()
+}
+
+fn builder(policy: OversizedPolicy) -> BufferedSenderBuilder {
+    BufferedSender::builder()
+        .with_buffer_size(None)
+        .with_diagnostics(false)
+        .with_max_event_bytes(Some(MAX_EVENT_BYTES), policy)
+}
+
+/// Records of the test, leaving out diagnostics records
+fn records(captured: &CapturingSender) -> Vec<LogStashRecord> {
+    captured
+        .records()
+        .into_iter()
+        .filter(|r| r.target != DIAGNOSTICS_TARGET)
+        .collect()
+}
+
+#[test]
+fn send_anyway_counts_violations_per_target() {
+    let captured = CapturingSender::new();
+    let sender = builder(OversizedPolicy::SendAnyway).build(captured.clone());
+    let small = record("small", 10);
+    let big = record("big", 5000);
+    let (small_size, big_size) = (small.estimated_json_size(), big.estimated_json_size());
+    sender.send(small.clone()).unwrap();
+    sender.send(small).unwrap();
+    sender.send(big).unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+
+    assert_eq!(records(&captured).len(), 3);
+    let sizes = sender.target_sizes();
+    assert_eq!(sizes.len(), 2);
+    let (target, big) = &sizes[0];
+    assert_eq!(target, "big");
+    assert_eq!(big.count, 1);
+    assert_eq!(big.max_bytes, big_size as u64);
+    assert_eq!(big.violations, 1);
+    let (target, small) = &sizes[1];
+    assert_eq!(target, "small");
+    assert_eq!(small.count, 2);
+    assert_eq!(small.avg_bytes(), small_size as u64);
+    assert_eq!(small.violations, 0);
+    assert_eq!(sender.stats().dropped, 0);
+}
+
+#[test]
+fn drop_policy_drops_oversized_records() {
+    let captured = CapturingSender::new();
+    let sender = builder(OversizedPolicy::Drop).build(captured.clone());
+    sender
+        .send_records(vec![
+            record("app", 10),
+            record("app", 5000),
+            record("app", 20),
+        ])
+        .unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+
+    let records = records(&captured);
+    assert_eq!(records.len(), 2);
+    assert!(records.iter().all(|r| !r.fields.contains_key("truncated")));
+    assert_eq!(sender.stats().dropped, 1);
+    assert_eq!(sender.target_sizes()[0].1.violations, 1);
+}
+
+#[test]
+fn truncate_policy_cuts_largest_fields() {
+    let captured = CapturingSender::new();
+    let sender = builder(OversizedPolicy::Truncate).build(captured.clone());
+    sender.send(record("app", 5000)).unwrap();
+    sender.send(record("app", 10)).unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+
+    let records = records(&captured);
+    assert_eq!(records.len(), 2);
+    let truncated = &records[0];
+    assert_eq!(truncated.fields["truncated"], true);
+    assert_eq!(truncated.fields["message"], "sized");
+    assert!(truncated.estimated_json_size() <= MAX_EVENT_BYTES);
+    assert!(truncated.fields["payload"].as_str().unwrap().len() > 500);
+    assert_eq!(records[1].fields["payload"], "x".repeat(10));
+    assert_eq!(sender.stats().dropped, 0);
+}
+
+#[test]
+fn tracked_targets_keep_the_largest() {
+    let captured = CapturingSender::new();
+    let sender = builder(OversizedPolicy::SendAnyway)
+        .with_max_tracked_targets(3)
+        .build(captured.clone());
+    for i in 0..10 {
+        sender.send(record(&format!("t{}", i), 100 * i)).unwrap();
+    }
+    // Smaller than every tracked target
+    sender.send(record("tiny", 0)).unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+
+    let targets: Vec<_> = sender.target_sizes().into_iter().map(|(t, _)| t).collect();
+    assert_eq!(targets, ["t9", "t8", "t7"]);
+}
+
+#[test]
+fn oversized_diagnostic_is_sent_once_per_target() {
+    let captured = CapturingSender::new();
+    let sender = builder(OversizedPolicy::Drop)
+        .with_diagnostics(true)
+        .with_oversized_diagnostics(true)
+        .build(captured.clone());
+    for target in ["big", "big", "huge", "big"] {
+        sender.send(record(target, 5000)).unwrap();
+    }
+    sender.flush_and_wait(TIMEOUT).unwrap();
+
+    let warnings: Vec<_> = captured
+        .records()
+        .into_iter()
+        .filter(|r| r.target == DIAGNOSTICS_TARGET)
+        .collect();
+    let targets: Vec<_> = warnings
+        .iter()
+        .map(|r| r.fields["record_target"].clone())
+        .collect();
+    assert_eq!(targets, ["big", "huge"]);
+    assert_eq!(warnings[0].level, Level::Warn);
+    assert_eq!(warnings[0].fields["max_event_bytes"], MAX_EVENT_BYTES);
+    assert_eq!(warnings[0].fields["policy"], "drop");
+    assert!(records(&captured).is_empty());
+}
@@ -0,0 +1,138 @@
+//! `RoutingSender` partitioning records between inner senders by target.
+
+use qoollo_logstash_rs::testing::record;
+use qoollo_logstash_rs::{Error, LogStashRecord, Result, RoutingSender, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Time every `flush_and_wait` of a `CallRecorder` takes
+const WAIT: Duration = Duration::from_millis(50);
+
+/// Sender recording the sequence numbers of every call, optionally failing them
+#[derive(Clone, Default)]
+struct CallRecorder {
+    calls: Arc<Mutex<Vec<Vec<u64>>>>,
+    /// Timeouts given to `flush_and_wait`
+    timeouts: Arc<Mutex<Vec<Duration>>>,
+    fail: bool,
+}
+
+impl CallRecorder {
+    fn failing() -> Self {
+        Self {
+            fail: true,
+            ..Default::default()
+        }
+    }
+
+    fn calls(&self) -> Vec<Vec<u64>> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    fn timeouts(&self) -> Vec<Duration> {
+        self.timeouts.lock().unwrap().clone()
+    }
+
+    fn record_call(&self, events: &[LogStashRecord]) -> Result<()> {
+        let seqs = events
+            .iter()
+            .map(|event| event.fields["seq"].as_u64().unwrap())
+            .collect();
+        self.calls.lock().unwrap().push(seqs);
+        if self.fail {
+            return Err(Error::Connection("unreachable".into()));
+        }
+        Ok(())
+    }
+}
+
+impl Sender for CallRecorder {
+    fn send(&self, event: LogStashRecord) -> Result<()> {
+        self.record_call(std::slice::from_ref(&event))
+    }
+
+    fn send_batch(&self, events: Vec<LogStashRecord>) -> Result<()> {
+        self.record_call(&events)
+    }
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn flush_and_wait(&self, timeout: Duration) -> Result<()> {
+        self.timeouts.lock().unwrap().push(timeout);
+        std::thread::sleep(WAIT);
+        self.flush()
+    }
+}
+
+fn routing(access: &CallRecorder, db: &CallRecorder, app: &CallRecorder) -> RoutingSender {
+    RoutingSender::new(Box::new(app.clone()))
+        .with_route("access", Box::new(access.clone()))
+        .with_route("app::db", Box::new(db.clone()))
+}
+
+const TARGETS: [&str; 4] = ["access", "app::web", "app::db::pool", "access::admin"];
+
+#[test]
+fn batch_is_partitioned_into_one_batch_per_destination() {
+    let (access, db, app) = Default::default();
+    let sender = routing(&access, &db, &app);
+
+    let batch = (0..12)
+        .map(|seq| record(TARGETS[seq as usize % TARGETS.len()], seq))
+        .collect();
+    sender.send_batch(batch).unwrap();
+
+    assert_eq!(access.calls(), [vec![0, 3, 4, 7, 8, 11]]);
+    assert_eq!(db.calls(), [vec![2, 6, 10]]);
+    assert_eq!(app.calls(), [vec![1, 5, 9]]);
+}
+
+#[test]
+fn single_records_go_to_the_longest_matching_route() {
+    let (access, db, app) = Default::default();
+    let sender = routing(&access, &db, &app);
+
+    for (seq, target) in TARGETS.iter().enumerate() {
+        sender.send(record(target, seq as u64)).unwrap();
+    }
+    // Routes match plain string prefixes of the target
+    sender.send(record("app", 4)).unwrap();
+    sender.send(record("accesslog", 5)).unwrap();
+
+    assert_eq!(access.calls(), [vec![0], vec![3], vec![5]]);
+    assert_eq!(db.calls(), [vec![2]]);
+    assert_eq!(app.calls(), [vec![1], vec![4]]);
+}
+
+#[test]
+fn failing_destination_does_not_hold_back_the_others() {
+    let (access, app) = (CallRecorder::failing(), CallRecorder::default());
+    let sender =
+        RoutingSender::new(Box::new(app.clone())).with_route("access", Box::new(access.clone()));
+
+    let batch = vec![record("access", 0), record("app", 1), record("access", 2)];
+    assert_eq!(sender.send_batch(batch).unwrap_err().kind(), "connection");
+    assert_eq!(access.calls(), [vec![0, 2]]);
+    assert_eq!(app.calls(), [vec![1]]);
+}
+
+#[test]
+fn routes_wait_for_the_flush_within_one_timeout() {
+    let (access, db, app) = Default::default();
+    let sender = routing(&access, &db, &app);
+    let timeout = Duration::from_secs(1);
+
+    sender.flush_and_wait(timeout).unwrap();
+    let mut timeouts: Vec<_> = [&access, &db, &app]
+        .iter()
+        .flat_map(|recorder| recorder.timeouts())
+        .collect();
+    assert_eq!(timeouts.len(), 3);
+    // Each route is left what the routes before it didn't use
+    timeouts.sort();
+    assert!(timeouts[0] <= timeout - WAIT * 2, "{:?}", timeouts);
+    assert!(timeouts[1] <= timeout - WAIT, "{:?}", timeouts);
+    assert!(timeouts[2] <= timeout, "{:?}", timeouts);
+}
@@ -0,0 +1,42 @@
+//! JSON schema of `LogStashRecord` compared with the snapshot in `tests/snapshots`. After an
+//! intended change of the record, update the snapshot with
+//! `cargo run --features schema --bin logstash-schema > tests/snapshots/schema.json`.
+
+use qoollo_logstash_rs::LogStashRecord;
+use serde_json::Value;
+
+const SNAPSHOT: &str = include_str!("snapshots/schema.json");
+
+fn schema() -> Value {
+    serde_json::to_value(LogStashRecord::json_schema()).unwrap()
+}
+
+#[test]
+fn schema_matches_snapshot() {
+    let snapshot: Value = serde_json::from_str(SNAPSHOT).unwrap();
+    assert_eq!(
+        schema(),
+        snapshot,
+        "schema changed, current schema:\n{}",
+        serde_json::to_string_pretty(&schema()).unwrap()
+    );
+}
+
+#[test]
+fn fields_are_additional_properties() {
+    let schema = schema();
+    assert_eq!(schema["additionalProperties"], true);
+    assert_eq!(schema["properties"]["@timestamp"]["format"], "date-time");
+    assert_eq!(schema["properties"]["level"]["type"], "string");
+    assert!(schema["properties"].get("fields").is_none());
+}
+
+#[test]
+fn tags_are_optional() {
+    let schema = schema();
+    assert_eq!(schema["properties"]["tags"]["type"], "array");
+    assert!(!schema["required"]
+        .as_array()
+        .unwrap()
+        .contains(&Value::from("tags")));
+}
@@ -0,0 +1,132 @@
+//! Self-metrics records sent by `BufferedSender` workers at a fixed interval.
+
+use log::Level;
+use qoollo_logstash_rs::{BufferedSender, LogStashRecord, Result, Sender, SELF_METRICS_TARGET};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const INTERVAL: Duration = Duration::from_millis(150);
+/// Allowed lateness of the worker waking up on a busy machine
+const SLACK: Duration = Duration::from_millis(80);
+
+/// Sender capturing records with the time each arrived, reporting a fixed number of
+/// reconnects
+#[derive(Clone, Default)]
+struct TimedSender {
+    received: Arc<Mutex<Vec<(Instant, LogStashRecord)>>>,
+}
+
+impl TimedSender {
+    fn received(&self) -> Vec<(Instant, LogStashRecord)> {
+        self.received.lock().unwrap().clone()
+    }
+}
+
+impl Sender for TimedSender {
+    fn send(&self, event: LogStashRecord) -> Result<()> {
+        self.received.lock().unwrap().push((Instant::now(), event));
+        Ok(())
+    }
+
+    fn send_batch(&self, events: Vec<LogStashRecord>) -> Result<()> {
+        let now = Instant::now();
+        let mut received = self.received.lock().unwrap();
+        received.extend(events.into_iter().map(|event| (now, event)));
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn reconnects(&self) -> u64 {
+        2
+    }
+}
+
+fn sender(timed: &TimedSender, interval: Option<Duration>) -> BufferedSender {
+    BufferedSender::builder()
+        .with_buffer_size(Some(2))
+        .with_ignore_buffer_level(Level::Trace)
+        .with_self_metrics_interval(interval)
+        .with_diagnostics(false)
+        .build(timed.clone())
+}
+
+fn send_records(sender: &BufferedSender, count: u64) {
+    for seq in 0..count {
+        sender
+            .send(
+                LogStashRecord::builder(Level::Info)
+                    .target("app")
+                    .field("seq", seq)
+                    .build(),
+            )
+            .unwrap();
+    }
+}
+
+fn is_self_metrics(record: &LogStashRecord) -> bool {
+    record.target == SELF_METRICS_TARGET
+}
+
+#[test]
+fn metrics_record_is_sent_after_the_interval_with_the_counters() {
+    let timed = TimedSender::default();
+    let started = Instant::now();
+    let sender = sender(&timed, Some(INTERVAL));
+
+    // Two full batches, the buffer is empty again
+    send_records(&sender, 4);
+    thread::sleep(INTERVAL * 2 + SLACK);
+    drop(sender);
+
+    let received = timed.received();
+    let metrics: Vec<_> = received
+        .iter()
+        .filter(|(_, record)| is_self_metrics(record))
+        .collect();
+    assert!(
+        (2..=3).contains(&metrics.len()),
+        "{} metrics records",
+        metrics.len()
+    );
+    let first = metrics[0].0.duration_since(started);
+    assert!(
+        first >= INTERVAL && first < INTERVAL + SLACK,
+        "first metrics record after {:?}",
+        first
+    );
+    for pair in metrics.windows(2) {
+        let gap = pair[1].0.duration_since(pair[0].0);
+        assert!(
+            gap >= INTERVAL - SLACK / 2 && gap < INTERVAL + SLACK,
+            "metrics records {:?} apart",
+            gap
+        );
+    }
+
+    let fields = &metrics[0].1.fields;
+    assert_eq!(metrics[0].1.level, Level::Info);
+    assert_eq!(fields["_self_metrics"], true);
+    assert_eq!(fields["events_sent"], 4);
+    assert_eq!(fields["events_dropped"], 0);
+    assert_eq!(fields["reconnects"], 2);
+    assert_eq!(fields["buffer_depth"], 0);
+    assert!(fields.contains_key("buffered_bytes"));
+}
+
+#[test]
+fn no_metrics_records_without_an_interval() {
+    let timed = TimedSender::default();
+    let sender = sender(&timed, None);
+
+    send_records(&sender, 4);
+    thread::sleep(INTERVAL + SLACK);
+    drop(sender);
+
+    let received = timed.received();
+    assert_eq!(received.len(), 4);
+    assert!(!received.iter().any(|(_, record)| is_self_metrics(record)));
+}
@@ -0,0 +1,102 @@
+//! Records serialized on the calling thread by a `BufferedSender` in serialize-early mode,
+//! compared on the wire with records serialized by the worker.
+
+use log::Level;
+use qoollo_logstash_rs::testing::MockLogstash;
+use qoollo_logstash_rs::{BufferedSender, LogStashRecord, Sender, TcpSender};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+const RECORDS: usize = 60;
+
+fn records() -> Vec<LogStashRecord> {
+    let levels = [Level::Info, Level::Debug, Level::Warn, Level::Error];
+    (0..RECORDS)
+        .map(|seq| {
+            let mut record = LogStashRecord::builder(levels[seq % levels.len()])
+                .target("serialize_early")
+                .message(format!("record {} \u{e9}t\u{e9}", seq))
+                .field("seq", seq)
+                .field(
+                    "nested",
+                    serde_json::json!({ "ok": seq % 2 == 0, "items": [1, 2] }),
+                )
+                .build();
+            if seq % 3 == 0 {
+                record.add_tag("third");
+            }
+            if seq % 7 == 0 {
+                record.escape_non_ascii(&["message"]);
+            }
+            record
+        })
+        .collect()
+}
+
+/// Lines received by a server from a sender sending `records` one by one and in batches
+fn wire_output(serialize_early: bool, records: &[LogStashRecord]) -> Vec<String> {
+    let server = MockLogstash::start().unwrap();
+    let tcp = TcpSender::builder()
+        .hostname("127.0.0.1")
+        .port(server.port())
+        .build()
+        .unwrap();
+    let sender = BufferedSender::builder()
+        .with_buffer_size(Some(8))
+        .with_buffer_lifetime(None)
+        // Warnings and errors are buffered, more verbose records are sent right away
+        .with_ignore_buffer_level(Level::Info)
+        .with_flush_bytes(Some(2048))
+        .with_serialize_early(serialize_early)
+        .with_diagnostics(false)
+        .build(tcp);
+
+    let (single, batched) = records.split_at(RECORDS / 2);
+    for record in single {
+        sender.send(record.clone()).unwrap();
+    }
+    for batch in batched.chunks(5) {
+        sender.send_batch(batch.to_vec()).unwrap();
+    }
+    sender.flush_and_wait(TIMEOUT).unwrap();
+    server
+        .wait_for_events(RECORDS, TIMEOUT)
+        .into_iter()
+        .map(|line| line.line)
+        .collect()
+}
+
+#[test]
+fn serialize_early_writes_the_same_bytes() {
+    let records = records();
+    let late = wire_output(false, &records);
+    let early = wire_output(true, &records);
+
+    assert_eq!(late.len(), RECORDS);
+    // Buffering reorders records by level the same way in both modes
+    assert_eq!(early, late);
+    assert!(early.iter().any(|line| line.contains("\\u00e9")));
+}
+
+#[test]
+fn frames_serialized_one_after_the_other_share_an_allocation() {
+    let records = records();
+    let frames: Vec<_> = records
+        .iter()
+        .map(|record| record.to_json_bytes_with_newline().unwrap())
+        .collect();
+
+    for (record, frame) in records.iter().zip(&frames) {
+        let (json, newline) = frame.split_at(frame.len() - 1);
+        assert_eq!(newline, b"\n");
+        let parsed: serde_json::Value = serde_json::from_slice(json).unwrap();
+        assert_eq!(parsed, serde_json::to_value(record).unwrap());
+    }
+    // The first frames fit in the initial buffer and are split off it back to back
+    let (first, second) = (&frames[0], &frames[1]);
+    assert_eq!(unsafe { first.as_ptr().add(first.len()) }, second.as_ptr());
+    assert_eq!(
+        &records[2].to_json_bytes().unwrap()[..],
+        &frames[2][..frames[2].len() - 1]
+    );
+}
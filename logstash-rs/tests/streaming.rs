@@ -0,0 +1,92 @@
+//! Peak memory of sending large records, serialized straight into the stdin of a child
+//! process instead of into an intermediate buffer. Every allocation of the process is
+//! counted, so this file holds a single test.
+#![cfg(unix)]
+
+use log::Level;
+use qoollo_logstash_rs::{ChildProcessSender, LogStashRecord, Sender};
+use serde_json::json;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Allocator tracking the bytes currently allocated and their peak
+struct PeakAllocator;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for PeakAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let current = CURRENT.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(current, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: PeakAllocator = PeakAllocator;
+
+/// Bytes allocated by `f` at its peak on top of what was allocated before
+fn peak_allocated(f: impl FnOnce()) -> usize {
+    let before = CURRENT.load(Ordering::Relaxed);
+    PEAK.store(before, Ordering::Relaxed);
+    f();
+    PEAK.load(Ordering::Relaxed) - before
+}
+
+fn large_record(seq: usize) -> LogStashRecord {
+    let document: Vec<_> = (0..10_000)
+        .map(|id| json!({ "id": id, "name": format!("item-{}", id), "payload": "x".repeat(64) }))
+        .collect();
+    LogStashRecord::builder(Level::Info)
+        .target("streaming")
+        .field("seq", seq)
+        .field("document", document)
+        .build()
+}
+
+#[test]
+fn large_batches_are_not_materialized() {
+    let sender = ChildProcessSender::new(
+        "sh",
+        vec!["-c".to_owned(), "exec cat > /dev/null".to_owned()],
+    );
+    sender.connect().unwrap();
+    let records: Vec<_> = (0..4).map(large_record).collect();
+
+    let mut serialized = 0;
+    let materialized = peak_allocated(|| {
+        let mut buf = vec![];
+        for record in &records {
+            serde_json::to_writer(&mut buf, record).unwrap();
+            buf.push(b'\n');
+        }
+        serialized = buf.len();
+    });
+    let streamed = peak_allocated(|| sender.send_batch_ref(&records).unwrap());
+    sender.flush().unwrap();
+    println!(
+        "{} records, {} bytes of JSON: peak {} bytes materialized, {} bytes streamed",
+        records.len(),
+        serialized,
+        materialized,
+        streamed
+    );
+
+    assert!(materialized >= serialized);
+    // Only the write buffer is allocated, not even one record is held at once
+    assert!(
+        streamed < serialized / records.len() / 10,
+        "{} bytes allocated streaming {} bytes",
+        streamed,
+        serialized
+    );
+}
@@ -0,0 +1,253 @@
+//! `TcpSender` against a `MockLogstash` closing or resetting connections.
+
+use log::Level;
+use qoollo_logstash_rs::testing::{record, records, MockBehavior, MockLogstash, WriteFaults};
+use qoollo_logstash_rs::{
+    BufferedSender, Error, LogStashRecord, ReconnectPolicy, Sender, TcpSender,
+};
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+const AUDIT_TIMEOUT: Duration = Duration::from_millis(200);
+
+fn tcp(server: &MockLogstash) -> TcpSender {
+    TcpSender::builder()
+        .hostname("127.0.0.1")
+        .port(server.port())
+        .build()
+        .unwrap()
+}
+
+/// Sequence numbers received by the server with the connection each came on
+fn received(server: &MockLogstash) -> Vec<(usize, u64)> {
+    server
+        .lines()
+        .iter()
+        .map(|line| {
+            (
+                line.connection,
+                line.json().unwrap()["seq"].as_u64().unwrap(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn audit_reports_a_batch_reset_by_the_peer() {
+    let server = MockLogstash::start_with_script(vec![MockBehavior::Reset]).unwrap();
+    let tcp = tcp(&server).with_audit(Some(AUDIT_TIMEOUT));
+
+    // The connection was opened for this batch, so it is not retried within the call
+    let err = tcp.send_batch(records("tcp", 0..3)).unwrap_err();
+    assert_eq!(err.kind(), "connection");
+    assert!(server.lines().is_empty());
+
+    // Retrying the batch opens a healthy connection
+    tcp.send_batch(records("tcp", 0..3)).unwrap();
+    server.wait_for_events(3, TIMEOUT);
+    assert_eq!(received(&server), [(1, 0), (1, 1), (1, 2)]);
+    assert_eq!(server.connections(), 2);
+}
+
+#[test]
+fn audit_resends_a_batch_reset_on_an_established_connection() {
+    let server = MockLogstash::start_with_script(vec![MockBehavior::Reset]).unwrap();
+    let tcp = tcp(&server).with_audit(Some(AUDIT_TIMEOUT));
+    tcp.pre_connect().unwrap();
+
+    tcp.send_batch(records("tcp", 0..3)).unwrap();
+    server.wait_for_events(3, TIMEOUT);
+    assert_eq!(received(&server), [(1, 0), (1, 1), (1, 2)]);
+    assert_eq!(server.connections(), 2);
+}
+
+#[test]
+fn audit_confirms_batches_on_a_healthy_connection() {
+    let server = MockLogstash::start().unwrap();
+    let tcp = tcp(&server).with_audit(Some(AUDIT_TIMEOUT));
+
+    tcp.send_batch(records("tcp", 0..2)).unwrap();
+    tcp.send(record("tcp", 2)).unwrap();
+    server.wait_for_events(3, TIMEOUT);
+    assert_eq!(received(&server), [(0, 0), (0, 1), (0, 2)]);
+    assert_eq!(server.connections(), 1);
+}
+
+#[test]
+fn without_audit_a_reset_batch_is_reported_sent() {
+    let server = MockLogstash::start_with_script(vec![MockBehavior::Reset]).unwrap();
+    let tcp = tcp(&server);
+
+    // Handed to the kernel before the peer reset the connection
+    tcp.send_batch(records("tcp", 0..3)).unwrap();
+    std::thread::sleep(AUDIT_TIMEOUT);
+    assert!(server.lines().is_empty());
+}
+
+#[test]
+fn flushing_an_idle_sender_opens_no_connection() {
+    let server = MockLogstash::start().unwrap();
+    let tcp = tcp(&server);
+    tcp.flush().unwrap();
+
+    let sender = BufferedSender::builder().with_diagnostics(false).build(tcp);
+    sender.flush_and_wait(TIMEOUT).unwrap();
+    std::thread::sleep(AUDIT_TIMEOUT);
+    assert_eq!(server.connections(), 0);
+}
+
+/// Bytes read by the server before it closes the first connection
+const CLOSE_AFTER: usize = 64 * 1024;
+/// Records in a batch larger than the socket buffers, so the close interrupts writing it
+const LARGE_BATCH: u64 = 8_000;
+
+fn large_records(seqs: std::ops::Range<u64>) -> Vec<LogStashRecord> {
+    seqs.map(|seq| {
+        LogStashRecord::builder(Level::Info)
+            .target("tcp")
+            .message("x".repeat(2048))
+            .field("seq", seq)
+            .build()
+    })
+    .collect()
+}
+
+/// Sequence numbers received on `connection` once the last record of the batch arrived
+fn wait_for_last(server: &MockLogstash, connection: usize) -> Vec<u64> {
+    let started = Instant::now();
+    loop {
+        let received = received(server);
+        if received.last().map(|&(_, seq)| seq) == Some(LARGE_BATCH - 1) {
+            return received
+                .into_iter()
+                .filter(|&(on, _)| on == connection)
+                .map(|(_, seq)| seq)
+                .collect();
+        }
+        assert!(
+            started.elapsed() < TIMEOUT,
+            "{} lines received",
+            received.len()
+        );
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn interrupted_batch_resends_only_the_records_not_written() {
+    let server =
+        MockLogstash::start_with_script(vec![MockBehavior::CloseAfterBytes(CLOSE_AFTER)]).unwrap();
+    let tcp = tcp(&server);
+    tcp.pre_connect().unwrap();
+
+    // The batch was written on an established connection, so it is retried on a fresh one
+    tcp.send_batch_ref(&large_records(0..LARGE_BATCH)).unwrap();
+    let resent = wait_for_last(&server, 1);
+    let first: Vec<_> = received(&server)
+        .into_iter()
+        .filter(|&(connection, _)| connection == 0)
+        .map(|(_, seq)| seq)
+        .collect();
+
+    // Complete lines within the bytes read before the close
+    assert!(!first.is_empty());
+    assert_eq!(first, (0..first.len() as u64).collect::<Vec<_>>());
+    // Records written before the failure are not resent, even those the server dropped
+    let written = resent[0];
+    assert!(written >= first.len() as u64 && written < LARGE_BATCH);
+    assert_eq!(resent, (written..LARGE_BATCH).collect::<Vec<_>>());
+    assert_eq!(server.connections(), 2);
+}
+
+#[test]
+fn interrupted_batch_on_a_new_connection_reports_a_partial_write() {
+    let server =
+        MockLogstash::start_with_script(vec![MockBehavior::CloseAfterBytes(CLOSE_AFTER)]).unwrap();
+    let tcp = tcp(&server);
+    let batch = large_records(0..LARGE_BATCH);
+
+    let err = tcp.send_batch_ref(&batch).unwrap_err();
+    assert_eq!(err.kind(), "partial_write");
+    let (confirmed, unconfirmed) = match err {
+        Error::PartialWrite {
+            confirmed,
+            unconfirmed,
+            ..
+        } => (confirmed, unconfirmed),
+        err => panic!("unexpected error {}", err),
+    };
+    let first = received(&server).len();
+    assert!(first > 0);
+    assert!(confirmed >= first && unconfirmed > 0);
+    assert_eq!(confirmed + unconfirmed, batch.len());
+
+    // The broken connection was dropped, the unconfirmed records go on a fresh one
+    tcp.send_batch_ref(&batch[confirmed..]).unwrap();
+    let resent = wait_for_last(&server, 1);
+    assert_eq!(resent, (confirmed as u64..LARGE_BATCH).collect::<Vec<_>>());
+    assert_eq!(server.connections(), 2);
+}
+
+fn faulty(server: &MockLogstash, tolerated_write_errors: u32) -> (TcpSender, WriteFaults) {
+    let faults = WriteFaults::new();
+    let tcp = tcp(server)
+        .with_reconnect_policy(ReconnectPolicy {
+            tolerated_write_errors,
+            ..Default::default()
+        })
+        .with_write_faults(faults.clone());
+    tcp.pre_connect().unwrap();
+    (tcp, faults)
+}
+
+#[test]
+fn tolerated_write_errors_are_retried_on_the_same_connection() {
+    let server = MockLogstash::start().unwrap();
+    let (tcp, faults) = faulty(&server, 3);
+
+    faults.fail_next(3);
+    tcp.send_batch(records("tcp", 0..2)).unwrap();
+    assert_eq!(faults.injected(), 3);
+    assert_eq!(tcp.reconnects(), 0);
+
+    // Errors are counted again after a successful write
+    faults.fail_next(2);
+    tcp.send(record("tcp", 2)).unwrap();
+    server.wait_for_events(3, TIMEOUT);
+    assert_eq!(received(&server), [(0, 0), (0, 1), (0, 2)]);
+    assert_eq!(tcp.reconnects(), 0);
+    assert_eq!(server.connections(), 1);
+}
+
+#[test]
+fn write_errors_beyond_the_tolerance_reconnect() {
+    let server = MockLogstash::start().unwrap();
+    let (tcp, faults) = faulty(&server, 2);
+
+    // The batch is sent whole on a fresh connection
+    faults.fail_next(3);
+    tcp.send_batch(records("tcp", 0..2)).unwrap();
+    server.wait_for_events(2, TIMEOUT);
+    assert_eq!(faults.injected(), 3);
+    assert_eq!(received(&server), [(1, 0), (1, 1)]);
+    assert_eq!(tcp.reconnects(), 1);
+}
+
+#[cfg(unix)]
+#[test]
+fn vectored_batches_arrive_like_copied_ones() {
+    let server = MockLogstash::start().unwrap();
+    let tcp = tcp(&server);
+    let events = records("tcp", 0..50);
+
+    tcp.send_batch_ref(&events).unwrap();
+    tcp.send_batch_vectored(&events).unwrap();
+    tcp.send_batch_vectored(&[]).unwrap();
+    let lines = server.wait_for_events(100, TIMEOUT);
+    let (copied, vectored) = lines.split_at(50);
+    assert_eq!(
+        copied.iter().map(|line| &line.line).collect::<Vec<_>>(),
+        vectored.iter().map(|line| &line.line).collect::<Vec<_>>()
+    );
+    assert_eq!(server.connections(), 1);
+}
@@ -0,0 +1,49 @@
+//! Records serialized without `@timestamp`. The setting is shared by all records of the
+//! process, so this file holds a single test.
+
+use log::Level;
+use qoollo_logstash_rs::event::{set_timestamp_enabled, timestamp_enabled};
+use qoollo_logstash_rs::testing::MockLogstash;
+use qoollo_logstash_rs::{LogStashRecord, Sender, TcpSender};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[test]
+fn disabled_timestamp_leaves_the_key_out() {
+    let record = LogStashRecord::builder(Level::Info)
+        .message("stamped on ingest")
+        .build();
+    assert!(timestamp_enabled());
+    let json = serde_json::to_value(&record).unwrap();
+    assert!(json.get("@timestamp").is_some());
+
+    set_timestamp_enabled(false);
+    assert!(!timestamp_enabled());
+    let json = serde_json::to_value(&record).unwrap();
+    assert!(json.get("@timestamp").is_none(), "{}", json);
+    assert_eq!(json["message"], "stamped on ingest");
+    assert!(!serde_json::to_string(&record)
+        .unwrap()
+        .contains("@timestamp"));
+
+    // Records written to the connection are serialized the same way
+    let server = MockLogstash::start().unwrap();
+    let tcp = TcpSender::builder()
+        .hostname("127.0.0.1")
+        .port(server.port())
+        .build()
+        .unwrap();
+    tcp.send(record.clone()).unwrap();
+    tcp.send_batch(vec![record.clone()]).unwrap();
+    let lines = server.wait_for_events(2, TIMEOUT);
+    for line in &lines {
+        let json = line.json().unwrap();
+        assert!(json.get("@timestamp").is_none(), "{}", line.line);
+        assert_eq!(json["message"], "stamped on ingest");
+    }
+
+    set_timestamp_enabled(true);
+    let json = serde_json::to_value(&record).unwrap();
+    assert!(json.get("@timestamp").is_some());
+}
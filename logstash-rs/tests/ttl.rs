@@ -0,0 +1,89 @@
+//! Records older than the TTL of a `BufferedSender` dropped at flush instead of sent.
+
+use chrono::Utc;
+use log::Level;
+use qoollo_logstash_rs::testing::CapturingSender;
+use qoollo_logstash_rs::{BufferedSender, LogStashRecord, Sender};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const TTL: Duration = Duration::from_secs(60);
+
+fn record(seq: u64, age: chrono::Duration) -> LogStashRecord {
+    LogStashRecord::builder(Level::Info)
+        .target("ttl")
+        .timestamp(Utc::now() - age)
+        .field("seq", seq)
+        .build()
+}
+
+fn seqs(records: &[LogStashRecord]) -> Vec<u64> {
+    records
+        .iter()
+        .map(|record| record.fields["seq"].as_u64().unwrap())
+        .collect()
+}
+
+fn buffered(record_ttl: Option<Duration>, captured: &CapturingSender) -> BufferedSender {
+    BufferedSender::builder()
+        .with_buffer_size(Some(100))
+        .with_buffer_lifetime(None)
+        .with_ignore_buffer_level(Level::Trace)
+        .with_record_ttl(record_ttl)
+        .with_diagnostics(false)
+        .build(captured.clone())
+}
+
+/// Alternates records from two hours ago with fresh ones
+fn send_backlog(sender: &BufferedSender) {
+    for seq in 0..10 {
+        let age = if seq % 2 == 0 {
+            chrono::Duration::hours(2)
+        } else {
+            chrono::Duration::seconds(1)
+        };
+        sender.send(record(seq, age)).unwrap();
+    }
+    sender.flush_and_wait(TIMEOUT).unwrap();
+}
+
+#[test]
+fn expired_records_are_dropped_and_counted() {
+    let captured = CapturingSender::new();
+    let sender = buffered(Some(TTL), &captured);
+    send_backlog(&sender);
+
+    assert_eq!(seqs(&captured.take()), [1, 3, 5, 7, 9]);
+    let stats = sender.stats();
+    assert_eq!(stats.sent, 5);
+    assert_eq!(stats.dropped, 5);
+}
+
+#[test]
+fn without_ttl_old_records_are_sent() {
+    let captured = CapturingSender::new();
+    let sender = buffered(None, &captured);
+    send_backlog(&sender);
+
+    assert_eq!(seqs(&captured.take()), (0..10).collect::<Vec<_>>());
+    assert_eq!(sender.stats().dropped, 0);
+}
+
+#[test]
+fn backlog_held_past_the_ttl_is_dropped_on_recovery() {
+    let captured = CapturingSender::new();
+    let sender = buffered(Some(Duration::from_millis(200)), &captured);
+
+    // The paused worker holds the records as a destination down would
+    sender.pause().unwrap();
+    for seq in 0..5 {
+        sender.send(record(seq, chrono::Duration::zero())).unwrap();
+    }
+    std::thread::sleep(Duration::from_millis(300));
+    sender.resume().unwrap();
+    sender.send(record(5, chrono::Duration::zero())).unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+
+    assert_eq!(seqs(&captured.take()), [5]);
+    assert_eq!(sender.stats().dropped, 5);
+}
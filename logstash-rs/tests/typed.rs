@@ -0,0 +1,91 @@
+//! Values of serializable types sent as records with `Sender::send_typed`.
+
+use log::Level;
+use qoollo_logstash_rs::testing::CapturingSender;
+use qoollo_logstash_rs::Sender;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+#[derive(Serialize)]
+struct Payment {
+    order_id: u64,
+    amount: f64,
+    currency: &'static str,
+    customer: Customer,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    coupon: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Customer {
+    id: u32,
+    tier: Tier,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Tier {
+    Gold,
+}
+
+fn sent_json(send: impl FnOnce(&CapturingSender)) -> Value {
+    let captured = CapturingSender::new();
+    send(&captured);
+    let mut records = captured.take();
+    assert_eq!(records.len(), 1);
+    serde_json::to_value(records.remove(0)).unwrap()
+}
+
+#[test]
+fn struct_fields_become_record_fields() {
+    let payment = Payment {
+        order_id: 42,
+        amount: 19.99,
+        currency: "EUR",
+        customer: Customer {
+            id: 7,
+            tier: Tier::Gold,
+        },
+        coupon: None,
+    };
+    let json = sent_json(|sender| {
+        sender
+            .send_typed(Level::Warn, "billing::payments", &payment)
+            .unwrap()
+    });
+
+    assert_eq!(json["level"], "WARN");
+    assert_eq!(json["target"], "billing::payments");
+    assert!(json["@timestamp"].is_string());
+    assert_eq!(json["order_id"], 42);
+    assert_eq!(json["amount"], 19.99);
+    assert_eq!(json["currency"], "EUR");
+    assert_eq!(json["customer"], json!({ "id": 7, "tier": "gold" }));
+    assert!(json.get("coupon").is_none());
+    assert!(json.get("data").is_none());
+}
+
+#[test]
+fn non_object_values_go_to_the_data_field() {
+    let json = sent_json(|sender| {
+        sender
+            .send_typed(Level::Info, "app", vec![1, 2, 3])
+            .unwrap()
+    });
+    assert_eq!(json["data"], json!([1, 2, 3]));
+
+    let json = sent_json(|sender| sender.send_typed(Level::Info, "app", Tier::Gold).unwrap());
+    assert_eq!(json["data"], "gold");
+
+    let json = sent_json(|sender| sender.send_typed(Level::Info, "app", ()).unwrap());
+    assert_eq!(json["data"], Value::Null);
+}
+
+#[test]
+fn values_which_fail_to_serialize_are_reported() {
+    let mut map = std::collections::HashMap::new();
+    map.insert(vec![1u8], "keys must be strings");
+    let captured = CapturingSender::new();
+    assert!(captured.send_typed(Level::Info, "app", map).is_err());
+    assert!(captured.records().is_empty());
+}
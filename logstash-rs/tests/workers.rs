@@ -0,0 +1,214 @@
+//! `BufferedSender` with several worker threads built by `build_with_factory`.
+
+use qoollo_logstash_rs::testing::record;
+use qoollo_logstash_rs::{BufferedSender, LogStashRecord, Result, Sender, WorkerDispatch};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const WORKERS: usize = 4;
+
+/// Record received by a worker sender: worker index, target and sequence number
+type Delivery = (usize, String, u64);
+
+/// Sender of one worker, recording what it receives in a log shared by all workers
+struct WorkerSender {
+    worker: usize,
+    delay: Duration,
+    deliveries: Arc<Mutex<Vec<Delivery>>>,
+}
+
+impl WorkerSender {
+    fn deliver(&self, events: &[LogStashRecord]) -> Result<()> {
+        std::thread::sleep(self.delay);
+        let mut deliveries = self.deliveries.lock().unwrap();
+        for event in events {
+            let seq = event.fields["seq"].as_u64().unwrap();
+            deliveries.push((self.worker, event.target.to_string(), seq));
+        }
+        Ok(())
+    }
+}
+
+impl Sender for WorkerSender {
+    fn send(&self, event: LogStashRecord) -> Result<()> {
+        self.deliver(std::slice::from_ref(&event))
+    }
+
+    fn send_batch(&self, events: Vec<LogStashRecord>) -> Result<()> {
+        self.deliver(&events)
+    }
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Workers with senders taking `delay` per call, and the log of their deliveries
+fn workers(
+    workers: usize,
+    dispatch: WorkerDispatch,
+    delay: Duration,
+) -> (BufferedSender, Arc<Mutex<Vec<Delivery>>>) {
+    let deliveries = Arc::new(Mutex::new(vec![]));
+    let next_worker = AtomicUsize::new(0);
+    let sender = BufferedSender::builder()
+        .with_buffer_size(None)
+        .with_workers(workers)
+        .with_worker_dispatch(dispatch)
+        .with_log_queue_len(10_000)
+        .with_diagnostics(false)
+        .build_with_factory(|| WorkerSender {
+            worker: next_worker.fetch_add(1, Ordering::Relaxed),
+            delay,
+            deliveries: deliveries.clone(),
+        });
+    (sender, deliveries)
+}
+
+/// Logs `per_target` records for each of `targets` targets from one thread per target,
+/// interleaving the targets
+fn log_from_threads(sender: &BufferedSender, targets: usize, per_target: u64) {
+    let threads: Vec<_> = (0..targets)
+        .map(|target| {
+            let sender = sender.clone();
+            std::thread::spawn(move || {
+                let target = format!("app::module{}", target);
+                for seq in 0..per_target {
+                    sender.send(record(&target, seq)).unwrap();
+                }
+            })
+        })
+        .collect();
+    threads
+        .into_iter()
+        .for_each(|thread| thread.join().unwrap());
+}
+
+#[test]
+fn sticky_dispatch_keeps_per_target_order() {
+    let (sender, deliveries) = workers(
+        WORKERS,
+        WorkerDispatch::StickyByTarget,
+        Duration::from_micros(200),
+    );
+    log_from_threads(&sender, 12, 50);
+    sender.flush_and_wait(TIMEOUT).unwrap();
+
+    let deliveries = deliveries.lock().unwrap();
+    assert_eq!(deliveries.len(), 12 * 50);
+    let mut by_target: HashMap<&str, (HashSet<usize>, Vec<u64>)> = HashMap::new();
+    for (worker, target, seq) in deliveries.iter() {
+        let (workers, seqs) = by_target.entry(target).or_default();
+        workers.insert(*worker);
+        seqs.push(*seq);
+    }
+    for (target, (workers, seqs)) in &by_target {
+        assert_eq!(workers.len(), 1, "{} went to workers {:?}", target, workers);
+        assert_eq!(
+            seqs,
+            &(0..50).collect::<Vec<_>>(),
+            "{} out of order",
+            target
+        );
+    }
+    // The targets are spread over the workers
+    let used: HashSet<_> = deliveries.iter().map(|(worker, ..)| *worker).collect();
+    assert!(used.len() > 1);
+}
+
+#[test]
+fn sticky_dispatch_splits_batches_by_target() {
+    let (sender, deliveries) = workers(WORKERS, WorkerDispatch::StickyByTarget, Duration::ZERO);
+    let batch = (0..40)
+        .map(|seq| record(&format!("batch::target{}", seq % 8), seq / 8))
+        .collect();
+    sender.send_records(batch).unwrap();
+    sender.flush_and_wait(TIMEOUT).unwrap();
+
+    let deliveries = deliveries.lock().unwrap();
+    assert_eq!(deliveries.len(), 40);
+    for target in 0..8 {
+        let target = format!("batch::target{}", target);
+        let of_target: Vec<_> = deliveries.iter().filter(|(_, t, _)| *t == target).collect();
+        assert!(of_target
+            .iter()
+            .all(|(worker, ..)| *worker == of_target[0].0));
+        let seqs: Vec<_> = of_target.iter().map(|(.., seq)| *seq).collect();
+        assert_eq!(seqs, [0, 1, 2, 3, 4]);
+    }
+}
+
+#[test]
+fn round_robin_uses_every_worker() {
+    let (sender, deliveries) = workers(WORKERS, WorkerDispatch::RoundRobin, Duration::ZERO);
+    for seq in 0..(WORKERS as u64 * 5) {
+        sender.send(record("app", seq)).unwrap();
+    }
+    sender.flush_and_wait(TIMEOUT).unwrap();
+
+    let deliveries = deliveries.lock().unwrap();
+    for worker in 0..WORKERS {
+        let count = deliveries.iter().filter(|(w, ..)| *w == worker).count();
+        assert_eq!(count, 5, "worker {}", worker);
+    }
+    assert_eq!(sender.stats().sent, WORKERS as u64 * 5);
+}
+
+/// Times `records` records through `workers` workers whose sender takes 2ms per call
+fn throughput(workers_count: usize, records: u64) -> Duration {
+    let (sender, deliveries) = workers(
+        workers_count,
+        WorkerDispatch::RoundRobin,
+        Duration::from_millis(2),
+    );
+    let started = Instant::now();
+    for seq in 0..records {
+        sender.send(record("bench", seq)).unwrap();
+    }
+    sender.flush_and_wait(TIMEOUT).unwrap();
+    let elapsed = started.elapsed();
+    assert_eq!(deliveries.lock().unwrap().len() as u64, records);
+    elapsed
+}
+
+#[test]
+fn four_workers_scale_with_slow_senders() {
+    let one = throughput(1, 40);
+    let four = throughput(WORKERS, 40);
+    println!(
+        "40 records with 2ms per send: 1 worker {:?}, 4 workers {:?}",
+        one, four
+    );
+    assert!(four * 2 < one, "4 workers {:?} vs 1 worker {:?}", four, one);
+}
+
+#[test]
+fn drop_waits_for_all_workers_within_one_shutdown_timeout() {
+    let shutdown_timeout = Duration::from_millis(300);
+    let deliveries = Arc::new(Mutex::new(vec![]));
+    let sender = BufferedSender::builder()
+        .with_buffer_size(None)
+        .with_workers(WORKERS)
+        .with_worker_dispatch(WorkerDispatch::RoundRobin)
+        .with_shutdown_timeout(shutdown_timeout)
+        .with_diagnostics(false)
+        .build_with_factory(|| WorkerSender {
+            worker: 0,
+            delay: Duration::from_secs(2),
+            deliveries: deliveries.clone(),
+        });
+    // Every worker is stuck in a send when the sender is dropped
+    for seq in 0..WORKERS as u64 {
+        sender.send(record("stuck", seq)).unwrap();
+    }
+    std::thread::sleep(Duration::from_millis(50));
+
+    let started = Instant::now();
+    drop(sender);
+    let elapsed = started.elapsed();
+    assert!(elapsed >= shutdown_timeout, "{:?}", elapsed);
+    assert!(elapsed < shutdown_timeout * 2, "{:?}", elapsed);
+}